@@ -13,78 +13,106 @@ macro_rules! gen_checker {
     ($marcro_name:ident, $code:block) => {
         #[proc_macro_attribute]
         pub fn $marcro_name(_attr: TokenStream, item: TokenStream) -> TokenStream {
-            let input = parse_macro_input!(item as ItemFn);
+            gen_checker!(@expand item, $code)
+        }
+    };
+    // the argument of the attribute is bound to $attr, like Admin in #[check_role(Admin)]
+    ($marcro_name:ident, $attr:ident, $code:block) => {
+        #[proc_macro_attribute]
+        pub fn $marcro_name(attr: TokenStream, item: TokenStream) -> TokenStream {
+            let $attr = parse_macro_input!(attr as syn::Ident);
 
-            let fn_inputs = &input.sig.inputs;
+            gen_checker!(@expand item, $code)
+        }
+    };
+    (@expand $item:ident, $code:block) => {{
+        let input = parse_macro_input!($item as ItemFn);
 
-            if fn_inputs.len() != 2 {
-                return quote! {
-                    compile_error!("only works with 2 arguments");
-                }
-                .into();
+        let fn_inputs = &input.sig.inputs;
+
+        if fn_inputs.len() != 2 {
+            return quote! {
+                compile_error!("only works with 2 arguments");
             }
+            .into();
+        }
 
-            let param_names = input
-                .sig
-                .inputs
-                .iter()
-                .filter_map(|arg| {
-                    if let syn::FnArg::Typed(pat_type) = arg {
-                        if let syn::Pat::Ident(ident) = &*pat_type.pat {
-                            Some(ident.ident.to_string())
-                        } else {
-                            None
-                        }
+        let param_names = input
+            .sig
+            .inputs
+            .iter()
+            .filter_map(|arg| {
+                if let syn::FnArg::Typed(pat_type) = arg {
+                    if let syn::Pat::Ident(ident) = &*pat_type.pat {
+                        Some(ident.ident.to_string())
                     } else {
                         None
                     }
-                })
-                .collect::<Vec<String>>();
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<String>>();
 
-            let expected_param_names = ["message", "state"];
+        let expected_param_names = ["message", "state"];
 
-            for expected in expected_param_names {
-                if !param_names.contains(&expected.to_string()) {
-                    return quote! {
-                        compile_error!(concat!("expect parameter name: ", #expected));
-                    }
-                    .into();
+        for expected in expected_param_names {
+            if !param_names.contains(&expected.to_string()) {
+                return quote! {
+                    compile_error!(concat!("expect parameter name: ", #expected));
                 }
+                .into();
             }
+        }
 
-            let fn_attrs = &input.attrs;
-            let fn_visibility = &input.vis;
-            let fn_sig = &input.sig;
-            let fn_block = &input.block;
+        let fn_attrs = &input.attrs;
+        let fn_visibility = &input.vis;
+        let fn_sig = &input.sig;
+        let fn_block = &input.block;
 
-            let tokens = quote_spanned! {input.span() =>
-                #(#fn_attrs)*
-                #fn_visibility #fn_sig {
-                    $code
+        let tokens = quote_spanned! {input.span() =>
+            #(#fn_attrs)*
+            #fn_visibility #fn_sig {
+                $code
 
-                    #fn_block
-                }
-            };
+                #fn_block
+            }
+        };
 
-            tokens.into()
-        }
-    };
+        tokens.into()
+    }};
 }
 
-gen_checker!(check_in_group, {
-    match message.chat() {
-        grammers_client::types::Chat::Group(_) => {}
-        _ => {
-            const CHECK_IN_GROUP_FAILED: &str = r"
-This bot must be used in a Group!
-
-Add this bot to a Group as Admin, and give it ability to Delete Messages.
+// anyone who can post in a group or channel can use the bot there,
+// so private chats are only allowed once the users are restricted
+// check_senders runs before it, so that strangers are ignored instead of answered
+gen_checker!(check_chat, {
+    if let grammers_client::types::Chat::User(_) = message.chat() {
+        let users = &crate::env::ENV.get().unwrap().telegram_user.users;
+
+        // users in the acl instead of tg_user_name once it's enabled
+        let is_allowed = match message.sender() {
+            Some(sender) if state.acl.is_enabled().await? => {
+                state.acl.get_role(sender.id()).await?.is_some()
+            }
+            sender => sender.is_some_and(|sender| {
+                sender
+                    .username()
+                    .is_some_and(|username| users.contains(&username.to_string()))
+            }),
+        };
+
+        if !is_allowed {
+            const CHECK_CHAT_FAILED: &str = r"
+This bot can only be used in private chats by users in tg_user_name or tg_admin_ids.
+
+Otherwise, add this bot to a Group or Channel as Admin, and give it ability to Delete Messages.
 ";
 
             message
-                .respond(CHECK_IN_GROUP_FAILED)
+                .respond(CHECK_CHAT_FAILED)
                 .await
-                .context(CHECK_IN_GROUP_FAILED)?;
+                .context(CHECK_CHAT_FAILED)?;
 
             return Ok(());
         }
@@ -94,8 +122,13 @@ Add this bot to a Group as Admin, and give it ability to Delete Messages.
 gen_checker!(check_senders, {
     let users = &crate::env::ENV.get().unwrap().telegram_user.users;
 
+    // user ids in the acl replace usernames once it's enabled
     if let Some(sender) = message.sender() {
-        if let Some(username) = sender.username() {
+        if state.acl.is_enabled().await? {
+            if state.acl.get_role(sender.id()).await?.is_none() {
+                return Ok(());
+            }
+        } else if let Some(username) = sender.username() {
             if !users.is_empty() && !users.contains(&username.to_string()) {
                 return Ok(());
             }
@@ -103,6 +136,14 @@ gen_checker!(check_senders, {
     }
 });
 
+// users in the acl need the role or a higher one
+// the code of the bottom attribute runs first, so put this one above check_chat and check_senders
+gen_checker!(check_role, role, {
+    if !state.acl.check(&message, crate::acl::Role::#role).await? {
+        return Ok(());
+    }
+});
+
 gen_checker!(check_tg_login, {
     let is_authorized = state.telegram_user.is_authorized().await?;
 
@@ -110,20 +151,38 @@ gen_checker!(check_tg_login, {
         let response = "You haven't logged in to Telegram.";
         message.respond(response).await.context(response)?;
 
-        let (rx, _, _server_abort_handle) = crate::auth_server::spawn().await?;
-        crate::handlers::auth::login_to_telegram(message.clone(), state.clone(), rx).await?;
+        // the flow is accepted by the auth server until the handle is dropped after login
+        let (channel, _, _auth_flow_handle) =
+            crate::auth_server::spawn(message.chat().id()).await?;
+        crate::handlers::auth::login_to_telegram(message.clone(), state.clone(), Some(channel))
+            .await?;
     }
 });
 
 gen_checker!(check_od_login, {
-    let is_authorized = state.onedrive.is_authorized().await;
+    // other storage backends don't need onedrive
+    let is_authorized = crate::env::ENV.get().unwrap().storage.kind
+        != crate::env::StorageKind::OneDrive
+        || state.onedrive.is_authorized().await;
 
     if !is_authorized {
         let response = "You haven't authorize OneDrive.";
         message.respond(response).await.context(response)?;
 
-        let (_, rx, _server_abort_handle) = crate::auth_server::spawn().await?;
-        crate::handlers::auth::authorize_onedrive(message.clone(), state.clone(), false, rx)
+        // the device code flow doesn't need the auth server
+        if state.onedrive.uses_device_code() {
+            crate::handlers::auth::authorize_onedrive(message.clone(), state.clone(), false, None)
+                .await?;
+        } else {
+            let (_, channel, _auth_flow_handle) =
+                crate::auth_server::spawn(message.chat().id()).await?;
+            crate::handlers::auth::authorize_onedrive(
+                message.clone(),
+                state.clone(),
+                false,
+                Some(channel),
+            )
             .await?;
+        }
     }
 });
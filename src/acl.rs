@@ -0,0 +1,286 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::{
+    env::AclEnv,
+    message::TelegramMessage,
+    utils::{create_table_if_not_exists, get_current_timestamp},
+};
+use anyhow::{Context, Result};
+use grammers_client::types::Chat;
+use sea_orm::{
+    entity::prelude::DeriveEntityModel, sea_query::Expr, ActiveModelBehavior, ColumnTrait,
+    DatabaseConnection, DerivePrimaryKey, DeriveRelation, EntityTrait, EnumIter, PaginatorTrait,
+    PrimaryKeyTrait, QueryFilter, QueryOrder, Set,
+};
+use std::{fmt::Display, str::FromStr};
+
+// ordered by permission, a role includes the roles below it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    // read-only commands, like /dir and /drive without arguments
+    Viewer,
+    // transfers and the upload directory
+    Uploader,
+    // accounts, settings, logs and users
+    Admin,
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "viewer" => Ok(Self::Viewer),
+            "uploader" => Ok(Self::Uploader),
+            "admin" => Ok(Self::Admin),
+            _ => Err(format!(
+                "unknown role: {}, should be admin, uploader or viewer",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Viewer => write!(f, "viewer"),
+            Self::Uploader => write!(f, "uploader"),
+            Self::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+// users added by /users, keyed by telegram user id since usernames can be changed by anyone
+#[derive(Clone, Debug, DeriveEntityModel)]
+#[sea_orm(table_name = "users")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub user_id: i64,
+    pub role: String,
+    // name when the user was added, only for listing
+    pub name: Option<String>,
+    pub added_by: Option<i64>,
+    pub timestamp: i64,
+}
+
+impl Model {
+    pub fn role(&self) -> Option<Role> {
+        self.role.parse().ok()
+    }
+}
+
+#[derive(Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+pub struct Acl {
+    connection: DatabaseConnection,
+    admin_ids: Vec<i64>,
+}
+
+impl Acl {
+    pub async fn new(acl_env: &AclEnv) -> Result<Self> {
+        let connection = sea_orm::Database::connect(format!("sqlite://{}?mode=rwc", acl_env.path))
+            .await
+            .context("failed to connect to users")?;
+
+        create_table_if_not_exists(&connection, Entity).await?;
+
+        Ok(Self {
+            connection,
+            admin_ids: acl_env.admin_ids.clone(),
+        })
+    }
+
+    // until an admin is configured or a user is added, tg_user_name decides who can control the bot
+    pub async fn is_enabled(&self) -> Result<bool> {
+        if !self.admin_ids.is_empty() {
+            return Ok(true);
+        }
+
+        let count = Entity::find()
+            .count(&self.connection)
+            .await
+            .context("failed to count users")?;
+
+        Ok(count > 0)
+    }
+
+    pub fn is_configured_admin(&self, user_id: i64) -> bool {
+        self.admin_ids.contains(&user_id)
+    }
+
+    pub fn admin_ids(&self) -> &[i64] {
+        &self.admin_ids
+    }
+
+    // everyone is an admin if the acl isn't enabled
+    pub async fn get_role(&self, user_id: i64) -> Result<Option<Role>> {
+        if self.is_configured_admin(user_id) || !self.is_enabled().await? {
+            return Ok(Some(Role::Admin));
+        }
+
+        Ok(self.get_user(user_id).await?.and_then(|user| user.role()))
+    }
+
+    pub async fn get_user(&self, user_id: i64) -> Result<Option<Model>> {
+        Entity::find_by_id(user_id)
+            .one(&self.connection)
+            .await
+            .context("failed to query user")
+    }
+
+    pub async fn list(&self) -> Result<Vec<Model>> {
+        Entity::find()
+            .order_by_asc(Column::Timestamp)
+            .all(&self.connection)
+            .await
+            .context("failed to list users")
+    }
+
+    // the user is replaced if it exists
+    pub async fn add_user(
+        &self,
+        user_id: i64,
+        role: Role,
+        name: Option<String>,
+        added_by: Option<i64>,
+    ) -> Result<()> {
+        self.remove_user(user_id).await?;
+
+        let insert_item = ActiveModel {
+            user_id: Set(user_id),
+            role: Set(role.to_string()),
+            name: Set(name),
+            added_by: Set(added_by),
+            timestamp: Set(get_current_timestamp()),
+        };
+
+        Entity::insert(insert_item)
+            .exec(&self.connection)
+            .await
+            .context("failed to insert user")?;
+
+        tracing::info!("user {} added as {}", user_id, role);
+
+        Ok(())
+    }
+
+    // returns false if the user doesn't exist
+    pub async fn set_role(&self, user_id: i64, role: Role) -> Result<bool> {
+        let rows_affected = Entity::update_many()
+            .filter(Column::UserId.eq(user_id))
+            .col_expr(Column::Role, Expr::value(role.to_string()))
+            .exec(&self.connection)
+            .await
+            .context("failed to update user role")?
+            .rows_affected;
+
+        if rows_affected > 0 {
+            tracing::info!("role of user {} set to {}", user_id, role);
+        }
+
+        Ok(rows_affected > 0)
+    }
+
+    // returns false if the user doesn't exist
+    pub async fn remove_user(&self, user_id: i64) -> Result<bool> {
+        let rows_affected = Entity::delete_by_id(user_id)
+            .exec(&self.connection)
+            .await
+            .context("failed to delete user")?
+            .rows_affected;
+
+        Ok(rows_affected > 0)
+    }
+
+    // users without a role are ignored silently, like senders not in tg_user_name,
+    // and only commands are answered when the role is too low
+    pub async fn check(&self, message: &TelegramMessage, role: Role) -> Result<bool> {
+        let sender_role = match message.sender() {
+            Some(sender) => self.get_role(sender.id()).await?,
+            // posts of a channel are checked by the channel id, like a user
+            None if matches!(message.chat(), Chat::Channel(_)) => {
+                self.get_role(message.chat().id()).await?
+            }
+            // anonymous group admins
+            None if self.is_enabled().await? => None,
+            None => Some(Role::Admin),
+        };
+
+        match sender_role {
+            Some(sender_role) if sender_role >= role => Ok(true),
+            Some(sender_role) => {
+                if message.text().starts_with('/') {
+                    let response = format!(
+                        "This command needs the {} role, you are a {}.",
+                        role, sender_role
+                    );
+                    message.respond(response.as_str()).await.context(response)?;
+                }
+
+                Ok(false)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TempPath;
+
+    async fn new_acl(path: &TempPath, admin_ids: Vec<i64>) -> Acl {
+        Acl::new(&AclEnv {
+            admin_ids,
+            path: path.as_str().to_string(),
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_roles() {
+        let path = TempPath::new("acl");
+        let acl = new_acl(&path, Vec::new()).await;
+
+        // everyone is an admin until the acl is enabled
+        assert!(!acl.is_enabled().await.unwrap());
+        assert_eq!(acl.get_role(1).await.unwrap(), Some(Role::Admin));
+
+        acl.add_user(1, Role::Admin, None, None).await.unwrap();
+        acl.add_user(2, Role::Viewer, Some("two".to_string()), Some(1))
+            .await
+            .unwrap();
+
+        assert!(acl.is_enabled().await.unwrap());
+        assert_eq!(acl.get_role(2).await.unwrap(), Some(Role::Viewer));
+        assert_eq!(acl.get_role(3).await.unwrap(), None);
+
+        assert!(acl.set_role(2, Role::Uploader).await.unwrap());
+        assert_eq!(acl.get_role(2).await.unwrap(), Some(Role::Uploader));
+        assert!(!acl.set_role(3, Role::Uploader).await.unwrap());
+
+        assert!(acl.remove_user(2).await.unwrap());
+        assert_eq!(acl.get_role(2).await.unwrap(), None);
+        assert_eq!(acl.list().await.unwrap().len(), 1);
+
+        let path = TempPath::new("acl");
+        let acl = new_acl(&path, vec![4]).await;
+        assert!(acl.is_enabled().await.unwrap());
+        assert_eq!(acl.get_role(4).await.unwrap(), Some(Role::Admin));
+        assert_eq!(acl.get_role(1).await.unwrap(), None);
+
+        assert!(Role::Admin > Role::Uploader && Role::Uploader > Role::Viewer);
+        assert_eq!("Uploader".parse::<Role>(), Ok(Role::Uploader));
+        assert!("owner".parse::<Role>().is_err());
+    }
+}
@@ -0,0 +1,131 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::TelegramPrompt;
+use crate::utils::get_current_timestamp;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::{mpsc::Sender, watch};
+
+// a flow not finished in time is rejected by the auth server
+const AUTH_FLOW_EXPIRATION_SECS: i64 = 600;
+
+// one /auth or /drive add, identified by the token in the auth page url
+pub struct AuthFlow {
+    chat_id: i64,
+    tx_tg: Sender<String>,
+    prompt: watch::Receiver<TelegramPrompt>,
+    tx_od: Sender<String>,
+    // the oauth state, cleared once a code is received so the callback can't be replayed
+    od_state: Option<String>,
+    expiration_timestamp: i64,
+}
+
+impl AuthFlow {
+    pub fn new(
+        chat_id: i64,
+        tx_tg: Sender<String>,
+        prompt: watch::Receiver<TelegramPrompt>,
+        tx_od: Sender<String>,
+        od_state: String,
+    ) -> Self {
+        Self {
+            chat_id,
+            tx_tg,
+            prompt,
+            tx_od,
+            od_state: Some(od_state),
+            expiration_timestamp: get_current_timestamp() + AUTH_FLOW_EXPIRATION_SECS,
+        }
+    }
+
+    fn is_expired(&self) -> bool {
+        get_current_timestamp() >= self.expiration_timestamp
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct AuthFlows(Arc<Mutex<HashMap<String, AuthFlow>>>);
+
+impl AuthFlows {
+    pub fn insert(&self, token: String, flow: AuthFlow) {
+        let mut flows = self.0.lock().unwrap();
+
+        // expired flows whose handler is still waiting are useless
+        flows.retain(|_, flow| !flow.is_expired());
+
+        flows.insert(token, flow);
+    }
+
+    pub fn remove(&self, token: &str) {
+        self.0.lock().unwrap().remove(token);
+    }
+
+    pub fn get_telegram(&self, token: &str) -> Option<(Sender<String>, TelegramPrompt)> {
+        self.0
+            .lock()
+            .unwrap()
+            .get(token)
+            .filter(|flow| !flow.is_expired())
+            .map(|flow| (flow.tx_tg.clone(), flow.prompt.borrow().clone()))
+    }
+
+    pub fn take_onedrive(&self, state: &str) -> Option<Sender<String>> {
+        let mut flows = self.0.lock().unwrap();
+
+        let flow = flows
+            .values_mut()
+            .filter(|flow| !flow.is_expired())
+            .find(|flow| flow.od_state.as_deref() == Some(state))?;
+
+        flow.od_state = None;
+
+        tracing::debug!("onedrive auth state matched for chat {}", flow.chat_id);
+
+        Some(flow.tx_od.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    fn new_flow(od_state: &str) -> AuthFlow {
+        let (tx_tg, _) = mpsc::channel(1);
+        let (_, prompt) = watch::channel(TelegramPrompt::default());
+        let (tx_od, _) = mpsc::channel(1);
+
+        AuthFlow::new(1, tx_tg, prompt, tx_od, od_state.to_string())
+    }
+
+    #[test]
+    fn test_auth_flows() {
+        let flows = AuthFlows::default();
+        flows.insert("token".to_string(), new_flow("state"));
+
+        assert!(flows.get_telegram("token").is_some());
+        assert!(flows.get_telegram("state").is_none());
+
+        // the state is one-time
+        assert!(flows.take_onedrive("token").is_none());
+        assert!(flows.take_onedrive("state").is_some());
+        assert!(flows.take_onedrive("state").is_none());
+
+        let mut expired_flow = new_flow("expired state");
+        expired_flow.expiration_timestamp = get_current_timestamp() - 1;
+        flows.insert("expired token".to_string(), expired_flow);
+
+        assert!(flows.get_telegram("expired token").is_none());
+        assert!(flows.take_onedrive("expired state").is_none());
+
+        flows.remove("token");
+        assert!(flows.get_telegram("token").is_none());
+    }
+}
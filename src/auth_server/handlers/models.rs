@@ -8,6 +8,18 @@
 use serde::Deserialize;
 
 #[derive(Deserialize)]
-pub struct CodeParams {
+pub struct TokenParams {
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+pub struct TelegramCodeParams {
+    pub token: String,
+    pub code: String,
+}
+
+#[derive(Deserialize)]
+pub struct OneDriveCodeParams {
     pub code: String,
+    pub state: String,
 }
@@ -5,20 +5,36 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use super::models::CodeParams;
-use crate::{auth_server::SenderOD, error::HttpError};
-use axum::{debug_handler, extract::Query, response::Result, Extension};
+use super::models::OneDriveCodeParams;
+use crate::{auth_server::flow::AuthFlows, error::HttpError};
+use axum::{
+    debug_handler,
+    extract::Query,
+    http::StatusCode,
+    response::{IntoResponse, Response, Result},
+    Extension,
+};
 
 pub const CODE_PATH: &str = "/auth";
 
 #[debug_handler]
 pub async fn code_handler(
-    Extension(SenderOD(tx)): Extension<SenderOD>,
-    Query(CodeParams { code }): Query<CodeParams>,
-) -> Result<String> {
-    tracing::debug!("received od auth code: {}", code);
+    Extension(flows): Extension<AuthFlows>,
+    Query(OneDriveCodeParams { code, state }): Query<OneDriveCodeParams>,
+) -> Result<Response> {
+    let Some(tx) = flows.take_onedrive(&state) else {
+        tracing::warn!("rejected od auth code with unknown or expired state");
+
+        return Ok((
+            StatusCode::FORBIDDEN,
+            "Authorization expired or invalid, please try again.",
+        )
+            .into_response());
+    };
+
+    tracing::debug!("received od auth code");
 
     tx.send(code).await.map_err(HttpError::new)?;
 
-    Ok("Authorization successful!".to_string())
+    Ok("Authorization successful!".into_response())
 }
@@ -5,11 +5,12 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use super::models::CodeParams;
-use crate::{auth_server::SenderTG, error::HttpError};
+use super::models::{TelegramCodeParams, TokenParams};
+use crate::{auth_server::flow::AuthFlows, error::HttpError};
 use anyhow::Context;
 use axum::{
     debug_handler,
+    extract::Query,
     http::StatusCode,
     response::{Html, IntoResponse, Response, Result},
     Extension, Json,
@@ -32,10 +33,15 @@ pub const CODE_PATH: &str = "/tg";
 
 #[debug_handler]
 pub async fn code_handler(
-    Extension(SenderTG(tx)): Extension<SenderTG>,
-    Json(CodeParams { code }): Json<CodeParams>,
+    Extension(flows): Extension<AuthFlows>,
+    Json(TelegramCodeParams { token, code }): Json<TelegramCodeParams>,
 ) -> Result<Response> {
-    tracing::debug!("received tg auth code: {}", code);
+    let Some((tx, _)) = flows.get_telegram(&token) else {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    };
+
+    // the 2FA password comes here too, so it's not logged
+    tracing::debug!("received tg auth input");
 
     tx.send(code)
         .await
@@ -44,3 +50,16 @@ pub async fn code_handler(
 
     Ok(StatusCode::OK.into_response())
 }
+
+pub const PROMPT_PATH: &str = "/tg/prompt";
+
+#[debug_handler]
+pub async fn prompt_handler(
+    Extension(flows): Extension<AuthFlows>,
+    Query(TokenParams { token }): Query<TokenParams>,
+) -> Response {
+    flows.get_telegram(&token).map_or_else(
+        || StatusCode::FORBIDDEN.into_response(),
+        |(_, prompt)| Json(prompt).into_response(),
+    )
+}
@@ -7,11 +7,13 @@
 
 mod auto_abort;
 mod cert;
+mod flow;
 mod handlers;
 
 use crate::{
-    env::{ENV, Env},
+    env::ENV,
     error::ResultExt,
+    utils::random_token,
 };
 use anyhow::{Context, Result};
 use auto_abort::AutoAbortHandle;
@@ -21,70 +23,186 @@ use axum::{
 };
 use axum_server::Handle;
 use cert::get_rustls_config;
+use flow::{AuthFlow, AuthFlows};
 use handlers::{onedrive, telegram};
-use std::net::TcpListener;
-use tokio::sync::mpsc::{self, Receiver, Sender};
+use serde::Serialize;
+use std::{
+    net::TcpListener,
+    sync::{Arc, Weak},
+};
+use tokio::{
+    sync::{
+        Mutex,
+        mpsc::{self, Receiver},
+        watch,
+    },
+    task::AbortHandle,
+};
+
+// shared by concurrent auth flows, shut down when the last one ends
+static AUTH_SERVER: Mutex<Weak<AuthServer>> = Mutex::const_new(Weak::new());
 
-#[derive(Clone)]
-struct SenderTG(Sender<String>);
+struct AuthServer {
+    flows: AuthFlows,
+    _auto_abort_handle: AutoAbortHandle,
+}
 
-#[derive(Clone)]
-struct SenderOD(Sender<String>);
+// what the auth page asks for, updated during telegram login
+#[derive(Clone, Default, Serialize)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum TelegramPrompt {
+    #[default]
+    Waiting,
+    Code,
+    Password {
+        hint: Option<String>,
+    },
+    QrCode {
+        url: String,
+    },
+    Done,
+}
 
-pub async fn spawn() -> Result<(Receiver<String>, Receiver<String>, AutoAbortHandle)> {
-    tracing::debug!("spawning auth server");
+// codes and passwords input on the auth page
+pub struct TelegramAuthChannel {
+    pub rx: Receiver<String>,
+    pub prompt: watch::Sender<TelegramPrompt>,
+    // the auth page with the one-time token of this flow
+    pub url: String,
+}
+
+// codes redirected from microsoft with the matching state
+pub struct OneDriveAuthChannel {
+    pub rx: Receiver<String>,
+    pub state: String,
+}
+
+// the flow is rejected by the auth server once dropped
+pub struct AuthFlowHandle {
+    token: String,
+    server: Arc<AuthServer>,
+}
 
-    let Env {
-        port,
-        use_reverse_proxy,
-        ..
-    } = ENV.get().unwrap();
+impl Drop for AuthFlowHandle {
+    fn drop(&mut self) {
+        self.server.flows.remove(&self.token);
+
+        tracing::debug!("auth flow ended");
+    }
+}
+
+pub async fn spawn(
+    chat_id: i64,
+) -> Result<(TelegramAuthChannel, OneDriveAuthChannel, AuthFlowHandle)> {
+    let server = get_or_spawn_server().await?;
 
     let (tx_tg, rx_tg) = mpsc::channel(1);
+    let (tx_prompt, rx_prompt) = watch::channel(TelegramPrompt::default());
     let (tx_od, rx_od) = mpsc::channel(1);
 
+    let token = random_token();
+    let od_state = random_token();
+
+    server.flows.insert(
+        token.clone(),
+        AuthFlow::new(chat_id, tx_tg, rx_prompt, tx_od, od_state.clone()),
+    );
+
+    tracing::debug!("auth flow started for chat {}", chat_id);
+
+    let server_uri = &ENV.get().unwrap().server_uri;
+
+    let telegram_auth_channel = TelegramAuthChannel {
+        rx: rx_tg,
+        prompt: tx_prompt,
+        url: format!("{}/?token={}", server_uri.trim_end_matches('/'), token),
+    };
+
+    let onedrive_auth_channel = OneDriveAuthChannel {
+        rx: rx_od,
+        state: od_state,
+    };
+
+    let auth_flow_handle = AuthFlowHandle { token, server };
+
+    Ok((
+        telegram_auth_channel,
+        onedrive_auth_channel,
+        auth_flow_handle,
+    ))
+}
+
+async fn get_or_spawn_server() -> Result<Arc<AuthServer>> {
+    let mut auth_server = AUTH_SERVER.lock().await;
+
+    if let Some(server) = auth_server.upgrade() {
+        return Ok(server);
+    }
+
+    tracing::debug!("spawning auth server");
+
+    let port = ENV.get().unwrap().port;
+
+    let flows = AuthFlows::default();
+
     let router = Router::new()
         .route(telegram::INDEX_PATH, get(telegram::index_handler))
         .route(telegram::CODE_PATH, post(telegram::code_handler))
+        .route(telegram::PROMPT_PATH, get(telegram::prompt_handler))
         .route(onedrive::CODE_PATH, get(onedrive::code_handler))
-        .layer(Extension(SenderTG(tx_tg)))
-        .layer(Extension(SenderOD(tx_od)));
+        .layer(Extension(flows.clone()));
 
+    let shutdown_handle = Handle::new();
+
+    let abort_handle = serve("auth server", port, router, shutdown_handle.clone()).await?;
+
+    let server = Arc::new(AuthServer {
+        flows,
+        _auto_abort_handle: AutoAbortHandle::new(abort_handle, shutdown_handle),
+    });
+
+    *auth_server = Arc::downgrade(&server);
+
+    Ok(server)
+}
+
+// https with the cert in ssl, or http behind a reverse proxy
+pub async fn serve(
+    name: &'static str,
+    port: u16,
+    router: Router,
+    shutdown_handle: Handle,
+) -> Result<AbortHandle> {
     let server =
         TcpListener::bind(format!("0.0.0.0:{}", port)).context("failed to create tcp listener")?;
 
-    let shutdown_handle = Handle::new();
-    let shutdown_handle_clone = shutdown_handle.clone();
-
-    let abort_handle = if use_reverse_proxy.to_owned() {
-        tracing::info!("auth server listening on http://0.0.0.0:{}", port);
+    let abort_handle = if ENV.get().unwrap().use_reverse_proxy {
+        tracing::info!("{} listening on http://0.0.0.0:{}", name, port);
 
         tokio::spawn(async move {
             axum_server::from_tcp(server)
-                .handle(shutdown_handle_clone)
+                .handle(shutdown_handle)
                 .serve(router.into_make_service())
                 .await
-                .context("auth server failed to serve")
+                .context(format!("{} failed to serve", name))
                 .trace();
         })
         .abort_handle()
     } else {
         let config = get_rustls_config().await?;
 
-        tracing::info!("auth server listening on https://0.0.0.0:{}", port);
+        tracing::info!("{} listening on https://0.0.0.0:{}", name, port);
 
         tokio::spawn(async move {
             axum_server::from_tcp_rustls(server, config)
-                .handle(shutdown_handle_clone)
+                .handle(shutdown_handle)
                 .serve(router.into_make_service())
                 .await
-                .context("auth server failed to serve")
+                .context(format!("{} failed to serve", name))
                 .trace();
         })
         .abort_handle()
     };
 
-    let auto_abort_handle = AutoAbortHandle::new(abort_handle, shutdown_handle);
-
-    Ok((rx_tg, rx_od, auto_abort_handle))
+    Ok(abort_handle)
 }
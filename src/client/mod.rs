@@ -6,8 +6,45 @@
 */
 
 pub mod onedrive;
+pub mod remote;
 mod telegram;
 pub mod utils;
 
+use crate::{
+    crypto::{session_cipher, Cipher},
+    env::ENV,
+};
+use anyhow::{anyhow, Context, Result};
 pub use onedrive::OneDriveClient;
+use onedrive::OneDriveSession;
 pub use telegram::TelegramClient;
+
+// run as `telegram-onedrive rotate-key` with the bot stopped
+pub async fn rotate_session_key() -> Result<()> {
+    let env = ENV.get().unwrap();
+
+    let new_key = env
+        .encryption
+        .new_key
+        .as_deref()
+        .ok_or_else(|| anyhow!("session_key_new is not set"))?;
+
+    let old = session_cipher();
+    let new = Cipher::new(new_key).context("invalid session_key_new")?;
+
+    for path in [
+        &env.telegram_bot.session_path,
+        &env.telegram_user.session_path,
+    ] {
+        if telegram::rotate_session_key(path, old, &new)? {
+            tracing::info!("rotated key of telegram session {}", path);
+        }
+    }
+
+    let count = OneDriveSession::rotate_key(&env.onedrive.session_path, old, &new).await?;
+
+    tracing::info!("rotated key of {} onedrive sessions", count);
+    tracing::info!("set session_key to the value of session_key_new and remove session_key_new");
+
+    Ok(())
+}
@@ -6,8 +6,15 @@
 */
 
 use super::OneDriveClient;
-use anyhow::Result;
-use onedrive_api::{DriveLocation, OneDrive};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Serialize)]
+pub struct DriveQuota {
+    pub used: u64,
+    pub total: u64,
+}
 
 impl OneDriveClient {
     pub async fn get_usernames(&self) -> Result<Vec<String>> {
@@ -18,13 +25,25 @@ impl OneDriveClient {
         self.session.read().await.get_current_username().await
     }
 
-    pub async fn change_account(&self, username: &str) -> Result<()> {
-        let mut session = self.session.write().await;
-
-        session.change_session(username).await?;
+    // of the current account
+    pub async fn get_quota(&self) -> Result<DriveQuota> {
+        let drive = self.graph_get("/me/drive").await?;
+
+        let get_quota_value = |name: &str| {
+            drive
+                .pointer(&format!("/quota/{}", name))
+                .and_then(Value::as_u64)
+                .ok_or_else(|| anyhow!("field quota.{} not found in drive", name))
+        };
+
+        Ok(DriveQuota {
+            used: get_quota_value("used")?,
+            total: get_quota_value("total")?,
+        })
+    }
 
-        *self.client.write().await =
-            OneDrive::new(session.access_token.clone(), DriveLocation::me());
+    pub async fn change_account(&self, username: &str) -> Result<()> {
+        self.session.write().await.change_session(username).await?;
 
         tracing::debug!("change account to {}", username);
 
@@ -0,0 +1,209 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::utils::HttpRoute;
+use anyhow::{anyhow, Context, Result};
+use reqwest::{header, Method, StatusCode};
+use serde_json::Value;
+
+// microsoft graph of the configured cloud, national clouds have their own endpoints
+pub struct GraphClient {
+    http_client: reqwest::Client,
+    graph_url: String,
+}
+
+impl GraphClient {
+    pub fn new(http_client: reqwest::Client, graph_url: &str) -> Self {
+        Self {
+            http_client,
+            graph_url: graph_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    // path is relative to the graph url, or an absolute url such as @odata.nextLink
+    pub async fn send(
+        &self,
+        access_token: &str,
+        method: Method,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<(StatusCode, Value)> {
+        let url = if path.starts_with("https://") || path.starts_with("http://") {
+            path.to_string()
+        } else {
+            format!("{}{}", self.graph_url, path)
+        };
+
+        let mut request = self
+            .http_client
+            .request(method, url)
+            .bearer_auth(access_token);
+
+        if let Some(body) = body {
+            request = request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(body.to_string());
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| HttpRoute::Graph.map_err(e))
+            .context("failed to send graph request")
+            .context(path.to_string())?;
+
+        let status = response.status();
+
+        let text = response
+            .text()
+            .await
+            .context("failed to get graph response text")?;
+
+        // some responses such as 204 have no body
+        let content = if text.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_str::<Value>(&text).context("failed to deserialize graph response")?
+        };
+
+        Ok((status, content))
+    }
+
+    pub async fn request(
+        &self,
+        access_token: &str,
+        method: Method,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<Value> {
+        let (status, content) = self.send(access_token, method, path, body).await?;
+
+        if !status.is_success() {
+            return Err(graph_error(status, &content)).context(path.to_string());
+        }
+
+        Ok(content)
+    }
+
+    pub async fn get_username(&self, access_token: &str) -> Result<String> {
+        let user_profile = self
+            .request(access_token, Method::GET, "/me", None)
+            .await
+            .context("failed to get user profile")?;
+
+        let username = user_profile
+            .get("userPrincipalName")
+            .ok_or_else(|| anyhow!("field userPrincipalName not found in user profile"))?
+            .as_str()
+            .ok_or_else(|| anyhow!("userPrincipalName value is not a string"))?
+            .to_string();
+
+        tracing::debug!("got onedrive username from request: {}", username);
+
+        Ok(username)
+    }
+}
+
+pub fn graph_error(status: StatusCode, content: &Value) -> anyhow::Error {
+    let message = content
+        .pointer("/error/message")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+
+    anyhow!("graph request failed with {}: {}", status, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::Query, http::HeaderMap, routing::get, Json, Router};
+    use serde_json::json;
+    use std::collections::HashMap;
+    use tokio::net::TcpListener;
+
+    // a mock graph endpoint of a national cloud
+    async fn serve_graph() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let base = format!("http://{}", addr);
+        let next_link = format!("{}/v1.0/me/drive/root/children?page=2", base);
+
+        let router = Router::new()
+            .route(
+                "/v1.0/me",
+                get(|headers: HeaderMap| async move {
+                    match headers.get("authorization").and_then(|v| v.to_str().ok()) {
+                        Some("Bearer access") => (
+                            StatusCode::OK,
+                            Json(
+                                json!({ "userPrincipalName": "user@contoso.partner.onmschina.cn" }),
+                            ),
+                        ),
+                        _ => (
+                            StatusCode::UNAUTHORIZED,
+                            Json(json!({ "error": { "message": "invalid token" } })),
+                        ),
+                    }
+                }),
+            )
+            .route(
+                "/v1.0/me/drive/root/children",
+                get(|Query(query): Query<HashMap<String, String>>| async move {
+                    if query.contains_key("page") {
+                        Json(json!({ "value": [{ "name": "b.txt" }] }))
+                    } else {
+                        Json(
+                            json!({ "value": [{ "name": "a.txt" }], "@odata.nextLink": next_link }),
+                        )
+                    }
+                }),
+            );
+
+        tokio::spawn(async move {
+            axum::serve(listener, router).await.unwrap();
+        });
+
+        base
+    }
+
+    #[tokio::test]
+    async fn test_get_username() {
+        let graph = GraphClient::new(
+            reqwest::Client::new(),
+            &format!("{}/v1.0/", serve_graph().await),
+        );
+
+        assert_eq!(
+            graph.get_username("access").await.unwrap(),
+            "user@contoso.partner.onmschina.cn"
+        );
+
+        let e = graph.get_username("expired").await.err().unwrap();
+        assert!(format!("{:#}", e).contains("invalid token"));
+    }
+
+    #[tokio::test]
+    async fn test_next_link() {
+        let graph = GraphClient::new(
+            reqwest::Client::new(),
+            &format!("{}/v1.0", serve_graph().await),
+        );
+
+        let page = graph
+            .request("access", Method::GET, "/me/drive/root/children", None)
+            .await
+            .unwrap();
+        let next_link = page["@odata.nextLink"].as_str().unwrap();
+
+        // absolute urls are requested as they are
+        let page = graph
+            .request("access", Method::GET, next_link, None)
+            .await
+            .unwrap();
+        assert_eq!(page["value"][0]["name"], "b.txt");
+    }
+}
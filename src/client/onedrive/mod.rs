@@ -7,30 +7,38 @@
 
 mod dir;
 mod drive;
+mod graph;
 pub mod invalid_name;
+mod oauth;
 mod session;
+pub mod target;
 mod upload;
 mod utils;
 
 use crate::{
-    env::{Env, OneDriveEnv, ENV},
+    auth_server::OneDriveAuthChannel,
+    env::{Env, OneDriveAuthFlow, OneDriveEnv, ENV},
     message::TelegramMessage,
+    metrics::METRICS,
+    utils::{get_http_client, HttpRoute},
 };
 use anyhow::{anyhow, Context, Result};
-use onedrive_api::{
-    Auth, ClientCredential, DriveLocation, OneDrive as Client, Permission, Tenant, TokenResponse,
-};
+pub use drive::DriveQuota;
+use graph::GraphClient;
+use oauth::{new_code_verifier, OAuth, TokenResponse};
 use path_slash::PathBufExt;
-use session::OneDriveSession;
-use std::{collections::HashMap, path::Path};
-use tokio::sync::{mpsc::Receiver, RwLock};
+use reqwest::Method;
+pub use session::OneDriveSession;
+use std::path::Path;
+use tokio::sync::RwLock;
 
 pub struct OneDriveClient {
-    client: RwLock<Client>,
+    // both share the http client that goes through od_proxy if set
+    graph: GraphClient,
+    oauth: OAuth,
     session: RwLock<OneDriveSession>,
-    auth_provider: Auth,
-    client_secret: String,
     session_path: String,
+    auth_flow: OneDriveAuthFlow,
     pub default_root_path: String,
     temp_root_path: RwLock<String>,
 }
@@ -44,34 +52,39 @@ impl OneDriveClient {
                     client_secret,
                     session_path,
                     root_path,
-                    ..
+                    authority_url,
+                    graph_url,
+                    tenant,
+                    auth_flow,
                 },
             server_uri,
             ..
         } = ENV.get().unwrap();
 
-        let client = RwLock::new(Client::new("", DriveLocation::me()));
-        let session = RwLock::new(
-            OneDriveSession::default()
-                .set_connection(session_path)
-                .await?,
-        );
-        let auth_provider = Auth::new(
+        let http_client = get_http_client(HttpRoute::Graph)?;
+        let graph = GraphClient::new(http_client.clone(), graph_url);
+        let oauth = OAuth::new(
+            http_client,
             client_id,
-            Permission::new_read()
-                .write(true)
-                .access_shared(true)
-                .offline_access(true),
-            Path::new(server_uri).join("auth").to_slash_lossy(),
-            Tenant::Common,
-        );
+            client_secret.as_deref(),
+            &Path::new(server_uri).join("auth").to_slash_lossy(),
+            authority_url,
+            tenant,
+            graph_url,
+        )?;
+        let mut session = OneDriveSession::default()
+            .set_connection(session_path)
+            .await?;
+        // for storage backends other than onedrive, overwritten once logged in
+        session.root_path.clone_from(root_path);
+        let session = RwLock::new(session);
 
         let onedrive_client = Self {
-            client,
+            graph,
+            oauth,
             session,
-            auth_provider,
-            client_secret: client_secret.clone(),
             session_path: session_path.clone(),
+            auth_flow: *auth_flow,
             default_root_path: root_path.to_string(),
             temp_root_path: RwLock::new(String::new()),
         };
@@ -85,7 +98,7 @@ impl OneDriveClient {
         &self,
         message: TelegramMessage,
         should_add: bool,
-        mut rx: Receiver<String>,
+        channel: Option<OneDriveAuthChannel>,
     ) -> Result<()> {
         tracing::info!("logging in to onedrive");
 
@@ -109,48 +122,30 @@ impl OneDriveClient {
             tracing::info!("onedrive auto login failed, login manually");
         }
 
-        let response = format!(
-            "Here are the authorization url of OneDrive:\n\n{}",
-            self.get_auth_url()
-        );
-        message.respond(response.as_str()).await.context(response)?;
-
-        tracing::info!("onedrive authorization url sent");
-
-        let code = rx
-            .recv()
-            .await
-            .ok_or_else(|| anyhow!("failed to receive onedrive code"))?;
-
-        tracing::info!("onedrive code received");
-        tracing::debug!("onedrive code: {}", code);
-
-        let response = "Code received, authorizing...";
-        message.respond(response).await.context(response)?;
-
-        tracing::info!("onedrive authorizing");
-
         let TokenResponse {
             expires_in_secs,
             access_token,
             refresh_token,
-            ..
-        } = self
-            .auth_provider
-            .login_with_code(&code, &ClientCredential::Secret(self.client_secret.clone()))
-            .await
-            .context("failed to get onedrive token response when login with code")?;
+        } = match self.auth_flow {
+            OneDriveAuthFlow::Redirect => {
+                let channel =
+                    channel.ok_or_else(|| anyhow!("auth server is required by redirect login"))?;
 
-        let refresh_token = refresh_token.ok_or_else(|| {
-            anyhow!("failed to receive onedrive refresh token when login with code")
-        })?;
+                self.login_with_redirect(&message, channel).await?
+            }
+            OneDriveAuthFlow::DeviceCode => self.login_with_device_code(&message).await?,
+        };
 
-        let client = Client::new(&access_token, DriveLocation::me());
+        let refresh_token = refresh_token
+            .ok_or_else(|| anyhow!("failed to receive onedrive refresh token when login"))?;
 
         tracing::info!("onedrive authorized");
 
+        let username = self.graph.get_username(&access_token).await?;
+
         let session = OneDriveSession::new(
-            &client,
+            &username,
+            &access_token,
             expires_in_secs,
             &refresh_token,
             &self.session_path,
@@ -163,17 +158,73 @@ impl OneDriveClient {
         if let Some(username) = self.get_current_username().await? {
             if username == session.username {
                 self.session.write().await.overwrite(session);
-                *self.client.write().await = client;
             }
         } else {
             session.set_current_user().await?;
             self.session.write().await.overwrite(session);
-            *self.client.write().await = client;
         }
 
         Ok(())
     }
 
+    async fn login_with_redirect(
+        &self,
+        message: &TelegramMessage,
+        OneDriveAuthChannel { mut rx, state }: OneDriveAuthChannel,
+    ) -> Result<TokenResponse> {
+        let code_verifier = new_code_verifier();
+
+        let response = format!(
+            "Here are the authorization url of OneDrive:\n\n{}",
+            self.get_auth_url(&state, &code_verifier)?
+        );
+        message.respond(response.as_str()).await.context(response)?;
+
+        tracing::info!("onedrive authorization url sent");
+
+        let code = rx
+            .recv()
+            .await
+            .ok_or_else(|| anyhow!("failed to receive onedrive code"))?;
+
+        tracing::info!("onedrive code received");
+
+        let response = "Code received, authorizing...";
+        message.respond(response).await.context(response)?;
+
+        tracing::info!("onedrive authorizing");
+
+        self.oauth
+            .login_with_code(&code, &code_verifier)
+            .await
+            .context("failed to get onedrive token response when login with code")
+    }
+
+    async fn login_with_device_code(&self, message: &TelegramMessage) -> Result<TokenResponse> {
+        let device_code = self
+            .oauth
+            .request_device_code()
+            .await
+            .context("failed to request onedrive device code")?;
+
+        let response = format!(
+            "Please visit {} and enter the code below to authorize OneDrive:\n\n{}",
+            device_code.verification_uri, device_code.user_code
+        );
+        message.respond(response.as_str()).await.context(response)?;
+
+        tracing::info!("onedrive device code sent, waiting for authorization");
+
+        self.oauth
+            .login_with_device_code(&device_code)
+            .await
+            .context("failed to get onedrive token response when login with device code")
+    }
+
+    pub const fn uses_device_code(&self) -> bool {
+        matches!(self.auth_flow, OneDriveAuthFlow::DeviceCode)
+    }
+
     async fn auto_login(&self) -> Result<()> {
         let mut session = OneDriveSession::load(&self.session_path).await?;
 
@@ -182,7 +233,6 @@ impl OneDriveClient {
             .await?;
 
         let access_token = token_response.access_token;
-        *self.client.write().await = Client::new(&access_token, DriveLocation::me());
 
         session.refresh_token = token_response.refresh_token.ok_or_else(|| {
             anyhow!("failed to receive onedrive refresh token when login with refresh token")
@@ -200,32 +250,21 @@ impl OneDriveClient {
         &self,
         refresh_token: &str,
     ) -> Result<TokenResponse> {
-        self.auth_provider
-            .login_with_refresh_token(
-                refresh_token,
-                &ClientCredential::Secret(self.client_secret.clone()),
-            )
+        let result = self
+            .oauth
+            .login_with_refresh_token(refresh_token)
             .await
-            .context("failed to get refresh token response when login with refresh token")
-    }
-
-    pub fn get_auth_url(&self) -> String {
-        let mut url = self.auth_provider.code_auth_url();
-
-        let mut query_pairs = url
-            .query_pairs()
-            .into_owned()
-            .collect::<HashMap<String, String>>();
-
-        query_pairs.get_mut("scope").unwrap().push_str(" user.read");
+            .context("failed to get refresh token response when login with refresh token");
 
-        url.query_pairs_mut().clear().extend_pairs(query_pairs);
-
-        let auth_url = url.to_string();
+        if result.is_err() {
+            METRICS.inc_token_refresh_failures();
+        }
 
-        tracing::info!("onedrive auth url: {}", auth_url);
+        result
+    }
 
-        auth_url
+    pub fn get_auth_url(&self, state: &str, code_verifier: &str) -> Result<String> {
+        self.oauth.code_auth_url(state, code_verifier)
     }
 
     pub async fn is_authorized(&self) -> bool {
@@ -235,7 +274,12 @@ impl OneDriveClient {
             self.refresh_access_token().await.ok();
         }
 
-        self.client.read().await.get_drive().await.is_ok()
+        let access_token = self.session.read().await.access_token.clone();
+
+        self.graph
+            .request(&access_token, Method::GET, "/me/drive", None)
+            .await
+            .is_ok()
     }
 
     pub async fn set_current_user(&self) -> Result<()> {
@@ -243,12 +287,7 @@ impl OneDriveClient {
     }
 
     pub async fn logout(&self, username: Option<String>) -> Result<()> {
-        let mut session = self.session.write().await;
-        session.remove_user(username).await?;
-
-        *self.client.write().await = Client::new(&session.access_token, DriveLocation::me());
-
-        Ok(())
+        self.session.write().await.remove_user(username).await
     }
 
     pub async fn refresh_access_token(&self) -> Result<()> {
@@ -268,9 +307,6 @@ impl OneDriveClient {
             session.set_expiration_timestamp(token_response.expires_in_secs);
 
             session.save().await?;
-
-            *self.client.write().await =
-                Client::new(session.access_token.clone(), DriveLocation::me());
         }
 
         Ok(())
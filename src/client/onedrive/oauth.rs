@@ -0,0 +1,408 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::utils::{random_token, HttpRoute};
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::time::{Duration, Instant};
+use url::Url;
+
+const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+// authorization code and device code flows against the microsoft identity platform of the configured cloud
+pub struct OAuth {
+    http_client: reqwest::Client,
+    client_id: String,
+    client_secret: Option<String>,
+    redirect_uri: String,
+    // like https://login.microsoftonline.com/common
+    authority: String,
+    scope: String,
+}
+
+#[derive(Deserialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    #[serde(rename = "expires_in")]
+    pub expires_in_secs: u64,
+}
+
+#[derive(Deserialize)]
+pub struct DeviceCodeResponse {
+    pub user_code: String,
+    pub verification_uri: String,
+    device_code: String,
+    #[serde(rename = "expires_in")]
+    expires_in_secs: u64,
+    #[serde(rename = "interval")]
+    interval_secs: u64,
+}
+
+#[derive(Deserialize)]
+struct TokenError {
+    error: String,
+    error_description: Option<String>,
+}
+
+impl TokenError {
+    fn into_error(self) -> anyhow::Error {
+        anyhow!(
+            "token request failed with {}: {}",
+            self.error,
+            self.error_description.unwrap_or_default()
+        )
+    }
+}
+
+impl OAuth {
+    pub fn new(
+        http_client: reqwest::Client,
+        client_id: &str,
+        client_secret: Option<&str>,
+        redirect_uri: &str,
+        authority_url: &str,
+        tenant: &str,
+        graph_url: &str,
+    ) -> Result<Self> {
+        // graph scopes of national clouds must be qualified with their graph resource
+        let graph_resource = Url::parse(graph_url)
+            .context("failed to parse graph url")?
+            .origin()
+            .ascii_serialization();
+
+        // sites are read to find the drive of a sharepoint site set by /drive target site
+        let scope = ["Files.ReadWrite.All", "Sites.Read.All", "User.Read"]
+            .iter()
+            .fold("offline_access".to_string(), |scope, permission| {
+                format!("{} {}/{}", scope, graph_resource, permission)
+            });
+
+        Ok(Self {
+            http_client,
+            client_id: client_id.to_string(),
+            client_secret: client_secret.map(str::to_string),
+            redirect_uri: redirect_uri.to_string(),
+            authority: format!("{}/{}", authority_url.trim_end_matches('/'), tenant),
+            scope,
+        })
+    }
+
+    // the state is checked by the auth server, the verifier is sent with the code later
+    pub fn code_auth_url(&self, state: &str, code_verifier: &str) -> Result<String> {
+        let mut url = Url::parse(&format!("{}/oauth2/v2.0/authorize", self.authority))
+            .context("failed to parse authority url")?;
+
+        url.query_pairs_mut()
+            .append_pair("client_id", &self.client_id)
+            .append_pair("scope", &self.scope)
+            .append_pair("redirect_uri", &self.redirect_uri)
+            .append_pair("response_type", "code")
+            .append_pair("state", state)
+            .append_pair("code_challenge", &code_challenge(code_verifier))
+            .append_pair("code_challenge_method", "S256");
+
+        Ok(url.to_string())
+    }
+
+    pub async fn login_with_code(&self, code: &str, code_verifier: &str) -> Result<TokenResponse> {
+        self.request_token(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("code_verifier", code_verifier),
+            ("redirect_uri", &self.redirect_uri),
+        ])
+        .await
+    }
+
+    pub async fn login_with_refresh_token(&self, refresh_token: &str) -> Result<TokenResponse> {
+        self.request_token(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+            ("redirect_uri", &self.redirect_uri),
+        ])
+        .await
+    }
+
+    pub async fn request_device_code(&self) -> Result<DeviceCodeResponse> {
+        let response = self
+            .http_client
+            .post(format!("{}/oauth2/v2.0/devicecode", self.authority))
+            .form(&[("client_id", &self.client_id), ("scope", &self.scope)])
+            .send()
+            .await
+            .map_err(|e| HttpRoute::Graph.map_err(e))
+            .context("failed to send device code request")?;
+
+        let status = response.status();
+
+        let text = response
+            .text()
+            .await
+            .context("failed to get device code response text")?;
+
+        if !status.is_success() {
+            return Err(serde_json::from_str::<TokenError>(&text).map_or_else(
+                |_| anyhow!("device code request failed with {}", status),
+                TokenError::into_error,
+            ));
+        }
+
+        serde_json::from_str(&text).context("failed to deserialize device code response")
+    }
+
+    // polls until the user enters the code and approves, or the code expires
+    pub async fn login_with_device_code(
+        &self,
+        device_code: &DeviceCodeResponse,
+    ) -> Result<TokenResponse> {
+        let deadline = Instant::now() + Duration::from_secs(device_code.expires_in_secs);
+        let mut interval = Duration::from_secs(device_code.interval_secs);
+
+        loop {
+            if Instant::now() >= deadline {
+                return Err(anyhow!("device code expired before authorization"));
+            }
+
+            tokio::time::sleep(interval).await;
+
+            match self
+                .post_token(&[
+                    ("grant_type", DEVICE_CODE_GRANT_TYPE),
+                    ("device_code", &device_code.device_code),
+                ])
+                .await?
+            {
+                Ok(token_response) => return Ok(token_response),
+                Err(e) if e.error == "authorization_pending" => {}
+                Err(e) if e.error == "slow_down" => interval += Duration::from_secs(5),
+                Err(e) => return Err(e.into_error()),
+            }
+        }
+    }
+
+    async fn request_token(&self, params: &[(&str, &str)]) -> Result<TokenResponse> {
+        self.post_token(params)
+            .await?
+            .map_err(TokenError::into_error)
+    }
+
+    async fn post_token(
+        &self,
+        params: &[(&str, &str)],
+    ) -> Result<std::result::Result<TokenResponse, TokenError>> {
+        let mut form = vec![
+            ("client_id", self.client_id.as_str()),
+            ("scope", self.scope.as_str()),
+        ];
+        if let Some(client_secret) = &self.client_secret {
+            form.push(("client_secret", client_secret));
+        }
+        form.extend_from_slice(params);
+
+        let response = self
+            .http_client
+            .post(format!("{}/oauth2/v2.0/token", self.authority))
+            .form(&form)
+            .send()
+            .await
+            .map_err(|e| HttpRoute::Graph.map_err(e))
+            .context("failed to send token request")?;
+
+        let status = response.status();
+
+        let text = response
+            .text()
+            .await
+            .context("failed to get token response text")?;
+
+        if !status.is_success() {
+            // errors like authorization_pending are expected while polling
+            return serde_json::from_str::<TokenError>(&text)
+                .map(Err)
+                .with_context(|| format!("token request failed with {}: {}", status, text));
+        }
+
+        serde_json::from_str(&text)
+            .map(Ok)
+            .context("failed to deserialize token response")
+    }
+}
+
+// pkce, 43 characters from 32 random bytes
+pub fn new_code_verifier() -> String {
+    random_token()
+}
+
+fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::StatusCode, routing::post, Json, Router};
+    use serde_json::json;
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    };
+    use tokio::net::TcpListener;
+
+    const SCOPE: &str = "offline_access https://microsoftgraph.chinacloudapi.cn/Files.ReadWrite.All https://microsoftgraph.chinacloudapi.cn/Sites.Read.All https://microsoftgraph.chinacloudapi.cn/User.Read";
+
+    // a mock identity platform of a national cloud
+    async fn serve_authority() -> String {
+        // the first poll of the device code is pending
+        let is_pending = Arc::new(AtomicBool::new(true));
+
+        let router = Router::new()
+            .route(
+                "/contoso/oauth2/v2.0/devicecode",
+                post(|| async {
+                    Json(json!({
+                        "user_code": "ABCD1234",
+                        "device_code": "device",
+                        "verification_uri": "https://microsoft.com/deviceloginchina",
+                        "expires_in": 900,
+                        "interval": 0,
+                    }))
+                }),
+            )
+            .route(
+                "/contoso/oauth2/v2.0/token",
+                post(|body: String| async move {
+                    let form = url::form_urlencoded::parse(body.as_bytes())
+                        .into_owned()
+                        .collect::<HashMap<String, String>>();
+
+                    let scope = form.get("scope").map(String::as_str);
+
+                    match (form.get("grant_type").map(String::as_str), scope) {
+                        (Some(DEVICE_CODE_GRANT_TYPE), _)
+                            if form.get("device_code").map(String::as_str) == Some("device")
+                                && !form.contains_key("client_secret") =>
+                        {
+                            if is_pending.swap(false, Ordering::SeqCst) {
+                                (
+                                    StatusCode::BAD_REQUEST,
+                                    Json(json!({ "error": "authorization_pending" })),
+                                )
+                            } else {
+                                (
+                                    StatusCode::OK,
+                                    Json(json!({
+                                        "access_token": "device access",
+                                        "refresh_token": "refresh",
+                                        "expires_in": 3600,
+                                    })),
+                                )
+                            }
+                        }
+                        (Some("authorization_code"), Some(SCOPE))
+                            if form.get("code").map(String::as_str) == Some("code")
+                                && form.get("code_verifier").map(String::as_str)
+                                    == Some("verifier") =>
+                        {
+                            (
+                                StatusCode::OK,
+                                Json(json!({
+                                    "access_token": "access",
+                                    "refresh_token": "refresh",
+                                    "expires_in": 3600,
+                                })),
+                            )
+                        }
+                        _ => (
+                            StatusCode::BAD_REQUEST,
+                            Json(json!({
+                                "error": "invalid_grant",
+                                "error_description": "the code is invalid",
+                            })),
+                        ),
+                    }
+                }),
+            );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            axum::serve(listener, router).await.unwrap();
+        });
+
+        format!("http://{}", addr)
+    }
+
+    fn new_oauth(authority_url: &str) -> OAuth {
+        new_oauth_with_secret(authority_url, Some("secret"))
+    }
+
+    fn new_oauth_with_secret(authority_url: &str, client_secret: Option<&str>) -> OAuth {
+        OAuth::new(
+            reqwest::Client::new(),
+            "client",
+            client_secret,
+            "https://example.com/auth",
+            authority_url,
+            "contoso",
+            "https://microsoftgraph.chinacloudapi.cn/v1.0",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_code_auth_url() {
+        let auth_url = new_oauth("https://login.chinacloudapi.cn/")
+            .code_auth_url("state", "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk")
+            .unwrap();
+
+        assert!(
+            auth_url.starts_with("https://login.chinacloudapi.cn/contoso/oauth2/v2.0/authorize?")
+        );
+        assert!(auth_url.contains("microsoftgraph.chinacloudapi.cn%2FFiles.ReadWrite.All"));
+        assert!(auth_url.contains("microsoftgraph.chinacloudapi.cn%2FSites.Read.All"));
+        assert!(auth_url.contains("state=state"));
+        // the example of rfc 7636
+        assert!(auth_url.contains("code_challenge=E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"));
+        assert!(auth_url.contains("code_challenge_method=S256"));
+    }
+
+    #[tokio::test]
+    async fn test_login_with_code() {
+        let oauth = new_oauth(&serve_authority().await);
+
+        let token_response = oauth.login_with_code("code", "verifier").await.unwrap();
+        assert_eq!(token_response.access_token, "access");
+        assert_eq!(token_response.refresh_token.as_deref(), Some("refresh"));
+        assert_eq!(token_response.expires_in_secs, 3600);
+
+        // the code is bound to the verifier it was requested with
+        let e = oauth
+            .login_with_code("code", "another verifier")
+            .await
+            .err()
+            .unwrap();
+        assert!(e.to_string().contains("the code is invalid"));
+    }
+
+    #[tokio::test]
+    async fn test_login_with_device_code() {
+        let oauth = new_oauth_with_secret(&serve_authority().await, None);
+
+        let device_code = oauth.request_device_code().await.unwrap();
+        assert_eq!(device_code.user_code, "ABCD1234");
+
+        let token_response = oauth.login_with_device_code(&device_code).await.unwrap();
+        assert_eq!(token_response.access_token, "device access");
+    }
+}
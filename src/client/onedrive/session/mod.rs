@@ -7,17 +7,18 @@
 
 mod models;
 
-use crate::utils::get_current_timestamp;
+use crate::{
+    crypto::{self, session_cipher, Cipher},
+    utils::{create_table_if_not_exists, get_current_timestamp},
+};
 use anyhow::{anyhow, Context, Result};
-use models::{current_user, session};
-use onedrive_api::OneDrive;
-use reqwest::header;
+use models::{current_user, drive_target, session};
 use sea_orm::{
     sea_query::Expr, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityName, EntityTrait,
-    ModelTrait, QueryFilter, QuerySelect, Schema, Set,
+    ModelTrait, QueryFilter, QuerySelect, Set, Statement, TransactionTrait,
 };
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use std::path::Path;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct OneDriveSession {
@@ -32,20 +33,20 @@ pub struct OneDriveSession {
 
 impl OneDriveSession {
     pub async fn new(
-        client: &OneDrive,
+        username: &str,
+        access_token: &str,
         expires_in_secs: u64,
         refresh_token: &str,
         session_path: &str,
         root_path: &str,
     ) -> Result<Self> {
-        let username = Self::get_username(client).await?;
         let expiration_timestamp = Self::get_expiration_timestamp(expires_in_secs);
-        let connection = Self::connect_db(session_path).await?;
+        let connection = Self::connect_db(session_path, session_cipher()).await?;
 
         Ok(Self {
-            username,
+            username: username.to_string(),
             expiration_timestamp,
-            access_token: client.access_token().to_string(),
+            access_token: access_token.to_string(),
             refresh_token: refresh_token.to_string(),
             root_path: root_path.to_string(),
             connection,
@@ -72,101 +73,171 @@ impl OneDriveSession {
         );
     }
 
-    async fn get_username(client: &OneDrive) -> Result<String> {
-        let http_client = client.client();
+    // plain text tokens are encrypted with the cipher if any
+    async fn connect_db(path: &str, cipher: Option<&Cipher>) -> Result<DatabaseConnection> {
+        let connection = sea_orm::Database::connect(format!("sqlite://{}?mode=rwc", path))
+            .await
+            .context("failed to connect to onedrive session")?;
 
-        let url = "https://graph.microsoft.com/v1.0/me/";
+        create_table_if_not_exists(&connection, session::Entity).await?;
+        create_table_if_not_exists(&connection, current_user::Entity).await?;
+        Self::migrate_drive_targets(&connection).await?;
+        create_table_if_not_exists(&connection, drive_target::Entity).await?;
 
-        let response = http_client
-            .get(url)
-            .header(
-                header::AUTHORIZATION,
-                format!("Bearer {}", client.access_token()),
-            )
-            .send()
-            .await
-            .context("failed to send request for user profile")?;
+        if let Some(cipher) = cipher {
+            Self::encrypt_plain_tokens(&connection, cipher).await?;
+        }
+
+        Ok(connection)
+    }
+
+    // targets saved before they were kept per account belong to the current user
+    async fn migrate_drive_targets(connection: &DatabaseConnection) -> Result<()> {
+        let backend = connection.get_database_backend();
 
-        let content = response
-            .text()
+        let rows = connection
+            .query_all(Statement::from_string(
+                backend,
+                format!("PRAGMA table_info({})", drive_target::Entity.table_name()),
+            ))
             .await
-            .context("failed to get response text for user profile")?;
+            .context("failed to get columns of onedrive drive target")?;
 
-        let user_profile = serde_json::from_str::<Value>(&content)
-            .context("failed to deserialize user profile into Value")?;
+        let columns = rows
+            .iter()
+            .map(|row| row.try_get::<String>("", "name"))
+            .collect::<Result<Vec<_>, _>>()
+            .context("failed to get column name of onedrive drive target")?;
 
-        let username = user_profile
-            .get("userPrincipalName")
-            .ok_or_else(|| anyhow!("field userPrincipalName not found in user profile"))?
-            .as_str()
-            .ok_or_else(|| anyhow!("userPrincipalName value is not a string"))?
-            .to_string();
+        if columns.is_empty() || columns.iter().any(|column| column == "username") {
+            return Ok(());
+        }
 
-        tracing::debug!("got onedrive username from request: {}", username);
+        tracing::info!("migrate onedrive drive targets to the current user");
 
-        Ok(username)
-    }
+        let txn = connection
+            .begin()
+            .await
+            .context("failed to begin onedrive drive target migration")?;
 
-    async fn connect_db(path: &str) -> Result<DatabaseConnection> {
-        let connection = sea_orm::Database::connect(format!("sqlite://{}?mode=rwc", path))
+        txn.execute_unprepared("ALTER TABLE drive_target RENAME TO drive_target_old")
             .await
-            .context("failed to connect to onedrive session")?;
+            .context("failed to rename old onedrive drive target")?;
 
-        Self::create_table_if_not_exists(&connection, session::Entity).await?;
-        Self::create_table_if_not_exists(&connection, current_user::Entity).await?;
+        create_table_if_not_exists(&txn, drive_target::Entity).await?;
 
-        Ok(connection)
-    }
+        txn.execute_unprepared(
+            "INSERT INTO drive_target (chat_id, username, target) \
+             SELECT drive_target_old.chat_id, current_user.username, drive_target_old.target \
+             FROM drive_target_old, current_user",
+        )
+        .await
+        .context("failed to copy old onedrive drive targets")?;
 
-    pub async fn set_connection(mut self, session_path: &str) -> Result<Self> {
-        self.connection = Self::connect_db(session_path).await?;
+        txn.execute_unprepared("DROP TABLE drive_target_old")
+            .await
+            .context("failed to drop old onedrive drive target")?;
 
-        Ok(self)
+        txn.commit()
+            .await
+            .context("failed to commit onedrive drive target migration")?;
+
+        Ok(())
     }
 
-    async fn is_table_exists<E>(connection: &DatabaseConnection) -> bool
-    where
-        E: EntityTrait,
-    {
-        let result = E::find().all(connection).await;
+    // sessions saved before session_key was set
+    async fn encrypt_plain_tokens(connection: &DatabaseConnection, cipher: &Cipher) -> Result<()> {
+        let sessions = session::Entity::find()
+            .all(connection)
+            .await
+            .context("failed to query onedrive sessions")?;
 
-        result.is_ok()
-    }
+        for session in sessions {
+            if crypto::is_encrypted_text(&session.access_token)
+                && crypto::is_encrypted_text(&session.refresh_token)
+            {
+                continue;
+            }
 
-    async fn create_table_if_not_exists<E>(connection: &DatabaseConnection, entity: E) -> Result<()>
-    where
-        E: EntityTrait + EntityName,
-    {
-        if Self::is_table_exists::<E>(connection).await {
-            tracing::debug!(
-                "onedrive session database table {} already exists",
-                entity.table_name()
-            );
-        } else {
-            tracing::debug!(
-                "onedrive session database table {} not exists, create it",
-                entity.table_name()
+            tracing::info!(
+                "encrypt plain text onedrive session of {}",
+                session.username
             );
 
-            let backend = connection.get_database_backend();
+            Self::update_tokens(connection, &session, Some(cipher), Some(cipher)).await?;
+        }
+
+        Ok(())
+    }
+
+    // returns the number of sessions encrypted with the new key
+    pub async fn rotate_key(path: &str, old: Option<&Cipher>, new: &Cipher) -> Result<usize> {
+        if !Path::new(path).exists() {
+            return Ok(0);
+        }
 
-            let table_create_statement = Schema::new(backend).create_table_from_entity(entity);
+        let connection = Self::connect_db(path, old).await?;
 
-            connection
-                .execute(backend.build(&table_create_statement))
-                .await
-                .context(format!("failed to create table {}", entity.table_name()))?;
+        let sessions = session::Entity::find()
+            .all(&connection)
+            .await
+            .context("failed to query onedrive sessions")?;
+
+        for session in &sessions {
+            if let Err(e) = Self::update_tokens(&connection, session, old, Some(new)).await {
+                // already encrypted with the new key if the last rotation was interrupted
+                Self::update_tokens(&connection, session, Some(new), Some(new))
+                    .await
+                    .map_err(|_| e)
+                    .context(format!(
+                        "failed to rotate onedrive session of {}",
+                        session.username
+                    ))?;
+            }
         }
 
+        Ok(sessions.len())
+    }
+
+    // tokens are decrypted with old and encrypted with new
+    async fn update_tokens(
+        connection: &DatabaseConnection,
+        session: &session::Model,
+        old: Option<&Cipher>,
+        new: Option<&Cipher>,
+    ) -> Result<()> {
+        let access_token = crypto::open_text(old, &session.access_token)?;
+        let refresh_token = crypto::open_text(old, &session.refresh_token)?;
+
+        session::Entity::update_many()
+            .filter(session::Column::Username.eq(&session.username))
+            .col_expr(
+                session::Column::AccessToken,
+                Expr::value(crypto::seal_text(new, &access_token)?),
+            )
+            .col_expr(
+                session::Column::RefreshToken,
+                Expr::value(crypto::seal_text(new, &refresh_token)?),
+            )
+            .exec(connection)
+            .await
+            .context("failed to update onedrive session tokens")?;
+
         Ok(())
     }
 
+    pub async fn set_connection(mut self, session_path: &str) -> Result<Self> {
+        self.connection = Self::connect_db(session_path, session_cipher()).await?;
+
+        Ok(self)
+    }
+
     pub async fn load(path: &str) -> Result<Self> {
         tracing::debug!("load onedrive session");
 
-        let connection = Self::connect_db(path).await?;
+        let connection = Self::connect_db(path, session_cipher()).await?;
 
-        let mut session = Self::from(Self::get_current_session(&connection).await?);
+        let mut session = Self::try_from(Self::get_current_session(&connection).await?)?;
 
         session.connection = connection;
 
@@ -186,8 +257,8 @@ impl OneDriveSession {
             let insert_item = session::ActiveModel {
                 username: Set(self.username.to_string()),
                 expiration_timestamp: Set(self.expiration_timestamp),
-                access_token: Set(self.access_token.to_string()),
-                refresh_token: Set(self.refresh_token.to_string()),
+                access_token: Set(crypto::seal_text(session_cipher(), &self.access_token)?),
+                refresh_token: Set(crypto::seal_text(session_cipher(), &self.refresh_token)?),
                 root_path: Set(self.root_path.to_string()),
             };
 
@@ -240,11 +311,11 @@ impl OneDriveSession {
             )
             .col_expr(
                 session::Column::AccessToken,
-                Expr::value(&self.access_token),
+                Expr::value(crypto::seal_text(session_cipher(), &self.access_token)?),
             )
             .col_expr(
                 session::Column::RefreshToken,
-                Expr::value(&self.refresh_token),
+                Expr::value(crypto::seal_text(session_cipher(), &self.refresh_token)?),
             )
             .col_expr(session::Column::RootPath, Expr::value(&self.root_path))
             .exec(&self.connection)
@@ -335,6 +406,53 @@ impl OneDriveSession {
         Ok(usernames)
     }
 
+    // targets are kept per account, switching accounts doesn't carry them over
+    pub async fn get_drive_target(&self, chat_id: i64) -> Result<Option<String>> {
+        let target = drive_target::Entity::find_by_id((chat_id, self.username.clone()))
+            .one(&self.connection)
+            .await
+            .context("failed to query onedrive drive target")?
+            .map(|model| model.target);
+
+        tracing::debug!(
+            "got onedrive drive target of {} for chat {}: {:?}",
+            self.username,
+            chat_id,
+            target
+        );
+
+        Ok(target)
+    }
+
+    // none resets the chat to the personal drive
+    pub async fn set_drive_target(&self, chat_id: i64, target: Option<String>) -> Result<()> {
+        drive_target::Entity::delete_by_id((chat_id, self.username.clone()))
+            .exec(&self.connection)
+            .await
+            .context("failed to delete onedrive drive target")?;
+
+        if let Some(target) = target {
+            let insert_item = drive_target::ActiveModel {
+                chat_id: Set(chat_id),
+                username: Set(self.username.clone()),
+                target: Set(target),
+            };
+
+            drive_target::Entity::insert(insert_item)
+                .exec(&self.connection)
+                .await
+                .context("failed to insert onedrive drive target")?;
+        }
+
+        tracing::debug!(
+            "set onedrive drive target of {} for chat {}",
+            self.username,
+            chat_id
+        );
+
+        Ok(())
+    }
+
     pub async fn get_current_username(&self) -> Result<Option<String>> {
         if let Some(model) = current_user::Entity::find()
             .one(&self.connection)
@@ -389,7 +507,7 @@ impl OneDriveSession {
             Ok(Some(session)) => {
                 tracing::debug!("new onedrive user: {}", session.username);
 
-                self.overwrite(Self::from(session));
+                self.overwrite(Self::try_from(session)?);
 
                 self.set_current_user().await?;
             }
@@ -428,7 +546,7 @@ impl OneDriveSession {
             session.username
         );
 
-        self.overwrite(Self::from(session));
+        self.overwrite(Self::try_from(session)?);
 
         self.set_current_user().await?;
 
@@ -444,15 +562,195 @@ impl OneDriveSession {
     }
 }
 
-impl From<session::Model> for OneDriveSession {
-    fn from(model: session::Model) -> Self {
-        Self {
+impl TryFrom<session::Model> for OneDriveSession {
+    type Error = anyhow::Error;
+
+    fn try_from(model: session::Model) -> Result<Self> {
+        Ok(Self {
+            access_token: crypto::open_text(session_cipher(), &model.access_token)
+                .context("failed to decrypt onedrive access token")?,
+            refresh_token: crypto::open_text(session_cipher(), &model.refresh_token)
+                .context("failed to decrypt onedrive refresh token")?,
             username: model.username,
             expiration_timestamp: model.expiration_timestamp,
-            access_token: model.access_token,
-            refresh_token: model.refresh_token,
             root_path: model.root_path,
             connection: DatabaseConnection::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TempPath;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    // without session_key, which is read from env
+    async fn new_session(path: &str, username: &str) -> OneDriveSession {
+        OneDriveSession {
+            username: username.to_string(),
+            connection: OneDriveSession::connect_db(path, None).await.unwrap(),
+            ..Default::default()
         }
     }
+
+    fn new_cipher() -> Cipher {
+        Cipher::new(&STANDARD.encode(rand::random::<[u8; 32]>())).unwrap()
+    }
+
+    async fn insert_tokens(path: &str, access_token: &str, refresh_token: &str) {
+        let connection = OneDriveSession::connect_db(path, None).await.unwrap();
+
+        let insert_item = session::ActiveModel {
+            username: Set("a".to_string()),
+            expiration_timestamp: Set(0),
+            access_token: Set(access_token.to_string()),
+            refresh_token: Set(refresh_token.to_string()),
+            root_path: Set("/".to_string()),
+        };
+
+        session::Entity::insert(insert_item)
+            .exec(&connection)
+            .await
+            .unwrap();
+    }
+
+    async fn get_tokens(connection: &DatabaseConnection) -> (String, String) {
+        let session = session::Entity::find()
+            .one(connection)
+            .await
+            .unwrap()
+            .unwrap();
+
+        (session.access_token, session.refresh_token)
+    }
+
+    #[tokio::test]
+    async fn test_encrypt_plain_tokens() {
+        let path = TempPath::new("onedrive-session");
+        let path = path.as_str();
+        let cipher = new_cipher();
+
+        insert_tokens(path, "access", "refresh").await;
+
+        let connection = OneDriveSession::connect_db(path, Some(&cipher))
+            .await
+            .unwrap();
+        let (access_token, refresh_token) = get_tokens(&connection).await;
+        assert_eq!(
+            crypto::open_text(Some(&cipher), &access_token).unwrap(),
+            "access"
+        );
+        assert_eq!(
+            crypto::open_text(Some(&cipher), &refresh_token).unwrap(),
+            "refresh"
+        );
+
+        // encrypted tokens are left as they are
+        let connection = OneDriveSession::connect_db(path, Some(&cipher))
+            .await
+            .unwrap();
+        assert_eq!(get_tokens(&connection).await, (access_token, refresh_token));
+    }
+
+    #[tokio::test]
+    async fn test_rotate_key() {
+        let path = TempPath::new("onedrive-session");
+        let path = path.as_str();
+        let old = new_cipher();
+        let new = new_cipher();
+
+        insert_tokens(
+            path,
+            &old.encrypt_text("access").unwrap(),
+            &old.encrypt_text("refresh").unwrap(),
+        )
+        .await;
+
+        // the second run is the same as resuming an interrupted rotation
+        for _ in 0..2 {
+            assert_eq!(
+                OneDriveSession::rotate_key(path, Some(&old), &new)
+                    .await
+                    .unwrap(),
+                1
+            );
+
+            let connection = OneDriveSession::connect_db(path, None).await.unwrap();
+            let (access_token, refresh_token) = get_tokens(&connection).await;
+            assert_eq!(new.decrypt_text(&access_token).unwrap(), "access");
+            assert_eq!(new.decrypt_text(&refresh_token).unwrap(), "refresh");
+        }
+
+        // the error of the old key is kept when neither key fits
+        let e = OneDriveSession::rotate_key(path, Some(&old), &new_cipher())
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", e).contains("session_key may be wrong"));
+    }
+
+    #[tokio::test]
+    async fn test_drive_target_per_account() {
+        let path = TempPath::new("onedrive-session");
+        let path = path.as_str();
+
+        let mut session = new_session(path, "a").await;
+
+        session
+            .set_drive_target(1, Some("site".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(
+            session.get_drive_target(1).await.unwrap(),
+            Some("site".to_string())
+        );
+
+        // another account of the same chat starts from its personal drive
+        session.username = "b".to_string();
+        assert_eq!(session.get_drive_target(1).await.unwrap(), None);
+
+        session
+            .set_drive_target(1, Some("group".to_string()))
+            .await
+            .unwrap();
+
+        session.username = "a".to_string();
+        assert_eq!(
+            session.get_drive_target(1).await.unwrap(),
+            Some("site".to_string())
+        );
+
+        session.set_drive_target(1, None).await.unwrap();
+        assert_eq!(session.get_drive_target(1).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_migrate_drive_targets() {
+        let path = TempPath::new("onedrive-session");
+        let path = path.as_str();
+
+        let connection = sea_orm::Database::connect(format!("sqlite://{}?mode=rwc", path))
+            .await
+            .unwrap();
+
+        // the table before targets were kept per account
+        for statement in [
+            "CREATE TABLE current_user (username TEXT NOT NULL PRIMARY KEY)",
+            "CREATE TABLE drive_target (chat_id INTEGER NOT NULL PRIMARY KEY, target TEXT NOT NULL)",
+            "INSERT INTO current_user (username) VALUES ('a')",
+            "INSERT INTO drive_target (chat_id, target) VALUES (1, 'site')",
+        ] {
+            connection.execute_unprepared(statement).await.unwrap();
+        }
+
+        let mut session = new_session(path, "a").await;
+
+        assert_eq!(
+            session.get_drive_target(1).await.unwrap(),
+            Some("site".to_string())
+        );
+
+        session.username = "b".to_string();
+        assert_eq!(session.get_drive_target(1).await.unwrap(), None);
+    }
 }
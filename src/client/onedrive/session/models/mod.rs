@@ -6,4 +6,5 @@
 */
 
 pub mod current_user;
+pub mod drive_target;
 pub mod session;
@@ -0,0 +1,244 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::OneDriveClient;
+use anyhow::{anyhow, Context, Result};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt::Display;
+use url::Url;
+
+// characters that can't be kept in a segment of a graph item path
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b':')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+// the drive that files are uploaded to, chosen per chat
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DriveTarget {
+    Me,
+    Drive {
+        drive_id: String,
+    },
+    Group {
+        group_id: String,
+    },
+    Site {
+        site_id: String,
+        site_url: Option<String>,
+    },
+    // a folder someone shared with the current account, paths are relative to it
+    Shared {
+        drive_id: String,
+        item_id: String,
+        name: String,
+    },
+}
+
+impl DriveTarget {
+    fn drive_path(&self) -> String {
+        match self {
+            Self::Me => "/me/drive".to_string(),
+            Self::Drive { drive_id } | Self::Shared { drive_id, .. } => {
+                format!("/drives/{}", drive_id)
+            }
+            Self::Group { group_id } => format!("/groups/{}/drive", group_id),
+            Self::Site { site_id, .. } => format!("/sites/{}/drive", site_id),
+        }
+    }
+
+    // /dir/a.txt -> /me/drive/root:/dir/a.txt:
+    pub fn item_path(&self, path: &str) -> String {
+        let base = match self {
+            Self::Shared { item_id, .. } => format!("{}/items/{}", self.drive_path(), item_id),
+            _ => format!("{}/root", self.drive_path()),
+        };
+
+        let encoded_path = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| utf8_percent_encode(segment, PATH_SEGMENT).to_string())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        if encoded_path.is_empty() {
+            base
+        } else {
+            format!("{}:/{}:", base, encoded_path)
+        }
+    }
+}
+
+impl Display for DriveTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Me => write!(f, "personal drive"),
+            Self::Drive { drive_id } => write!(f, "drive {}", drive_id),
+            Self::Group { group_id } => write!(f, "drive of group {}", group_id),
+            Self::Site {
+                site_url: Some(site_url),
+                ..
+            } => write!(f, "drive of site {}", site_url),
+            Self::Site { site_id, .. } => write!(f, "drive of site {}", site_id),
+            Self::Shared { name, .. } => write!(f, "shared folder {}", name),
+        }
+    }
+}
+
+impl OneDriveClient {
+    pub async fn graph_get(&self, path: &str) -> Result<Value> {
+        self.graph_request(Method::GET, path, None).await
+    }
+
+    pub async fn graph_post(&self, path: &str, body: &Value) -> Result<Value> {
+        self.graph_request(Method::POST, path, Some(body)).await
+    }
+
+    async fn graph_request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<Value> {
+        self.refresh_access_token().await?;
+
+        let access_token = self.session.read().await.access_token.clone();
+
+        self.graph.request(&access_token, method, path, body).await
+    }
+
+    pub async fn get_drive_target(&self, chat_id: i64) -> Result<DriveTarget> {
+        match self.session.read().await.get_drive_target(chat_id).await? {
+            Some(target) => {
+                serde_json::from_str(&target).context("failed to deserialize drive target")
+            }
+            None => Ok(DriveTarget::Me),
+        }
+    }
+
+    // the target is checked before saved, returns the name of its root folder
+    pub async fn set_drive_target(&self, chat_id: i64, target: &DriveTarget) -> Result<String> {
+        let root = self.graph_get(&target.item_path("/")).await?;
+
+        let name = root
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+
+        let target = match target {
+            DriveTarget::Me => None,
+            _ => Some(serde_json::to_string(target).context("failed to serialize drive target")?),
+        };
+
+        self.session
+            .read()
+            .await
+            .set_drive_target(chat_id, target)
+            .await?;
+
+        Ok(name)
+    }
+
+    // https://contoso.sharepoint.com/sites/team -> site id
+    pub async fn resolve_site(&self, site_url: &str) -> Result<DriveTarget> {
+        let url = Url::parse(site_url).context("failed to parse site url")?;
+
+        let host = url
+            .host_str()
+            .ok_or_else(|| anyhow!("host not found in site url"))?;
+
+        let path = match url.path().trim_end_matches('/') {
+            "" => format!("/sites/{}", host),
+            path => format!("/sites/{}:{}", host, path),
+        };
+
+        let site = self.graph_get(&path).await?;
+
+        let site_id = site
+            .get("id")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("site id not found"))?
+            .to_string();
+
+        Ok(DriveTarget::Site {
+            site_id,
+            site_url: Some(site_url.to_string()),
+        })
+    }
+
+    pub async fn list_shared_folders(&self) -> Result<Vec<DriveTarget>> {
+        let shared_items = self.graph_get("/me/drive/sharedWithMe").await?;
+
+        let folders = shared_items
+            .get("value")
+            .and_then(Value::as_array)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|item| {
+                let remote_item = item.get("remoteItem")?;
+                remote_item.get("folder")?;
+
+                Some(DriveTarget::Shared {
+                    drive_id: remote_item
+                        .pointer("/parentReference/driveId")?
+                        .as_str()?
+                        .to_string(),
+                    item_id: remote_item.get("id")?.as_str()?.to_string(),
+                    name: remote_item.get("name")?.as_str()?.to_string(),
+                })
+            })
+            .collect();
+
+        Ok(folders)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DriveTarget;
+
+    #[test]
+    fn test_item_path() {
+        assert_eq!(DriveTarget::Me.item_path("/"), "/me/drive/root");
+        assert_eq!(
+            DriveTarget::Me.item_path("/a b/c#1.txt"),
+            "/me/drive/root:/a%20b/c%231.txt:"
+        );
+        assert_eq!(
+            DriveTarget::Site {
+                site_id: "site".to_string(),
+                site_url: None
+            }
+            .item_path("/dir"),
+            "/sites/site/drive/root:/dir:"
+        );
+        assert_eq!(
+            DriveTarget::Shared {
+                drive_id: "b!drive".to_string(),
+                item_id: "item".to_string(),
+                name: "team".to_string()
+            }
+            .item_path("/dir/a.txt"),
+            "/drives/b!drive/items/item:/dir/a.txt:"
+        );
+    }
+}
@@ -5,41 +5,111 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use super::OneDriveClient;
+use super::{graph::graph_error, target::DriveTarget, OneDriveClient};
 use anyhow::{anyhow, Context, Result};
-use onedrive_api::{
-    option::DriveItemPutOption, ConflictBehavior, ItemLocation, UploadSession, UploadSessionMeta,
-};
+use onedrive_api::resource::DriveItem;
 use path_slash::PathBufExt;
+use reqwest::{Method, StatusCode};
+use serde_json::{json, Value};
 use std::path::Path;
 
 impl OneDriveClient {
+    // returns the upload url and the start of the next expected range
     pub async fn multipart_upload_session_builder(
         &self,
         root_path: &str,
         filename: &str,
-    ) -> Result<(UploadSession, UploadSessionMeta)> {
+        target: &DriveTarget,
+    ) -> Result<(String, u64)> {
         let file_path_obj = Path::new(root_path).join(filename);
         let file_path = file_path_obj.to_slash_lossy();
 
-        let item_location = ItemLocation::from_path(&file_path)
-            .ok_or_else(|| anyhow!("file path does not start with /"))?;
-
-        self.refresh_access_token().await?;
-
         let session = self
-            .client
-            .read()
-            .await
-            .new_upload_session_with_option(
-                item_location,
-                DriveItemPutOption::new().conflict_behavior(ConflictBehavior::Rename),
+            .graph_post(
+                &format!("{}/createUploadSession", target.item_path(&file_path)),
+                &json!({ "item": { "@microsoft.graph.conflictBehavior": "rename" } }),
             )
             .await
             .context("failed to create upload session")?;
 
-        tracing::debug!("built upload session for {}", filename);
+        let upload_url = session
+            .get("uploadUrl")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("upload url not found in upload session"))?
+            .to_string();
+
+        // ranges are like 0- or 26-49
+        let current_length = session
+            .pointer("/nextExpectedRanges/0")
+            .and_then(Value::as_str)
+            .and_then(|range| range.split('-').next())
+            .and_then(|start| start.parse::<u64>().ok())
+            .unwrap_or_default();
+
+        tracing::debug!("built upload session for {} in {}", filename, target);
+
+        Ok((upload_url, current_length))
+    }
+
+    pub async fn get_item(&self, path: &str, target: &DriveTarget) -> Result<Option<DriveItem>> {
+        if !path.starts_with('/') {
+            return Err(anyhow!("path does not start with /"));
+        }
+
+        self.refresh_access_token().await?;
+
+        let access_token = self.session.read().await.access_token.clone();
+
+        let (status, content) = self
+            .graph
+            .send(&access_token, Method::GET, &target.item_path(path), None)
+            .await?;
+
+        // 404: Not Found
+        if status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        if !status.is_success() {
+            return Err(graph_error(status, &content))
+                .context("failed to get onedrive item")
+                .context(path.to_string());
+        }
+
+        serde_json::from_value(content)
+            .map(Some)
+            .context("failed to deserialize onedrive item")
+    }
+
+    pub async fn list_children(&self, path: &str, target: &DriveTarget) -> Result<Vec<DriveItem>> {
+        if !path.starts_with('/') {
+            return Err(anyhow!("path does not start with /"));
+        }
+
+        let mut children = Vec::new();
+        let mut next_link = Some(format!("{}/children", target.item_path(path)));
+
+        // children are paged, the next page is an absolute url
+        while let Some(link) = next_link {
+            let page = self
+                .graph_get(&link)
+                .await
+                .context("failed to list onedrive directory")
+                .context(path.to_string())?;
+
+            next_link = page
+                .get("@odata.nextLink")
+                .and_then(Value::as_str)
+                .map(str::to_string);
+
+            let items = page.get("value").cloned().unwrap_or_default();
+
+            children.extend(
+                serde_json::from_value::<Vec<DriveItem>>(items)
+                    .context("failed to deserialize onedrive items")?,
+            );
+        }
 
-        Ok(session)
+        Ok(children)
     }
 }
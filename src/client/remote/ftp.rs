@@ -0,0 +1,113 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{get_name, Credentials, RemoteEntry, RemoteReader};
+use anyhow::{Context, Result};
+use suppaftp::{types::FileType, AsyncFtpStream};
+use tokio_util::compat::FuturesAsyncReadCompatExt;
+
+pub struct FtpClient {
+    stream: AsyncFtpStream,
+}
+
+impl FtpClient {
+    pub async fn connect(host: &str, port: u16, credentials: &Credentials) -> Result<Self> {
+        let mut stream = AsyncFtpStream::connect(format!("{}:{}", host, port))
+            .await
+            .context("failed to connect to ftp server")?;
+
+        let username = credentials.username.as_deref().unwrap_or("anonymous");
+        let password = credentials.password.as_deref().unwrap_or_default();
+
+        stream
+            .login(username, password)
+            .await
+            .context("failed to login to ftp server")?;
+
+        stream
+            .transfer_type(FileType::Binary)
+            .await
+            .context("failed to set ftp transfer type to binary")?;
+
+        Ok(Self { stream })
+    }
+
+    // SIZE is only answered for files, so directories are detected by CWD
+    pub async fn stat(&mut self, path: &str) -> Result<RemoteEntry> {
+        let name = get_name(path);
+
+        if let Ok(size) = self.stream.size(path).await {
+            return Ok(RemoteEntry {
+                name,
+                path: path.to_string(),
+                is_dir: false,
+                size: size as u64,
+            });
+        }
+
+        self.stream
+            .cwd(path)
+            .await
+            .context("file or directory not found on ftp server")
+            .context(path.to_string())?;
+
+        Ok(RemoteEntry {
+            name,
+            path: path.to_string(),
+            is_dir: true,
+            size: 0,
+        })
+    }
+
+    pub async fn list(&mut self, path: &str) -> Result<Vec<RemoteEntry>> {
+        let names = self
+            .stream
+            .nlst(Some(path))
+            .await
+            .context("failed to list ftp directory")
+            .context(path.to_string())?;
+
+        let mut entries = Vec::new();
+
+        for name in names {
+            // some servers return full paths
+            let name = get_name(&name);
+
+            if name.is_empty() || name == "." || name == ".." {
+                continue;
+            }
+
+            let path = format!("{}/{}", path.trim_end_matches('/'), name);
+
+            entries.push(self.stat(&path).await?);
+        }
+
+        Ok(entries)
+    }
+
+    pub async fn open(&mut self, path: &str, offset: u64) -> Result<RemoteReader> {
+        if offset > 0 {
+            self.stream
+                .resume_transfer(offset as usize)
+                .await
+                .context("failed to resume ftp transfer")?;
+        }
+
+        let data_stream = self
+            .stream
+            .retr_as_stream(path)
+            .await
+            .context("failed to retrieve file from ftp server")
+            .context(path.to_string())?;
+
+        Ok(Box::new(data_stream.compat()))
+    }
+
+    pub async fn close(mut self) {
+        let _ = self.stream.quit().await;
+    }
+}
@@ -0,0 +1,179 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{get_name, Credentials, RemoteEntry, RemoteReader};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use russh::client::{self, Handle};
+use russh_keys::key::PublicKey;
+use russh_sftp::client::SftpSession;
+use std::{io::SeekFrom, sync::Arc};
+use tokio::io::AsyncSeekExt;
+
+pub struct SftpClient {
+    session: Handle<SshHandler>,
+    sftp: SftpSession,
+}
+
+pub struct SshHandler {
+    host_key: Option<String>,
+}
+
+#[async_trait]
+impl client::Handler for SshHandler {
+    type Error = russh::Error;
+
+    async fn check_server_key(
+        &mut self,
+        server_public_key: &PublicKey,
+    ) -> Result<bool, Self::Error> {
+        let fingerprint = server_public_key.fingerprint();
+
+        // unpinned keys are rejected, otherwise credentials could be sent to anyone in the middle
+        match &self.host_key {
+            Some(host_key) => Ok(host_key.trim_start_matches("SHA256:") == fingerprint),
+            None => {
+                tracing::warn!(
+                    "sftp host key not pinned, rejected SHA256:{}, set it as host_key of the fetch profile after checking it on the server",
+                    fingerprint
+                );
+
+                Ok(false)
+            }
+        }
+    }
+}
+
+impl SftpClient {
+    pub async fn connect(host: &str, port: u16, credentials: &Credentials) -> Result<Self> {
+        let config = Arc::new(client::Config::default());
+        let handler = SshHandler {
+            host_key: credentials.host_key.clone(),
+        };
+
+        let mut session = client::connect(config, (host, port), handler)
+            .await
+            .context(
+                "failed to connect to sftp server, fetch_profile_$name_host_key should be set as the fingerprint of its key",
+            )?;
+
+        let username = credentials
+            .username
+            .as_deref()
+            .ok_or_else(|| anyhow!("username of sftp server not found in url or fetch profile"))?;
+
+        let is_authenticated = match (&credentials.private_key, &credentials.password) {
+            (Some(private_key), _) => {
+                let key_pair = russh_keys::load_secret_key(private_key, None)
+                    .context("failed to load private key of fetch profile")?;
+
+                session
+                    .authenticate_publickey(username, Arc::new(key_pair))
+                    .await
+                    .context("failed to authenticate with private key")?
+            }
+            (None, Some(password)) => session
+                .authenticate_password(username, password)
+                .await
+                .context("failed to authenticate with password")?,
+            (None, None) => {
+                return Err(anyhow!(
+                    "password or private key of sftp server not found in fetch profile"
+                ))
+            }
+        };
+
+        if !is_authenticated {
+            return Err(anyhow!(
+                "sftp server rejected the credentials of {}",
+                username
+            ));
+        }
+
+        let channel = session
+            .channel_open_session()
+            .await
+            .context("failed to open ssh channel")?;
+
+        channel
+            .request_subsystem(true, "sftp")
+            .await
+            .context("failed to request sftp subsystem")?;
+
+        let sftp = SftpSession::new(channel.into_stream())
+            .await
+            .context("failed to create sftp session")?;
+
+        Ok(Self { session, sftp })
+    }
+
+    pub async fn stat(&mut self, path: &str) -> Result<RemoteEntry> {
+        let metadata = self
+            .sftp
+            .metadata(path)
+            .await
+            .context("file or directory not found on sftp server")
+            .context(path.to_string())?;
+
+        Ok(RemoteEntry {
+            name: get_name(path),
+            path: path.to_string(),
+            is_dir: metadata.is_dir(),
+            size: metadata.size.unwrap_or_default(),
+        })
+    }
+
+    pub async fn list(&mut self, path: &str) -> Result<Vec<RemoteEntry>> {
+        let read_dir = self
+            .sftp
+            .read_dir(path)
+            .await
+            .context("failed to list sftp directory")
+            .context(path.to_string())?;
+
+        let entries = read_dir
+            .filter(|entry| entry.file_name() != "." && entry.file_name() != "..")
+            .map(|entry| {
+                let metadata = entry.metadata();
+
+                RemoteEntry {
+                    path: format!("{}/{}", path.trim_end_matches('/'), entry.file_name()),
+                    name: entry.file_name(),
+                    is_dir: metadata.is_dir(),
+                    size: metadata.size.unwrap_or_default(),
+                }
+            })
+            .collect();
+
+        Ok(entries)
+    }
+
+    pub async fn open(&mut self, path: &str, offset: u64) -> Result<RemoteReader> {
+        let mut file = self
+            .sftp
+            .open(path)
+            .await
+            .context("failed to open file on sftp server")
+            .context(path.to_string())?;
+
+        if offset > 0 {
+            file.seek(SeekFrom::Start(offset))
+                .await
+                .context("failed to seek file on sftp server")?;
+        }
+
+        Ok(Box::new(file))
+    }
+
+    pub async fn close(self) {
+        let _ = self.sftp.close().await;
+        let _ = self
+            .session
+            .disconnect(russh::Disconnect::ByApplication, "", "")
+            .await;
+    }
+}
@@ -0,0 +1,211 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{get_name, Credentials, RemoteEntry, RemoteReader};
+use crate::{
+    error::ResultExt,
+    utils::{basic_auth_header, get_http_client_for_url, HttpRoute, RequestHeaders},
+};
+use anyhow::{anyhow, Context, Result};
+use futures::TryStreamExt;
+use percent_encoding::percent_decode_str;
+use regex::Regex;
+use reqwest::{header, Method, StatusCode};
+use std::io;
+use tokio_util::io::StreamReader;
+use url::Url;
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:"><d:prop><d:resourcetype/><d:getcontentlength/></d:prop></d:propfind>"#;
+
+pub struct WebDavClient {
+    // scheme, host and port of the server
+    base_url: Url,
+    http_client: reqwest::Client,
+}
+
+impl WebDavClient {
+    pub fn connect(url: &Url, credentials: &Credentials) -> Result<Self> {
+        let scheme = match url.scheme() {
+            "davs" | "webdavs" => "https",
+            _ => "http",
+        };
+
+        let host = url
+            .host_str()
+            .ok_or_else(|| anyhow!("host not found in url"))?;
+
+        let base_url = match url.port() {
+            Some(port) => format!("{}://{}:{}", scheme, host, port),
+            None => format!("{}://{}", scheme, host),
+        };
+        let base_url = Url::parse(&base_url).context("failed to parse webdav url")?;
+
+        let mut headers = RequestHeaders::new();
+
+        if let Some(username) = &credentials.username {
+            let password = credentials.password.as_deref().unwrap_or_default();
+
+            headers.push((
+                header::AUTHORIZATION.to_string(),
+                basic_auth_header(&format!("{}:{}", username, password)),
+            ));
+        }
+
+        let http_client = get_http_client_for_url(HttpRoute::Origin, base_url.as_str(), &headers)?;
+
+        Ok(Self::new(base_url, http_client))
+    }
+
+    pub const fn new(base_url: Url, http_client: reqwest::Client) -> Self {
+        Self {
+            base_url,
+            http_client,
+        }
+    }
+
+    fn url(&self, path: &str) -> Result<Url> {
+        self.base_url
+            .join(path)
+            .context("failed to join webdav path")
+            .context(path.to_string())
+    }
+
+    async fn propfind(&self, path: &str, depth: u8) -> Result<Vec<RemoteEntry>> {
+        let response = self
+            .http_client
+            .request(
+                Method::from_bytes(b"PROPFIND").context("invalid http method")?,
+                self.url(path)?,
+            )
+            .header("Depth", depth.to_string())
+            .header(header::CONTENT_TYPE, "application/xml")
+            .body(PROPFIND_BODY)
+            .send()
+            .await
+            .map_err(|e| HttpRoute::Origin.map_err(e))
+            .context("failed to send propfind request")?;
+
+        match response.status() {
+            StatusCode::MULTI_STATUS => {}
+            StatusCode::UNAUTHORIZED => {
+                return Err(anyhow!(
+                    "webdav server rejected the credentials, check the fetch profile"
+                ))
+            }
+            StatusCode::NOT_FOUND => {
+                return Err(anyhow!(
+                    "file or directory not found on webdav server: {}",
+                    path
+                ))
+            }
+            status => return Err(anyhow!("unexpected webdav response status: {}", status)),
+        }
+
+        let body = response
+            .text()
+            .await
+            .context("failed to get propfind response")?;
+
+        Ok(parse_multistatus(&body))
+    }
+
+    pub async fn stat(&mut self, path: &str) -> Result<RemoteEntry> {
+        self.propfind(path, 0)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("empty propfind response of {}", path))
+    }
+
+    pub async fn list(&mut self, path: &str) -> Result<Vec<RemoteEntry>> {
+        let dir = format!("{}/", path.trim_end_matches('/'));
+
+        // the first response is the directory itself
+        let entries = self
+            .propfind(&dir, 1)
+            .await?
+            .into_iter()
+            .filter(|entry| entry.path.trim_end_matches('/') != dir.trim_end_matches('/'))
+            .collect();
+
+        Ok(entries)
+    }
+
+    pub async fn open(&mut self, path: &str, offset: u64) -> Result<RemoteReader> {
+        let mut request = self.http_client.get(self.url(path)?);
+
+        if offset > 0 {
+            request = request.header(header::RANGE, format!("bytes={}-", offset));
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| HttpRoute::Origin.map_err(e))
+            .context("failed to send request for webdav file")?
+            .error_for_status()
+            .context("failed to get webdav file")?;
+
+        if offset > 0 && response.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(anyhow!("webdav server does not support resuming"));
+        }
+
+        let stream = response.bytes_stream().map_err(io::Error::other);
+
+        Ok(Box::new(StreamReader::new(stream)))
+    }
+}
+
+// namespace prefixes differ between servers, e.g. d: for nextcloud and D: for apache
+fn parse_multistatus(body: &str) -> Vec<RemoteEntry> {
+    let tag = |name: &str| {
+        let pattern = format!(r"(?s)<(?:\w+:)?{0}(?:\s[^>]*)?>(.*?)</(?:\w+:)?{0}>", name);
+
+        Regex::new(&pattern)
+            .context("invalid regex pattern")
+            .context(pattern)
+            .unwrap_or_trace()
+    };
+
+    let response_re = tag("response");
+    let href_re = tag("href");
+    let length_re = tag("getcontentlength");
+
+    let pattern = r"<(?:\w+:)?collection\s*/>";
+    let collection_re = Regex::new(pattern)
+        .context("invalid regex pattern")
+        .context(pattern)
+        .unwrap_or_trace();
+
+    response_re
+        .captures_iter(body)
+        .filter_map(|response| {
+            let response = response.get(1)?.as_str();
+
+            // href may be a full url or an absolute path
+            let href = href_re.captures(response)?.get(1)?.as_str().trim();
+            let path =
+                Url::parse(href).map_or_else(|_| href.to_string(), |url| url.path().to_string());
+
+            let size = length_re
+                .captures(response)
+                .and_then(|cap| cap.get(1))
+                .and_then(|length| length.as_str().trim().parse::<u64>().ok())
+                .unwrap_or_default();
+
+            Some(RemoteEntry {
+                name: percent_decode_str(&get_name(&path))
+                    .decode_utf8_lossy()
+                    .to_string(),
+                is_dir: collection_re.is_match(response),
+                path,
+                size,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,246 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{session::save_session, TelegramClient};
+use crate::{
+    auth_server::{TelegramAuthChannel, TelegramPrompt},
+    env::{Env, TelegramLoginMethod, TelegramUserEnv, ENV},
+    message::TelegramMessage,
+    utils::get_current_timestamp,
+};
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use grammers_client::{grammers_tl_types as tl, types::PasswordToken, SignInError};
+use std::time::Duration;
+
+// telegram accepts the token once it's scanned, then exporting returns success
+const QR_CODE_POLL_INTERVAL: Duration = Duration::from_secs(3);
+// a new qr code is shown a bit before the current one expires
+const QR_CODE_REFRESH_MARGIN_SECS: i64 = 5;
+
+impl TelegramClient {
+    pub async fn login(
+        &self,
+        message: TelegramMessage,
+        channel: Option<TelegramAuthChannel>,
+    ) -> Result<()> {
+        if self.is_authorized().await? {
+            return Ok(());
+        }
+
+        let Env {
+            telegram_user:
+                TelegramUserEnv {
+                    login_method,
+                    session_path,
+                    ..
+                },
+            ..
+        } = ENV.get().unwrap();
+
+        let mut channel =
+            channel.ok_or_else(|| anyhow!("auth server is required by telegram login"))?;
+
+        match login_method {
+            TelegramLoginMethod::Code => self.login_with_code(&message, &mut channel).await?,
+            TelegramLoginMethod::QrCode => self.login_with_qr_code(&message, &mut channel).await?,
+        }
+
+        channel.prompt.send_replace(TelegramPrompt::Done);
+
+        save_session(self.raw().session(), session_path)
+            .context("failed to save session for telegram user client")?;
+
+        Ok(())
+    }
+
+    async fn login_with_code(
+        &self,
+        message: &TelegramMessage,
+        channel: &mut TelegramAuthChannel,
+    ) -> Result<()> {
+        let Env {
+            telegram_user: TelegramUserEnv { phone_number, .. },
+            ..
+        } = ENV.get().unwrap();
+
+        let phone_number = phone_number
+            .as_ref()
+            .ok_or_else(|| anyhow!("tg_user_phone is required by telegram code login"))?;
+
+        let client = self.raw();
+
+        let response = "Sending telegram login code...\nThis may take a while.";
+        message.respond(response).await.context(response)?;
+
+        let token = client
+            .request_login_code(phone_number)
+            .await
+            .context("failed to request telegram user login code")?;
+
+        channel.prompt.send_replace(TelegramPrompt::Code);
+
+        let response = format!(
+            "Please visit {} to input your code to login to Telegram.",
+            channel.url
+        );
+        message.respond(response.as_str()).await.context(response)?;
+
+        loop {
+            let code = channel
+                .rx
+                .recv()
+                .await
+                .ok_or_else(|| anyhow!("failed to receive telegram code"))?;
+
+            let response = "Code received, logining...";
+            message.respond(response).await.context(response)?;
+
+            match client.sign_in(&token, &code).await {
+                Ok(_) => return Ok(()),
+                Err(SignInError::PasswordRequired(password_token)) => {
+                    return self.check_password(message, channel, password_token).await;
+                }
+                Err(SignInError::InvalidCode) => {
+                    message.respond("Code invalid, please input again.").await?;
+                }
+                Err(e) => {
+                    return Err(e).context("failed to sign in telegram user");
+                }
+            };
+        }
+    }
+
+    async fn login_with_qr_code(
+        &self,
+        message: &TelegramMessage,
+        channel: &mut TelegramAuthChannel,
+    ) -> Result<()> {
+        let Env {
+            telegram_user: TelegramUserEnv {
+                api_id, api_hash, ..
+            },
+            ..
+        } = ENV.get().unwrap();
+
+        let client = self.raw();
+
+        let response = format!(
+            "Please visit {} and scan the QR code with Telegram on your phone, in Settings > Devices > Link Desktop Device.",
+            channel.url
+        );
+        message.respond(response.as_str()).await.context(response)?;
+
+        let mut shown_expires = 0;
+
+        loop {
+            let result = client
+                .invoke(&tl::functions::auth::ExportLoginToken {
+                    api_id: *api_id,
+                    api_hash: api_hash.clone(),
+                    except_ids: Vec::new(),
+                })
+                .await;
+
+            match result {
+                Ok(tl::enums::auth::LoginToken::Token(token)) => {
+                    // every export returns a new token, keep the shown one until it's about to expire
+                    if get_current_timestamp() + QR_CODE_REFRESH_MARGIN_SECS
+                        >= i64::from(shown_expires)
+                    {
+                        shown_expires = token.expires;
+
+                        channel.prompt.send_replace(TelegramPrompt::QrCode {
+                            url: format!(
+                                "tg://login?token={}",
+                                URL_SAFE_NO_PAD.encode(token.token)
+                            ),
+                        });
+
+                        tracing::debug!("telegram login qr code refreshed");
+                    }
+                }
+                Ok(tl::enums::auth::LoginToken::Success(_)) => return Ok(()),
+                Ok(tl::enums::auth::LoginToken::MigrateTo(migrate_to)) => {
+                    return Err(anyhow!(
+                        "telegram account belongs to dc {}, which qr code login can't switch to, please use code login",
+                        migrate_to.dc_id
+                    ));
+                }
+                Err(e) if e.is("SESSION_PASSWORD_NEEDED") => {
+                    let password_token = client
+                        .get_password_information()
+                        .await
+                        .context("failed to get telegram user 2FA information")?;
+
+                    return self.check_password(message, channel, password_token).await;
+                }
+                Err(e) => return Err(e).context("failed to export telegram login token"),
+            }
+
+            tokio::time::sleep(QR_CODE_POLL_INTERVAL).await;
+        }
+    }
+
+    // the password is asked on the auth page if tg_user_password is not set
+    async fn check_password(
+        &self,
+        message: &TelegramMessage,
+        channel: &mut TelegramAuthChannel,
+        mut password_token: PasswordToken,
+    ) -> Result<()> {
+        let Env {
+            telegram_user: TelegramUserEnv { password, .. },
+            ..
+        } = ENV.get().unwrap();
+
+        let client = self.raw();
+
+        if let Some(password) = password {
+            client
+                .check_password(password_token, password)
+                .await
+                .context("failed to pass telegram user 2FA")?;
+
+            return Ok(());
+        }
+
+        let response = format!(
+            "Telegram 2FA password required, please visit {} to input it.",
+            channel.url
+        );
+        message.respond(response.as_str()).await.context(response)?;
+
+        loop {
+            channel.prompt.send_replace(TelegramPrompt::Password {
+                hint: password_token.hint().map(ToString::to_string),
+            });
+
+            let password = channel
+                .rx
+                .recv()
+                .await
+                .ok_or_else(|| anyhow!("failed to receive telegram 2FA password"))?;
+
+            match client.check_password(password_token, password).await {
+                Ok(_) => return Ok(()),
+                Err(SignInError::InvalidPassword) => {
+                    message
+                        .respond("Password invalid, please input again.")
+                        .await?;
+
+                    // a token can only be checked once
+                    password_token = client
+                        .get_password_information()
+                        .await
+                        .context("failed to get telegram user 2FA information")?;
+                }
+                Err(e) => return Err(e).context("failed to pass telegram user 2FA"),
+            }
+        }
+    }
+}
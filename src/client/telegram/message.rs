@@ -9,6 +9,7 @@ use super::TelegramClient;
 use crate::{
     error::ResultExt,
     message::{ChatEntity, QueuedMessage, QueuedMessageType, TelegramMessage},
+    metrics::METRICS,
 };
 use anyhow::{anyhow, Context, Result};
 use grammers_client::{
@@ -222,6 +223,10 @@ impl TelegramClient {
                             }
                         };
 
+                        if let Err(e) = &message_result {
+                            METRICS.observe_telegram_error(e);
+                        }
+
                         tx.send(message_result)
                             .await
                             .context("failed to send message result to rx")
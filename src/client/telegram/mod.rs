@@ -6,17 +6,18 @@
 */
 
 mod file;
+mod login;
 mod message;
+mod session;
 
-use crate::{
-    env::{Env, TelegramBotEnv, TelegramUserEnv, ENV},
-    message::TelegramMessage,
-};
-use anyhow::{anyhow, Context, Result};
-use grammers_client::{session::Session, Client, Config, SignInError};
+use crate::env::{redact_proxy_url, Env, TelegramBotEnv, TelegramUserEnv, ENV};
+use anyhow::{Context, Result};
+use grammers_client::{Client, Config};
 use message::ChatMessageVecDeque;
+pub use session::rotate_session_key;
+use session::{load_session, save_session};
 use std::sync::Arc;
-use tokio::sync::{mpsc::Receiver, Mutex};
+use tokio::sync::Mutex;
 
 // messages to be sent or edited in each chat
 type ChatMessageQueue = Arc<Mutex<ChatMessageVecDeque>>;
@@ -47,7 +48,7 @@ impl TelegramClient {
             ..
         } = ENV.get().unwrap();
 
-        let session = Session::load_file_or_create(session_path)
+        let session = load_session(session_path)
             .context("failed to load or create session for telegram bot client")?;
 
         let config = Config {
@@ -57,7 +58,7 @@ impl TelegramClient {
             params: params.clone(),
         };
 
-        let client = Client::connect(config)
+        let client = Self::connect(config)
             .await
             .context("failed to create telegram bot client")?;
 
@@ -72,9 +73,7 @@ impl TelegramClient {
                 .await
                 .context("failed to sign in telegram bot")?;
 
-            client
-                .session()
-                .save_to_file(session_path)
+            save_session(client.session(), session_path)
                 .context("failed to save session for telegram bot client")?;
         }
 
@@ -101,7 +100,7 @@ impl TelegramClient {
             ..
         } = ENV.get().unwrap();
 
-        let session = Session::load_file_or_create(session_path)
+        let session = load_session(session_path)
             .context("failed to load or create session for telegram user client")?;
 
         let config = Config {
@@ -111,7 +110,7 @@ impl TelegramClient {
             params: params.clone(),
         };
 
-        let client = Client::connect(config)
+        let client = Self::connect(config)
             .await
             .context("failed to create telegram user client")?;
 
@@ -125,6 +124,18 @@ impl TelegramClient {
         Ok(telegram_client)
     }
 
+    async fn connect(config: Config) -> Result<Client> {
+        let result = Client::connect(config).await;
+
+        match &ENV.get().unwrap().proxy.telegram {
+            Some(proxy) => result.context(format!(
+                "failed to connect to telegram through proxy {}, check tg_proxy",
+                redact_proxy_url(proxy)
+            )),
+            None => result.context("failed to connect to telegram"),
+        }
+    }
+
     pub const fn raw(&self) -> &Client {
         match self {
             Self::Bot { client, .. } | Self::User { client, .. } => client,
@@ -142,78 +153,6 @@ impl TelegramClient {
         }
     }
 
-    pub async fn login(&self, message: TelegramMessage, mut rx: Receiver<String>) -> Result<()> {
-        if !self.is_authorized().await? {
-            let Env {
-                telegram_user:
-                    TelegramUserEnv {
-                        phone_number,
-                        password,
-                        session_path,
-                        ..
-                    },
-                server_uri,
-                ..
-            } = ENV.get().unwrap();
-
-            let client = self.raw();
-
-            let response = "Sending telegram login code...\nThis may take a while.";
-            message.respond(response).await.context(response)?;
-
-            let token = client
-                .request_login_code(phone_number)
-                .await
-                .context("failed to request telegram user login code")?;
-
-            let response = format!(
-                "Please visit {} to input your code to login to Telegram.",
-                server_uri
-            );
-            message.respond(response.as_str()).await.context(response)?;
-
-            loop {
-                let code = rx
-                    .recv()
-                    .await
-                    .ok_or_else(|| anyhow!("failed to receive telegram code"))?;
-
-                let response = "Code received, logining...";
-                message.respond(response).await.context(response)?;
-
-                match client.sign_in(&token, &code).await {
-                    Ok(_) => {
-                        break;
-                    }
-                    Err(SignInError::PasswordRequired(password_token)) => match password {
-                        Some(password) => {
-                            client
-                                .check_password(password_token, password)
-                                .await
-                                .context("failed to pass telegram user 2FA")?;
-
-                            break;
-                        }
-                        None => Err(anyhow!("password for telegram user 2FA required"))?,
-                    },
-                    Err(SignInError::InvalidCode) => {
-                        message.respond("Code invalid, please input again.").await?;
-                    }
-                    Err(e) => {
-                        return Err(e).context("failed to sign in telegram user");
-                    }
-                };
-            }
-
-            client
-                .session()
-                .save_to_file(session_path)
-                .context("failed to save session for telegram user client")?;
-        }
-
-        Ok(())
-    }
-
     pub async fn is_authorized(&self) -> Result<bool> {
         self.raw()
             .is_authorized()
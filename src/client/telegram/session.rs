@@ -0,0 +1,94 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::crypto::{self, session_cipher, Cipher};
+use anyhow::{Context, Result};
+use grammers_client::session::Session;
+use std::{fs, path::Path};
+
+// the session is encrypted with session_key if it's set, a plain text session is encrypted when loaded
+pub fn load_session(path: &str) -> Result<Session> {
+    if !Path::new(path).exists() {
+        return Ok(Session::new());
+    }
+
+    let data = fs::read(path).context("failed to read telegram session")?;
+
+    let session = Session::load(&crypto::open(session_cipher(), &data)?)
+        .context("failed to parse telegram session")?;
+
+    if session_cipher().is_some() && !crypto::is_encrypted(&data) {
+        tracing::info!("encrypt plain text telegram session {}", path);
+
+        save_session(&session, path)?;
+    }
+
+    Ok(session)
+}
+
+pub fn save_session(session: &Session, path: &str) -> Result<()> {
+    write_session(path, &crypto::seal(session_cipher(), &session.save())?)
+}
+
+// returns false if there is no session
+pub fn rotate_session_key(path: &str, old: Option<&Cipher>, new: &Cipher) -> Result<bool> {
+    if !Path::new(path).exists() {
+        return Ok(false);
+    }
+
+    let data = fs::read(path).context("failed to read telegram session")?;
+
+    let data = match crypto::open(old, &data) {
+        Ok(data) => data,
+        // already encrypted with the new key if the last rotation was interrupted
+        Err(e) => crypto::open(Some(new), &data).map_err(|_| e)?,
+    };
+
+    write_session(path, &new.encrypt(&data)?)?;
+
+    Ok(true)
+}
+
+// written to a temporary file first, so the session isn't lost if the bot stops halfway
+fn write_session(path: &str, data: &[u8]) -> Result<()> {
+    let temp_path = format!("{}.tmp", path);
+
+    fs::write(&temp_path, data).context("failed to write telegram session")?;
+    fs::rename(&temp_path, path).context("failed to replace telegram session")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TempPath;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    fn new_cipher() -> Cipher {
+        Cipher::new(&STANDARD.encode(rand::random::<[u8; 32]>())).unwrap()
+    }
+
+    #[test]
+    fn test_rotate_session_key() {
+        let path = TempPath::new("telegram-session");
+        let path = path.as_str();
+        let new = new_cipher();
+
+        assert!(!rotate_session_key(path, None, &new).unwrap());
+
+        // a plain text session, then an interrupted rotation
+        fs::write(path, b"session").unwrap();
+        for _ in 0..2 {
+            assert!(rotate_session_key(path, None, &new).unwrap());
+            assert_eq!(new.decrypt(&fs::read(path).unwrap()).unwrap(), b"session");
+        }
+
+        // the error of the old key is kept when neither key fits
+        let old = new_cipher();
+        let e = rotate_session_key(path, Some(&old), &new_cipher()).unwrap_err();
+        assert!(format!("{:#}", e).contains("session_key may be wrong"));
+    }
+}
@@ -11,3 +11,15 @@ use grammers_client::types::PackedChat;
 pub fn chat_from_hex(chat_hex: &str) -> Result<PackedChat> {
     PackedChat::from_hex(chat_hex).context("failed to parse chat hex to packed chat")
 }
+
+// messages in private chats have no links
+pub fn format_message_link(chat: PackedChat, message_id: i32, filename: &str) -> String {
+    if chat.is_user() {
+        filename.to_string()
+    } else {
+        format!(
+            "<a href=\"https://t.me/c/{}/{}\">{}</a>",
+            chat.id, message_id, filename
+        )
+    }
+}
@@ -0,0 +1,174 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::{env::ENV, error::ResultExt};
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{aead::Aead, ChaCha20Poly1305, Key, KeyInit, Nonce};
+use std::sync::OnceLock;
+
+// marks encrypted session files, plain text sessions are migrated when they are loaded
+const MAGIC: &[u8] = b"TGODENC1";
+// marks encrypted values in session databases
+const TEXT_PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+static SESSION_CIPHER: OnceLock<Option<Cipher>> = OnceLock::new();
+
+pub struct Cipher {
+    cipher: ChaCha20Poly1305,
+}
+
+impl Cipher {
+    // the key is 32 random bytes encoded in base64, like the output of `openssl rand -base64 32`
+    pub fn new(key: &str) -> Result<Self> {
+        let key = STANDARD
+            .decode(key.trim())
+            .context("session key is not base64")?;
+
+        if key.len() != KEY_LEN {
+            return Err(anyhow!(
+                "session key should be {} bytes, got {} bytes",
+                KEY_LEN,
+                key.len()
+            ));
+        }
+
+        Ok(Self {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&key)),
+        })
+    }
+
+    // magic + nonce + ciphertext
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let nonce = rand::random::<[u8; NONCE_LEN]>();
+
+        let ciphertext = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .map_err(|_| anyhow!("failed to encrypt session"))?;
+
+        Ok([MAGIC, nonce.as_slice(), ciphertext.as_slice()].concat())
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let data = data
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| anyhow!("session is not encrypted"))?;
+
+        if data.len() < NONCE_LEN {
+            return Err(anyhow!("encrypted session is truncated"));
+        }
+
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("failed to decrypt session, session_key may be wrong"))
+    }
+
+    pub fn encrypt_text(&self, plaintext: &str) -> Result<String> {
+        let data = self.encrypt(plaintext.as_bytes())?;
+
+        Ok(format!("{}{}", TEXT_PREFIX, STANDARD.encode(data)))
+    }
+
+    pub fn decrypt_text(&self, text: &str) -> Result<String> {
+        let encoded = text
+            .strip_prefix(TEXT_PREFIX)
+            .ok_or_else(|| anyhow!("session value is not encrypted"))?;
+
+        let data = STANDARD
+            .decode(encoded)
+            .context("failed to decode encrypted session value")?;
+
+        String::from_utf8(self.decrypt(&data)?).context("decrypted session value is not utf-8")
+    }
+}
+
+// none if session_key is not set
+pub fn session_cipher() -> Option<&'static Cipher> {
+    SESSION_CIPHER
+        .get_or_init(|| {
+            ENV.get().unwrap().encryption.key.as_deref().map(|key| {
+                Cipher::new(key)
+                    .context("invalid session_key")
+                    .unwrap_or_trace()
+            })
+        })
+        .as_ref()
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+pub fn is_encrypted_text(text: &str) -> bool {
+    text.starts_with(TEXT_PREFIX)
+}
+
+// plain text is returned as is, so sessions saved before session_key was set can be migrated
+pub fn open(cipher: Option<&Cipher>, data: &[u8]) -> Result<Vec<u8>> {
+    match (cipher, is_encrypted(data)) {
+        (Some(cipher), true) => cipher.decrypt(data),
+        (None, true) => Err(anyhow!("session is encrypted but session_key is not set")),
+        (_, false) => Ok(data.to_vec()),
+    }
+}
+
+pub fn seal(cipher: Option<&Cipher>, data: &[u8]) -> Result<Vec<u8>> {
+    cipher.map_or_else(|| Ok(data.to_vec()), |cipher| cipher.encrypt(data))
+}
+
+pub fn open_text(cipher: Option<&Cipher>, text: &str) -> Result<String> {
+    match (cipher, is_encrypted_text(text)) {
+        (Some(cipher), true) => cipher.decrypt_text(text),
+        (None, true) => Err(anyhow!("session is encrypted but session_key is not set")),
+        (_, false) => Ok(text.to_string()),
+    }
+}
+
+pub fn seal_text(cipher: Option<&Cipher>, text: &str) -> Result<String> {
+    cipher.map_or_else(|| Ok(text.to_string()), |cipher| cipher.encrypt_text(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_key() -> String {
+        STANDARD.encode(rand::random::<[u8; KEY_LEN]>())
+    }
+
+    #[test]
+    fn test_key() {
+        assert!(Cipher::new(&new_key()).is_ok());
+        assert!(Cipher::new("passphrase").is_err());
+        assert!(Cipher::new(&STANDARD.encode([0u8; 16])).is_err());
+    }
+
+    #[test]
+    fn test_cipher() {
+        let cipher = Cipher::new(&new_key()).unwrap();
+
+        let data = cipher.encrypt(b"session").unwrap();
+        assert!(is_encrypted(&data));
+        assert_eq!(open(Some(&cipher), &data).unwrap(), b"session");
+        assert!(open(None, &data).is_err());
+        assert!(Cipher::new(&new_key()).unwrap().decrypt(&data).is_err());
+
+        let text = seal_text(Some(&cipher), "token").unwrap();
+        assert!(is_encrypted_text(&text));
+        assert_ne!(text, seal_text(Some(&cipher), "token").unwrap());
+        assert_eq!(open_text(Some(&cipher), &text).unwrap(), "token");
+
+        // plain text sessions are read as is
+        assert_eq!(open(Some(&cipher), b"session").unwrap(), b"session");
+        assert_eq!(open_text(Some(&cipher), "token").unwrap(), "token");
+    }
+}
@@ -0,0 +1,40 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    utils::{exit_on_error, get_env_value},
+    var::ACL_PATH,
+};
+use anyhow::Context;
+
+pub struct AclEnv {
+    // telegram user ids that are always admins, they enable the acl
+    pub admin_ids: Vec<i64>,
+    // users added by /users
+    pub path: String,
+}
+
+impl AclEnv {
+    pub fn new() -> Self {
+        let admin_ids = get_env_value::<String>("tg_admin_ids")
+            .map(|ids| {
+                ids.split(',')
+                    .map(str::trim)
+                    .filter(|id| !id.is_empty())
+                    .map(|id| {
+                        id.parse()
+                            .context(format!("invalid user id {} in tg_admin_ids", id))
+                            .unwrap_or_else(|e| exit_on_error(&e))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let path = ACL_PATH.to_string();
+
+        Self { admin_ids, path }
+    }
+}
@@ -0,0 +1,36 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{utils::get_env_value, var::API_NOTIFY_PATH};
+
+pub struct ApiEnv {
+    // bearer tokens separated by commas, the http api is disabled if not set
+    pub tokens: Vec<String>,
+    // where the chat bound by /api notify is saved
+    pub notify_path: String,
+}
+
+impl ApiEnv {
+    pub fn new() -> Self {
+        let tokens = get_env_value::<String>("api_tokens")
+            .map(|tokens| {
+                tokens
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|token| !token.is_empty())
+                    .map(ToString::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let notify_path = API_NOTIFY_PATH.to_string();
+
+        Self {
+            tokens,
+            notify_path,
+        }
+    }
+}
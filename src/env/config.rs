@@ -0,0 +1,586 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    export::GeoFormat,
+    note::NoteFormat,
+    onedrive::{OneDriveAuthFlow, OneDriveCloud},
+    quota::ByteSize,
+    storage::StorageKind,
+    telegram_user::TelegramLoginMethod,
+    utils::{exit_on_error, get_env_value},
+    var::CONFIG_PATH,
+};
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::BTreeMap, fs, path::Path, str::FromStr, sync::RwLock};
+use tracing_subscriber::EnvFilter;
+
+// flattened values of the config file, read by env getters before env vars
+static FILE_VALUES: RwLock<BTreeMap<String, String>> = RwLock::new(BTreeMap::new());
+static CHATS: RwLock<BTreeMap<i64, ChatConfig>> = RwLock::new(BTreeMap::new());
+static QUOTAS: RwLock<BTreeMap<i64, QuotaConfig>> = RwLock::new(BTreeMap::new());
+
+// larger weights leave too few turns to tell them apart
+const MAX_WEIGHT: u64 = 100;
+
+// values of these keys are masked in traces
+const SECRET_KEYS: [&str; 10] = [
+    "tg_bot_token",
+    "tg_api_hash",
+    "tg_user_password",
+    "od_client_secret",
+    "dashboard_password",
+    "webhook_secret",
+    "storage_s3_access_key",
+    "storage_s3_secret_key",
+    "session_key",
+    "session_key_new",
+];
+
+pub struct ConfigEnv {
+    // none if there is no config file
+    pub path: Option<String>,
+}
+
+impl ConfigEnv {
+    // must be read before other env
+    pub fn new() -> Self {
+        let path = get_env_value::<String>("config_path");
+
+        // the default file is optional
+        let path = match path {
+            Ok(path) => path,
+            Err(_) if Path::new(CONFIG_PATH).exists() => CONFIG_PATH.to_string(),
+            Err(_) => return Self { path: None },
+        };
+
+        let config = ConfigFile::load(&path).unwrap_or_else(|e| exit_on_error(&e));
+        apply_config_file(&config);
+
+        Self { path: Some(path) }
+    }
+}
+
+pub fn get_file_value(name: &str) -> Option<String> {
+    FILE_VALUES.read().unwrap().get(name).cloned()
+}
+
+// values of secrets in the file, including ones that take effect after restart
+pub fn get_file_secrets() -> Vec<String> {
+    FILE_VALUES
+        .read()
+        .unwrap()
+        .iter()
+        .flat_map(|(key, value)| {
+            if key == "api_tokens" {
+                value
+                    .split(',')
+                    .map(|token| token.trim().to_string())
+                    .collect()
+            } else if SECRET_KEYS.contains(&key.as_str())
+                || (key.starts_with("fetch_profile_") && key.ends_with("_password"))
+                || (key.starts_with("url_profile_") && key.ends_with("_cookie"))
+            {
+                vec![value.clone()]
+            } else {
+                Vec::new()
+            }
+        })
+        .collect()
+}
+
+pub fn get_chat_config(chat_id: i64) -> ChatConfig {
+    CHATS
+        .read()
+        .unwrap()
+        .get(&chat_id)
+        .cloned()
+        .unwrap_or_default()
+}
+
+pub fn get_quota_config(user_id: i64) -> QuotaConfig {
+    QUOTAS
+        .read()
+        .unwrap()
+        .get(&user_id)
+        .cloned()
+        .unwrap_or_default()
+}
+
+// returns the changed keys, chats and quotas are reported as a whole
+pub fn apply_config_file(config: &ConfigFile) -> Vec<String> {
+    let values = config.values();
+    let chats = parse_id_keys(&config.chats);
+    let quotas = parse_id_keys(&config.quotas);
+
+    let mut file_values = FILE_VALUES.write().unwrap();
+
+    let mut changed_keys = file_values
+        .iter()
+        .filter(|(key, value)| values.get(*key) != Some(value))
+        .chain(
+            values
+                .iter()
+                .filter(|(key, _)| !file_values.contains_key(*key)),
+        )
+        .map(|(key, _)| key.clone())
+        .collect::<Vec<_>>();
+
+    *file_values = values;
+    drop(file_values);
+
+    let mut chats_guard = CHATS.write().unwrap();
+    if *chats_guard != chats {
+        changed_keys.push("chats".to_string());
+
+        *chats_guard = chats;
+    }
+    drop(chats_guard);
+
+    let mut quotas_guard = QUOTAS.write().unwrap();
+    if *quotas_guard != quotas {
+        changed_keys.push("quotas".to_string());
+
+        *quotas_guard = quotas;
+    }
+
+    changed_keys
+}
+
+// a single string or a list, lists are joined by commas like in env vars
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+enum StringList {
+    One(String),
+    Many(Vec<String>),
+}
+
+// bytes, or a size with a unit like 10G
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Size {
+    Bytes(u64),
+    Text(String),
+}
+
+impl Size {
+    pub fn bytes(&self) -> Result<u64> {
+        match self {
+            Self::Bytes(bytes) => Ok(*bytes),
+            Self::Text(text) => text
+                .parse::<ByteSize>()
+                .map(|size| size.0)
+                .map_err(|e| anyhow!(e)),
+        }
+    }
+}
+
+// keys are the same as env vars
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    trace_level: Option<String>,
+    worker_num: Option<u8>,
+    server_uri: Option<String>,
+    port: Option<u16>,
+    reverse_proxy: Option<bool>,
+    auto_delete: Option<bool>,
+    tg_bot_token: Option<String>,
+    tg_api_id: Option<i32>,
+    tg_api_hash: Option<String>,
+    tg_user_phone: Option<String>,
+    tg_user_password: Option<String>,
+    tg_user_name: Option<StringList>,
+    tg_admin_ids: Option<Vec<i64>>,
+    tg_user_login: Option<String>,
+    tg_proxy: Option<String>,
+    od_client_id: Option<String>,
+    od_client_secret: Option<String>,
+    od_root_path: Option<String>,
+    od_cloud: Option<String>,
+    od_authority_url: Option<String>,
+    od_graph_url: Option<String>,
+    od_tenant: Option<String>,
+    od_auth_flow: Option<String>,
+    od_proxy: Option<String>,
+    url_proxy: Option<String>,
+    proxy_bypass: Option<StringList>,
+    tls_ca_bundle: Option<String>,
+    tls_insecure_hosts: Option<StringList>,
+    http_connect_timeout: Option<u64>,
+    http_read_timeout: Option<u64>,
+    http_keepalive: Option<u64>,
+    http_pool_size: Option<usize>,
+    note_format: Option<String>,
+    note_forwarded: Option<bool>,
+    export_geo_format: Option<String>,
+    #[serde(default)]
+    url_profiles: BTreeMap<String, UrlProfileConfig>,
+    #[serde(default)]
+    url_resolvers: BTreeMap<String, UrlResolverConfig>,
+    #[serde(default)]
+    fetch_profiles: BTreeMap<String, FetchProfileConfig>,
+    storage_backend: Option<String>,
+    storage_local_root: Option<String>,
+    storage_s3_endpoint: Option<String>,
+    storage_s3_region: Option<String>,
+    storage_s3_bucket: Option<String>,
+    storage_s3_access_key: Option<String>,
+    storage_s3_secret_key: Option<String>,
+    storage_s3_path_style: Option<bool>,
+    dashboard_password: Option<String>,
+    dashboard_port: Option<u16>,
+    api_tokens: Option<StringList>,
+    webhook_urls: Option<StringList>,
+    webhook_secret: Option<String>,
+    session_key: Option<String>,
+    session_key_new: Option<String>,
+    quota_daily_bytes: Option<Size>,
+    quota_monthly_bytes: Option<Size>,
+    quota_daily_tasks: Option<u64>,
+    quota_monthly_tasks: Option<u64>,
+    quota_max_pending: Option<u64>,
+    quota_max_running: Option<u64>,
+    // keyed by chat id
+    #[serde(default)]
+    chats: BTreeMap<String, ChatConfig>,
+    // keyed by telegram user id, override the quota_* settings
+    #[serde(default)]
+    quotas: BTreeMap<String, QuotaConfig>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct UrlProfileConfig {
+    hosts: Option<StringList>,
+    headers: Option<BTreeMap<String, String>>,
+    cookie: Option<String>,
+    user_agent: Option<String>,
+    referer: Option<String>,
+    basic_auth: Option<String>,
+    bearer: Option<String>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct UrlResolverConfig {
+    pattern: Option<String>,
+    url: Option<String>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct FetchProfileConfig {
+    hosts: Option<StringList>,
+    username: Option<String>,
+    password: Option<String>,
+    private_key: Option<String>,
+    host_key: Option<String>,
+}
+
+// settings of a chat, reloaded with the file
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChatConfig {
+    // overrides /autoDelete in the chat
+    pub auto_delete: Option<bool>,
+    // share of workers against other chats, 1 by default
+    pub weight: Option<u64>,
+}
+
+// limits of a telegram user, 0 means unlimited, except for weight
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuotaConfig {
+    pub daily_bytes: Option<Size>,
+    pub monthly_bytes: Option<Size>,
+    pub daily_tasks: Option<u64>,
+    pub monthly_tasks: Option<u64>,
+    pub max_pending: Option<u64>,
+    pub max_running: Option<u64>,
+    // share of workers against other users, 1 by default
+    pub weight: Option<u64>,
+}
+
+impl ConfigFile {
+    pub fn load(path: &str) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .context("failed to read config file")
+            .context(path.to_string())?;
+
+        Self::parse(&content).context(format!("invalid config file {}", path))
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        let config = toml::from_str::<Self>(content)?;
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    // types are checked when parsing, values of enums and ranges are checked here
+    fn validate(&self) -> Result<()> {
+        if self.worker_num == Some(0) {
+            return Err(anyhow!("worker_num should be at least 1"));
+        }
+
+        if let Some(trace_level) = &self.trace_level {
+            EnvFilter::try_new(trace_level)
+                .context(format!("invalid value trace_level={}", trace_level))?;
+        }
+
+        check_value::<OneDriveAuthFlow>("od_auth_flow", self.od_auth_flow.as_deref())?;
+        check_value::<OneDriveCloud>("od_cloud", self.od_cloud.as_deref())?;
+        check_value::<TelegramLoginMethod>("tg_user_login", self.tg_user_login.as_deref())?;
+        check_value::<StorageKind>("storage_backend", self.storage_backend.as_deref())?;
+        check_value::<NoteFormat>("note_format", self.note_format.as_deref())?;
+        check_value::<GeoFormat>("export_geo_format", self.export_geo_format.as_deref())?;
+
+        for (name, resolver) in &self.url_resolvers {
+            if let Some(pattern) = &resolver.pattern {
+                Regex::new(pattern).context(format!("invalid pattern of url resolver {}", name))?;
+            }
+        }
+
+        for (chat_id, chat) in &self.chats {
+            chat_id
+                .parse::<i64>()
+                .context(format!("invalid chat id {} in chats", chat_id))?;
+
+            if chat
+                .weight
+                .is_some_and(|weight| !(1..=MAX_WEIGHT).contains(&weight))
+            {
+                return Err(anyhow!(
+                    "weight of chat {} should be between 1 and {}",
+                    chat_id,
+                    MAX_WEIGHT
+                ));
+            }
+        }
+
+        for size in [&self.quota_daily_bytes, &self.quota_monthly_bytes]
+            .into_iter()
+            .flatten()
+        {
+            size.bytes().context("invalid quota size")?;
+        }
+
+        for (user_id, quota) in &self.quotas {
+            user_id
+                .parse::<i64>()
+                .context(format!("invalid user id {} in quotas", user_id))?;
+
+            for size in [&quota.daily_bytes, &quota.monthly_bytes]
+                .into_iter()
+                .flatten()
+            {
+                size.bytes()
+                    .context(format!("invalid quota size of user {}", user_id))?;
+            }
+
+            if quota
+                .weight
+                .is_some_and(|weight| !(1..=MAX_WEIGHT).contains(&weight))
+            {
+                return Err(anyhow!(
+                    "weight of user {} should be between 1 and {}",
+                    user_id,
+                    MAX_WEIGHT
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // flattened like env vars, e.g. url_profiles.a.hosts -> url_profile_a_hosts
+    fn values(&self) -> BTreeMap<String, String> {
+        let mut values = BTreeMap::new();
+
+        let Ok(Value::Object(fields)) = serde_json::to_value(self) else {
+            return values;
+        };
+
+        for (key, value) in fields {
+            match (key.as_str(), value) {
+                ("chats" | "quotas", _) => {}
+                ("url_profiles" | "url_resolvers" | "fetch_profiles", Value::Object(profiles)) => {
+                    let prefix = key.trim_end_matches('s');
+
+                    if !profiles.is_empty() {
+                        let names = profiles.keys().cloned().collect::<Vec<_>>().join(",");
+                        values.insert(key.clone(), names);
+                    }
+
+                    for (name, profile) in profiles {
+                        let Value::Object(profile_fields) = profile else {
+                            continue;
+                        };
+
+                        for (field, value) in profile_fields {
+                            if let Some(value) = to_env_value(&value) {
+                                values.insert(format!("{}_{}_{}", prefix, name, field), value);
+                            }
+                        }
+                    }
+                }
+                (_, value) => {
+                    if let Some(value) = to_env_value(&value) {
+                        values.insert(key, value);
+                    }
+                }
+            }
+        }
+
+        values
+    }
+}
+
+// keys are checked when the file is validated
+fn parse_id_keys<T: Clone>(map: &BTreeMap<String, T>) -> BTreeMap<i64, T> {
+    map.iter()
+        .filter_map(|(id, value)| id.parse().ok().map(|id| (id, value.clone())))
+        .collect()
+}
+
+fn check_value<T>(name: &str, value: Option<&str>) -> Result<()>
+where
+    T: FromStr<Err = String>,
+{
+    if let Some(value) = value {
+        value
+            .parse::<T>()
+            .map_err(|e| anyhow!(e))
+            .context(format!("invalid value {}={}", name, value))?;
+    }
+
+    Ok(())
+}
+
+fn to_env_value(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(value) => Some(value.clone()),
+        Value::Array(items) => Some(
+            items
+                .iter()
+                .filter_map(to_env_value)
+                .collect::<Vec<_>>()
+                .join(","),
+        ),
+        // headers of url profiles are read as a json object
+        value => Some(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values() {
+        let config = ConfigFile::parse(
+            r#"
+            worker_num = 3
+            auto_delete = true
+            api_tokens = ["a", "b"]
+            quota_daily_bytes = "10G"
+            quota_max_running = 2
+
+            [url_profiles.example]
+            hosts = ["example.com", "*.example.org"]
+            headers = { "X-Api-Key" = "xxx" }
+
+            [url_resolvers.intranet]
+            pattern = '^https://files\.lan/share/(\w+)$'
+            url = "https://files.lan/download/$1"
+
+            [chats.100]
+            auto_delete = false
+            weight = 2
+
+            [quotas.200]
+            monthly_bytes = 1024
+            weight = 3
+            "#,
+        )
+        .unwrap();
+
+        let values = config.values();
+        assert_eq!(values["worker_num"], "3");
+        assert_eq!(values["auto_delete"], "true");
+        assert_eq!(values["api_tokens"], "a,b");
+        assert_eq!(values["url_profiles"], "example");
+        assert_eq!(
+            values["url_profile_example_hosts"],
+            "example.com,*.example.org"
+        );
+        assert_eq!(
+            values["url_profile_example_headers"],
+            r#"{"X-Api-Key":"xxx"}"#
+        );
+        assert_eq!(values["url_resolvers"], "intranet");
+        assert_eq!(
+            values["url_resolver_intranet_url"],
+            "https://files.lan/download/$1"
+        );
+        assert!(!values.contains_key("chats"));
+
+        assert_eq!(values["quota_daily_bytes"], "10G");
+        assert_eq!(values["quota_max_running"], "2");
+        assert!(!values.contains_key("quotas"));
+
+        assert_eq!(parse_id_keys(&config.chats)[&100].auto_delete, Some(false));
+        assert_eq!(parse_id_keys(&config.chats)[&100].weight, Some(2));
+        assert_eq!(
+            parse_id_keys(&config.quotas)[&200].monthly_bytes,
+            Some(Size::Bytes(1024))
+        );
+        assert_eq!(parse_id_keys(&config.quotas)[&200].weight, Some(3));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let e = ConfigFile::parse("worker_num = 300").unwrap_err();
+        assert!(format!("{:#}", e).contains("worker_num"));
+
+        let e = ConfigFile::parse("worker_num = 0").unwrap_err();
+        assert!(format!("{:#}", e).contains("worker_num"));
+
+        let e = ConfigFile::parse("od_auth_flow = \"password\"").unwrap_err();
+        assert!(format!("{:#}", e).contains("od_auth_flow=password"));
+
+        let e = ConfigFile::parse("unknown = 1").unwrap_err();
+        assert!(format!("{:#}", e).contains("unknown"));
+
+        let e = ConfigFile::parse("[url_resolvers.a]\npattern = \"(\"").unwrap_err();
+        assert!(format!("{:#}", e).contains("url resolver a"));
+
+        let e = ConfigFile::parse("[chats.abc]\nauto_delete = true").unwrap_err();
+        assert!(format!("{:#}", e).contains("abc"));
+
+        let e = ConfigFile::parse("[quotas.1]\ndaily_bytes = \"10X\"").unwrap_err();
+        assert!(format!("{:#}", e).contains("10X"));
+
+        let e = ConfigFile::parse("[chats.1]\nweight = 0").unwrap_err();
+        assert!(format!("{:#}", e).contains("weight"));
+
+        let e = ConfigFile::parse("[quotas.1]\nweight = 0").unwrap_err();
+        assert!(format!("{:#}", e).contains("weight"));
+
+        let e = ConfigFile::parse("[chats.1]\nweight = 101").unwrap_err();
+        assert!(format!("{:#}", e).contains("between 1 and 100"));
+
+        let e = ConfigFile::parse("[quotas.1]\nweight = 65537").unwrap_err();
+        assert!(format!("{:#}", e).contains("between 1 and 100"));
+    }
+}
@@ -0,0 +1,24 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::utils::{get_env_value, get_env_value_option};
+
+pub struct DashboardEnv {
+    // the dashboard is disabled if not set
+    pub password: Option<String>,
+    // port of the web server, which is always on, so it doesn't share the port of the auth server
+    pub port: u16,
+}
+
+impl DashboardEnv {
+    pub fn new() -> Self {
+        let password = get_env_value("dashboard_password").ok();
+        let port = get_env_value_option("dashboard_port", 8081);
+
+        Self { password, port }
+    }
+}
@@ -0,0 +1,24 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::utils::get_env_value;
+
+pub struct EncryptionEnv {
+    // master key of the telegram and onedrive sessions, sessions are stored in plain text if not set
+    pub key: Option<String>,
+    // only read by the rotate-key command
+    pub new_key: Option<String>,
+}
+
+impl EncryptionEnv {
+    pub fn new() -> Self {
+        let key = get_env_value("session_key").ok();
+        let new_key = get_env_value("session_key_new").ok();
+
+        Self { key, new_key }
+    }
+}
@@ -0,0 +1,39 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::utils::get_env_value_option;
+use std::str::FromStr;
+
+pub struct ExportEnv {
+    pub geo_format: GeoFormat,
+}
+
+impl ExportEnv {
+    pub fn new() -> Self {
+        let geo_format = get_env_value_option("export_geo_format", GeoFormat::GeoJson);
+
+        Self { geo_format }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum GeoFormat {
+    GeoJson,
+    Gpx,
+}
+
+impl FromStr for GeoFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "geojson" => Ok(Self::GeoJson),
+            "gpx" => Ok(Self::Gpx),
+            _ => Err(format!("unknown geo format: {}", s)),
+        }
+    }
+}
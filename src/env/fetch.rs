@@ -0,0 +1,74 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::utils::{get_env_value, get_env_value_option, host_matches};
+
+pub struct FetchEnv {
+    pub profiles: Vec<FetchProfile>,
+}
+
+impl FetchEnv {
+    pub fn new() -> Self {
+        let profiles = get_env_value_option("fetch_profiles", String::new())
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(FetchProfile::new)
+            .collect();
+
+        Self { profiles }
+    }
+
+    pub fn get_profile(&self, name: &str) -> Option<&FetchProfile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    pub fn match_profile(&self, host: &str) -> Option<&FetchProfile> {
+        self.profiles.iter().find(|profile| profile.matches(host))
+    }
+}
+
+// credentials of ftp, sftp and webdav servers, so that they are never sent in chat
+pub struct FetchProfile {
+    pub name: String,
+    pub hosts: Vec<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // path of the private key file, for sftp
+    pub private_key: Option<String>,
+    // sha256 fingerprint of the server key, for sftp
+    pub host_key: Option<String>,
+}
+
+impl FetchProfile {
+    fn new(name: &str) -> Self {
+        let prefix = format!("fetch_profile_{}", name);
+
+        let hosts = get_env_value_option(&format!("{}_hosts", prefix), String::new())
+            .split(',')
+            .map(|host| host.trim().to_lowercase())
+            .filter(|host| !host.is_empty())
+            .collect();
+        let username = get_env_value(&format!("{}_username", prefix)).ok();
+        let password = get_env_value(&format!("{}_password", prefix)).ok();
+        let private_key = get_env_value(&format!("{}_private_key", prefix)).ok();
+        let host_key = get_env_value(&format!("{}_host_key", prefix)).ok();
+
+        Self {
+            name: name.to_string(),
+            hosts,
+            username,
+            password,
+            private_key,
+            host_key,
+        }
+    }
+
+    pub fn matches(&self, host: &str) -> bool {
+        host_matches(&self.hosts, host)
+    }
+}
@@ -0,0 +1,63 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::utils::{exit_on_error, get_env_value, get_env_value_option, host_matches};
+use anyhow::Context;
+use reqwest::Certificate;
+use std::{fs, time::Duration};
+
+pub struct HttpEnv {
+    // trusted in addition to the system roots
+    pub ca_certs: Vec<Certificate>,
+    // self-signed origins whose certificates are not verified
+    pub insecure_hosts: Vec<String>,
+    pub connect_timeout: Duration,
+    pub read_timeout: Duration,
+    pub tcp_keepalive: Duration,
+    pub pool_max_idle_per_host: usize,
+}
+
+impl HttpEnv {
+    pub fn new() -> Self {
+        let ca_certs = get_env_value::<String>("tls_ca_bundle").map_or_else(
+            |_| Vec::new(),
+            |path| {
+                let pem_bundle = fs::read(&path)
+                    .context("failed to read tls ca bundle")
+                    .context(path.clone())
+                    .unwrap_or_else(|e| exit_on_error(&e));
+
+                Certificate::from_pem_bundle(&pem_bundle)
+                    .context("failed to parse tls ca bundle")
+                    .context(path)
+                    .unwrap_or_else(|e| exit_on_error(&e))
+            },
+        );
+        let insecure_hosts = get_env_value_option("tls_insecure_hosts", String::new())
+            .split(',')
+            .map(|host| host.trim().to_lowercase())
+            .filter(|host| !host.is_empty())
+            .collect();
+        let connect_timeout = Duration::from_secs(get_env_value_option("http_connect_timeout", 30));
+        let read_timeout = Duration::from_secs(get_env_value_option("http_read_timeout", 60));
+        let tcp_keepalive = Duration::from_secs(get_env_value_option("http_keepalive", 60));
+        let pool_max_idle_per_host = get_env_value_option("http_pool_size", 16);
+
+        Self {
+            ca_certs,
+            insecure_hosts,
+            connect_timeout,
+            read_timeout,
+            tcp_keepalive,
+            pool_max_idle_per_host,
+        }
+    }
+
+    pub fn is_insecure_host(&self, host: &str) -> bool {
+        host_matches(&self.insecure_hosts, host)
+    }
+}
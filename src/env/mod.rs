@@ -5,35 +5,79 @@
 :license: MIT, see LICENSE for more details.
 */
 
+mod acl;
+mod api;
+mod config;
+mod dashboard;
+mod encryption;
+mod export;
+mod fetch;
+mod http;
+mod note;
 mod onedrive;
+mod proxy;
+mod quota;
+mod storage;
 mod telegram_bot;
 mod telegram_user;
+mod url;
 mod utils;
 mod var;
+mod webhook;
 
+pub use acl::AclEnv;
 use anyhow::Context;
-pub use onedrive::OneDriveEnv;
+pub use api::ApiEnv;
+use config::ConfigEnv;
+pub use config::{
+    apply_config_file, get_chat_config, get_file_secrets, get_quota_config, ConfigFile,
+};
+pub use dashboard::DashboardEnv;
+pub use encryption::EncryptionEnv;
+pub use export::{ExportEnv, GeoFormat};
+pub use fetch::{FetchEnv, FetchProfile};
+pub use http::HttpEnv;
+pub use note::{NoteEnv, NoteFormat};
+pub use onedrive::{OneDriveAuthFlow, OneDriveEnv};
+pub use proxy::{redact_proxy_url, ProxyEnv};
+pub use quota::Quota;
 use std::{fs, sync::OnceLock};
+pub use storage::{S3Env, StorageEnv, StorageKind};
 pub use telegram_bot::TelegramBotEnv;
-pub use telegram_user::TelegramUserEnv;
-use utils::{get_env_value, get_env_value_option, get_env_value_option_legacy};
+pub use telegram_user::{TelegramLoginMethod, TelegramUserEnv};
+pub use url::{UrlEnv, UrlProfile, UrlRewrite};
+use utils::{exit_on_error, get_env_value, get_env_value_option, get_env_value_option_legacy};
 pub use var::LOGS_PATH;
 use var::SESSION_DIR;
-
-use crate::error::ResultExt;
+pub use webhook::WebhookEnv;
 
 pub static ENV: OnceLock<Env> = OnceLock::new();
 
 pub struct Env {
+    pub config: ConfigEnv,
     pub telegram_bot: TelegramBotEnv,
     pub telegram_user: TelegramUserEnv,
     pub onedrive: OneDriveEnv,
+    pub note: NoteEnv,
+    pub export: ExportEnv,
+    pub url: UrlEnv,
+    pub fetch: FetchEnv,
+    pub proxy: ProxyEnv,
+    pub http: HttpEnv,
+    pub storage: StorageEnv,
+    pub dashboard: DashboardEnv,
+    pub api: ApiEnv,
+    pub webhook: WebhookEnv,
+    pub encryption: EncryptionEnv,
+    pub acl: AclEnv,
     pub trace_level: String,
     pub port: u16,
     pub server_uri: String,
     pub use_reverse_proxy: bool,
     pub should_auto_delete: bool,
     pub tasker_session_path: String,
+    pub upload_spool_dir: String,
+    pub tasker_history_path: String,
     pub task_handler_num: u8,
 }
 
@@ -41,35 +85,80 @@ impl Env {
     pub fn new() -> Self {
         Self::init();
 
+        // values in the file are read by the getters below
+        let config = ConfigEnv::new();
         let telegram_bot = TelegramBotEnv::new();
         let telegram_user = TelegramUserEnv::new();
         let onedrive = OneDriveEnv::new();
-        let trace_level = get_env_value_option("trace_level", "info".to_string());
+        let note = NoteEnv::new();
+        let export = ExportEnv::new();
+        let url = UrlEnv::new();
+        let fetch = FetchEnv::new();
+        let proxy = ProxyEnv::new();
+        let http = HttpEnv::new();
+        let storage = StorageEnv::new();
+        let dashboard = DashboardEnv::new();
+        let api = ApiEnv::new();
+        let webhook = WebhookEnv::new();
+        let encryption = EncryptionEnv::new();
+        let acl = AclEnv::new();
+        // quotas are read on every check, invalid values stop the bot here instead
+        Quota::read(0);
+        let trace_level = Self::read_trace_level();
         let port = get_env_value_option("port", 8080);
-        let server_uri = get_env_value("server_uri").unwrap_or_trace();
+        let server_uri = get_env_value("server_uri").unwrap_or_else(|e| exit_on_error(&e));
         let use_reverse_proxy = get_env_value_option("reverse_proxy", false);
-        let should_auto_delete =
-            get_env_value_option_legacy(&["auto_delete", "delete_flag"], false);
+        let should_auto_delete = Self::read_should_auto_delete();
         let tasker_session_path = var::TASKER_SESSION_PATH.to_string();
-        let task_handler_num = get_env_value_option("worker_num", 5);
+        let upload_spool_dir = var::UPLOAD_SPOOL_DIR.to_string();
+        let tasker_history_path = var::TASKER_HISTORY_PATH.to_string();
+        let task_handler_num = Self::read_task_handler_num();
 
         Self {
+            config,
             telegram_bot,
             telegram_user,
             onedrive,
+            note,
+            export,
+            url,
+            fetch,
+            proxy,
+            http,
+            storage,
+            dashboard,
+            api,
+            webhook,
+            encryption,
+            acl,
             trace_level,
             port,
             server_uri,
             use_reverse_proxy,
             should_auto_delete,
             tasker_session_path,
+            upload_spool_dir,
+            tasker_history_path,
             task_handler_num,
         }
     }
 
+    // settings below are read again when the config file is reloaded
+    pub fn read_trace_level() -> String {
+        get_env_value_option("trace_level", "info".to_string())
+    }
+
+    pub fn read_should_auto_delete() -> bool {
+        get_env_value_option_legacy(&["auto_delete", "delete_flag"], false)
+    }
+
+    pub fn read_task_handler_num() -> u8 {
+        get_env_value_option::<u8>("worker_num", 5).max(1)
+    }
+
     fn init() {
         fs::create_dir_all(SESSION_DIR)
             .context("failed to create session dir")
-            .unwrap_or_trace();
+            .unwrap_or_else(|e| exit_on_error(&e));
     }
 }
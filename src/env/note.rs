@@ -0,0 +1,44 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::utils::get_env_value_option;
+use std::str::FromStr;
+
+pub struct NoteEnv {
+    pub format: NoteFormat,
+    pub should_archive_forwarded: bool,
+}
+
+impl NoteEnv {
+    pub fn new() -> Self {
+        let format = get_env_value_option("note_format", NoteFormat::Markdown);
+        let should_archive_forwarded = get_env_value_option("note_forwarded", false);
+
+        Self {
+            format,
+            should_archive_forwarded,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum NoteFormat {
+    Markdown,
+    Html,
+}
+
+impl FromStr for NoteFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "markdown" | "md" => Ok(Self::Markdown),
+            "html" => Ok(Self::Html),
+            _ => Err(format!("unknown note format: {}", s)),
+        }
+    }
+}
@@ -5,33 +5,135 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use crate::error::ResultExt;
-
 use super::{
-    utils::{get_env_value, get_env_value_option_legacy},
+    utils::{exit_on_error, get_env_value, get_env_value_option, get_env_value_option_legacy},
     var::OD_SESSION_PATH,
 };
+use std::str::FromStr;
 
 pub struct OneDriveEnv {
     pub client_id: String,
-    pub client_secret: String,
+    // public clients of the device code flow have no secret
+    pub client_secret: Option<String>,
     pub root_path: String,
     pub session_path: String,
+    pub authority_url: String,
+    pub graph_url: String,
+    pub tenant: String,
+    pub auth_flow: OneDriveAuthFlow,
 }
 
 impl OneDriveEnv {
     pub fn new() -> Self {
-        let client_id = get_env_value("od_client_id").unwrap_or_trace();
-        let client_secret = get_env_value("od_client_secret").unwrap_or_trace();
+        let client_id = get_env_value("od_client_id").unwrap_or_else(|e| exit_on_error(&e));
+        let auth_flow = get_env_value_option("od_auth_flow", OneDriveAuthFlow::Redirect);
+        let client_secret = match auth_flow {
+            OneDriveAuthFlow::Redirect => {
+                Some(get_env_value("od_client_secret").unwrap_or_else(|e| exit_on_error(&e)))
+            }
+            OneDriveAuthFlow::DeviceCode => None,
+        };
         let root_path =
             get_env_value_option_legacy(&["od_root_path", "remote_root_path"], "/".to_string());
         let session_path = OD_SESSION_PATH.to_string();
+        let cloud = get_env_value_option("od_cloud", OneDriveCloud::Global);
+        let authority_url =
+            get_env_value_option("od_authority_url", cloud.authority_url().to_string());
+        let graph_url = get_env_value_option("od_graph_url", cloud.graph_url().to_string());
+        let tenant = get_env_value_option("od_tenant", "common".to_string());
 
         Self {
             client_id,
             client_secret,
             root_path,
             session_path,
+            authority_url,
+            graph_url,
+            tenant,
+            auth_flow,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OneDriveAuthFlow {
+    // microsoft redirects the code to the auth server at server_uri
+    Redirect,
+    // the user enters a code on microsoft's page, no inbound connection needed
+    DeviceCode,
+}
+
+impl FromStr for OneDriveAuthFlow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('-', "_").as_str() {
+            "redirect" => Ok(Self::Redirect),
+            "device_code" => Ok(Self::DeviceCode),
+            _ => Err(format!("unknown onedrive auth flow: {}", s)),
+        }
+    }
+}
+
+// national clouds are separated from the global one, with their own endpoints and app registrations
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OneDriveCloud {
+    Global,
+    // operated by 21Vianet
+    China,
+    UsGov,
+    UsGovDod,
+}
+
+impl OneDriveCloud {
+    pub const fn authority_url(self) -> &'static str {
+        match self {
+            Self::Global => "https://login.microsoftonline.com",
+            Self::China => "https://login.chinacloudapi.cn",
+            Self::UsGov | Self::UsGovDod => "https://login.microsoftonline.us",
         }
     }
+
+    pub const fn graph_url(self) -> &'static str {
+        match self {
+            Self::Global => "https://graph.microsoft.com/v1.0",
+            Self::China => "https://microsoftgraph.chinacloudapi.cn/v1.0",
+            Self::UsGov => "https://graph.microsoft.us/v1.0",
+            Self::UsGovDod => "https://dod-graph.microsoft.us/v1.0",
+        }
+    }
+}
+
+impl FromStr for OneDriveCloud {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('-', "_").as_str() {
+            "global" => Ok(Self::Global),
+            "china" | "21vianet" => Ok(Self::China),
+            "us_gov" | "gcc_high" => Ok(Self::UsGov),
+            "us_gov_dod" | "dod" => Ok(Self::UsGovDod),
+            _ => Err(format!("unknown onedrive cloud: {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OneDriveCloud;
+
+    #[test]
+    fn test_cloud_preset() {
+        let cloud = "21Vianet".parse::<OneDriveCloud>().unwrap();
+        assert!(cloud == OneDriveCloud::China);
+        assert_eq!(
+            cloud.graph_url(),
+            "https://microsoftgraph.chinacloudapi.cn/v1.0"
+        );
+
+        let cloud = "us-gov".parse::<OneDriveCloud>().unwrap();
+        assert_eq!(cloud.authority_url(), "https://login.microsoftonline.us");
+
+        assert!("mars".parse::<OneDriveCloud>().is_err());
+    }
 }
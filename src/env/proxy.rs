@@ -0,0 +1,101 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::utils::{exit_on_error, get_env_value};
+use anyhow::{anyhow, Context, Result};
+use url::Url;
+
+pub struct ProxyEnv {
+    // socks5 only, for mtproto
+    pub telegram: Option<String>,
+    // for /url downloads
+    pub url: Option<String>,
+    // for graph api calls and uploads to onedrive
+    pub graph: Option<String>,
+    // hosts connected directly, *.example.com matches subdomains
+    pub bypass: Vec<String>,
+}
+
+impl ProxyEnv {
+    pub fn new() -> Self {
+        let telegram = get_env_value::<String>("tg_proxy").ok();
+        let url = get_env_value::<String>("url_proxy").ok();
+        let graph = get_env_value::<String>("od_proxy").ok();
+        let bypass = get_env_value::<String>("proxy_bypass")
+            .map(|bypass| {
+                bypass
+                    .split(',')
+                    .map(|host| host.trim().to_lowercase())
+                    .filter(|host| !host.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        if let Some(proxy) = &telegram {
+            validate_proxy_url(proxy, &["socks5"])
+                .context("tg_proxy")
+                .unwrap_or_else(|e| exit_on_error(&e));
+        }
+
+        for (name, proxy) in [("url_proxy", &url), ("od_proxy", &graph)] {
+            if let Some(proxy) = proxy {
+                validate_proxy_url(proxy, &["http", "https", "socks5", "socks5h"])
+                    .context(name)
+                    .unwrap_or_else(|e| exit_on_error(&e));
+            }
+        }
+
+        Self {
+            telegram,
+            url,
+            graph,
+            bypass,
+        }
+    }
+
+    // in the form of reqwest no proxy list
+    pub fn no_proxy(&self) -> String {
+        self.bypass
+            .iter()
+            .map(|host| host.strip_prefix('*').unwrap_or(host))
+            .collect::<Vec<&str>>()
+            .join(",")
+    }
+}
+
+fn validate_proxy_url(proxy: &str, schemes: &[&str]) -> Result<()> {
+    let url = Url::parse(proxy).context("invalid proxy url")?;
+
+    if !schemes.contains(&url.scheme()) {
+        return Err(anyhow!(
+            "proxy scheme should be one of {}: {}",
+            schemes.join(", "),
+            url.scheme()
+        ));
+    }
+
+    if url.host_str().is_none() {
+        return Err(anyhow!("proxy host not found"));
+    }
+
+    Ok(())
+}
+
+// hide credentials when the proxy url is shown in logs or messages
+pub fn redact_proxy_url(proxy: &str) -> String {
+    match Url::parse(proxy) {
+        Ok(mut url) => {
+            if !url.username().is_empty() || url.password().is_some() {
+                let _ = url.set_username("***");
+                let _ = url.set_password(None);
+            }
+
+            url.to_string()
+        }
+        Err(_) => "***".to_string(),
+    }
+}
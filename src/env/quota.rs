@@ -0,0 +1,109 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    config::{get_quota_config, Size},
+    utils::get_env_value_option,
+};
+use std::str::FromStr;
+
+// bytes with an optional binary unit, like 500M, 10G or 1.5TiB
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let error = || format!("invalid size: {}, should be like 1024, 500M or 10G", s);
+
+        let number_end = s
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(s.len());
+        let (number, unit) = s.split_at(number_end);
+
+        let number = number.parse::<f64>().map_err(|_| error())?;
+
+        let unit = unit.trim().to_uppercase();
+        let unit = unit.trim_end_matches('B').trim_end_matches('I');
+
+        let multiplier = match unit {
+            "" => 1u64,
+            "K" => 1 << 10,
+            "M" => 1 << 20,
+            "G" => 1 << 30,
+            "T" => 1 << 40,
+            _ => return Err(error()),
+        };
+
+        Ok(Self((number * multiplier as f64) as u64))
+    }
+}
+
+// limits of a telegram user, none means unlimited
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Quota {
+    pub daily_bytes: Option<u64>,
+    pub monthly_bytes: Option<u64>,
+    pub daily_tasks: Option<u64>,
+    pub monthly_tasks: Option<u64>,
+    // waiting and running tasks, checked when tasks are added
+    pub max_pending: Option<u64>,
+    // checked when tasks are fetched
+    pub max_running: Option<u64>,
+}
+
+impl Quota {
+    // read on every check, so changes of the config file apply immediately
+    pub fn read(user_id: i64) -> Self {
+        let quota_config = get_quota_config(user_id);
+
+        let size = |user_value: Option<Size>, name: &str| {
+            user_value
+                .and_then(|size| size.bytes().ok())
+                .unwrap_or_else(|| get_env_value_option(name, ByteSize(0)).0)
+        };
+        let number = |user_value: Option<u64>, name: &str| {
+            user_value.unwrap_or_else(|| get_env_value_option(name, 0))
+        };
+
+        Self {
+            daily_bytes: to_limit(size(quota_config.daily_bytes, "quota_daily_bytes")),
+            monthly_bytes: to_limit(size(quota_config.monthly_bytes, "quota_monthly_bytes")),
+            daily_tasks: to_limit(number(quota_config.daily_tasks, "quota_daily_tasks")),
+            monthly_tasks: to_limit(number(quota_config.monthly_tasks, "quota_monthly_tasks")),
+            max_pending: to_limit(number(quota_config.max_pending, "quota_max_pending")),
+            max_running: to_limit(number(quota_config.max_running, "quota_max_running")),
+        }
+    }
+}
+
+// 0 means unlimited
+const fn to_limit(value: u64) -> Option<u64> {
+    if value == 0 {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_size() {
+        assert_eq!("1024".parse(), Ok(ByteSize(1024)));
+        assert_eq!("500M".parse(), Ok(ByteSize(500 << 20)));
+        assert_eq!("10 GB".parse(), Ok(ByteSize(10 << 30)));
+        assert_eq!("1.5GiB".parse(), Ok(ByteSize(3 << 29)));
+        assert_eq!("2t".parse(), Ok(ByteSize(2 << 40)));
+        assert!("10X".parse::<ByteSize>().is_err());
+        assert!("G".parse::<ByteSize>().is_err());
+    }
+}
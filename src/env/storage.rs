@@ -0,0 +1,80 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::utils::{get_env_value, get_env_value_option};
+use std::str::FromStr;
+
+pub struct StorageEnv {
+    pub kind: StorageKind,
+    pub local_root: String,
+    pub s3: S3Env,
+}
+
+impl StorageEnv {
+    pub fn new() -> Self {
+        let kind = get_env_value_option("storage_backend", StorageKind::OneDrive);
+        let local_root = get_env_value_option("storage_local_root", "/storage".to_string());
+        let s3 = S3Env::new();
+
+        Self {
+            kind,
+            local_root,
+            s3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum StorageKind {
+    OneDrive,
+    Local,
+    S3,
+}
+
+impl FromStr for StorageKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "onedrive" => Ok(Self::OneDrive),
+            "local" => Ok(Self::Local),
+            "s3" => Ok(Self::S3),
+            _ => Err(format!("unknown storage backend: {}", s)),
+        }
+    }
+}
+
+// any s3 compatible service, such as minio
+pub struct S3Env {
+    pub endpoint: Option<String>,
+    pub region: String,
+    pub bucket: Option<String>,
+    pub access_key: Option<String>,
+    pub secret_key: Option<String>,
+    // most self-hosted services don't support virtual-hosted-style bucket domains
+    pub path_style: bool,
+}
+
+impl S3Env {
+    fn new() -> Self {
+        let endpoint = get_env_value("storage_s3_endpoint").ok();
+        let region = get_env_value_option("storage_s3_region", "us-east-1".to_string());
+        let bucket = get_env_value("storage_s3_bucket").ok();
+        let access_key = get_env_value("storage_s3_access_key").ok();
+        let secret_key = get_env_value("storage_s3_secret_key").ok();
+        let path_style = get_env_value_option("storage_s3_path_style", true);
+
+        Self {
+            endpoint,
+            region,
+            bucket,
+            access_key,
+            secret_key,
+            path_style,
+        }
+    }
+}
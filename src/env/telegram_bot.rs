@@ -5,10 +5,8 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use crate::error::ResultExt;
-
 use super::{
-    utils::get_env_value,
+    utils::{exit_on_error, get_env_value},
     var::{RECONNECTION_POLICY, TG_BOT_SESSION_PATH},
 };
 
@@ -22,12 +20,13 @@ pub struct TelegramBotEnv {
 
 impl TelegramBotEnv {
     pub fn new() -> Self {
-        let api_id = get_env_value("tg_api_id").unwrap_or_trace();
-        let api_hash = get_env_value("tg_api_hash").unwrap_or_trace();
-        let token = get_env_value("tg_bot_token").unwrap_or_trace();
+        let api_id = get_env_value("tg_api_id").unwrap_or_else(|e| exit_on_error(&e));
+        let api_hash = get_env_value("tg_api_hash").unwrap_or_else(|e| exit_on_error(&e));
+        let token = get_env_value("tg_bot_token").unwrap_or_else(|e| exit_on_error(&e));
         let session_path = TG_BOT_SESSION_PATH.to_string();
         let params = grammers_client::InitParams {
             reconnection_policy: &RECONNECTION_POLICY,
+            proxy_url: get_env_value("tg_proxy").ok(),
             ..Default::default()
         };
 
@@ -5,18 +5,20 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use crate::error::ResultExt;
-
 use super::{
-    utils::get_env_value,
+    utils::{exit_on_error, get_env_value, get_env_value_option},
     var::{RECONNECTION_POLICY, TG_USER_SESSION_PATH},
 };
+use std::str::FromStr;
 
 pub struct TelegramUserEnv {
     pub api_id: i32,
     pub api_hash: String,
     pub users: Vec<String>,
-    pub phone_number: String,
+    pub login_method: TelegramLoginMethod,
+    // not needed by qr code login
+    pub phone_number: Option<String>,
+    // asked on the auth page if not set
     pub password: Option<String>,
     pub session_path: String,
     pub params: grammers_client::InitParams,
@@ -24,14 +26,21 @@ pub struct TelegramUserEnv {
 
 impl TelegramUserEnv {
     pub fn new() -> Self {
-        let api_id = get_env_value("tg_api_id").unwrap_or_trace();
-        let api_hash = get_env_value("tg_api_hash").unwrap_or_trace();
+        let api_id = get_env_value("tg_api_id").unwrap_or_else(|e| exit_on_error(&e));
+        let api_hash = get_env_value("tg_api_hash").unwrap_or_else(|e| exit_on_error(&e));
         let users = Self::parse_users();
-        let phone_number = get_env_value("tg_user_phone").unwrap_or_trace();
+        let login_method = get_env_value_option("tg_user_login", TelegramLoginMethod::Code);
+        let phone_number = match login_method {
+            TelegramLoginMethod::Code => {
+                Some(get_env_value("tg_user_phone").unwrap_or_else(|e| exit_on_error(&e)))
+            }
+            TelegramLoginMethod::QrCode => get_env_value("tg_user_phone").ok(),
+        };
         let password = get_env_value("tg_user_password").ok();
         let session_path = TG_USER_SESSION_PATH.to_string();
         let params = grammers_client::InitParams {
             reconnection_policy: &RECONNECTION_POLICY,
+            proxy_url: get_env_value("tg_proxy").ok(),
             ..Default::default()
         };
 
@@ -39,6 +48,7 @@ impl TelegramUserEnv {
             api_id,
             api_hash,
             users,
+            login_method,
             phone_number,
             password,
             session_path,
@@ -58,3 +68,23 @@ impl TelegramUserEnv {
         users
     }
 }
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TelegramLoginMethod {
+    // a code sent by telegram, input on the auth page
+    Code,
+    // scanned by telegram on a logged in device
+    QrCode,
+}
+
+impl FromStr for TelegramLoginMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "code" => Ok(Self::Code),
+            "qr" | "qr_code" => Ok(Self::QrCode),
+            _ => Err(format!("unknown telegram login method: {}", s)),
+        }
+    }
+}
@@ -0,0 +1,241 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::utils::{exit_on_error, get_env_value, get_env_value_option, host_matches};
+use crate::utils::{basic_auth_header, RequestHeaders};
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+use reqwest::header;
+use std::collections::BTreeMap;
+use url::Url;
+
+pub struct UrlEnv {
+    pub profiles: Vec<UrlProfile>,
+    pub resolvers: Vec<UrlRewrite>,
+}
+
+impl UrlEnv {
+    pub fn new() -> Self {
+        let profiles = get_env_value_option("url_profiles", String::new())
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(UrlProfile::new)
+            .collect();
+
+        let resolvers = get_env_value_option("url_resolvers", String::new())
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(UrlRewrite::new)
+            .collect();
+
+        Self {
+            profiles,
+            resolvers,
+        }
+    }
+
+    pub fn get_profile(&self, name: &str) -> Option<&UrlProfile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    pub fn match_profile(&self, host: &str) -> Option<&UrlProfile> {
+        self.profiles.iter().find(|profile| profile.matches(host))
+    }
+
+    // headers of a request to url, which may be a redirect or a resolved share link of origin
+    // credentials never leave their hosts: a profile only goes to the hosts it matches,
+    // and headers given with /url only to the origin
+    pub fn request_headers(
+        &self,
+        profile: Option<&str>,
+        headers: &[(String, String)],
+        origin: &Url,
+        url: &Url,
+    ) -> Result<RequestHeaders> {
+        let host = url.host_str().unwrap_or_default();
+
+        // a profile named in /url takes the place of the first one matching the host
+        let profile = match profile {
+            Some(name) => {
+                let profile = self
+                    .get_profile(name)
+                    .ok_or_else(|| anyhow!("url profile not found: {}", name))?;
+
+                profile.matches(host).then_some(profile)
+            }
+            None => self.match_profile(host),
+        };
+
+        let mut request_headers = profile
+            .map(|profile| profile.headers.clone())
+            .unwrap_or_default();
+
+        if url.origin() == origin.origin() {
+            for (name, value) in headers {
+                request_headers.retain(|(origin_name, _)| !origin_name.eq_ignore_ascii_case(name));
+                request_headers.push((name.clone(), value.clone()));
+            }
+        }
+
+        Ok(request_headers)
+    }
+}
+
+// named credentials attached to /url requests whose host matches one of the patterns
+pub struct UrlProfile {
+    pub name: String,
+    pub hosts: Vec<String>,
+    pub headers: RequestHeaders,
+}
+
+impl UrlProfile {
+    fn new(name: &str) -> Self {
+        let prefix = format!("url_profile_{}", name);
+
+        let hosts = get_env_value_option(&format!("{}_hosts", prefix), String::new())
+            .split(',')
+            .map(|host| host.trim().to_lowercase())
+            .filter(|host| !host.is_empty())
+            .collect();
+
+        let mut headers = RequestHeaders::new();
+
+        // json object, e.g. {"X-Api-Key": "xxx"}
+        if let Ok(custom_headers) = get_env_value::<String>(&format!("{}_headers", prefix)) {
+            let custom_headers = serde_json::from_str::<BTreeMap<String, String>>(&custom_headers)
+                .context("headers of url profile should be a json object")
+                .context(name.to_string())
+                .unwrap_or_else(|e| exit_on_error(&e));
+
+            headers.extend(custom_headers);
+        }
+
+        if let Ok(cookie) = get_env_value::<String>(&format!("{}_cookie", prefix)) {
+            headers.push((header::COOKIE.to_string(), cookie));
+        }
+
+        if let Ok(user_agent) = get_env_value::<String>(&format!("{}_user_agent", prefix)) {
+            headers.push((header::USER_AGENT.to_string(), user_agent));
+        }
+
+        if let Ok(referer) = get_env_value::<String>(&format!("{}_referer", prefix)) {
+            headers.push((header::REFERER.to_string(), referer));
+        }
+
+        // user:password
+        if let Ok(basic_auth) = get_env_value::<String>(&format!("{}_basic_auth", prefix)) {
+            headers.push((
+                header::AUTHORIZATION.to_string(),
+                basic_auth_header(&basic_auth),
+            ));
+        }
+
+        if let Ok(token) = get_env_value::<String>(&format!("{}_bearer", prefix)) {
+            headers.push((
+                header::AUTHORIZATION.to_string(),
+                format!("Bearer {}", token),
+            ));
+        }
+
+        Self {
+            name: name.to_string(),
+            hosts,
+            headers,
+        }
+    }
+
+    pub fn matches(&self, host: &str) -> bool {
+        host_matches(&self.hosts, host)
+    }
+}
+
+// turns share links of hosts the builtin resolvers don't know, like internal ones,
+// into direct download urls
+pub struct UrlRewrite {
+    pub name: String,
+    // matched against the whole url
+    pub pattern: Regex,
+    // groups of the pattern can be used like $1 or ${id}
+    pub url: String,
+}
+
+impl UrlRewrite {
+    fn new(name: &str) -> Self {
+        let prefix = format!("url_resolver_{}", name);
+
+        let pattern = get_env_value(&format!("{}_pattern", prefix))
+            .context(name.to_string())
+            .unwrap_or_else(|e| exit_on_error(&e));
+
+        let url = get_env_value(&format!("{}_url", prefix))
+            .context(name.to_string())
+            .unwrap_or_else(|e| exit_on_error(&e));
+
+        Self {
+            name: name.to_string(),
+            pattern,
+            url,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url_env() -> UrlEnv {
+        UrlEnv {
+            profiles: vec![UrlProfile {
+                name: "example".to_string(),
+                hosts: vec!["*.example.com".to_string()],
+                headers: vec![("X-Api-Key".to_string(), "secret".to_string())],
+            }],
+            resolvers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_request_headers() {
+        let url_env = url_env();
+        let origin = Url::parse("https://files.example.com/a.zip").unwrap();
+        let redirect = Url::parse("https://cdn.example.org/a.zip").unwrap();
+        let headers = vec![
+            ("x-api-key".to_string(), "override".to_string()),
+            ("Cookie".to_string(), "a=1".to_string()),
+        ];
+
+        // the profile matching the host, overridden by options
+        assert_eq!(
+            url_env
+                .request_headers(None, &headers, &origin, &origin)
+                .unwrap(),
+            headers
+        );
+        assert_eq!(
+            url_env
+                .request_headers(None, &[], &origin, &origin)
+                .unwrap(),
+            vec![("X-Api-Key".to_string(), "secret".to_string())]
+        );
+
+        // neither the profile nor options follow a redirect to another host
+        assert!(url_env
+            .request_headers(None, &headers, &origin, &redirect)
+            .unwrap()
+            .is_empty());
+        assert!(url_env
+            .request_headers(Some("example"), &headers, &origin, &redirect)
+            .unwrap()
+            .is_empty());
+
+        assert!(url_env
+            .request_headers(Some("unknown"), &[], &origin, &origin)
+            .is_err());
+    }
+}
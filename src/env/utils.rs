@@ -5,15 +5,37 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use anyhow::{Context, Result};
-use std::{env, str::FromStr};
+use super::config::get_file_value;
+use anyhow::{anyhow, Context, Error, Result};
+use std::{env, fmt::Display, fs, str::FromStr};
+
+// the config file takes precedence over env vars, then secret files
+fn get_raw_value(name: &str) -> Option<String> {
+    get_file_value(name)
+        .or_else(|| env::var(name).ok())
+        .or_else(|| read_secret_file(name))
+}
+
+// docker and kubernetes secrets are mounted as files, e.g. tg_bot_token_file=/run/secrets/tg_bot_token
+fn read_secret_file(name: &str) -> Option<String> {
+    let path = [format!("{}_file", name), format!("{}_FILE", name)]
+        .into_iter()
+        .find_map(|name| env::var(name).ok())?;
+
+    let value = fs::read_to_string(&path)
+        .context("failed to read secret file")
+        .context(format!("{}_file={}", name, path))
+        .unwrap_or_else(|e| exit_on_error(&e));
+
+    Some(value.trim_end_matches(['\r', '\n']).to_string())
+}
 
 pub fn get_env_value<T>(name: &str) -> Result<T>
 where
     T: FromStr,
     T::Err: std::error::Error + Send + Sync + 'static,
 {
-    let value_s = env::var(name)
+    let value_s = get_raw_value(name)
         .context("failed to get env value")
         .context(name.to_string())?;
 
@@ -25,24 +47,53 @@ where
     Ok(value)
 }
 
+// an invalid value stops the bot instead of falling back to the default
 pub fn get_env_value_option<T>(name: &str, default: T) -> T
 where
     T: FromStr,
+    T::Err: Display,
 {
-    match env::var(name) {
-        Ok(value_s) => value_s.parse::<T>().unwrap_or(default),
-        Err(_) => default,
-    }
+    get_raw_value(name).map_or(default, |value_s| parse_or_exit(name, &value_s))
+}
+
+fn parse_or_exit<T>(name: &str, value_s: &str) -> T
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value_s.parse::<T>().unwrap_or_else(|e| {
+        exit_on_error(&anyhow!("{}", e).context(format!("invalid value {}={}", name, value_s)))
+    })
+}
+
+// tracing isn't registered while env is read, so the error is printed directly
+pub fn exit_on_error(e: &Error) -> ! {
+    eprintln!("Error: {:?}", e);
+
+    std::process::exit(1)
+}
+
+// *.example.com matches subdomains of example.com, other patterns match the host exactly
+pub fn host_matches(patterns: &[String], host: &str) -> bool {
+    let host = host.to_lowercase();
+
+    patterns.iter().any(|pattern| {
+        pattern.strip_prefix("*.").map_or_else(
+            || host == *pattern,
+            |domain| host.ends_with(&format!(".{}", domain)),
+        )
+    })
 }
 
 // to be compatible with the python version
 pub fn get_env_value_option_legacy<T>(names: &[&str], default: T) -> T
 where
     T: FromStr,
+    T::Err: Display,
 {
     for name in names {
-        if let Ok(value_s) = env::var(name) {
-            return value_s.parse::<T>().unwrap_or(default);
+        if let Some(value_s) = get_raw_value(name) {
+            return parse_or_exit(name, &value_s);
         }
     }
 
@@ -10,11 +10,21 @@ use std::time::Duration;
 
 pub const LOGS_PATH: &str = "./logs";
 
+// optional unless config_path is set
+pub const CONFIG_PATH: &str = "./config.toml";
+
 pub const SESSION_DIR: &str = "./session";
 pub const TG_BOT_SESSION_PATH: &str = "./session/tg-bot.session";
 pub const TG_USER_SESSION_PATH: &str = "./session/tg-user.session";
 pub const OD_SESSION_PATH: &str = "./session/od.session";
 pub const TASKER_SESSION_PATH: &str = "./session/tasker.session";
+// raw uploads of the http api, removed on startup like the task session
+pub const UPLOAD_SPOOL_DIR: &str = "./session/uploads";
+// not removed on startup like the task session
+pub const TASKER_HISTORY_PATH: &str = "./session/history.session";
+pub const API_NOTIFY_PATH: &str = "./session/api-notify.json";
+pub const WEBHOOK_LOG_PATH: &str = "./session/webhook.session";
+pub const ACL_PATH: &str = "./session/users.session";
 
 pub const RECONNECTION_POLICY: FixedReconnect = FixedReconnect {
     attempts: 5,
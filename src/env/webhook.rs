@@ -0,0 +1,39 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{utils::get_env_value, var::WEBHOOK_LOG_PATH};
+
+pub struct WebhookEnv {
+    // targets separated by commas, webhooks are disabled if not set
+    pub urls: Vec<String>,
+    // key of the hmac-sha256 signature, payloads are unsigned if not set
+    pub secret: Option<String>,
+    // deliveries are recorded here
+    pub log_path: String,
+}
+
+impl WebhookEnv {
+    pub fn new() -> Self {
+        let urls = get_env_value::<String>("webhook_urls")
+            .map(|urls| {
+                urls.split(',')
+                    .map(str::trim)
+                    .filter(|url| !url.is_empty())
+                    .map(ToString::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let secret = get_env_value("webhook_secret").ok();
+        let log_path = WEBHOOK_LOG_PATH.to_string();
+
+        Self {
+            urls,
+            secret,
+            log_path,
+        }
+    }
+}
@@ -0,0 +1,101 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::text::cmd_parser,
+};
+use crate::{
+    env::ENV,
+    message::TelegramMessage,
+    state::AppState,
+    web::{get_notify_chat, set_notify_chat, NotifyChat},
+};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::InputMessage;
+use proc_macros::{check_chat, check_role, check_senders, check_tg_login};
+
+pub const PATTERN: &str = "/api";
+
+#[check_tg_login]
+#[check_role(Admin)]
+#[check_chat]
+#[check_senders]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /api
+        show_api(message).await
+    } else if cmd.len() == 2 {
+        if cmd[1] == "notify" {
+            // /api notify
+            bind_notify_chat(message, state).await
+        } else if cmd[1] == "help" {
+            // /api help
+            message
+                .respond(InputMessage::html(format_help(PATTERN)))
+                .await
+                .context("help")?;
+
+            Ok(())
+        } else {
+            Err(anyhow!("sub command error")).context(format_unknown_command_help(PATTERN))
+        }
+    } else if cmd.len() == 3 && cmd[1] == "notify" && cmd[2] == "off" {
+        // /api notify off
+        set_notify_chat(None).await?;
+
+        let response = "Api tasks won't be notified.";
+        message.respond(response).await.context(response)?;
+
+        Ok(())
+    } else {
+        Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN))
+    }
+}
+
+async fn show_api(message: TelegramMessage) -> Result<()> {
+    let api_env = &ENV.get().unwrap().api;
+
+    let enabled = if api_env.tokens.is_empty() {
+        "Http api is disabled, set api_tokens to enable it."
+    } else {
+        "Http api is enabled."
+    };
+
+    let notify = match get_notify_chat().await? {
+        Some(notify_chat) if notify_chat.chat_id == message.chat().id() => {
+            "Api tasks are notified in this chat."
+        }
+        Some(_) => "Api tasks are notified in another chat.",
+        None => "Api tasks are not notified.",
+    };
+
+    let response = format!("{}\n{}", enabled, notify);
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn bind_notify_chat(message: TelegramMessage, state: AppState) -> Result<()> {
+    let chat_user = state.get_reader_chat(message.chat()).await?;
+
+    set_notify_chat(Some(&NotifyChat {
+        chat_id: message.chat().id(),
+        chat_bot_hex: message.chat().pack().to_hex(),
+        chat_user_hex: chat_user.pack().to_hex(),
+    }))
+    .await?;
+
+    tracing::info!("api tasks are notified in chat {}", message.chat().id());
+
+    let response = "Api tasks will be notified in this chat.";
+    message.respond(response).await.context(response)?;
+
+    Ok(())
+}
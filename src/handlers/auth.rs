@@ -5,21 +5,36 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use crate::{auth_server, message::TelegramMessage, state::AppState};
+use crate::{
+    auth_server::{self, OneDriveAuthChannel, TelegramAuthChannel},
+    message::TelegramMessage,
+    state::AppState,
+};
 use anyhow::{Context, Result};
-use proc_macros::{check_in_group, check_senders};
-use tokio::sync::mpsc::Receiver;
+use proc_macros::{check_chat, check_role, check_senders};
 
 pub const PATTERN: &str = "/auth";
 
+#[check_role(Admin)]
+#[check_chat]
 #[check_senders]
-#[check_in_group]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
-    let (rx_tg, rx_od, _server_abort_handle) = auth_server::spawn().await?;
+    // the auth server only receives codes, it's not needed when no code comes to it
+    let should_spawn_server =
+        !state.telegram_user.is_authorized().await? || !state.onedrive.uses_device_code();
 
-    login_to_telegram(message.clone(), state.clone(), rx_tg).await?;
+    let (channel_tg, channel_od, _auth_flow_handle) = if should_spawn_server {
+        let (channel_tg, channel_od, auth_flow_handle) =
+            auth_server::spawn(message.chat().id()).await?;
 
-    authorize_onedrive(message, state.clone(), false, rx_od).await?;
+        (Some(channel_tg), Some(channel_od), Some(auth_flow_handle))
+    } else {
+        (None, None, None)
+    };
+
+    login_to_telegram(message.clone(), state.clone(), channel_tg).await?;
+
+    authorize_onedrive(message, state.clone(), false, channel_od).await?;
 
     let onedrive = &state.onedrive;
     onedrive.set_current_user().await?;
@@ -30,11 +45,11 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
 pub async fn login_to_telegram(
     message: TelegramMessage,
     state: AppState,
-    rx: Receiver<String>,
+    channel: Option<TelegramAuthChannel>,
 ) -> Result<()> {
     let telegram_user = &state.telegram_user;
 
-    telegram_user.login(message.clone(), rx).await?;
+    telegram_user.login(message.clone(), channel).await?;
 
     let response = "Login to Telegram successful!";
     message.respond(response).await.context(response)?;
@@ -46,11 +61,11 @@ pub async fn authorize_onedrive(
     message: TelegramMessage,
     state: AppState,
     should_add: bool,
-    rx: Receiver<String>,
+    channel: Option<OneDriveAuthChannel>,
 ) -> Result<()> {
     let onedrive = &state.onedrive;
 
-    onedrive.login(message.clone(), should_add, rx).await?;
+    onedrive.login(message.clone(), should_add, channel).await?;
 
     let response = "OneDrive authorization successful!";
     message.respond(response).await.context(response)?;
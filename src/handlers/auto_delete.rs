@@ -7,13 +7,14 @@
 
 use crate::{message::TelegramMessage, state::AppState};
 use anyhow::{Context, Result};
-use proc_macros::{check_in_group, check_senders};
+use proc_macros::{check_chat, check_role, check_senders};
 use std::sync::atomic::Ordering;
 
 pub const PATTERN: &str = "/autoDelete";
 
+#[check_role(Admin)]
+#[check_chat]
 #[check_senders]
-#[check_in_group]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     let should_auto_delete = state.should_auto_delete.load(Ordering::Acquire);
 
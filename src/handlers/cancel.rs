@@ -0,0 +1,92 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::text::cmd_parser,
+};
+use crate::{message::TelegramMessage, state::AppState, tasker::cancel_task};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::InputMessage;
+use proc_macros::{check_chat, check_role, check_senders};
+
+pub const PATTERN: &str = "/cancel";
+
+// deleting messages cancels tasks in groups and channels,
+// but deletions in private chats aren't sent to the bot
+#[check_role(Uploader)]
+#[check_chat]
+#[check_senders]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /cancel as a reply to a message or its indicator
+        let message_id = message.reply_to_message_id().ok_or_else(|| {
+            anyhow!("reply /cancel to a message or its response, or use /cancel all")
+        })?;
+
+        cancel_tasks(message, state, Some(message_id)).await
+    } else if cmd.len() == 2 && cmd[1] == "all" {
+        // /cancel all
+        cancel_tasks(message, state, None).await
+    } else if cmd.len() == 2 && cmd[1] == "help" {
+        // /cancel help
+        message
+            .respond(InputMessage::html(format_help(PATTERN)))
+            .await
+            .context("help")?;
+
+        Ok(())
+    } else {
+        Err(anyhow!("sub command error")).context(format_unknown_command_help(PATTERN))
+    }
+}
+
+async fn cancel_tasks(
+    message: TelegramMessage,
+    state: AppState,
+    message_id: Option<i32>,
+) -> Result<()> {
+    let chat_id = message.chat().id();
+    let task_session = &state.task_session;
+
+    // stop the batch or links first, so that no more tasks are added
+    let mut batch_aborters = task_session.batch_aborters.lock().await;
+    match message_id {
+        Some(message_id) => {
+            if let Some(batch_aborter) = batch_aborters.remove(&(chat_id, message_id)) {
+                batch_aborter.abort();
+            }
+        }
+        None => batch_aborters.retain(|(batch_chat_id, _), batch_aborter| {
+            if *batch_chat_id == chat_id {
+                batch_aborter.abort();
+            }
+
+            *batch_chat_id != chat_id
+        }),
+    }
+    drop(batch_aborters);
+
+    let mut cancelled_number = 0;
+
+    for task in task_session.list_chat_tasks(chat_id, message_id).await? {
+        if cancel_task(&state, task.id).await? {
+            cancelled_number += 1;
+        }
+    }
+
+    let response = if cancelled_number > 0 {
+        format!("{} tasks cancelled.", cancelled_number)
+    } else {
+        "No task to cancel.".to_string()
+    };
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
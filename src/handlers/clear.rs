@@ -10,19 +10,29 @@ use crate::{
     state::AppState,
 };
 use anyhow::{Context, Result};
-use proc_macros::{check_in_group, check_senders, check_tg_login};
+use grammers_client::types::Chat;
+use proc_macros::{check_chat, check_role, check_senders, check_tg_login};
 
 pub const PATTERN: &str = "/clear";
 
 #[check_tg_login]
+#[check_role(Admin)]
+#[check_chat]
 #[check_senders]
-#[check_in_group]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     let telegram_user = &state.telegram_user;
     let task_session = &state.task_session;
 
     task_session.clear().await?;
 
+    // the bot can't read the history of private chats, and the user isn't in them
+    if let Chat::User(_) = message.chat() {
+        let response = "Tasks are cleared, but history of private chats can't be cleared.";
+        message.respond(response).await.context(response)?;
+
+        return Ok(());
+    }
+
     let chat = telegram_user
         .get_chat(&ChatEntity::from(message.chat()))
         .await?;
@@ -9,21 +9,29 @@ use super::{
     docs::{format_help, format_unknown_command_help},
     utils::{text::cmd_parser, validate_root_path},
 };
-use crate::{client::OneDriveClient, message::TelegramMessage, state::AppState};
+use crate::{acl::Role, client::OneDriveClient, message::TelegramMessage, state::AppState};
 use anyhow::{anyhow, Context, Result};
 use grammers_client::InputMessage;
-use proc_macros::{check_in_group, check_od_login, check_senders};
+use proc_macros::{check_chat, check_od_login, check_role, check_senders};
 
 pub const PATTERN: &str = "/dir";
 
 #[check_od_login]
+#[check_role(Viewer)]
+#[check_chat]
 #[check_senders]
-#[check_in_group]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     let onedrive = &state.onedrive;
 
     let cmd = cmd_parser(message.text());
 
+    // viewers can only show the directory
+    let is_read_only = cmd.len() == 1 || cmd[1] == "help";
+
+    if !is_read_only && !state.acl.check(&message, Role::Uploader).await? {
+        return Ok(());
+    }
+
     if cmd.len() == 1 {
         // /dir
         show_dir(onedrive, message).await?;
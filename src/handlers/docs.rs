@@ -34,10 +34,38 @@ To show command help.
 const HELP_URL: &str = "\
 <pre><code>/url $url</code></pre>
 To upload file through url.
+Share links of Google Drive, Dropbox, GitHub, OneDrive and SharePoint are resolved to direct download urls.
+<pre><code>/url $url
+header: Name: value
+cookie: a=1; b=2
+user-agent: $user_agent
+referer: $referer
+auth: $user:$password
+bearer: $token
+profile: $name</code></pre>
+To upload file with custom request options, one option per line. Options are only sent to the host of the url. Profiles are only sent to the hosts they match, and are attached automatically.
 <pre><code>/url help</code></pre>
 To show command help.
 ";
 
+const HELP_FETCH: &str = "\
+<pre><code>/fetch $url</code></pre>
+To transfer a file or a whole directory from ftp://, sftp://, dav:// or davs:// (WebDAV, e.g. Nextcloud).
+<pre><code>/fetch $url $profile</code></pre>
+To transfer with the credentials of a fetch profile. Profiles whose hosts match the url are used automatically. Never put passwords in the url.
+<pre><code>/fetch help</code></pre>
+To show command help.
+";
+
+const HELP_NOTE: &str = "\
+<pre><code>/note $message_link</code></pre>
+To archive the text of a message as a note.
+<pre><code>/note</code></pre>
+Reply to a message to archive its text as a note.
+<pre><code>/note help</code></pre>
+To show command help.
+";
+
 const HELP_LOGS: &str = "\
 <pre><code>/logs</code></pre>
 To send logs zip.
@@ -58,6 +86,20 @@ To change the OneDrive account.
 To logout current OneDrive account.
 <pre><code>/drive logout $index</code></pre>
 To logout specified OneDrive account.
+<pre><code>/drive target</code></pre>
+To show the drive files in this chat are uploaded to.
+<pre><code>/drive target site $site_url</code></pre>
+To upload to the document library of a SharePoint site, like https://contoso.sharepoint.com/sites/team. A site id works too.
+<pre><code>/drive target group $group_id</code></pre>
+To upload to the drive of a Microsoft 365 group.
+<pre><code>/drive target drive $drive_id</code></pre>
+To upload to a drive by its id.
+<pre><code>/drive target shared</code></pre>
+To list folders shared with the account.
+<pre><code>/drive target shared $index</code></pre>
+To upload to a folder shared with the account.
+<pre><code>/drive target me</code></pre>
+To upload to the personal drive again.
 <pre><code>/drive help</code></pre>
 To show command help.
 ";
@@ -77,13 +119,56 @@ To reset OneDrive directory to default.
 To show command help.
 ";
 
+const HELP_API: &str = "\
+<pre><code>/api</code></pre>
+To show whether the http api is enabled and where its tasks are notified.
+<pre><code>/api notify</code></pre>
+To notify tasks added through the http api in this chat.
+<pre><code>/api notify off</code></pre>
+To stop notifying tasks added through the http api.
+<pre><code>/api help</code></pre>
+To show command help.
+";
+
+const HELP_USERS: &str = "\
+<pre><code>/users</code></pre>
+To list users and their roles.
+<pre><code>/users add $user_id $role</code></pre>
+To add a user as admin, uploader or viewer, default to uploader. Reply to a message of the user instead of passing the id.
+<pre><code>/users remove $user_id</code></pre>
+To remove a user.
+<pre><code>/users role $user_id $role</code></pre>
+To change the role of a user.
+<pre><code>/users help</code></pre>
+To show command help.
+";
+
+const HELP_USAGE: &str = "\
+<pre><code>/usage</code></pre>
+To show transfers of each user today and this month, and their quotas.
+<pre><code>/usage help</code></pre>
+To show command help.
+";
+
+const HELP_CANCEL: &str = "\
+<pre><code>/cancel</code></pre>
+Reply to a responded message or the message you sent to cancel its tasks.
+<pre><code>/cancel all</code></pre>
+To cancel all tasks in this chat.
+<pre><code>/cancel help</code></pre>
+To show command help.
+";
+
 const INSTRUCTION: &str = "\
 - To transfer files, forward or upload to me.
 - To transfer restricted content, right click the content, copy the message link, and send to me.
+- Message links and http(s) urls in a text are recognized automatically, each of them becomes a job.
 - Tap the file name on the Progress message to locate the job.
 - To upload files through url, the headers of the file response must includes Content-Length.
 - To cancel a job, delete the responded message.
 - To cancel batch or links tasks, delete the message you sent.
+- In private chats, deleted messages can't be noticed, reply /cancel to the message instead.
+- Contacts, locations, venues and polls are exported as .vcf, .geojson or .gpx, and .json files.
 - Support files with extension .t2o as scripts.
 
 See <a href=\"https://github.com/hlf20010508/telegram-onedrive#example\">example</a>.
@@ -101,16 +186,34 @@ pub fn format_help(name: &str) -> String {
     match name {
         "/help" => {
             format!(
-                "{}{}{}{}{}{}\n{}",
-                HELP_BASE, HELP_LINKS, HELP_URL, HELP_LOGS, HELP_DRIVE, HELP_DIR, INSTRUCTION
+                "{}{}{}{}{}{}{}{}{}{}{}{}\n{}",
+                HELP_BASE,
+                HELP_LINKS,
+                HELP_URL,
+                HELP_FETCH,
+                HELP_NOTE,
+                HELP_LOGS,
+                HELP_DRIVE,
+                HELP_DIR,
+                HELP_API,
+                HELP_USERS,
+                HELP_USAGE,
+                HELP_CANCEL,
+                INSTRUCTION
             )
         }
         "/start" => GREETING.to_string(),
         "/links" => HELP_LINKS.to_string(),
         "/url" => HELP_URL.to_string(),
+        "/fetch" => HELP_FETCH.to_string(),
+        "/note" => HELP_NOTE.to_string(),
         "/logs" => HELP_LOGS.to_string(),
         "/drive" => HELP_DRIVE.to_string(),
         "/dir" => HELP_DIR.to_string(),
+        "/api" => HELP_API.to_string(),
+        "/users" => HELP_USERS.to_string(),
+        "/usage" => HELP_USAGE.to_string(),
+        "/cancel" => HELP_CANCEL.to_string(),
         _ => String::new(),
     }
 }
@@ -10,23 +10,41 @@ use super::{
     utils::text::cmd_parser,
 };
 use crate::{
-    auth_server, client::OneDriveClient, handlers::auth::authorize_onedrive,
-    message::TelegramMessage, state::AppState,
+    acl::Role,
+    auth_server,
+    client::{onedrive::target::DriveTarget, OneDriveClient},
+    handlers::auth::authorize_onedrive,
+    message::TelegramMessage,
+    state::AppState,
 };
 use anyhow::{anyhow, Context, Result};
 use grammers_client::InputMessage;
-use proc_macros::{check_in_group, check_senders};
+use proc_macros::{check_chat, check_role, check_senders};
 
 pub const PATTERN: &str = "/drive";
 
+#[check_role(Viewer)]
+#[check_chat]
 #[check_senders]
-#[check_in_group]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     let onedrive = &state.onedrive;
 
     let cmd = cmd_parser(message.text());
 
-    if cmd.len() == 1 {
+    // viewers can only list, changing accounts and targets needs an admin
+    let is_read_only = cmd.len() == 1
+        || cmd[1] == "help"
+        || cmd[1..] == ["target"]
+        || cmd[1..] == ["target", "shared"];
+
+    if !is_read_only && !state.acl.check(&message, Role::Admin).await? {
+        return Ok(());
+    }
+
+    if cmd.len() >= 2 && cmd[1] == "target" {
+        // /drive target ...
+        target_drive(onedrive, message, &cmd[2..]).await?;
+    } else if cmd.len() == 1 {
         // /drive
         show_drive(onedrive, message).await?;
     } else if cmd.len() == 2 {
@@ -99,8 +117,13 @@ async fn show_drive(onedrive: &OneDriveClient, message: TelegramMessage) -> Resu
 }
 
 async fn add_drive(message: TelegramMessage, state: AppState) -> Result<()> {
-    let (_, rx, _server_abort_handle) = auth_server::spawn().await?;
-    authorize_onedrive(message, state, true, rx).await?;
+    // the device code flow doesn't need the auth server
+    if state.onedrive.uses_device_code() {
+        return authorize_onedrive(message, state, true, None).await;
+    }
+
+    let (_, channel, _auth_flow_handle) = auth_server::spawn(message.chat().id()).await?;
+    authorize_onedrive(message, state, true, Some(channel)).await?;
 
     Ok(())
 }
@@ -163,6 +186,90 @@ async fn set_drive(
     Ok(())
 }
 
+async fn target_drive(
+    onedrive: &OneDriveClient,
+    message: TelegramMessage,
+    args: &[String],
+) -> Result<()> {
+    let chat_id = message.chat().id();
+
+    let target = match args {
+        [] => {
+            // /drive target
+            let target = onedrive.get_drive_target(chat_id).await?;
+
+            let response = format!("Files in this chat are uploaded to {}.", target);
+            message.respond(response.as_str()).await.context(response)?;
+
+            return Ok(());
+        }
+        [kind] if kind == "me" => DriveTarget::Me,
+        [kind] if kind == "shared" => {
+            // /drive target shared
+            let folders = onedrive.list_shared_folders().await?;
+
+            let response = if folders.is_empty() {
+                "No folder is shared with this account.".to_string()
+            } else {
+                let mut response = "Folders shared with this account:\n".to_string();
+
+                for (i, folder) in folders.iter().enumerate() {
+                    response.push_str(&format!("{}. {}\n", i + 1, folder));
+                }
+
+                response.push_str("\nUse /drive target shared $index to choose one.");
+
+                response
+            };
+            message.respond(response.as_str()).await.context(response)?;
+
+            return Ok(());
+        }
+        [kind, value] => match kind.as_str() {
+            "site" if value.starts_with("https://") => onedrive.resolve_site(value).await?,
+            "site" => DriveTarget::Site {
+                site_id: value.clone(),
+                site_url: None,
+            },
+            "group" => DriveTarget::Group {
+                group_id: value.clone(),
+            },
+            "drive" => DriveTarget::Drive {
+                drive_id: value.clone(),
+            },
+            "shared" => {
+                let index = value
+                    .parse::<usize>()
+                    .context("shared folder index should be integer")?;
+
+                onedrive
+                    .list_shared_folders()
+                    .await?
+                    .into_iter()
+                    .nth(index.wrapping_sub(1))
+                    .ok_or_else(|| anyhow!("shared folder index out of range"))?
+            }
+            _ => {
+                return Err(anyhow!("unknown drive target: {}", kind))
+                    .context(format_unknown_command_help(PATTERN))
+            }
+        },
+        _ => {
+            return Err(anyhow!("sub command error")).context(format_unknown_command_help(PATTERN))
+        }
+    };
+
+    let name = onedrive.set_drive_target(chat_id, &target).await?;
+
+    let response = format!(
+        "Files in this chat will be uploaded to {}, whose root folder is {}.",
+        target, name
+    );
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
 async fn logout_drive(
     onedrive: &OneDriveClient,
     message: TelegramMessage,
@@ -0,0 +1,38 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::utils::{
+    content::insert_content_task,
+    export::{export_media, Export},
+};
+use crate::{message::TelegramMessage, state::AppState, tasker::CmdType};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_chat, check_od_login, check_role, check_senders, check_tg_login};
+
+#[check_od_login]
+#[check_tg_login]
+#[check_role(Uploader)]
+#[check_chat]
+#[check_senders]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    insert_export_task(&message, &state, &message, None).await
+}
+
+pub async fn insert_export_task(
+    message: &TelegramMessage,
+    state: &AppState,
+    message_origin: &TelegramMessage,
+    link: Option<&str>,
+) -> Result<()> {
+    let media = message_origin
+        .media()
+        .ok_or_else(|| anyhow!("message does not contain any media"))?;
+
+    let Export { filename, content } = export_media(&media, message_origin.date())?;
+
+    insert_content_task(message, state, CmdType::Export, &filename, content, link).await
+}
@@ -0,0 +1,241 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{preprocess_url_file_name, text::cmd_parser},
+};
+use crate::{
+    client::{
+        remote::{RemoteClient, RemoteFile},
+        utils::format_message_link,
+    },
+    env::{FetchProfile, ENV},
+    error::{ErrorExt, ResultUnwrapExt},
+    message::TelegramMessage,
+    state::AppState,
+    tasker::{BatchAborter, CmdType, InsertTask},
+};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::{types::Chat, InputMessage};
+use path_slash::PathBufExt;
+use proc_macros::{check_chat, check_od_login, check_role, check_senders, check_tg_login};
+use std::path::Path;
+use url::Url;
+
+pub const PATTERN: &str = "/fetch";
+
+#[check_od_login]
+#[check_tg_login]
+#[check_role(Uploader)]
+#[check_chat]
+#[check_senders]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 2 && cmd[1] == "help" {
+        // /fetch help
+        message
+            .respond(InputMessage::html(format_help(PATTERN)))
+            .await
+            .context("help")?;
+
+        Ok(())
+    } else if cmd.len() == 2 || cmd.len() == 3 {
+        // /fetch $url
+        // /fetch $url $profile
+        let url = Url::parse(&cmd[1]).context("failed to parse url")?;
+
+        let cmd_type = match url.scheme() {
+            "ftp" => CmdType::Ftp,
+            "sftp" => CmdType::Sftp,
+            "dav" | "davs" | "webdav" | "webdavs" => CmdType::WebDav,
+            scheme => return Err(anyhow!("unsupported scheme: {}", scheme)),
+        };
+
+        let fetch_env = &ENV.get().unwrap().fetch;
+        let profile = match cmd.get(2) {
+            Some(name) => Some(
+                fetch_env
+                    .get_profile(name)
+                    .ok_or_else(|| anyhow!("fetch profile not found: {}", name))?,
+            ),
+            None => url
+                .host_str()
+                .and_then(|host| fetch_env.match_profile(host)),
+        };
+
+        let mut remote_client = RemoteClient::connect(&url, profile).await?;
+        let path = remote_client.path_from_url(&url);
+        let files = remote_client.walk(&path).await;
+        remote_client.close().await;
+        let files = files?;
+
+        let chat_user = state.get_reader_chat(message.chat()).await?;
+
+        if let [file] = files.as_slice() {
+            return insert_fetch_task(&message, &state, &chat_user, &cmd_type, &url, profile, file)
+                .await;
+        }
+
+        fetch_files(
+            &message, &state, &chat_user, &cmd_type, &url, profile, &files,
+        )
+        .await
+    } else {
+        Err(anyhow!(format_unknown_command_help(PATTERN)))
+    }
+}
+
+// files of a directory are inserted as a batch, so that deleting the command cancels them all
+async fn fetch_files(
+    message: &TelegramMessage,
+    state: &AppState,
+    chat_user: &Chat,
+    cmd_type: &CmdType,
+    url: &Url,
+    profile: Option<&FetchProfile>,
+    files: &[RemoteFile],
+) -> Result<()> {
+    if files.is_empty() {
+        return Err(anyhow!("no file found in the directory"));
+    }
+
+    let mut batch_aborters = state.task_session.batch_aborters.lock().await;
+    // /fetch may be in a batch
+    #[allow(clippy::option_if_let_else)]
+    let (cancellation_token, wrapped_in_batch) =
+        if let Some(batch_aborter) = batch_aborters.get(&(chat_user.id(), message.id())) {
+            (batch_aborter.token.clone(), true)
+        } else {
+            let batch_aborter = BatchAborter::new();
+            let cancellation_token = batch_aborter.token.clone();
+            batch_aborters.insert((chat_user.id(), message.id()), batch_aborter);
+
+            (cancellation_token, false)
+        };
+    // allow cancellation
+    drop(batch_aborters);
+
+    let fut = async {
+        for file in files {
+            if let Err(e) =
+                insert_fetch_task(message, state, chat_user, cmd_type, url, profile, file)
+                    .await
+                    .context(file.path.clone())
+            {
+                e.send(message.clone()).await.unwrap_both().trace();
+            }
+        }
+    };
+
+    tokio::select! {
+        () = fut => {}
+        () = cancellation_token.cancelled() => {}
+    }
+
+    if !wrapped_in_batch {
+        let mut batch_aborters = state.task_session.batch_aborters.lock().await;
+        if let Some(batch_aborter) = batch_aborters.get_mut(&(chat_user.id(), message.id())) {
+            batch_aborter.processing = false;
+        }
+    }
+
+    Ok(())
+}
+
+async fn insert_fetch_task(
+    message: &TelegramMessage,
+    state: &AppState,
+    chat_user: &Chat,
+    cmd_type: &CmdType,
+    url: &Url,
+    profile: Option<&FetchProfile>,
+    file: &RemoteFile,
+) -> Result<()> {
+    let onedrive = &state.onedrive;
+    let task_session = &state.task_session;
+
+    // onedrive upload sessions don't accept empty files
+    if file.size == 0 {
+        return Err(anyhow!("empty file is not supported"));
+    }
+
+    let filename = preprocess_url_file_name(&file.name);
+
+    let mut file_url = url.clone();
+    file_url.set_path(&file.path);
+
+    let response = format!(
+        "{}\n\n{}",
+        file_url,
+        format_message_link(chat_user.pack(), message.id(), &filename)
+    );
+
+    // held until the task is inserted
+    let _quota_guard = task_session
+        .check_quota(message.sender().map(|sender| sender.id()), file.size)
+        .await?;
+
+    // in case if cancellation happens before inserting the task
+    let _aborters = state.task_session.task_aborters.lock().await;
+
+    let message_indicator_id = message
+        .respond(InputMessage::html(&response))
+        .await
+        .context(response)?
+        .id();
+
+    let root_path = Path::new(&onedrive.get_root_path(true).await?)
+        .join(&file.relative_dir)
+        .to_slash_lossy()
+        .to_string();
+
+    let upload_target = state
+        .storage
+        .create_upload_session(message.chat().id(), &root_path, &filename, file.size)
+        .await?;
+
+    let chat_bot_hex = message.chat().pack().to_hex();
+    let chat_user_hex = chat_user.pack().to_hex();
+
+    let auto_delete = state.should_auto_delete_in(message.chat().id());
+
+    task_session
+        .insert_task(InsertTask {
+            cmd_type: cmd_type.clone(),
+            filename: filename.clone(),
+            root_path,
+            url: Some(file_url.to_string()),
+            headers: None,
+            profile: profile.map(|profile| profile.name.clone()),
+            content: None,
+            spool_path: None,
+            upload_url: upload_target.upload_url,
+            current_length: upload_target.current_length,
+            total_length: file.size,
+            chat_id: chat_user.id(),
+            chat_bot_hex,
+            chat_user_hex,
+            chat_origin_hex: None,
+            message_id: message.id(),
+            message_indicator_id,
+            message_origin_id: None,
+            auto_delete,
+            sender_id: message.sender().map(|sender| sender.id()),
+        })
+        .await?;
+
+    tracing::info!(
+        "inserted {} task: {} size: {}",
+        cmd_type,
+        filename,
+        file.size
+    );
+
+    Ok(())
+}
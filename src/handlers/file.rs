@@ -5,33 +5,32 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use std::sync::atomic::Ordering;
-
 use super::utils::upload::upload_thumb;
 use crate::{
-    handlers::utils::{get_tg_file_size, message::format_message_link, preprocess_tg_file_name},
-    message::{ChatEntity, TelegramMessage},
+    client::utils::format_message_link,
+    handlers::utils::{get_tg_file_size, preprocess_tg_file_name},
+    message::TelegramMessage,
     state::AppState,
     tasker::{CmdType, InsertTask},
 };
 use anyhow::{anyhow, Context, Result};
 use grammers_client::{types::Media, InputMessage};
-use proc_macros::{check_in_group, check_od_login, check_senders, check_tg_login};
+use proc_macros::{check_chat, check_od_login, check_role, check_senders, check_tg_login};
 
 #[check_od_login]
 #[check_tg_login]
+#[check_role(Uploader)]
+#[check_chat]
 #[check_senders]
-#[check_in_group]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
-    let telegram_user = &state.telegram_user;
     let onedrive = &state.onedrive;
     let task_session = &state.task_session;
 
-    let chat_user = telegram_user
-        .get_chat(&ChatEntity::from(message.chat()))
-        .await?;
+    let chat_user = state.get_reader_chat(message.chat()).await?;
+
+    let reader = state.reader(chat_user.pack());
 
-    let message_user = telegram_user.get_message(&chat_user, message.id()).await?;
+    let message_user = reader.get_message(&chat_user, message.id()).await?;
 
     let media = message_user
         .media()
@@ -51,18 +50,23 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     };
 
     let uploaded = match media {
-        Media::Photo(file) => upload_thumb(state.clone(), file.thumbs()).await?,
-        Media::Document(file) => upload_thumb(state.clone(), file.thumbs()).await?,
-        Media::Sticker(file) => upload_thumb(state.clone(), file.document.thumbs()).await?,
+        Media::Photo(file) => upload_thumb(state.clone(), reader, file.thumbs()).await?,
+        Media::Document(file) => upload_thumb(state.clone(), reader, file.thumbs()).await?,
+        Media::Sticker(file) => upload_thumb(state.clone(), reader, file.document.thumbs()).await?,
         _ => Err(anyhow!(
             "media type is not one of photo, document and sticker",
         ))?,
     };
 
+    // held until the task is inserted
+    let _quota_guard = task_session
+        .check_quota(message.sender().map(|sender| sender.id()), total_length)
+        .await?;
+
     // in case if cancellation happens before inserting the task
     let _aborters = state.task_session.task_aborters.lock().await;
 
-    let response = format_message_link(chat_user.id(), message_id, &filename);
+    let response = format_message_link(chat_user.pack(), message_id, &filename);
     let message_indicator_id = match uploaded {
         Some(uploaded) => message
             .respond(InputMessage::html(&response).photo(uploaded))
@@ -80,20 +84,15 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
 
     let root_path = onedrive.get_root_path(true).await?;
 
-    let (upload_session, upload_session_meta) = onedrive
-        .multipart_upload_session_builder(&root_path, &filename)
+    let upload_target = state
+        .storage
+        .create_upload_session(message.chat().id(), &root_path, &filename, total_length)
         .await?;
 
-    // all task should be new, so this should always be 0
-    let current_length = upload_session_meta
-        .next_expected_ranges
-        .first()
-        .map_or(0, |range| range.start);
-
     let chat_bot_hex = message.chat().pack().to_hex();
     let chat_user_hex = chat_user.pack().to_hex();
 
-    let auto_delete = state.should_auto_delete.load(Ordering::Acquire);
+    let auto_delete = state.should_auto_delete_in(message.chat().id());
 
     task_session
         .insert_task(InsertTask {
@@ -101,8 +100,12 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
             filename: filename.clone(),
             root_path,
             url: None,
-            upload_url: upload_session.upload_url().to_string(),
-            current_length,
+            headers: None,
+            profile: None,
+            content: None,
+            spool_path: None,
+            upload_url: upload_target.upload_url,
+            current_length: upload_target.current_length,
             total_length,
             chat_id: chat_user.id(),
             chat_bot_hex,
@@ -112,6 +115,7 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
             message_indicator_id,
             message_origin_id: None,
             auto_delete,
+            sender_id: message.sender().map(|sender| sender.id()),
         })
         .await?;
 
@@ -5,23 +5,26 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use std::sync::atomic::Ordering;
-
-use super::utils::{message::get_message_from_link, upload::upload_thumb};
+use super::{
+    export::insert_export_task,
+    utils::{export::is_exportable, message::get_message_from_link, upload::upload_thumb},
+};
 use crate::{
-    handlers::utils::{get_tg_file_size, message::format_message_link, preprocess_tg_file_name},
-    message::{ChatEntity, TelegramMessage},
+    client::utils::format_message_link,
+    handlers::utils::{get_tg_file_size, preprocess_tg_file_name},
+    message::TelegramMessage,
     state::AppState,
     tasker::{CmdType, InsertTask},
 };
 use anyhow::{anyhow, Context, Result};
 use grammers_client::{types::Media, InputMessage};
-use proc_macros::{check_in_group, check_od_login, check_senders, check_tg_login};
+use proc_macros::{check_chat, check_od_login, check_role, check_senders, check_tg_login};
 
 #[check_od_login]
 #[check_tg_login]
+#[check_role(Uploader)]
+#[check_chat]
 #[check_senders]
-#[check_in_group]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     let telegram_user = &state.telegram_user;
     let onedrive = &state.onedrive;
@@ -31,14 +34,22 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
 
     let message_origin = get_message_from_link(telegram_user, &link).await?;
 
-    let chat_user = telegram_user
-        .get_chat(&ChatEntity::from(message.chat()))
-        .await?;
+    let chat_user = state.get_reader_chat(message.chat()).await?;
 
     let media = message_origin
         .media()
         .ok_or_else(|| anyhow!("message does not contain any media"))?;
 
+    if is_exportable(&media) {
+        return insert_export_task(&message, &state, &message_origin, Some(&link)).await;
+    }
+
+    if matches!(media, Media::Dice(_)) {
+        tracing::info!("dice message is ignored: {}", link);
+
+        return Ok(());
+    }
+
     let filename = preprocess_tg_file_name(&media);
 
     let total_length = get_tg_file_size(&media);
@@ -52,21 +63,28 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
 
     // send its file name and thumb if exists so that information of uploading successful can be showed
     let uploaded = match media {
-        Media::Photo(file) => upload_thumb(state.clone(), file.thumbs()).await?,
-        Media::Document(file) => upload_thumb(state.clone(), file.thumbs()).await?,
-        Media::Sticker(file) => upload_thumb(state.clone(), file.document.thumbs()).await?,
+        Media::Photo(file) => upload_thumb(state.clone(), telegram_user, file.thumbs()).await?,
+        Media::Document(file) => upload_thumb(state.clone(), telegram_user, file.thumbs()).await?,
+        Media::Sticker(file) => {
+            upload_thumb(state.clone(), telegram_user, file.document.thumbs()).await?
+        }
         _ => Err(anyhow!(
             "media type is not one of photo, document and sticker",
         ))?,
     };
 
+    // held until the task is inserted
+    let _quota_guard = task_session
+        .check_quota(message.sender().map(|sender| sender.id()), total_length)
+        .await?;
+
     // in case if cancellation happens before inserting the task
     let _aborters = state.task_session.task_aborters.lock().await;
 
     let response = format!(
         "{}\n\n{}",
         link,
-        format_message_link(chat_user.id(), message.id(), &filename)
+        format_message_link(chat_user.pack(), message.id(), &filename)
     );
     let message_indicator_id = match uploaded {
         Some(uploaded) => message
@@ -85,21 +103,16 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
 
     let root_path = onedrive.get_root_path(true).await?;
 
-    let (upload_session, upload_session_meta) = onedrive
-        .multipart_upload_session_builder(&root_path, &filename)
+    let upload_target = state
+        .storage
+        .create_upload_session(message.chat().id(), &root_path, &filename, total_length)
         .await?;
 
-    // all task should be new, so this should always be 0
-    let current_length = upload_session_meta
-        .next_expected_ranges
-        .first()
-        .map_or(0, |range| range.start);
-
     let chat_bot_hex = message.chat().pack().to_hex();
     let chat_user_hex = chat_user.pack().to_hex();
     let chat_origin_hex = message_origin.chat().pack().to_hex();
 
-    let auto_delete = state.should_auto_delete.load(Ordering::Acquire);
+    let auto_delete = state.should_auto_delete_in(message.chat().id());
 
     task_session
         .insert_task(InsertTask {
@@ -107,8 +120,12 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
             filename: filename.clone(),
             root_path,
             url: None,
-            upload_url: upload_session.upload_url().to_string(),
-            current_length,
+            headers: None,
+            profile: None,
+            content: None,
+            spool_path: None,
+            upload_url: upload_target.upload_url,
+            current_length: upload_target.current_length,
             total_length,
             chat_id: chat_user.id(),
             chat_bot_hex,
@@ -118,6 +135,7 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
             message_indicator_id,
             message_origin_id: Some(message_origin.id()),
             auto_delete,
+            sender_id: message.sender().map(|sender| sender.id()),
         })
         .await?;
 
@@ -125,3 +143,35 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
 
     Ok(())
 }
+
+pub struct LinkFile {
+    pub filename: String,
+    pub total_length: u64,
+    pub chat_origin_hex: String,
+    pub message_origin_id: i32,
+}
+
+// shared with the http api, which only transfers photos, documents and stickers
+pub async fn probe_link(link: &str, state: &AppState) -> Result<LinkFile> {
+    let message_origin = get_message_from_link(&state.telegram_user, link).await?;
+
+    let media = message_origin
+        .media()
+        .ok_or_else(|| anyhow!("message does not contain any media"))?;
+
+    if !matches!(
+        media,
+        Media::Photo(_) | Media::Document(_) | Media::Sticker(_)
+    ) {
+        return Err(anyhow!(
+            "media type is not one of photo, document and sticker",
+        ));
+    }
+
+    Ok(LinkFile {
+        filename: preprocess_tg_file_name(&media),
+        total_length: get_tg_file_size(&media),
+        chat_origin_hex: message_origin.chat().pack().to_hex(),
+        message_origin_id: message_origin.id(),
+    })
+}
@@ -15,20 +15,21 @@ use super::{
 };
 use crate::{
     error::ResultExt,
-    message::{ChatEntity, MessageInfo, TelegramMessage},
+    message::{MessageInfo, TelegramMessage},
     state::AppState,
     tasker::BatchAborter,
 };
 use anyhow::{anyhow, Context, Result};
 use grammers_client::InputMessage;
-use proc_macros::{check_in_group, check_od_login, check_senders, check_tg_login};
+use proc_macros::{check_chat, check_od_login, check_role, check_senders, check_tg_login};
 
 pub const PATTERN: &str = "/links";
 
 #[check_od_login]
 #[check_tg_login]
+#[check_role(Uploader)]
+#[check_chat]
 #[check_senders]
-#[check_in_group]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     let cmd = cmd_parser(message.text());
 
@@ -50,11 +51,7 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
             id: head_message_id,
         } = get_message_info(link_head)?;
 
-        let telegram_user = &state.telegram_user;
-
-        let chat_user = telegram_user
-            .get_chat(&ChatEntity::from(message.chat()))
-            .await?;
+        let chat_user = state.get_reader_chat(message.chat()).await?;
 
         let mut batch_aborters = state.task_session.batch_aborters.lock().await;
         // /links may be in a batch
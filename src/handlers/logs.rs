@@ -12,13 +12,14 @@ use super::{
 use crate::{client::TelegramClient, env::LOGS_PATH, message::TelegramMessage, state::AppState};
 use anyhow::{Context, Result};
 use grammers_client::InputMessage;
-use proc_macros::{check_in_group, check_senders};
+use proc_macros::{check_chat, check_role, check_senders};
 use tokio::fs;
 
 pub const PATTERN: &str = "/logs";
 
+#[check_role(Admin)]
+#[check_chat]
 #[check_senders]
-#[check_in_group]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     {
         let metadata = fs::metadata(LOGS_PATH).await;
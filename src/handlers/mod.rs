@@ -5,19 +5,27 @@
 :license: MIT, see LICENSE for more details.
 */
 
+pub mod api;
 pub mod auth;
 pub mod auto_delete;
+pub mod cancel;
 // pub mod batch;
 pub mod clear;
 pub mod dir;
 mod docs;
 pub mod drive;
+pub mod export;
+pub mod fetch;
 pub mod file;
 pub mod help;
 pub mod link;
 pub mod links;
 pub mod logs;
+pub mod note;
 pub mod start;
+pub mod text;
 pub mod url;
+pub mod usage;
+pub mod users;
 mod utils;
 pub mod version;
@@ -0,0 +1,105 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{
+        content::insert_content_task, message::get_message_from_link, preprocess_text_file_name,
+        text::cmd_parser,
+    },
+};
+use crate::{
+    env::{NoteFormat, ENV},
+    message::TelegramMessage,
+    state::AppState,
+    tasker::CmdType,
+};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::InputMessage;
+use proc_macros::{check_chat, check_od_login, check_role, check_senders, check_tg_login};
+
+pub const PATTERN: &str = "/note";
+
+#[check_od_login]
+#[check_tg_login]
+#[check_role(Uploader)]
+#[check_chat]
+#[check_senders]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 2 && cmd[1] == "help" {
+        // /note help
+        message
+            .respond(InputMessage::html(format_help(PATTERN)))
+            .await
+            .context("help")?;
+    } else if cmd.len() == 2 {
+        // /note $message_link
+        let link = &cmd[1];
+
+        let message_origin = get_message_from_link(&state.telegram_user, link).await?;
+
+        insert_note_task(message, state, message_origin, Some(link)).await?;
+    } else if cmd.len() == 1 {
+        // /note as a reply to a message
+        let message_origin_id = message
+            .reply_to_message_id()
+            .ok_or_else(|| anyhow!("reply /note to a message, or pass a message link"))?;
+
+        let chat_user = state.get_reader_chat(message.chat()).await?;
+
+        let message_origin = state
+            .reader(chat_user.pack())
+            .get_message(&chat_user, message_origin_id)
+            .await?;
+
+        insert_note_task(message, state, message_origin, None).await?;
+    } else {
+        return Err(anyhow!(format_unknown_command_help(PATTERN)));
+    }
+
+    Ok(())
+}
+
+// forwarded text-only message, only triggered when note_forwarded is enabled
+#[check_od_login]
+#[check_tg_login]
+#[check_senders]
+#[check_role(Uploader)]
+#[check_chat]
+pub async fn forward_handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    insert_note_task(message.clone(), state, message, None).await
+}
+
+async fn insert_note_task(
+    message: TelegramMessage,
+    state: AppState,
+    message_origin: TelegramMessage,
+    link: Option<&str>,
+) -> Result<()> {
+    let text = message_origin.raw.text();
+    if text.trim().is_empty() {
+        return Err(anyhow!("message does not contain any text"));
+    }
+
+    // html notes are kept as .md too, markdown renderers show inline html
+    let filename = preprocess_text_file_name(message_origin.date(), text, "md");
+
+    let content = render_note(&message_origin, ENV.get().unwrap().note.format);
+
+    insert_content_task(&message, &state, CmdType::Note, &filename, content, link).await
+}
+
+fn render_note(message: &TelegramMessage, format: NoteFormat) -> Vec<u8> {
+    let text = match format {
+        NoteFormat::Markdown => message.markdown_text(),
+        NoteFormat::Html => message.html_text(),
+    };
+
+    (text + "\n").into_bytes()
+}
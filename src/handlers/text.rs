@@ -0,0 +1,170 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{link, url, utils::text::extract_urls};
+use crate::{
+    error::{ErrorExt, ResultUnwrapExt},
+    message::TelegramMessage,
+    state::AppState,
+    tasker::{BatchAborter, BatchAborters},
+};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_chat, check_od_login, check_role, check_senders, check_tg_login};
+
+const TELEGRAM_LINK_PREFIX: &str = "https://t.me/";
+
+#[check_od_login]
+#[check_tg_login]
+#[check_role(Uploader)]
+#[check_chat]
+#[check_senders]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let links = extract_links(&message.plain_text(), message.hidden_urls());
+
+    if links.is_empty() {
+        return Err(anyhow!("no message link or url found in the message"));
+    }
+
+    if links.len() == 1 {
+        return dispatch_link(message, state, &links[0]).await;
+    }
+
+    tracing::info!("handle {} links in text", links.len());
+
+    let chat_user = state.get_reader_chat(message.chat()).await?;
+
+    let mut batch_aborters = state.task_session.batch_aborters.lock().await;
+    // text may be a line in a batch
+    #[allow(clippy::option_if_let_else)]
+    let (cancellation_token, wrapped_in_batch) =
+        if let Some(batch_aborter) = batch_aborters.get(&(chat_user.id(), message.id())) {
+            (batch_aborter.token.clone(), true)
+        } else {
+            let batch_aborter = BatchAborter::new();
+            let cancellation_token = batch_aborter.token.clone();
+            batch_aborters.insert((chat_user.id(), message.id()), batch_aborter);
+
+            (cancellation_token, false)
+        };
+    // allow cancellation
+    drop(batch_aborters);
+
+    // the batch of a line is cleaned up by the batch itself
+    let mut batch_guard = (!wrapped_in_batch).then(|| BatchGuard {
+        batch_aborters: state.task_session.batch_aborters.clone(),
+        key: (chat_user.id(), message.id()),
+        has_tasks: false,
+    });
+
+    let fut = async {
+        for link in &links {
+            if let Err(e) = dispatch_link(message.clone(), state.clone(), link)
+                .await
+                .context(link.clone())
+            {
+                e.send(message.clone()).await.unwrap_both().trace();
+
+                continue;
+            }
+
+            if let Some(batch_guard) = batch_guard.as_mut() {
+                batch_guard.has_tasks = true;
+            }
+        }
+    };
+
+    tokio::select! {
+        () = fut => {}
+        () = cancellation_token.cancelled() => {}
+    }
+
+    Ok(())
+}
+
+// however handling the links ends, the batch aborter is left to the tasks of the links,
+// or removed if no task was added
+struct BatchGuard {
+    batch_aborters: BatchAborters,
+    key: (i64, i32),
+    has_tasks: bool,
+}
+
+impl Drop for BatchGuard {
+    fn drop(&mut self) {
+        let batch_aborters = self.batch_aborters.clone();
+        let key = self.key;
+        let has_tasks = self.has_tasks;
+
+        // the lock can't be awaited here
+        tokio::spawn(async move {
+            let mut batch_aborters = batch_aborters.lock().await;
+
+            if has_tasks {
+                if let Some(batch_aborter) = batch_aborters.get_mut(&key) {
+                    batch_aborter.processing = false;
+                }
+            } else {
+                batch_aborters.remove(&key);
+            }
+        });
+    }
+}
+
+fn extract_links(text: &str, hidden_urls: Vec<String>) -> Vec<String> {
+    let mut links = Vec::new();
+
+    for url in extract_urls(text).into_iter().chain(hidden_urls) {
+        if !links.contains(&url) {
+            links.push(url);
+        }
+    }
+
+    links
+}
+
+async fn dispatch_link(message: TelegramMessage, state: AppState, link: &str) -> Result<()> {
+    let mut message_clone = message.clone();
+
+    if link.starts_with(TELEGRAM_LINK_PREFIX) {
+        message_clone.override_text(link.to_string());
+
+        link::handler(message_clone, state).await
+    } else {
+        message_clone.override_text(format!("{} {}", url::PATTERN, link));
+
+        url::handler(message_clone, state).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_links() {
+        let text = "https://t.me/c/1/2 and http://example.com/a.zip, again https://t.me/c/1/2";
+        let hidden_urls = vec![
+            "http://example.com/a.zip".to_string(),
+            "https://t.me/channel/3".to_string(),
+            "https://example.com/b.zip".to_string(),
+        ];
+
+        // visible links first, then hidden ones, each only once
+        let links = extract_links(text, hidden_urls);
+        assert_eq!(
+            links,
+            vec![
+                "https://t.me/c/1/2",
+                "http://example.com/a.zip",
+                "https://t.me/channel/3",
+                "https://example.com/b.zip",
+            ]
+        );
+
+        assert!(extract_links("no links here", Vec::new()).is_empty());
+    }
+}
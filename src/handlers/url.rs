@@ -5,35 +5,39 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use std::sync::atomic::Ordering;
-
 use super::{
     docs::{format_help, format_unknown_command_help},
     utils::{
-        get_filename,
+        get_filename, preprocess_url_file_name,
+        request::{parse_request_options, RequestOptions},
         text::{cmd_parser, TextExt},
     },
 };
 use crate::{
-    handlers::utils::message::format_message_link,
-    message::{ChatEntity, TelegramMessage},
+    client::utils::format_message_link,
+    env::ENV,
+    message::TelegramMessage,
     state::AppState,
     tasker::{CmdType, InsertTask},
-    utils::get_http_client,
+    utils::{get_http_client_for_url, send_with_host_headers, HttpRoute},
 };
 use anyhow::{anyhow, Context, Result};
 use grammers_client::InputMessage;
-use proc_macros::{check_in_group, check_od_login, check_senders, check_tg_login};
-use reqwest::header;
+use proc_macros::{check_chat, check_od_login, check_role, check_senders, check_tg_login};
+use reqwest::{header, Method, StatusCode};
+use url::Url;
 
 pub const PATTERN: &str = "/url";
 
 #[check_od_login]
 #[check_tg_login]
+#[check_role(Uploader)]
+#[check_chat]
 #[check_senders]
-#[check_in_group]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
-    let cmd = cmd_parser(message.text());
+    // options such as headers and cookies follow the command line by line
+    let text = message.text();
+    let cmd = cmd_parser(text.lines().next().unwrap_or_default());
 
     if cmd.len() == 2 {
         if cmd[1] == "help" {
@@ -46,48 +50,32 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
             Ok(())
         } else {
             // /url $url
-            let telegram_user = &state.telegram_user;
             let onedrive = &state.onedrive;
             let task_session = &state.task_session;
 
             let url = cmd[1].url_encode();
 
             if url.starts_with("http://") || url.starts_with("https://") {
-                let http_client = get_http_client()?;
+                let plain_text = message.plain_text();
+                let UrlFile {
+                    url,
+                    filename,
+                    total_length,
+                    headers,
+                    profile,
+                } = probe_url(&url, plain_text.lines().skip(1), &state).await?;
 
-                let response = http_client
-                    .head(&url)
-                    .send()
-                    .await
-                    .context("failed to send head request for /url")?;
-
-                let filename = get_filename(
-                    response.url().as_ref(),
-                    &response,
-                    &onedrive.get_root_path(false).await?,
-                )?;
-
-                let total_length = match response.headers().get(header::CONTENT_LENGTH) {
-                    Some(content_length) => content_length
-                        .to_str()
-                        .context("header Content-Length has invisible ASCII chars")?
-                        .parse::<u64>()
-                        .context("failed to parse header Content-Length to u64")?,
-                    None => return Err(anyhow!(
-                        "Content-Length not found in response headers.\nStatus code:\n{}\nResponse headers:\n{:#?}",
-                        response.status(),
-                        response.headers()
-                    )),
-                };
-
-                let chat_user = telegram_user
-                    .get_chat(&ChatEntity::from(message.chat()))
+                let chat_user = state.get_reader_chat(message.chat()).await?;
+
+                // held until the task is inserted
+                let _quota_guard = task_session
+                    .check_quota(message.sender().map(|sender| sender.id()), total_length)
                     .await?;
 
                 let response = format!(
                     "{}\n\n{}",
                     url,
-                    format_message_link(chat_user.id(), message.id(), &filename)
+                    format_message_link(chat_user.pack(), message.id(), &filename)
                 );
                 let message_indicator_id = message
                     .respond(InputMessage::html(&response))
@@ -97,19 +85,15 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
 
                 let root_path = onedrive.get_root_path(true).await?;
 
-                let (upload_session, upload_session_meta) = onedrive
-                    .multipart_upload_session_builder(&root_path, &filename)
+                let upload_target = state
+                    .storage
+                    .create_upload_session(message.chat().id(), &root_path, &filename, total_length)
                     .await?;
 
-                let current_length = upload_session_meta
-                    .next_expected_ranges
-                    .first()
-                    .map_or(0, |range| range.start);
-
                 let chat_bot_hex = message.chat().pack().to_hex();
                 let chat_user_hex = chat_user.pack().to_hex();
 
-                let auto_delete = state.should_auto_delete.load(Ordering::Acquire);
+                let auto_delete = state.should_auto_delete_in(message.chat().id());
 
                 // in case if cancellation happens before inserting the task
                 let _aborters = state.task_session.task_aborters.lock().await;
@@ -120,10 +104,14 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
                         filename: filename.clone(),
                         root_path,
                         url: Some(url),
-                        upload_url: upload_session.upload_url().to_string(),
-                        current_length,
+                        headers,
+                        profile,
+                        content: None,
+                        spool_path: None,
+                        upload_url: upload_target.upload_url,
+                        current_length: upload_target.current_length,
                         total_length,
-                        chat_id: message.chat().id(),
+                        chat_id: chat_user.id(),
                         chat_bot_hex,
                         chat_user_hex,
                         chat_origin_hex: None,
@@ -131,6 +119,7 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
                         message_indicator_id,
                         message_origin_id: None,
                         auto_delete,
+                        sender_id: message.sender().map(|sender| sender.id()),
                     })
                     .await?;
 
@@ -145,3 +134,95 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
         Err(anyhow!(format_unknown_command_help(PATTERN)))
     }
 }
+
+pub struct UrlFile {
+    // may differ from the requested url if it's a share page
+    pub url: String,
+    pub filename: String,
+    pub total_length: u64,
+    // request headers given with /url in json, only sent to the host of url
+    pub headers: Option<String>,
+    // name of the url profile given with /url
+    pub profile: Option<String>,
+}
+
+// shared with the http api, options are lines like those following /url
+pub async fn probe_url<'a, I>(url: &str, option_lines: I, state: &AppState) -> Result<UrlFile>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let url_env = &ENV.get().unwrap().url;
+
+    let RequestOptions {
+        mut headers,
+        profile,
+    } = parse_request_options(option_lines, url_env)?;
+
+    // resolvers talk to the apis of file hosting services, which get no credentials
+    let http_client = get_http_client_for_url(HttpRoute::Origin, url, &[])?;
+
+    // share pages of file hosting services are turned into direct download urls
+    let (resolved_url, resolved_filename) =
+        match state.url_resolvers.resolve(url, &http_client).await? {
+            Some(resolved) => (resolved.url, resolved.filename),
+            None => (url.to_string(), None),
+        };
+
+    let origin = Url::parse(url).context("failed to parse url")?;
+    let task_url = Url::parse(&resolved_url).context("failed to parse resolved url")?;
+
+    // the resolved url may be on another host
+    if task_url.origin() != origin.origin() {
+        headers.clear();
+    }
+
+    let response = send_with_host_headers(
+        HttpRoute::Origin,
+        &Method::HEAD,
+        task_url.as_str(),
+        |request_url| url_env.request_headers(profile.as_deref(), &headers, &task_url, request_url),
+    )
+    .await
+    .context("failed to send head request for /url")?;
+
+    let url = resolved_url;
+
+    let filename = match resolved_filename {
+        Some(filename) if response.status() == StatusCode::OK => {
+            preprocess_url_file_name(&filename)
+        }
+        _ => get_filename(
+            response.url().as_ref(),
+            &response,
+            &state.onedrive.get_root_path(false).await?,
+        )?,
+    };
+
+    let total_length = match response.headers().get(header::CONTENT_LENGTH) {
+        Some(content_length) => content_length
+            .to_str()
+            .context("header Content-Length has invisible ASCII chars")?
+            .parse::<u64>()
+            .context("failed to parse header Content-Length to u64")?,
+        None => {
+            return Err(anyhow!(
+                "Content-Length not found in response headers.\nStatus code:\n{}\nResponse headers:\n{:#?}",
+                response.status(),
+                response.headers()
+            ))
+        }
+    };
+
+    let headers = (!headers.is_empty())
+        .then(|| serde_json::to_string(&headers))
+        .transpose()
+        .context("failed to serialize request headers")?;
+
+    Ok(UrlFile {
+        url,
+        filename,
+        total_length,
+        headers,
+        profile,
+    })
+}
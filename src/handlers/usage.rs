@@ -0,0 +1,97 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{docs::format_help, utils::text::cmd_parser};
+use crate::{env::Quota, message::TelegramMessage, state::AppState};
+use anyhow::{Context, Result};
+use grammers_client::InputMessage;
+use proc_macros::{check_chat, check_role, check_senders};
+
+pub const PATTERN: &str = "/usage";
+
+#[check_role(Viewer)]
+#[check_chat]
+#[check_senders]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /usage
+        show_usage(message, state).await?;
+    } else if cmd.len() == 2 && cmd[1] == "help" {
+        // /usage help
+        message
+            .respond(InputMessage::html(format_help(PATTERN)))
+            .await
+            .context("help")?;
+    } else {
+        message
+            .reply(InputMessage::html(format_help(PATTERN)))
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn show_usage(message: TelegramMessage, state: AppState) -> Result<()> {
+    let usages = state.task_session.get_usages().await?;
+
+    if usages.is_empty() {
+        let response = "No transfers this month.";
+        message.respond(response).await.context(response)?;
+
+        return Ok(());
+    }
+
+    let mut blocks = Vec::new();
+
+    for (user_id, usage) in usages {
+        let quota = Quota::read(user_id);
+
+        let name = state
+            .acl
+            .get_user(user_id)
+            .await?
+            .and_then(|user| user.name)
+            .map(|name| format!(" ({})", name))
+            .unwrap_or_default();
+
+        blocks.push(format!(
+            "{}{}\nToday: {}, {} tasks\nThis month: {}, {} tasks\nPending: {} tasks, {} running",
+            user_id,
+            name,
+            format_limit(usage.daily_bytes, quota.daily_bytes, format_size),
+            format_limit(usage.daily_tasks, quota.daily_tasks, format_number),
+            format_limit(usage.monthly_bytes, quota.monthly_bytes, format_size),
+            format_limit(usage.monthly_tasks, quota.monthly_tasks, format_number),
+            format_limit(usage.pending_tasks, quota.max_pending, format_number),
+            format_limit(usage.running_tasks, quota.max_running, format_number),
+        ));
+    }
+
+    // days and months start at 00:00 utc
+    let response = format!("Usage (UTC):\n\n{}", blocks.join("\n\n"));
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+fn format_number(number: u64) -> String {
+    number.to_string()
+}
+
+fn format_size(size: u64) -> String {
+    format!("{:.2}MB", size as f64 / 1024.0 / 1024.0)
+}
+
+// the value alone if there is no limit
+fn format_limit(value: u64, limit: Option<u64>, format: impl Fn(u64) -> String) -> String {
+    match limit {
+        Some(limit) => format!("{}/{}", format(value), format(limit)),
+        None => format(value),
+    }
+}
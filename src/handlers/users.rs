@@ -0,0 +1,206 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::text::cmd_parser,
+};
+use crate::{acl::Role, message::TelegramMessage, state::AppState};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::InputMessage;
+use proc_macros::{check_chat, check_role, check_senders};
+
+pub const PATTERN: &str = "/users";
+
+#[check_role(Admin)]
+#[check_chat]
+#[check_senders]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /users
+        show_users(message, state).await
+    } else if cmd[1] == "help" && cmd.len() == 2 {
+        // /users help
+        message
+            .respond(InputMessage::html(format_help(PATTERN)))
+            .await
+            .context("help")?;
+
+        Ok(())
+    } else if cmd[1] == "add" {
+        // /users add $user_id $role
+        add_user(message, state, &cmd[2..]).await
+    } else if cmd[1] == "remove" {
+        // /users remove $user_id
+        remove_user(message, state, &cmd[2..]).await
+    } else if cmd[1] == "role" {
+        // /users role $user_id $role
+        set_role(message, state, &cmd[2..]).await
+    } else {
+        Err(anyhow!("sub command error")).context(format_unknown_command_help(PATTERN))
+    }
+}
+
+async fn show_users(message: TelegramMessage, state: AppState) -> Result<()> {
+    let acl = &state.acl;
+
+    if !acl.is_enabled().await? {
+        let response = "Access control is disabled, tg_user_name decides who can control the bot.\nAdd a user to enable it, you will be added as an admin.";
+        message.respond(response).await.context(response)?;
+
+        return Ok(());
+    }
+
+    let mut lines = acl
+        .admin_ids()
+        .iter()
+        .map(|user_id| format!("{} admin (tg_admin_ids)", user_id))
+        .collect::<Vec<_>>();
+
+    for user in acl.list().await? {
+        let name = user
+            .name
+            .map(|name| format!(" ({})", name))
+            .unwrap_or_default();
+
+        lines.push(format!("{} {}{}", user.user_id, user.role, name));
+    }
+
+    let response = format!("Users:\n{}", lines.join("\n"));
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn add_user(message: TelegramMessage, state: AppState, args: &[String]) -> Result<()> {
+    let (user_id, name, args) = get_target_user(&message, &state, args).await?;
+
+    let role = match args {
+        [] => Role::Uploader,
+        [role] => role.parse().map_err(|e: String| anyhow!(e))?,
+        _ => return Err(anyhow!(format_unknown_command_help(PATTERN))),
+    };
+
+    let acl = &state.acl;
+
+    // usernames aren't checked once the acl is enabled, so the sender becomes an admin first
+    if !acl.is_enabled().await? {
+        if let Some(sender) = message.sender() {
+            if sender.id() != user_id {
+                acl.add_user(
+                    sender.id(),
+                    Role::Admin,
+                    Some(sender.name().to_string()),
+                    None,
+                )
+                .await?;
+            }
+        }
+    }
+
+    let sender_id = message.sender().map(|sender| sender.id());
+
+    acl.add_user(user_id, role, name, sender_id).await?;
+
+    let response = format!("User {} is added as {}.", user_id, role);
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn remove_user(message: TelegramMessage, state: AppState, args: &[String]) -> Result<()> {
+    let (user_id, _, args) = get_target_user(&message, &state, args).await?;
+
+    if !args.is_empty() {
+        return Err(anyhow!(format_unknown_command_help(PATTERN)));
+    }
+
+    check_changeable(&message, &state, user_id)?;
+
+    let response = if state.acl.remove_user(user_id).await? {
+        tracing::info!("user {} removed", user_id);
+
+        format!("User {} is removed.", user_id)
+    } else {
+        format!("User {} is not in the list.", user_id)
+    };
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn set_role(message: TelegramMessage, state: AppState, args: &[String]) -> Result<()> {
+    let (user_id, _, args) = get_target_user(&message, &state, args).await?;
+
+    let [role] = args else {
+        return Err(anyhow!(format_unknown_command_help(PATTERN)));
+    };
+    let role = role.parse::<Role>().map_err(|e| anyhow!(e))?;
+
+    check_changeable(&message, &state, user_id)?;
+
+    let response = if state.acl.set_role(user_id, role).await? {
+        format!("User {} is {} now.", user_id, role)
+    } else {
+        format!(
+            "User {} is not in the list, add it with /users add {} {}.",
+            user_id, user_id, role
+        )
+    };
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+// the user id in the first argument, or the sender of the replied message
+async fn get_target_user<'a>(
+    message: &TelegramMessage,
+    state: &AppState,
+    args: &'a [String],
+) -> Result<(i64, Option<String>, &'a [String])> {
+    if let Some((user_id, args)) = args.split_first() {
+        if let Ok(user_id) = user_id.parse::<i64>() {
+            return Ok((user_id, None, args));
+        }
+    }
+
+    let reply_to_message_id = message
+        .reply_to_message_id()
+        .ok_or_else(|| anyhow!("pass a user id, or reply to a message of the user"))?;
+
+    let message_replied = state
+        .telegram_bot
+        .get_message(&message.chat(), reply_to_message_id)
+        .await?;
+
+    let sender = message_replied
+        .sender()
+        .ok_or_else(|| anyhow!("the replied message has no sender"))?;
+
+    Ok((sender.id(), Some(sender.name().to_string()), args))
+}
+
+// so that admins can't lock themselves out
+fn check_changeable(message: &TelegramMessage, state: &AppState, user_id: i64) -> Result<()> {
+    if state.acl.is_configured_admin(user_id) {
+        return Err(anyhow!(
+            "user {} is an admin in tg_admin_ids, change it in env or the config file",
+            user_id
+        ));
+    }
+
+    if message
+        .sender()
+        .is_some_and(|sender| sender.id() == user_id)
+    {
+        return Err(anyhow!("you can't remove yourself or change your own role"));
+    }
+
+    Ok(())
+}
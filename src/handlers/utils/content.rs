@@ -0,0 +1,100 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::{
+    client::utils::format_message_link,
+    message::TelegramMessage,
+    state::AppState,
+    tasker::{CmdType, InsertTask},
+};
+use anyhow::{Context, Result};
+use grammers_client::InputMessage;
+
+// insert a task whose file is generated in memory, such as notes and exports
+pub async fn insert_content_task(
+    message: &TelegramMessage,
+    state: &AppState,
+    cmd_type: CmdType,
+    filename: &str,
+    content: Vec<u8>,
+    link: Option<&str>,
+) -> Result<()> {
+    let onedrive = &state.onedrive;
+    let task_session = &state.task_session;
+
+    let total_length = content.len() as u64;
+
+    let chat_user = state.get_reader_chat(message.chat()).await?;
+
+    // held until the task is inserted
+    let _quota_guard = task_session
+        .check_quota(message.sender().map(|sender| sender.id()), total_length)
+        .await?;
+
+    // in case if cancellation happens before inserting the task
+    let _aborters = state.task_session.task_aborters.lock().await;
+
+    let response = {
+        let message_link = format_message_link(chat_user.pack(), message.id(), filename);
+
+        match link {
+            Some(link) => format!("{}\n\n{}", link, message_link),
+            None => message_link,
+        }
+    };
+    let message_indicator_id = message
+        .respond(InputMessage::html(&response))
+        .await
+        .context(response)?
+        .id();
+
+    let root_path = onedrive.get_root_path(true).await?;
+
+    let upload_target = state
+        .storage
+        .create_upload_session(message.chat().id(), &root_path, filename, total_length)
+        .await?;
+
+    let chat_bot_hex = message.chat().pack().to_hex();
+    let chat_user_hex = chat_user.pack().to_hex();
+
+    let auto_delete = state.should_auto_delete_in(message.chat().id());
+
+    task_session
+        .insert_task(InsertTask {
+            cmd_type: cmd_type.clone(),
+            filename: filename.to_string(),
+            root_path,
+            url: None,
+            headers: None,
+            profile: None,
+            content: Some(content),
+            spool_path: None,
+            upload_url: upload_target.upload_url,
+            current_length: upload_target.current_length,
+            total_length,
+            chat_id: chat_user.id(),
+            chat_bot_hex,
+            chat_user_hex,
+            chat_origin_hex: None,
+            message_id: message.id(),
+            message_indicator_id,
+            message_origin_id: None,
+            auto_delete,
+            sender_id: message.sender().map(|sender| sender.id()),
+        })
+        .await?;
+
+    tracing::info!(
+        "inserted {} task: {} size: {}",
+        cmd_type,
+        filename,
+        total_length
+    );
+
+    Ok(())
+}
@@ -0,0 +1,489 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::preprocess_text_file_name;
+use crate::env::{GeoFormat, ENV};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use grammers_client::{
+    grammers_tl_types as tl,
+    types::media::{Contact, Geo, Media, Poll},
+};
+use serde_json::json;
+
+pub struct Export {
+    pub filename: String,
+    pub content: Vec<u8>,
+}
+
+// media without a file that can be rendered into a file in memory
+pub const fn is_exportable(media: &Media) -> bool {
+    matches!(
+        media,
+        Media::Contact(_) | Media::Geo(_) | Media::GeoLive(_) | Media::Venue(_) | Media::Poll(_)
+    )
+}
+
+pub fn export_media(media: &Media, date: DateTime<Utc>) -> Result<Export> {
+    let geo_format = ENV.get().unwrap().export.geo_format;
+
+    match media {
+        Media::Contact(contact) => Ok(export_contact(&Card::new(contact), date)),
+        Media::Geo(geo) => export_place(&Place::new(geo, None, None), geo_format, date),
+        Media::GeoLive(geo_live) => {
+            let geo = geo_live
+                .geo
+                .as_ref()
+                .ok_or_else(|| anyhow!("live location does not contain any geo point"))?;
+
+            export_place(&Place::new(geo, None, None), geo_format, date)
+        }
+        Media::Venue(venue) => {
+            let geo = venue
+                .geo
+                .as_ref()
+                .ok_or_else(|| anyhow!("venue does not contain any geo point"))?;
+
+            export_place(
+                &Place::new(geo, Some(venue.title()), Some(venue.address())),
+                geo_format,
+                date,
+            )
+        }
+        Media::Poll(poll) => export_poll(&PollSummary::new(poll), date),
+        _ => Err(anyhow!(
+            "media type is not one of contact, location, venue and poll"
+        )),
+    }
+}
+
+struct Card<'c> {
+    first_name: &'c str,
+    last_name: &'c str,
+    phone_number: &'c str,
+    vcard: &'c str,
+}
+
+impl<'c> Card<'c> {
+    fn new(contact: &'c Contact) -> Self {
+        Self {
+            first_name: contact.first_name(),
+            last_name: contact.last_name(),
+            phone_number: contact.phone_number(),
+            vcard: contact.vcard(),
+        }
+    }
+}
+
+fn export_contact(card: &Card, date: DateTime<Utc>) -> Export {
+    let name = format!("{} {}", card.first_name, card.last_name);
+    let name = name.trim();
+
+    let title = if name.is_empty() {
+        card.phone_number
+    } else {
+        name
+    };
+
+    let filename = preprocess_text_file_name(date, title, "vcf");
+
+    // telegram keeps the original vcard if the contact is shared from a vcf file
+    let content = if card.vcard.trim().is_empty() {
+        format!(
+            "BEGIN:VCARD\r\nVERSION:3.0\r\nN:{};{};;;\r\nFN:{}\r\nTEL;TYPE=CELL:{}\r\nEND:VCARD\r\n",
+            escape_vcard(card.last_name),
+            escape_vcard(card.first_name),
+            escape_vcard(title),
+            card.phone_number
+        )
+    } else {
+        card.vcard.to_string()
+    };
+
+    Export {
+        filename,
+        content: content.into_bytes(),
+    }
+}
+
+struct Place<'p> {
+    latitude: f64,
+    longitude: f64,
+    accuracy_radius: Option<i32>,
+    name: Option<&'p str>,
+    address: Option<&'p str>,
+}
+
+impl<'p> Place<'p> {
+    fn new(geo: &Geo, name: Option<&'p str>, address: Option<&'p str>) -> Self {
+        Self {
+            latitude: geo.latitue(),
+            longitude: geo.longitude(),
+            accuracy_radius: geo.accuracy_radius(),
+            name,
+            address,
+        }
+    }
+}
+
+fn export_place(place: &Place, geo_format: GeoFormat, date: DateTime<Utc>) -> Result<Export> {
+    let title = place.name.unwrap_or("location");
+
+    let export = match geo_format {
+        GeoFormat::GeoJson => {
+            let feature = json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "Point",
+                    "coordinates": [place.longitude, place.latitude],
+                },
+                "properties": {
+                    "name": place.name,
+                    "address": place.address,
+                    "accuracy_radius": place.accuracy_radius,
+                    "date": date.to_rfc3339(),
+                },
+            });
+
+            Export {
+                filename: preprocess_text_file_name(date, title, "geojson"),
+                content: serde_json::to_vec_pretty(&feature)
+                    .context("failed to serialize geojson")?,
+            }
+        }
+        GeoFormat::Gpx => {
+            let mut waypoint = format!(
+                "  <wpt lat=\"{}\" lon=\"{}\">\n    <time>{}</time>\n",
+                place.latitude,
+                place.longitude,
+                date.to_rfc3339()
+            );
+
+            if let Some(name) = place.name {
+                waypoint.push_str(&format!("    <name>{}</name>\n", escape_xml(name)));
+            }
+
+            if let Some(address) = place.address {
+                waypoint.push_str(&format!("    <desc>{}</desc>\n", escape_xml(address)));
+            }
+
+            waypoint.push_str("  </wpt>\n");
+
+            let content = format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<gpx version=\"1.1\" creator=\"{}\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n{}</gpx>\n",
+                env!("CARGO_PKG_NAME"),
+                waypoint
+            );
+
+            Export {
+                filename: preprocess_text_file_name(date, title, "gpx"),
+                content: content.into_bytes(),
+            }
+        }
+    };
+
+    Ok(export)
+}
+
+struct PollSummary<'p> {
+    question: &'p str,
+    quiz: bool,
+    closed: bool,
+    multiple_choice: bool,
+    total_voters: Option<i32>,
+    answers: Vec<PollAnswer<'p>>,
+}
+
+struct PollAnswer<'p> {
+    text: &'p str,
+    voters: Option<i32>,
+    chosen: bool,
+    correct: bool,
+}
+
+impl<'p> PollSummary<'p> {
+    fn new(poll: &'p Poll) -> Self {
+        let voters = poll
+            .raw_results
+            .as_ref()
+            .and_then(|results| results.results.as_ref())
+            .map(|results| {
+                results
+                    .iter()
+                    .map(|result| {
+                        let tl::enums::PollAnswerVoters::Voters(voters) = result;
+
+                        voters
+                    })
+                    .collect::<Vec<&tl::types::PollAnswerVoters>>()
+            })
+            .unwrap_or_default();
+
+        let answers = poll
+            .raw
+            .answers
+            .iter()
+            .map(|answer| {
+                let tl::enums::PollAnswer::Answer(answer) = answer;
+                let tl::enums::TextWithEntities::Entities(text) = &answer.text;
+
+                let result = voters.iter().find(|voters| voters.option == answer.option);
+
+                PollAnswer {
+                    text: &text.text,
+                    voters: result.map(|result| result.voters),
+                    chosen: result.is_some_and(|result| result.chosen),
+                    correct: result.is_some_and(|result| result.correct),
+                }
+            })
+            .collect();
+
+        Self {
+            question: poll.question(),
+            quiz: poll.raw.quiz,
+            closed: poll.raw.closed,
+            multiple_choice: poll.raw.multiple_choice,
+            total_voters: poll
+                .raw_results
+                .as_ref()
+                .and_then(|results| results.total_voters),
+            answers,
+        }
+    }
+}
+
+fn export_poll(poll: &PollSummary, date: DateTime<Utc>) -> Result<Export> {
+    let answers = poll
+        .answers
+        .iter()
+        .map(|answer| {
+            json!({
+                "text": answer.text,
+                "voters": answer.voters,
+                "chosen": answer.chosen,
+                "correct": answer.correct,
+            })
+        })
+        .collect::<Vec<serde_json::Value>>();
+
+    let content = json!({
+        "question": poll.question,
+        "quiz": poll.quiz,
+        "closed": poll.closed,
+        "multiple_choice": poll.multiple_choice,
+        "total_voters": poll.total_voters,
+        "answers": answers,
+        "date": date.to_rfc3339(),
+    });
+
+    Ok(Export {
+        filename: preprocess_text_file_name(date, poll.question, "json"),
+        content: serde_json::to_vec_pretty(&content).context("failed to serialize poll")?,
+    })
+}
+
+fn escape_vcard(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(';', "\\;")
+        .replace('\n', "\\n")
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn date() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap()
+    }
+
+    fn place<'p>(name: Option<&'p str>, address: Option<&'p str>) -> Place<'p> {
+        Place {
+            latitude: 31.2304,
+            longitude: 121.4737,
+            accuracy_radius: Some(10),
+            name,
+            address,
+        }
+    }
+
+    #[test]
+    fn test_export_contact() {
+        let card = Card {
+            first_name: "Jane",
+            last_name: "Doe; Jr.",
+            phone_number: "+10000000000",
+            vcard: "",
+        };
+
+        let export = export_contact(&card, date());
+
+        assert_eq!(export.filename, "2024-01-02 Jane Doe; Jr..vcf");
+        // N is last name first, then first name
+        assert_eq!(
+            String::from_utf8(export.content).unwrap(),
+            "BEGIN:VCARD\r\nVERSION:3.0\r\nN:Doe\\; Jr.;Jane;;;\r\nFN:Jane Doe\\; Jr.\r\nTEL;TYPE=CELL:+10000000000\r\nEND:VCARD\r\n"
+        );
+
+        // no name, so the phone number is the title
+        let card = Card {
+            first_name: "",
+            last_name: "",
+            phone_number: "+10000000000",
+            vcard: " ",
+        };
+
+        let export = export_contact(&card, date());
+
+        assert_eq!(export.filename, "2024-01-02 +10000000000.vcf");
+        assert!(String::from_utf8(export.content)
+            .unwrap()
+            .contains("N:;;;;\r\nFN:+10000000000\r\n"));
+
+        // the original vcard is kept as is
+        let vcard = "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Jane\r\nEND:VCARD\r\n";
+        let card = Card {
+            first_name: "Jane",
+            last_name: "",
+            phone_number: "+10000000000",
+            vcard,
+        };
+
+        assert_eq!(export_contact(&card, date()).content, vcard.as_bytes());
+    }
+
+    #[test]
+    fn test_escape_vcard() {
+        assert_eq!(escape_vcard("a\\b,c;d\ne"), "a\\\\b\\,c\\;d\\ne");
+    }
+
+    #[test]
+    fn test_export_place_geojson() {
+        let export = export_place(
+            &place(Some("Bund"), Some("Zhongshan Road")),
+            GeoFormat::GeoJson,
+            date(),
+        )
+        .unwrap();
+
+        assert_eq!(export.filename, "2024-01-02 Bund.geojson");
+
+        let feature: serde_json::Value = serde_json::from_slice(&export.content).unwrap();
+
+        // geojson puts longitude first
+        assert_eq!(
+            feature["geometry"]["coordinates"],
+            json!([121.4737, 31.2304])
+        );
+        assert_eq!(feature["properties"]["name"], "Bund");
+        assert_eq!(feature["properties"]["address"], "Zhongshan Road");
+        assert_eq!(feature["properties"]["accuracy_radius"], 10);
+        assert_eq!(feature["properties"]["date"], date().to_rfc3339());
+
+        let export = export_place(&place(None, None), GeoFormat::GeoJson, date()).unwrap();
+
+        assert_eq!(export.filename, "2024-01-02 location.geojson");
+
+        let feature: serde_json::Value = serde_json::from_slice(&export.content).unwrap();
+
+        assert!(feature["properties"]["name"].is_null());
+    }
+
+    #[test]
+    fn test_export_place_gpx() {
+        let export = export_place(
+            &place(Some("Tom & Jerry's"), Some("<1> \"Main\" St")),
+            GeoFormat::Gpx,
+            date(),
+        )
+        .unwrap();
+
+        assert_eq!(export.filename, "2024-01-02 Tom & Jerry's.gpx");
+
+        let content = String::from_utf8(export.content).unwrap();
+
+        assert!(content.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<gpx "));
+        assert!(content.contains("<wpt lat=\"31.2304\" lon=\"121.4737\">"));
+        assert!(content.contains(&format!("<time>{}</time>", date().to_rfc3339())));
+        assert!(content.contains("<name>Tom &amp; Jerry&apos;s</name>"));
+        assert!(content.contains("<desc>&lt;1&gt; &quot;Main&quot; St</desc>"));
+        assert!(content.ends_with("</wpt>\n</gpx>\n"));
+
+        let content = String::from_utf8(
+            export_place(&place(None, None), GeoFormat::Gpx, date())
+                .unwrap()
+                .content,
+        )
+        .unwrap();
+
+        assert!(!content.contains("<name>"));
+        assert!(!content.contains("<desc>"));
+    }
+
+    #[test]
+    fn test_export_poll() {
+        let poll = PollSummary {
+            question: "Which one?\nPick wisely",
+            quiz: true,
+            closed: false,
+            multiple_choice: false,
+            total_voters: Some(3),
+            answers: vec![
+                PollAnswer {
+                    text: "A",
+                    voters: Some(1),
+                    chosen: true,
+                    correct: false,
+                },
+                PollAnswer {
+                    text: "B",
+                    voters: Some(2),
+                    chosen: false,
+                    correct: true,
+                },
+                PollAnswer {
+                    text: "C",
+                    voters: None,
+                    chosen: false,
+                    correct: false,
+                },
+            ],
+        };
+
+        let export = export_poll(&poll, date()).unwrap();
+
+        assert_eq!(export.filename, "2024-01-02 Which one_.json");
+
+        let content: serde_json::Value = serde_json::from_slice(&export.content).unwrap();
+
+        assert_eq!(
+            content,
+            json!({
+                "question": "Which one?\nPick wisely",
+                "quiz": true,
+                "closed": false,
+                "multiple_choice": false,
+                "total_voters": 3,
+                "answers": [
+                    {"text": "A", "voters": 1, "chosen": true, "correct": false},
+                    {"text": "B", "voters": 2, "chosen": false, "correct": true},
+                    {"text": "C", "voters": null, "chosen": false, "correct": false},
+                ],
+                "date": date().to_rfc3339(),
+            })
+        );
+    }
+}
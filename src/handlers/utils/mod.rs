@@ -5,7 +5,10 @@
 :license: MIT, see LICENSE for more details.
 */
 
+pub mod content;
+pub mod export;
 pub mod message;
+pub mod request;
 pub mod text;
 pub mod upload;
 pub mod zip;
@@ -16,6 +19,7 @@ use crate::{
     utils::{get_current_timestamp, get_ext},
 };
 use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
 use grammers_client::types::media::{Document, Media};
 use mime_guess::get_mime_extensions_str;
 use percent_encoding::percent_decode_str;
@@ -26,6 +30,8 @@ use url::Url;
 
 // according to https://support.microsoft.com/en-us/office/restrictions-and-limitations-in-onedrive-and-sharepoint-64883a5d-228e-48f5-b3d2-eb39e07630fa#filenamepathlengths
 const MAX_FILE_NAME_LEN: usize = 400;
+// only a short part of the first line is used as the file name of notes and exports
+const MAX_TEXT_TITLE_LEN: usize = 64;
 
 pub fn get_filename(url: &str, response: &Response, od_root_path: &str) -> Result<String> {
     if response.status() != StatusCode::OK {
@@ -218,7 +224,7 @@ pub async fn validate_root_path(root_path: &str) -> Result<()> {
     Ok(())
 }
 
-fn preprocess_url_file_name(filename: &str) -> String {
+pub fn preprocess_url_file_name(filename: &str) -> String {
     if validate_filename(filename) {
         filename
             .trim()
@@ -260,6 +266,32 @@ pub fn preprocess_tg_file_name(media: &Media) -> String {
     }
 }
 
+pub fn preprocess_text_file_name(date: DateTime<Utc>, text: &str, ext: &str) -> String {
+    let mut title = text
+        .lines()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty())
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_TEXT_TITLE_LEN)
+        .collect::<String>();
+
+    for component in INVALID_COMPONENT {
+        title = title.replace(component, "_");
+    }
+
+    let title = title.trim_start_matches(INVALID_NAME_PREFIX).trim();
+
+    let date = date.format("%Y-%m-%d");
+
+    if title.is_empty() {
+        format!("{}.{}", date, ext)
+    } else {
+        format!("{} {}.{}", date, title, ext)
+    }
+}
+
 fn get_tg_document_name_and_id(document: &Document) -> (String, i64) {
     let mut filename = document.name().to_string();
     let file_id = document.id();
@@ -288,3 +320,45 @@ pub fn get_tg_file_size(media: &Media) -> u64 {
 
     size as u64
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_preprocess_text_file_name() {
+        let date = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+
+        // the first line that isn't blank
+        assert_eq!(
+            preprocess_text_file_name(date, "\n  hello world  \nsecond line", "md"),
+            "2024-01-02 hello world.md"
+        );
+        assert_eq!(
+            preprocess_text_file_name(date, " \n\t", "html"),
+            "2024-01-02.html"
+        );
+
+        // truncated by chars, not bytes
+        assert_eq!(
+            preprocess_text_file_name(date, &"a".repeat(100), "md"),
+            format!("2024-01-02 {}.md", "a".repeat(MAX_TEXT_TITLE_LEN))
+        );
+        assert_eq!(
+            preprocess_text_file_name(date, &"\u{e9}".repeat(100), "md"),
+            format!("2024-01-02 {}.md", "\u{e9}".repeat(MAX_TEXT_TITLE_LEN))
+        );
+
+        // invalid components, control chars and the invalid prefix are dropped or replaced
+        assert_eq!(
+            preprocess_text_file_name(date, "a/b:c?*d|e#f<g>h\"i\\j _vti_", "md"),
+            "2024-01-02 a_b_c__d_e_f_g_h_i_j _.md"
+        );
+        assert_eq!(
+            preprocess_text_file_name(date, "~$draft\u{7}", "md"),
+            "2024-01-02 draft.md"
+        );
+        assert_eq!(preprocess_text_file_name(date, "~$", "md"), "2024-01-02.md");
+    }
+}
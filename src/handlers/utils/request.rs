@@ -0,0 +1,77 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::{
+    env::UrlEnv,
+    utils::{basic_auth_header, RequestHeaders},
+};
+use anyhow::{anyhow, Context, Result};
+use reqwest::header;
+
+pub struct RequestOptions {
+    pub headers: RequestHeaders,
+    // otherwise the profile matching the host of each request is used
+    pub profile: Option<String>,
+}
+
+// each option line of /url is in the form of `key: value`, e.g.
+// header: X-Api-Key: xxx
+// cookie: session=xxx
+pub fn parse_request_options<'a, I>(option_lines: I, url_env: &UrlEnv) -> Result<RequestOptions>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut profile = None;
+    let mut headers = RequestHeaders::new();
+
+    for line in option_lines {
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once(':')
+            .map(|(key, value)| (key.trim().to_lowercase(), value.trim()))
+            .ok_or_else(|| anyhow!("option should be in the form of key: value"))
+            .context(line.to_string())?;
+
+        match key.as_str() {
+            "header" => {
+                let (name, value) = value
+                    .split_once(':')
+                    .ok_or_else(|| anyhow!("header should be in the form of header: Name: value"))
+                    .context(line.to_string())?;
+
+                headers.push((name.trim().to_string(), value.trim().to_string()));
+            }
+            "cookie" => headers.push((header::COOKIE.to_string(), value.to_string())),
+            "user-agent" | "ua" => {
+                headers.push((header::USER_AGENT.to_string(), value.to_string()));
+            }
+            "referer" => headers.push((header::REFERER.to_string(), value.to_string())),
+            "auth" => headers.push((header::AUTHORIZATION.to_string(), basic_auth_header(value))),
+            "bearer" => headers.push((
+                header::AUTHORIZATION.to_string(),
+                format!("Bearer {}", value),
+            )),
+            "profile" => {
+                let profile_name = url_env
+                    .get_profile(value)
+                    .ok_or_else(|| anyhow!("url profile not found: {}", value))?
+                    .name
+                    .clone();
+
+                profile = Some(profile_name);
+            }
+            _ => return Err(anyhow!("unknown option: {}", key)),
+        }
+    }
+
+    Ok(RequestOptions { headers, profile })
+}
@@ -22,6 +22,23 @@ where
         .collect()
 }
 
+// extract all http(s) urls in the text in order of appearance
+pub fn extract_urls(text: &str) -> Vec<String> {
+    let pattern = r#"https?://[^\s<>"'`]+"#;
+    let re = Regex::new(pattern)
+        .context("invalid regex pattern")
+        .context(pattern)
+        .unwrap_or_trace();
+
+    re.find_iter(text)
+        .map(|m| {
+            m.as_str()
+                .trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']', '}'])
+                .to_string()
+        })
+        .collect()
+}
+
 pub trait TextExt {
     fn purify(&self) -> String;
     fn url_encode(&self) -> String;
@@ -71,3 +88,35 @@ where
             .to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_urls() {
+        // in order of appearance, duplicates included
+        assert_eq!(
+            extract_urls("see https://t.me/c/1/2 and http://example.com/a.zip\nhttps://t.me/c/1/2"),
+            vec![
+                "https://t.me/c/1/2",
+                "http://example.com/a.zip",
+                "https://t.me/c/1/2",
+            ]
+        );
+
+        // trailing punctuation isn't part of the url
+        assert_eq!(
+            extract_urls("(https://example.com/a?b=1&c=2), https://example.com/b."),
+            vec!["https://example.com/a?b=1&c=2", "https://example.com/b"]
+        );
+
+        // quotes and brackets end the url
+        assert_eq!(
+            extract_urls(r#"<a href="https://example.com/a">'https://example.com/b'</a>"#),
+            vec!["https://example.com/a", "https://example.com/b"]
+        );
+
+        assert!(extract_urls("ftp://example.com t.me/c/1/2 https://").is_empty());
+    }
+}
@@ -38,6 +38,8 @@ pub enum EventType {
     Command(String),
     Text,
     Media,
+    Note,
+    Export,
 }
 
 impl EventType {
@@ -53,11 +55,21 @@ impl EventType {
         Self::Media
     }
 
+    pub const fn note() -> Self {
+        Self::Note
+    }
+
+    pub const fn export() -> Self {
+        Self::Export
+    }
+
     pub fn to_str(&self) -> &str {
         match self {
             Self::Command(command) => command.as_str(),
             Self::Text => "__TEXT__",
             Self::Media => "__MEDIA__",
+            Self::Note => "__NOTE__",
+            Self::Export => "__EXPORT__",
         }
     }
 }
@@ -77,6 +89,10 @@ impl From<&String> for EventType {
             Self::Text
         } else if value == Self::Media.to_str() {
             Self::Media
+        } else if value == Self::Note.to_str() {
+            Self::Note
+        } else if value == Self::Export.to_str() {
+            Self::Export
         } else {
             Self::Command(value.to_string())
         }
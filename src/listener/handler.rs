@@ -7,8 +7,9 @@
 
 use super::{EventType, Events};
 use crate::{
+    env::ENV,
     error::{ErrorExt, ResultUnwrapExt},
-    message::{ChatEntity, TelegramMessage},
+    message::TelegramMessage,
     state::AppState,
     tasker::BatchAborter,
 };
@@ -34,11 +35,20 @@ impl<'h> Handler<'h> {
                 Media::Photo(_) | Media::Document(_) | Media::Sticker(_) => {
                     self.handle_media(message).await?;
                 }
+                Media::Contact(_)
+                | Media::Geo(_)
+                | Media::GeoLive(_)
+                | Media::Venue(_)
+                | Media::Poll(_) => {
+                    self.handle_export(message).await?;
+                }
+                // games are not converted to media and carry no text, so they are ignored as well
+                Media::Dice(_) => tracing::debug!("dice is ignored when handle message"),
                 // sending a task with a link may cause the text being wrapped as a web page
-                Media::WebPage(_) => self.handle_text(message).await?,
+                Media::WebPage(_) => self.handle_text_or_note(message).await?,
                 _ => tracing::debug!("unsupported media type when handle message"),
             },
-            None => self.handle_text(message).await?,
+            None => self.handle_text_or_note(message).await?,
         }
 
         Ok(())
@@ -76,28 +86,48 @@ impl<'h> Handler<'h> {
         Ok(())
     }
 
+    async fn handle_text_or_note(&self, message: TelegramMessage) -> Result<()> {
+        let should_archive_forwarded = ENV.get().unwrap().note.should_archive_forwarded;
+
+        if should_archive_forwarded && message.is_forwarded() {
+            self.handle_note(message).await
+        } else {
+            self.handle_text(message).await
+        }
+    }
+
+    async fn handle_note(&self, message: TelegramMessage) -> Result<()> {
+        tracing::info!("handle note");
+
+        self.trigger(EventType::Note, message).await
+    }
+
     async fn handle_media(&self, message: TelegramMessage) -> Result<()> {
         tracing::info!("handle media");
 
         self.trigger(EventType::Media, message).await
     }
 
+    async fn handle_export(&self, message: TelegramMessage) -> Result<()> {
+        tracing::info!("handle export");
+
+        self.trigger(EventType::Export, message).await
+    }
+
     async fn handle_batch(&self, message: TelegramMessage) -> Result<()> {
         tracing::info!("handle batch");
 
-        let telegram_user = self.state.telegram_user.clone();
+        let chat_user = self.state.get_reader_chat(message.chat()).await?;
 
-        let chat_user = telegram_user
-            .get_chat(&ChatEntity::from(message.chat()))
-            .await?;
+        let reader = self.state.reader(chat_user.pack());
 
-        let message_user = telegram_user.get_message(&chat_user, message.id()).await?;
+        let message_user = reader.get_message(&chat_user, message.id()).await?;
 
         let media = message_user
             .media()
             .ok_or_else(|| anyhow!("message does not contain any media"))?;
 
-        let mut download = telegram_user.iter_download(&media);
+        let mut download = reader.iter_download(&media);
         let mut batch_bytes = Vec::new();
         while let Some(chunk) = download
             .next()
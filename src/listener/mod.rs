@@ -7,6 +7,7 @@
 
 mod events;
 mod handler;
+mod reload;
 
 use crate::{
     client::utils::chat_from_hex,
@@ -14,6 +15,7 @@ use crate::{
     message::{ChatEntity, TelegramMessage},
     state::{AppState, State},
     tasker::Tasker,
+    web,
 };
 use anyhow::{Ok, Result};
 use events::Events;
@@ -42,6 +44,10 @@ impl Listener {
             tasker.run().await;
         });
 
+        tokio::spawn(reload::watch_config(self.state.clone()));
+
+        web::spawn(self.state.clone()).await.unwrap_or_trace();
+
         let state = self.state.clone();
         tokio::spawn(async move {
             loop {
@@ -68,6 +74,10 @@ impl Listener {
                 if !message_raw.outgoing() {
                     let message = TelegramMessage::new(client.clone(), message_raw);
 
+                    self.state
+                        .latest_messages
+                        .record(message.chat().id(), message.id());
+
                     let handler = Handler::new(&self.events, self.state.clone());
                     if let Err(e) = handler.handle_message(message.clone()).await {
                         e.send(message).await.unwrap_both().trace();
@@ -79,7 +89,8 @@ impl Listener {
                 // bot can only catch deleted message immediately if it is sent by itself
                 let mut task_aborters = task_session.task_aborters.lock().await;
 
-                // ignore the deletion in none-channel chat
+                // ignore the deletion in none-channel chat, deletions in private chats aren't sent
+                // to the bot, and /cancel is used instead
                 if let Some(chat_id) = messages_info.channel_id() {
                     for message_indicator_id in messages_info.messages() {
                         if let Some(task_aborter) =
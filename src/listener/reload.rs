@@ -0,0 +1,87 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::{
+    env::{apply_config_file, ConfigFile, Env, ENV},
+    error::ResultExt,
+    state::AppState,
+    trace::{reload_secrets, set_trace_level},
+};
+use anyhow::{Context, Result};
+use std::{
+    fs,
+    sync::atomic::Ordering,
+    time::{Duration, SystemTime},
+};
+use tokio::time::sleep;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+// only settings that are safe to change while running are applied, others need a restart
+pub async fn watch_config(state: AppState) {
+    let Some(path) = &ENV.get().unwrap().config.path else {
+        return;
+    };
+
+    let mut modified = get_modified(path);
+
+    loop {
+        sleep(POLL_INTERVAL).await;
+
+        let new_modified = get_modified(path);
+        if new_modified == modified {
+            continue;
+        }
+        modified = new_modified;
+
+        reload_config(path, &state).trace();
+    }
+}
+
+fn get_modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn reload_config(path: &str, state: &AppState) -> Result<()> {
+    // the current settings are kept if the file is invalid
+    let config = ConfigFile::load(path).context("config file not reloaded")?;
+
+    let changed_keys = apply_config_file(&config);
+
+    reload_secrets(ENV.get().unwrap());
+
+    let mut restart_keys = Vec::new();
+
+    for key in changed_keys {
+        match key.as_str() {
+            "worker_num" => state.handler_limit.resize(Env::read_task_handler_num()),
+            "trace_level" => set_trace_level(&Env::read_trace_level())?,
+            "auto_delete" => state
+                .should_auto_delete
+                .store(Env::read_should_auto_delete(), Ordering::Release),
+            // read when tasks are added
+            "chats" => {}
+            // read on every check
+            "quotas" => {}
+            key if key.starts_with("quota_") => {}
+            _ => restart_keys.push(key),
+        }
+    }
+
+    tracing::info!("config file reloaded");
+
+    if !restart_keys.is_empty() {
+        tracing::warn!(
+            "changes of {} take effect after restart",
+            restart_keys.join(", ")
+        );
+    }
+
+    Ok(())
+}
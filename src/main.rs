@@ -5,21 +5,29 @@
 :license: MIT, see LICENSE for more details.
 */
 
+mod acl;
 mod auth_server;
 mod client;
+mod crypto;
 mod env;
 mod error;
 mod handlers;
 mod listener;
 mod message;
+mod metrics;
+mod resolver;
 mod state;
+mod storage;
 mod tasker;
 mod trace;
 mod utils;
+mod web;
 
 use env::{Env, ENV};
+use error::ResultExt;
 use handlers::{
-    auth, auto_delete, clear, dir, drive, file, help, link, links, logs, start, url, version,
+    api, auth, auto_delete, cancel, clear, dir, drive, export, fetch, file, help, link, links,
+    logs, note, start, text, url, usage, users, version,
 };
 use listener::{EventType, HashMapExt, Listener};
 use std::collections::HashMap;
@@ -34,6 +42,13 @@ async fn main() {
 
     trace_registor();
 
+    // re-encrypts the sessions with session_key_new and exits
+    if std::env::args().nth(1).as_deref() == Some("rotate-key") {
+        client::rotate_session_key().await.unwrap_or_trace();
+
+        return;
+    }
+
     let events = HashMap::new()
         .on(EventType::command(start::PATTERN), start::handler)
         .on(EventType::command(help::PATTERN), help::handler)
@@ -44,13 +59,21 @@ async fn main() {
         .on(EventType::command(logs::PATTERN), logs::handler)
         .on(EventType::command(auth::PATTERN), auth::handler)
         .on(EventType::command(clear::PATTERN), clear::handler)
+        .on(EventType::command(cancel::PATTERN), cancel::handler)
         .on(EventType::command(dir::PATTERN), dir::handler)
         .on(EventType::command(drive::PATTERN), drive::handler)
+        .on(EventType::command(api::PATTERN), api::handler)
+        .on(EventType::command(users::PATTERN), users::handler)
+        .on(EventType::command(usage::PATTERN), usage::handler)
         .on(EventType::command(url::PATTERN), url::handler)
+        .on(EventType::command(fetch::PATTERN), fetch::handler)
         .on(EventType::command(links::PATTERN), links::handler)
+        .on(EventType::command(note::PATTERN), note::handler)
         .on(EventType::command(version::PATTERN), version::handler)
         .on(EventType::media(), file::handler)
-        .on(EventType::text(), link::handler);
+        .on(EventType::export(), export::handler)
+        .on(EventType::text(), text::handler)
+        .on(EventType::note(), note::forward_handler);
 
     Listener::new(events).await.run().await;
 }
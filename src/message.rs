@@ -7,7 +7,11 @@
 
 use crate::client::TelegramClient;
 use anyhow::Result;
-use grammers_client::types::{Chat, InputMessage, Media, Message, PackedChat};
+use chrono::{DateTime, Utc};
+use grammers_client::{
+    grammers_tl_types as tl,
+    types::{Chat, InputMessage, Media, Message, PackedChat},
+};
 use std::sync::Arc;
 use tokio::sync::mpsc::Sender;
 
@@ -41,10 +45,59 @@ impl TelegramMessage {
             .unwrap_or_else(|| self.raw.html_text())
     }
 
+    // text without formatting
+    pub fn plain_text(&self) -> String {
+        self.text_override
+            .clone()
+            .unwrap_or_else(|| self.raw.text().to_string())
+    }
+
+    // urls hidden behind text links and link previews
+    // they belong to the original message, so none is returned once the text is overridden
+    pub fn hidden_urls(&self) -> Vec<String> {
+        if self.text_override.is_some() {
+            return Vec::new();
+        }
+
+        let media = self.media();
+        let web_page = match &media {
+            Some(Media::WebPage(web_page)) => Some(&web_page.raw.webpage),
+            _ => None,
+        };
+
+        let entities = self
+            .raw
+            .fmt_entities()
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        collect_hidden_urls(entities, web_page)
+    }
+
     pub fn id(&self) -> i32 {
         self.raw.id()
     }
 
+    pub fn markdown_text(&self) -> String {
+        self.raw.markdown_text()
+    }
+
+    pub fn html_text(&self) -> String {
+        self.raw.html_text()
+    }
+
+    pub fn date(&self) -> DateTime<Utc> {
+        self.raw.date()
+    }
+
+    pub fn is_forwarded(&self) -> bool {
+        self.raw.forward_header().is_some()
+    }
+
+    pub fn reply_to_message_id(&self) -> Option<i32> {
+        self.raw.reply_to_message_id()
+    }
+
     pub fn media(&self) -> Option<Media> {
         self.raw.media()
     }
@@ -135,3 +188,75 @@ impl MessageInfo {
         Self { chat_entity, id }
     }
 }
+
+fn collect_hidden_urls(
+    entities: &[tl::enums::MessageEntity],
+    web_page: Option<&tl::enums::WebPage>,
+) -> Vec<String> {
+    let mut urls = entities
+        .iter()
+        .filter_map(|entity| match entity {
+            tl::enums::MessageEntity::TextUrl(entity) => Some(entity.url.clone()),
+            _ => None,
+        })
+        .collect::<Vec<String>>();
+
+    let url = match web_page {
+        Some(tl::enums::WebPage::Page(page)) => Some(page.url.clone()),
+        Some(tl::enums::WebPage::Pending(page)) => page.url.clone(),
+        Some(tl::enums::WebPage::Empty(page)) => page.url.clone(),
+        Some(tl::enums::WebPage::NotModified(_)) | None => None,
+    };
+
+    if let Some(url) = url {
+        urls.push(url);
+    }
+
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_url(offset: i32, length: i32, url: &str) -> tl::enums::MessageEntity {
+        tl::types::MessageEntityTextUrl {
+            offset,
+            length,
+            url: url.to_string(),
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_collect_hidden_urls() {
+        let entities: Vec<tl::enums::MessageEntity> = vec![
+            tl::types::MessageEntityBold {
+                offset: 0,
+                length: 4,
+            }
+            .into(),
+            text_url(5, 4, "https://example.com/a"),
+            // visible urls are already in the text
+            tl::types::MessageEntityUrl {
+                offset: 10,
+                length: 21,
+            }
+            .into(),
+            text_url(32, 4, "https://t.me/c/1/2"),
+            text_url(37, 4, "https://example.com/a"),
+        ];
+
+        // in order, duplicates are left to the caller
+        assert_eq!(
+            collect_hidden_urls(&entities, None),
+            vec![
+                "https://example.com/a",
+                "https://t.me/c/1/2",
+                "https://example.com/a",
+            ]
+        );
+
+        assert!(collect_hidden_urls(&[], None).is_empty());
+    }
+}
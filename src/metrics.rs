@@ -0,0 +1,310 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+// counters exposed at /metrics in the prometheus text format,
+// gauges of the queue are read from the task session when scraped
+
+use grammers_client::InvocationError;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+const PREFIX: &str = "telegram_onedrive";
+// upper bounds in seconds
+const LATENCY_BUCKETS: [f64; 9] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+pub static METRICS: Metrics = Metrics::new();
+
+pub struct Metrics {
+    active_workers: AtomicI64,
+    // (source, account) -> bytes
+    transferred_bytes: Mutex<BTreeMap<(String, String), u64>>,
+    part_upload_latency: Mutex<Histogram>,
+    // operation -> retries
+    retries: Mutex<BTreeMap<&'static str, u64>>,
+    token_refresh_failures: AtomicU64,
+    flood_waits: AtomicU64,
+}
+
+struct Histogram {
+    // not cumulative, the last one is for +Inf
+    counts: [u64; LATENCY_BUCKETS.len() + 1],
+    sum: f64,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            active_workers: AtomicI64::new(0),
+            transferred_bytes: Mutex::new(BTreeMap::new()),
+            part_upload_latency: Mutex::new(Histogram {
+                counts: [0; LATENCY_BUCKETS.len() + 1],
+                sum: 0.0,
+            }),
+            retries: Mutex::new(BTreeMap::new()),
+            token_refresh_failures: AtomicU64::new(0),
+            flood_waits: AtomicU64::new(0),
+        }
+    }
+
+    // the worker is counted until the guard is dropped
+    pub fn start_worker(&self) -> WorkerGuard<'_> {
+        self.active_workers.fetch_add(1, Ordering::Relaxed);
+
+        WorkerGuard { metrics: self }
+    }
+
+    pub fn add_transferred_bytes(&self, source: &str, account: &str, bytes: u64) {
+        *self
+            .transferred_bytes
+            .lock()
+            .unwrap()
+            .entry((source.to_string(), account.to_string()))
+            .or_default() += bytes;
+    }
+
+    pub fn observe_part_upload(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+
+        let index = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| secs <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+
+        let mut histogram = self.part_upload_latency.lock().unwrap();
+        histogram.counts[index] += 1;
+        histogram.sum += secs;
+    }
+
+    pub fn inc_retries(&self, operation: &'static str) {
+        *self.retries.lock().unwrap().entry(operation).or_default() += 1;
+    }
+
+    pub fn inc_token_refresh_failures(&self) {
+        self.token_refresh_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // flood waits shorter than the threshold of grammers are slept through silently
+    pub fn observe_telegram_error(&self, error: &anyhow::Error) {
+        let is_flood_wait = error.chain().any(|cause| {
+            matches!(
+                cause.downcast_ref::<InvocationError>(),
+                Some(InvocationError::Rpc(rpc_error)) if rpc_error.name == "FLOOD_WAIT"
+            )
+        });
+
+        if is_flood_wait {
+            self.flood_waits.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // queue is the number of tasks by status
+    pub fn render(&self, queue: &BTreeMap<String, u64>) -> String {
+        let mut output = String::new();
+
+        write_header(
+            &mut output,
+            "tasks",
+            "gauge",
+            "Tasks in the queue by status.",
+        );
+        for (status, num) in queue {
+            writeln!(output, "{}_tasks{{status=\"{}\"}} {}", PREFIX, status, num).unwrap();
+        }
+
+        write_header(
+            &mut output,
+            "active_workers",
+            "gauge",
+            "Tasks being handled.",
+        );
+        writeln!(
+            output,
+            "{}_active_workers {}",
+            PREFIX,
+            self.active_workers.load(Ordering::Relaxed)
+        )
+        .unwrap();
+
+        write_header(
+            &mut output,
+            "transferred_bytes_total",
+            "counter",
+            "Bytes of completed tasks by source type and account.",
+        );
+        for ((source, account), bytes) in self.transferred_bytes.lock().unwrap().iter() {
+            writeln!(
+                output,
+                "{}_transferred_bytes_total{{source=\"{}\",account=\"{}\"}} {}",
+                PREFIX,
+                escape_label(source),
+                escape_label(account),
+                bytes
+            )
+            .unwrap();
+        }
+
+        write_header(
+            &mut output,
+            "part_upload_duration_seconds",
+            "histogram",
+            "Time to upload a part, retries included.",
+        );
+        {
+            let histogram = self.part_upload_latency.lock().unwrap();
+
+            let mut cumulative_count = 0;
+            for (index, count) in histogram.counts.iter().enumerate() {
+                cumulative_count += count;
+
+                let bound = LATENCY_BUCKETS
+                    .get(index)
+                    .map_or_else(|| "+Inf".to_string(), ToString::to_string);
+
+                writeln!(
+                    output,
+                    "{}_part_upload_duration_seconds_bucket{{le=\"{}\"}} {}",
+                    PREFIX, bound, cumulative_count
+                )
+                .unwrap();
+            }
+
+            writeln!(
+                output,
+                "{}_part_upload_duration_seconds_sum {}",
+                PREFIX, histogram.sum
+            )
+            .unwrap();
+            writeln!(
+                output,
+                "{}_part_upload_duration_seconds_count {}",
+                PREFIX, cumulative_count
+            )
+            .unwrap();
+        }
+
+        write_header(
+            &mut output,
+            "retries_total",
+            "counter",
+            "Retries of downloading chunks and uploading parts.",
+        );
+        for (operation, retries) in self.retries.lock().unwrap().iter() {
+            writeln!(
+                output,
+                "{}_retries_total{{operation=\"{}\"}} {}",
+                PREFIX, operation, retries
+            )
+            .unwrap();
+        }
+
+        write_header(
+            &mut output,
+            "token_refresh_failures_total",
+            "counter",
+            "Failures of refreshing the OneDrive token.",
+        );
+        writeln!(
+            output,
+            "{}_token_refresh_failures_total {}",
+            PREFIX,
+            self.token_refresh_failures.load(Ordering::Relaxed)
+        )
+        .unwrap();
+
+        write_header(
+            &mut output,
+            "flood_waits_total",
+            "counter",
+            "Telegram flood waits returned as errors.",
+        );
+        writeln!(
+            output,
+            "{}_flood_waits_total {}",
+            PREFIX,
+            self.flood_waits.load(Ordering::Relaxed)
+        )
+        .unwrap();
+
+        output
+    }
+}
+
+pub struct WorkerGuard<'a> {
+    metrics: &'a Metrics,
+}
+
+impl Drop for WorkerGuard<'_> {
+    fn drop(&mut self) {
+        self.metrics.active_workers.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+fn write_header(output: &mut String, name: &str, metric_type: &str, help: &str) {
+    writeln!(output, "# HELP {}_{} {}", PREFIX, name, help).unwrap();
+    writeln!(output, "# TYPE {}_{} {}", PREFIX, name, metric_type).unwrap();
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+
+        {
+            let _worker = metrics.start_worker();
+            metrics.add_transferred_bytes("url", "a@example.com", 10);
+            metrics.add_transferred_bytes("url", "a@example.com", 5);
+            metrics.observe_part_upload(Duration::from_millis(200));
+            metrics.observe_part_upload(Duration::from_secs(120));
+            metrics.inc_retries("upload");
+            metrics.inc_token_refresh_failures();
+
+            let output = metrics.render(&BTreeMap::new());
+            assert!(output.contains("telegram_onedrive_active_workers 1\n"));
+        }
+
+        let queue = BTreeMap::from([("waiting".to_string(), 2)]);
+        let output = metrics.render(&queue);
+
+        assert!(output.contains("telegram_onedrive_tasks{status=\"waiting\"} 2\n"));
+        assert!(output.contains("telegram_onedrive_active_workers 0\n"));
+        assert!(output.contains(
+            "telegram_onedrive_transferred_bytes_total{source=\"url\",account=\"a@example.com\"} 15\n"
+        ));
+        assert!(output
+            .contains("telegram_onedrive_part_upload_duration_seconds_bucket{le=\"0.1\"} 0\n"));
+        assert!(output
+            .contains("telegram_onedrive_part_upload_duration_seconds_bucket{le=\"0.25\"} 1\n"));
+        assert!(output
+            .contains("telegram_onedrive_part_upload_duration_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(output.contains("telegram_onedrive_part_upload_duration_seconds_count 2\n"));
+        assert!(output.contains("telegram_onedrive_retries_total{operation=\"upload\"} 1\n"));
+        assert!(output.contains("telegram_onedrive_token_refresh_failures_total 1\n"));
+        assert!(output.contains("telegram_onedrive_flood_waits_total 0\n"));
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
@@ -0,0 +1,49 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{host_is, Resolved, UrlResolver};
+use anyhow::Result;
+use futures::{future::BoxFuture, FutureExt};
+use url::Url;
+
+const HOSTS: [&str; 2] = ["www.dropbox.com", "dropbox.com"];
+
+pub struct DropboxResolver;
+
+impl UrlResolver for DropboxResolver {
+    fn name(&self) -> &str {
+        "dropbox"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        host_is(url, &HOSTS)
+    }
+
+    fn resolve<'a>(
+        &'a self,
+        url: &'a Url,
+        _http_client: &'a reqwest::Client,
+    ) -> BoxFuture<'a, Result<Resolved>> {
+        async move {
+            // dl=1 makes dropbox respond with the file instead of the preview page
+            let query_pairs = url
+                .query_pairs()
+                .into_owned()
+                .filter(|(key, _)| key != "dl")
+                .collect::<Vec<(String, String)>>();
+
+            let mut url = url.clone();
+            url.query_pairs_mut()
+                .clear()
+                .extend_pairs(query_pairs)
+                .append_pair("dl", "1");
+
+            Ok(Resolved::new(url))
+        }
+        .boxed()
+    }
+}
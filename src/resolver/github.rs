@@ -0,0 +1,120 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{host_is, Resolved, UrlResolver};
+use anyhow::{anyhow, Context, Result};
+use futures::{future::BoxFuture, FutureExt};
+use serde::Deserialize;
+use url::Url;
+
+const HOSTS: [&str; 2] = ["github.com", "www.github.com"];
+
+pub struct GitHubResolver {
+    api_base: String,
+}
+
+impl GitHubResolver {
+    pub fn new(api_base: &str) -> Self {
+        Self {
+            api_base: api_base.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+impl Default for GitHubResolver {
+    fn default() -> Self {
+        Self::new("https://api.github.com")
+    }
+}
+
+#[derive(Deserialize)]
+struct Release {
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl UrlResolver for GitHubResolver {
+    fn name(&self) -> &str {
+        "github"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        if !host_is(url, &HOSTS) {
+            return false;
+        }
+
+        let segments = url.path_segments().map_or_else(Vec::new, Iterator::collect);
+
+        // /$owner/$repo/blob/$branch/$path or /$owner/$repo/releases/tag/$tag
+        matches!(
+            segments.as_slice(),
+            [_, _, "blob", _, _, ..] | [_, _, "releases", "tag", _]
+        )
+    }
+
+    fn resolve<'a>(
+        &'a self,
+        url: &'a Url,
+        http_client: &'a reqwest::Client,
+    ) -> BoxFuture<'a, Result<Resolved>> {
+        async move {
+            let segments = url.path_segments().map_or_else(Vec::new, Iterator::collect);
+
+            match segments.as_slice() {
+                [owner, repo, "blob", rest @ ..] => Ok(Resolved::new(format!(
+                    "https://raw.githubusercontent.com/{}/{}/{}",
+                    owner,
+                    repo,
+                    rest.join("/")
+                ))),
+                [owner, repo, "releases", "tag", tag] => {
+                    let api_url = format!(
+                        "{}/repos/{}/{}/releases/tags/{}",
+                        self.api_base, owner, repo, tag
+                    );
+
+                    let response = http_client
+                        .get(&api_url)
+                        .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+                        .send()
+                        .await
+                        .context("failed to send request for github release")?
+                        .error_for_status()
+                        .context("github release not found")?
+                        .text()
+                        .await
+                        .context("failed to get github release")?;
+
+                    let release = serde_json::from_str::<Release>(&response)
+                        .context("failed to deserialize github release")?;
+
+                    match release.assets.as_slice() {
+                        [asset] => Ok(Resolved::new(&asset.browser_download_url)
+                            .filename(&asset.name)),
+                        [] => Err(anyhow!("github release {} has no assets", tag)),
+                        assets => Err(anyhow!(
+                            "github release {} has more than one asset, pass one of the download urls:\n{}",
+                            tag,
+                            assets
+                                .iter()
+                                .map(|asset| asset.browser_download_url.as_str())
+                                .collect::<Vec<&str>>()
+                                .join("\n")
+                        )),
+                    }
+                }
+                _ => Err(anyhow!("unsupported github url")),
+            }
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,139 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{host_is, Resolved, UrlResolver};
+use crate::error::ResultExt;
+use anyhow::{anyhow, Context, Result};
+use futures::{future::BoxFuture, FutureExt};
+use regex::Regex;
+use reqwest::header;
+use url::Url;
+
+const HOSTS: [&str; 2] = ["drive.google.com", "docs.google.com"];
+
+pub struct GoogleDriveResolver {
+    download_base: String,
+}
+
+impl GoogleDriveResolver {
+    pub fn new(download_base: &str) -> Self {
+        Self {
+            download_base: download_base.trim_end_matches('/').to_string(),
+        }
+    }
+
+    fn get_file_id(url: &Url) -> Option<String> {
+        let segments = url.path_segments().map_or_else(Vec::new, Iterator::collect);
+
+        // /file/d/$id/view
+        if let ["file", "d", id, ..] = segments.as_slice() {
+            return Some((*id).to_string());
+        }
+
+        // /open?id=$id or /uc?id=$id
+        url.query_pairs()
+            .find(|(key, _)| key == "id")
+            .map(|(_, value)| value.to_string())
+    }
+}
+
+impl Default for GoogleDriveResolver {
+    fn default() -> Self {
+        Self::new("https://drive.usercontent.google.com")
+    }
+}
+
+impl UrlResolver for GoogleDriveResolver {
+    fn name(&self) -> &str {
+        "google drive"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        host_is(url, &HOSTS) && Self::get_file_id(url).is_some()
+    }
+
+    fn resolve<'a>(
+        &'a self,
+        url: &'a Url,
+        http_client: &'a reqwest::Client,
+    ) -> BoxFuture<'a, Result<Resolved>> {
+        async move {
+            let id =
+                Self::get_file_id(url).ok_or_else(|| anyhow!("google drive file id not found"))?;
+
+            let download_url = format!("{}/download?id={}&export=download", self.download_base, id);
+
+            let response = http_client
+                .get(&download_url)
+                .send()
+                .await
+                .context("failed to send request for google drive file")?;
+
+            let is_html = response
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|content_type| content_type.to_str().ok())
+                .is_some_and(|content_type| content_type.starts_with("text/html"));
+
+            if !is_html {
+                return Ok(Resolved::new(download_url));
+            }
+
+            // large files are behind a virus scan warning page with a download form
+            let page = response
+                .text()
+                .await
+                .context("failed to get google drive warning page")?;
+
+            parse_warning_page(&page, &self.download_base)
+        }
+        .boxed()
+    }
+}
+
+fn parse_warning_page(page: &str, download_base: &str) -> Result<Resolved> {
+    let pattern = r#"<form[^>]*id="download-form"[^>]*action="([^"]+)""#;
+    let re = Regex::new(pattern)
+        .context("invalid regex pattern")
+        .context(pattern)
+        .unwrap_or_trace();
+
+    let action = re
+        .captures(page)
+        .and_then(|cap| cap.get(1))
+        .map(|m| m.as_str().replace("&amp;", "&"))
+        .ok_or_else(|| {
+            anyhow!("google drive download form not found, the file may not be shared publicly")
+        })?;
+
+    let mut url = Url::parse(&action)
+        .or_else(|_| Url::parse(download_base).and_then(|base| base.join(&action)))
+        .context("failed to parse google drive download form action")?;
+
+    let pattern = r#"<input type="hidden" name="([^"]+)" value="([^"]*)""#;
+    let re = Regex::new(pattern)
+        .context("invalid regex pattern")
+        .context(pattern)
+        .unwrap_or_trace();
+
+    for cap in re.captures_iter(page) {
+        url.query_pairs_mut().append_pair(&cap[1], &cap[2]);
+    }
+
+    let pattern = r#"<span class="uc-name-size"><a[^>]*>([^<]+)</a>"#;
+    let re = Regex::new(pattern)
+        .context("invalid regex pattern")
+        .context(pattern)
+        .unwrap_or_trace();
+
+    let resolved = match re.captures(page).and_then(|cap| cap.get(1)) {
+        Some(filename) => Resolved::new(url).filename(filename.as_str()),
+        None => Resolved::new(url),
+    };
+
+    Ok(resolved)
+}
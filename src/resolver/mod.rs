@@ -0,0 +1,281 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+mod dropbox;
+mod github;
+mod google_drive;
+mod onedrive;
+mod rewrite;
+
+use crate::env::UrlRewrite;
+use anyhow::{Context, Result};
+pub use dropbox::DropboxResolver;
+use futures::future::BoxFuture;
+pub use github::GitHubResolver;
+pub use google_drive::GoogleDriveResolver;
+pub use onedrive::OneDriveResolver;
+pub use rewrite::RewriteResolver;
+use url::Url;
+
+// turn a share page into a direct download url before the head request of /url
+pub trait UrlResolver: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn matches(&self, url: &Url) -> bool;
+
+    fn resolve<'a>(
+        &'a self,
+        url: &'a Url,
+        http_client: &'a reqwest::Client,
+    ) -> BoxFuture<'a, Result<Resolved>>;
+}
+
+pub struct Resolved {
+    pub url: String,
+    // some hosts only expose the real file name on the share page
+    pub filename: Option<String>,
+}
+
+impl Resolved {
+    pub fn new<T: ToString>(url: T) -> Self {
+        Self {
+            url: url.to_string(),
+            filename: None,
+        }
+    }
+
+    pub fn filename<T: ToString>(mut self, filename: T) -> Self {
+        self.filename = Some(filename.to_string());
+
+        self
+    }
+}
+
+pub struct UrlResolvers {
+    resolvers: Vec<Box<dyn UrlResolver>>,
+}
+
+impl UrlResolvers {
+    pub fn new() -> Self {
+        Self {
+            resolvers: Vec::new(),
+        }
+    }
+
+    // resolvers set by url_resolvers
+    pub fn with_rewrites(self, rewrites: &[UrlRewrite]) -> Self {
+        rewrites.iter().fold(self, |resolvers, rewrite| {
+            resolvers.register(RewriteResolver::new(rewrite))
+        })
+    }
+
+    // register custom resolvers before the builtin ones so that they take precedence
+    pub fn with_builtin(self) -> Self {
+        self.register(GoogleDriveResolver::default())
+            .register(DropboxResolver)
+            .register(GitHubResolver::default())
+            .register(OneDriveResolver::default())
+    }
+
+    pub fn register<R>(mut self, resolver: R) -> Self
+    where
+        R: UrlResolver + 'static,
+    {
+        self.resolvers.push(Box::new(resolver));
+
+        self
+    }
+
+    pub async fn resolve(
+        &self,
+        url: &str,
+        http_client: &reqwest::Client,
+    ) -> Result<Option<Resolved>> {
+        let parsed_url = Url::parse(url).context("failed to parse url")?;
+
+        for resolver in &self.resolvers {
+            if resolver.matches(&parsed_url) {
+                let name = resolver.name();
+
+                tracing::info!("resolve url with {} resolver", name);
+
+                let resolved = resolver
+                    .resolve(&parsed_url, http_client)
+                    .await
+                    .context(format!("failed to resolve url with {} resolver", name))?;
+
+                tracing::debug!("resolved url: {} -> {}", url, resolved.url);
+
+                return Ok(Some(resolved));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+fn host_is(url: &Url, hosts: &[&str]) -> bool {
+    url.host_str().is_some_and(|host| hosts.contains(&host))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Router};
+    use regex::Regex;
+    use tokio::net::TcpListener;
+
+    async fn serve_fixtures(router: Router) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            axum::serve(listener, router).await.unwrap();
+        });
+
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_google_drive_virus_scan_page() {
+        let base = serve_fixtures(Router::new().route(
+            "/download",
+            get(|| async {
+                axum::response::Html(include_str!("fixtures/google_drive_virus_scan.html"))
+            }),
+        ))
+        .await;
+
+        let resolvers = UrlResolvers::new().register(GoogleDriveResolver::new(&base));
+        let resolved = resolvers
+            .resolve(
+                "https://drive.google.com/file/d/1AbCdEf/view?usp=sharing",
+                &reqwest::Client::new(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            resolved.url,
+            format!(
+                "{}/download?id=1AbCdEf&export=download&confirm=t&uuid=0f1e2d3c",
+                base
+            )
+        );
+        assert_eq!(resolved.filename.as_deref(), Some("dataset.zip"));
+    }
+
+    #[tokio::test]
+    async fn test_github_release_page() {
+        let base = serve_fixtures(Router::new().route(
+            "/repos/owner/repo/releases/tags/v1.0.0",
+            get(|| async {
+                (
+                    [("content-type", "application/json")],
+                    include_str!("fixtures/github_release.json"),
+                )
+            }),
+        ))
+        .await;
+
+        let resolvers = UrlResolvers::new().register(GitHubResolver::new(&base));
+        let resolved = resolvers
+            .resolve(
+                "https://github.com/owner/repo/releases/tag/v1.0.0",
+                &reqwest::Client::new(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            resolved.url,
+            "https://github.com/owner/repo/releases/download/v1.0.0/tool-linux-amd64.tar.gz"
+        );
+        assert_eq!(
+            resolved.filename.as_deref(),
+            Some("tool-linux-amd64.tar.gz")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rewrite_resolver() {
+        let rewrite = UrlRewrite {
+            name: "intranet".to_string(),
+            pattern: Regex::new(r"^https://files\.lan/share/(?<id>\w+)$").unwrap(),
+            url: "https://files.lan/download/${id}".to_string(),
+        };
+
+        let resolvers = UrlResolvers::new().with_rewrites(&[rewrite]).with_builtin();
+        let http_client = reqwest::Client::new();
+
+        let resolved = resolvers
+            .resolve("https://files.lan/share/abc123", &http_client)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(resolved.url, "https://files.lan/download/abc123");
+
+        assert!(resolvers
+            .resolve("https://files.lan/share/abc123/preview", &http_client)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_url_rewrite() {
+        let resolvers = UrlResolvers::new().with_builtin();
+        let http_client = reqwest::Client::new();
+
+        let resolved = resolvers
+            .resolve(
+                "https://www.dropbox.com/scl/fi/abc/file.pdf?rlkey=xyz&dl=0",
+                &http_client,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            resolved.url,
+            "https://www.dropbox.com/scl/fi/abc/file.pdf?rlkey=xyz&dl=1"
+        );
+
+        let resolved = resolvers
+            .resolve(
+                "https://github.com/owner/repo/blob/main/docs/guide.pdf",
+                &http_client,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            resolved.url,
+            "https://raw.githubusercontent.com/owner/repo/main/docs/guide.pdf"
+        );
+
+        let resolved = resolvers
+            .resolve(
+                "https://contoso.sharepoint.com/:u:/g/personal/user/EAbC?e=xYz",
+                &http_client,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            resolved.url,
+            "https://contoso.sharepoint.com/:u:/g/personal/user/EAbC?e=xYz&download=1"
+        );
+
+        assert!(resolvers
+            .resolve("https://example.com/file.zip", &http_client)
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
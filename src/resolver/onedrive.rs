@@ -0,0 +1,71 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{host_is, Resolved, UrlResolver};
+use anyhow::Result;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use futures::{future::BoxFuture, FutureExt};
+use url::Url;
+
+const PERSONAL_HOSTS: [&str; 2] = ["1drv.ms", "onedrive.live.com"];
+
+pub struct OneDriveResolver {
+    api_base: String,
+}
+
+impl OneDriveResolver {
+    pub fn new(api_base: &str) -> Self {
+        Self {
+            api_base: api_base.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+impl Default for OneDriveResolver {
+    fn default() -> Self {
+        Self::new("https://api.onedrive.com/v1.0")
+    }
+}
+
+impl UrlResolver for OneDriveResolver {
+    fn name(&self) -> &str {
+        "onedrive"
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        host_is(url, &PERSONAL_HOSTS)
+            || url
+                .host_str()
+                .is_some_and(|host| host.ends_with(".sharepoint.com"))
+                && url.path().starts_with("/:")
+    }
+
+    fn resolve<'a>(
+        &'a self,
+        url: &'a Url,
+        _http_client: &'a reqwest::Client,
+    ) -> BoxFuture<'a, Result<Resolved>> {
+        async move {
+            if host_is(url, &PERSONAL_HOSTS) {
+                // see https://learn.microsoft.com/en-us/onedrive/developer/rest-api/api/shares_get#encoding-sharing-urls
+                let share_id = format!("u!{}", URL_SAFE_NO_PAD.encode(url.as_str()));
+
+                Ok(Resolved::new(format!(
+                    "{}/shares/{}/root/content",
+                    self.api_base, share_id
+                )))
+            } else {
+                // sharepoint sharing links download the file directly with download=1
+                let mut url = url.clone();
+                url.query_pairs_mut().append_pair("download", "1");
+
+                Ok(Resolved::new(url))
+            }
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,59 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{Resolved, UrlResolver};
+use crate::env::UrlRewrite;
+use anyhow::{anyhow, Result};
+use futures::{future::BoxFuture, FutureExt};
+use regex::Regex;
+use url::Url;
+
+// set by url_resolvers, the url is rewritten without requests
+pub struct RewriteResolver {
+    name: String,
+    pattern: Regex,
+    url: String,
+}
+
+impl RewriteResolver {
+    pub fn new(rewrite: &UrlRewrite) -> Self {
+        Self {
+            name: rewrite.name.clone(),
+            pattern: rewrite.pattern.clone(),
+            url: rewrite.url.clone(),
+        }
+    }
+}
+
+impl UrlResolver for RewriteResolver {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        self.pattern.is_match(url.as_str())
+    }
+
+    fn resolve<'a>(
+        &'a self,
+        url: &'a Url,
+        _http_client: &'a reqwest::Client,
+    ) -> BoxFuture<'a, Result<Resolved>> {
+        async move {
+            let captures = self
+                .pattern
+                .captures(url.as_str())
+                .ok_or_else(|| anyhow!("url does not match the pattern"))?;
+
+            let mut resolved_url = String::new();
+            captures.expand(&self.url, &mut resolved_url);
+
+            Ok(Resolved::new(resolved_url))
+        }
+        .boxed()
+    }
+}
@@ -6,19 +6,34 @@
 */
 
 use crate::{
+    acl::Acl,
     client::{OneDriveClient, TelegramClient},
-    env::ENV,
+    env::{get_chat_config, ENV},
     error::ResultExt,
-    tasker::TaskSession,
+    message::ChatEntity,
+    resolver::UrlResolvers,
+    storage::{new_storage_backend, StorageBackend},
+    tasker::{HandlerLimit, LatestMessages, TaskSession, Webhook},
+    utils::{get_http_client, HttpRoute},
+};
+use anyhow::Result;
+use grammers_client::types::{Chat, PackedChat};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
 };
-use std::sync::{atomic::AtomicBool, Arc};
 
 pub struct State {
     pub telegram_bot: TelegramClient,
     pub telegram_user: TelegramClient,
-    pub onedrive: OneDriveClient,
+    pub onedrive: Arc<OneDriveClient>,
+    pub storage: Box<dyn StorageBackend>,
     pub should_auto_delete: AtomicBool,
     pub task_session: TaskSession,
+    pub handler_limit: HandlerLimit,
+    pub url_resolvers: UrlResolvers,
+    pub acl: Acl,
+    pub latest_messages: LatestMessages,
 }
 
 impl State {
@@ -27,18 +42,66 @@ impl State {
 
         let telegram_bot = TelegramClient::new_bot().await.unwrap_or_trace();
         let telegram_user = TelegramClient::new_user().await.unwrap_or_trace();
-        let onedrive = OneDriveClient::new().await.unwrap_or_trace();
+        let onedrive = Arc::new(OneDriveClient::new().await.unwrap_or_trace());
+        let storage = new_storage_backend(&env.storage, onedrive.clone()).unwrap_or_trace();
         let should_auto_delete = AtomicBool::new(env.should_auto_delete);
-        let task_session = TaskSession::new(&env.tasker_session_path)
-            .await
-            .unwrap_or_trace();
+        let webhook = Webhook::new(
+            &env.webhook,
+            get_http_client(HttpRoute::Origin).unwrap_or_trace(),
+        )
+        .await
+        .unwrap_or_trace();
+        let task_session = TaskSession::new(
+            &env.tasker_session_path,
+            &env.upload_spool_dir,
+            &env.tasker_history_path,
+            webhook,
+        )
+        .await
+        .unwrap_or_trace();
+        let handler_limit = HandlerLimit::new(env.task_handler_num);
+        let url_resolvers = UrlResolvers::new()
+            .with_rewrites(&env.url.resolvers)
+            .with_builtin();
+        let acl = Acl::new(&env.acl).await.unwrap_or_trace();
+        let latest_messages = LatestMessages::default();
 
         Self {
             telegram_bot,
             telegram_user,
             onedrive,
+            storage,
             should_auto_delete,
             task_session,
+            handler_limit,
+            url_resolvers,
+            acl,
+            latest_messages,
+        }
+    }
+
+    // the setting of the chat in the config file takes precedence over /autoDelete
+    pub fn should_auto_delete_in(&self, chat_id: i64) -> bool {
+        get_chat_config(chat_id)
+            .auto_delete
+            .unwrap_or_else(|| self.should_auto_delete.load(Ordering::Acquire))
+    }
+
+    // private chats can only be read by the bot, since message ids differ on each side,
+    // messages in groups and channels are read by the user for restricted content
+    pub fn reader(&self, chat: PackedChat) -> &TelegramClient {
+        if chat.is_user() {
+            &self.telegram_bot
+        } else {
+            &self.telegram_user
+        }
+    }
+
+    // the chat as seen by its reader
+    pub async fn get_reader_chat(&self, chat: Chat) -> Result<Chat> {
+        match chat {
+            Chat::User(_) => Ok(chat),
+            chat => self.telegram_user.get_chat(&ChatEntity::from(chat)).await,
         }
     }
 }
@@ -0,0 +1,409 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    conflict_free_name, join_path, relative_path, StorageBackend, StoredItem, UploadTarget,
+};
+use anyhow::{anyhow, Context, Result};
+use futures::future::BoxFuture;
+use std::{
+    io::{ErrorKind, SeekFrom},
+    ops::Range,
+    path::PathBuf,
+};
+use tokio::{
+    fs::{self, OpenOptions},
+    io::{AsyncSeekExt, AsyncWriteExt},
+};
+
+const PART_EXTENSION: &str = ".part";
+
+// a directory on the host, such as a mounted nas share
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: &str) -> Self {
+        Self {
+            root: PathBuf::from(root),
+        }
+    }
+
+    fn local_path(&self, path: &str) -> Result<PathBuf> {
+        Ok(self.root.join(relative_path(path)?))
+    }
+}
+
+// /dir/a.txt -> /dir/.a.txt.0123456789abcdef.part, so that parallel uploads of the same name don't collide
+fn part_path(path: &str) -> String {
+    let (dir, filename) = split_path(path);
+    let id = rand::random::<u64>();
+
+    join_path(dir, &format!(".{}.{:016x}{}", filename, id, PART_EXTENSION))
+}
+
+fn filename_from_part_path(part_path: &str) -> Result<(&str, &str)> {
+    let (dir, part_name) = split_path(part_path);
+
+    let filename = part_name
+        .strip_prefix('.')
+        .and_then(|part_name| part_name.strip_suffix(PART_EXTENSION))
+        .and_then(|part_name| part_name.rsplit_once('.'))
+        .map(|(filename, _id)| filename)
+        .ok_or_else(|| anyhow!("invalid local upload session: {}", part_path))?;
+
+    Ok((dir, filename))
+}
+
+fn split_path(path: &str) -> (&str, &str) {
+    match path.rsplit_once('/') {
+        Some(("", filename)) => ("/", filename),
+        Some((dir, filename)) => (dir, filename),
+        None => ("/", path),
+    }
+}
+
+fn is_part_file(filename: &str) -> bool {
+    filename.starts_with('.') && filename.ends_with(PART_EXTENSION)
+}
+
+impl StorageBackend for LocalStorage {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn create_upload_session<'a>(
+        &'a self,
+        _chat_id: i64,
+        root_path: &'a str,
+        filename: &'a str,
+        _total_length: u64,
+    ) -> BoxFuture<'a, Result<UploadTarget>> {
+        Box::pin(async move {
+            let upload_url = part_path(&join_path(root_path, filename));
+
+            let local_path = self.local_path(&upload_url)?;
+
+            if let Some(dir) = local_path.parent() {
+                fs::create_dir_all(dir)
+                    .await
+                    .context("failed to create local storage directory")?;
+            }
+
+            fs::File::create(&local_path)
+                .await
+                .context("failed to create local upload file")?;
+
+            tracing::debug!("built local upload session for {}", filename);
+
+            Ok(UploadTarget {
+                upload_url,
+                current_length: 0,
+            })
+        })
+    }
+
+    fn upload_range<'a>(
+        &'a self,
+        upload_url: &'a str,
+        buffer: &'a [u8],
+        range: Range<u64>,
+        _total_length: u64,
+    ) -> BoxFuture<'a, Result<Option<StoredItem>>> {
+        Box::pin(async move {
+            let mut file = OpenOptions::new()
+                .write(true)
+                .open(self.local_path(upload_url)?)
+                .await
+                .context("local upload session not found")?;
+
+            let file_length = file
+                .metadata()
+                .await
+                .context("failed to get local upload file metadata")?
+                .len();
+
+            if range.start > file_length {
+                return Err(anyhow!(
+                    "local upload file has {} bytes, can't write from {}",
+                    file_length,
+                    range.start
+                ));
+            }
+
+            file.seek(SeekFrom::Start(range.start))
+                .await
+                .context("failed to seek local upload file")?;
+            file.write_all(buffer)
+                .await
+                .context("failed to write local upload file")?;
+            file.flush()
+                .await
+                .context("failed to flush local upload file")?;
+
+            Ok(None)
+        })
+    }
+
+    fn finalize<'a>(
+        &'a self,
+        upload_url: &'a str,
+        total_length: u64,
+    ) -> BoxFuture<'a, Result<StoredItem>> {
+        Box::pin(async move {
+            let (dir, filename) = filename_from_part_path(upload_url)?;
+
+            let part = self
+                .stat(0, upload_url)
+                .await?
+                .ok_or_else(|| anyhow!("local upload session not found"))?;
+
+            if part.size != total_length {
+                return Err(anyhow!(
+                    "local upload file has {} bytes, expected {}",
+                    part.size,
+                    total_length
+                ));
+            }
+
+            let mut existing_names = self
+                .list(0, dir)
+                .await?
+                .into_iter()
+                .map(|item| item.name)
+                .collect::<Vec<_>>();
+
+            let part_path = self.local_path(upload_url)?;
+
+            // unlike renaming, linking fails if another upload takes the name meanwhile
+            let name = loop {
+                let name = conflict_free_name(filename, &existing_names);
+
+                match fs::hard_link(&part_path, self.local_path(&join_path(dir, &name))?).await {
+                    Ok(()) => break name,
+                    Err(e) if e.kind() == ErrorKind::AlreadyExists => existing_names.push(name),
+                    Err(e) => return Err(e).context("failed to link local upload file"),
+                }
+            };
+
+            fs::remove_file(&part_path)
+                .await
+                .context("failed to remove local upload part")?;
+
+            Ok(StoredItem {
+                name,
+                size: total_length,
+                is_dir: false,
+                id: None,
+            })
+        })
+    }
+
+    fn stat<'a>(
+        &'a self,
+        _chat_id: i64,
+        path: &'a str,
+    ) -> BoxFuture<'a, Result<Option<StoredItem>>> {
+        Box::pin(async move {
+            let metadata = match fs::metadata(self.local_path(path)?).await {
+                Ok(metadata) => metadata,
+                Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+                Err(e) => {
+                    return Err(e)
+                        .context("failed to stat local file")
+                        .context(path.to_string())
+                }
+            };
+
+            Ok(Some(StoredItem {
+                name: split_path(path).1.to_string(),
+                size: metadata.len(),
+                is_dir: metadata.is_dir(),
+                id: None,
+            }))
+        })
+    }
+
+    fn list<'a>(&'a self, _chat_id: i64, path: &'a str) -> BoxFuture<'a, Result<Vec<StoredItem>>> {
+        Box::pin(async move {
+            let mut read_dir = match fs::read_dir(self.local_path(path)?).await {
+                Ok(read_dir) => read_dir,
+                Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+                Err(e) => {
+                    return Err(e)
+                        .context("failed to list local directory")
+                        .context(path.to_string())
+                }
+            };
+
+            let mut items = Vec::new();
+
+            while let Some(entry) = read_dir
+                .next_entry()
+                .await
+                .context("failed to read local directory entry")?
+            {
+                let name = entry.file_name().to_string_lossy().to_string();
+
+                if is_part_file(&name) {
+                    continue;
+                }
+
+                let metadata = entry
+                    .metadata()
+                    .await
+                    .context("failed to get local file metadata")?;
+
+                items.push(StoredItem {
+                    name,
+                    size: metadata.len(),
+                    is_dir: metadata.is_dir(),
+                    id: None,
+                });
+            }
+
+            Ok(items)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LocalStorage;
+    use crate::{
+        storage::{StorageBackend, StoredItem},
+        utils::TempPath,
+    };
+
+    fn temp_root(name: &str) -> TempPath {
+        let root = TempPath::new(name);
+        std::fs::create_dir_all(&*root).unwrap();
+
+        root
+    }
+
+    #[tokio::test]
+    async fn test_upload() {
+        let root = temp_root("local-storage");
+        let storage = LocalStorage::new(root.as_str());
+
+        let content = b"hello world";
+
+        for expected_name in ["a.txt", "a (1).txt"] {
+            let target = storage
+                .create_upload_session(0, "/dir", "a.txt", content.len() as u64)
+                .await
+                .unwrap();
+            assert_eq!(target.current_length, 0);
+
+            // parts arrive in order, the second one after a resume
+            for (start, part) in [(0, &content[..5]), (5, &content[5..])] {
+                let item = storage
+                    .upload_range(
+                        &target.upload_url,
+                        part,
+                        start..start + part.len() as u64,
+                        content.len() as u64,
+                    )
+                    .await
+                    .unwrap();
+                assert!(item.is_none());
+            }
+
+            let item = storage
+                .finalize(&target.upload_url, content.len() as u64)
+                .await
+                .unwrap();
+            assert_eq!(item.name, expected_name);
+            assert_eq!(
+                std::fs::read(root.join("dir").join(expected_name)).unwrap(),
+                content
+            );
+        }
+
+        let mut items = storage.list(0, "/dir").await.unwrap();
+        items.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(
+            items,
+            vec![
+                StoredItem {
+                    name: "a (1).txt".to_string(),
+                    size: 11,
+                    is_dir: false,
+                    id: None
+                },
+                StoredItem {
+                    name: "a.txt".to_string(),
+                    size: 11,
+                    is_dir: false,
+                    id: None
+                }
+            ]
+        );
+
+        assert!(storage.stat(0, "/dir").await.unwrap().unwrap().is_dir);
+        assert!(storage.stat(0, "/dir/b.txt").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_finalize_same_name() {
+        let root = temp_root("local-storage");
+        let storage = LocalStorage::new(root.as_str());
+
+        let mut upload_urls = Vec::new();
+        for content in [b"first", b"other"] {
+            let target = storage
+                .create_upload_session(0, "/", "a.txt", 5)
+                .await
+                .unwrap();
+            storage
+                .upload_range(&target.upload_url, content, 0..5, 5)
+                .await
+                .unwrap();
+
+            upload_urls.push(target.upload_url);
+        }
+
+        let (first, other) = tokio::join!(
+            storage.finalize(&upload_urls[0], 5),
+            storage.finalize(&upload_urls[1], 5)
+        );
+
+        // neither file is replaced by the other
+        let mut names = [first.unwrap().name, other.unwrap().name];
+        names.sort();
+        assert_eq!(names, ["a (1).txt", "a.txt"]);
+
+        let mut contents = names.map(|name| std::fs::read(root.join(name)).unwrap());
+        contents.sort();
+        assert_eq!(contents, [b"first".to_vec(), b"other".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn test_incomplete_upload() {
+        let root = temp_root("local-storage-incomplete");
+        let storage = LocalStorage::new(root.as_str());
+
+        let target = storage
+            .create_upload_session(0, "/", "a.txt", 10)
+            .await
+            .unwrap();
+
+        assert!(storage
+            .upload_range(&target.upload_url, b"abc", 5..8, 10)
+            .await
+            .is_err());
+
+        storage
+            .upload_range(&target.upload_url, b"abc", 0..3, 10)
+            .await
+            .unwrap();
+        assert!(storage.finalize(&target.upload_url, 10).await.is_err());
+        assert!(storage.list(0, "/").await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,156 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+mod local;
+mod onedrive;
+mod s3;
+
+use crate::{
+    client::OneDriveClient,
+    env::{StorageEnv, StorageKind},
+};
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+pub use local::LocalStorage;
+pub use onedrive::OneDriveStorage;
+use path_slash::PathBufExt;
+pub use s3::S3Storage;
+use std::{
+    ops::Range,
+    path::{Component, Path},
+    sync::Arc,
+};
+
+// the destination of transfers, paths are absolute slash paths like /dir/file
+pub trait StorageBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // the upload url is stored in the task so that the upload can be resumed,
+    // it is an opaque session id for backends other than onedrive,
+    // backends may choose the destination per chat
+    fn create_upload_session<'a>(
+        &'a self,
+        chat_id: i64,
+        root_path: &'a str,
+        filename: &'a str,
+        total_length: u64,
+    ) -> BoxFuture<'a, Result<UploadTarget>>;
+
+    // ranges are uploaded in order, the stored item is returned if the backend
+    // completes the upload on the last range by itself
+    fn upload_range<'a>(
+        &'a self,
+        upload_url: &'a str,
+        buffer: &'a [u8],
+        range: Range<u64>,
+        total_length: u64,
+    ) -> BoxFuture<'a, Result<Option<StoredItem>>>;
+
+    fn finalize<'a>(
+        &'a self,
+        upload_url: &'a str,
+        total_length: u64,
+    ) -> BoxFuture<'a, Result<StoredItem>>;
+
+    fn stat<'a>(&'a self, chat_id: i64, path: &'a str)
+        -> BoxFuture<'a, Result<Option<StoredItem>>>;
+
+    fn list<'a>(&'a self, chat_id: i64, path: &'a str) -> BoxFuture<'a, Result<Vec<StoredItem>>>;
+}
+
+pub struct UploadTarget {
+    pub upload_url: String,
+    // where an existing session should continue from
+    pub current_length: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredItem {
+    // may differ from the requested name if a file with the same name exists
+    pub name: String,
+    pub size: u64,
+    pub is_dir: bool,
+    // only onedrive has item ids
+    pub id: Option<String>,
+}
+
+pub fn new_storage_backend(
+    env: &StorageEnv,
+    onedrive: Arc<OneDriveClient>,
+) -> Result<Box<dyn StorageBackend>> {
+    let storage: Box<dyn StorageBackend> = match env.kind {
+        StorageKind::OneDrive => Box::new(OneDriveStorage::new(onedrive)?),
+        StorageKind::Local => Box::new(LocalStorage::new(&env.local_root)),
+        StorageKind::S3 => Box::new(S3Storage::new(&env.s3)?),
+    };
+
+    tracing::info!("storage backend: {}", storage.name());
+
+    Ok(storage)
+}
+
+fn join_path(root_path: &str, filename: &str) -> String {
+    Path::new(root_path)
+        .join(filename)
+        .to_slash_lossy()
+        .to_string()
+}
+
+// file names come from chats and urls, they must not escape the root
+fn relative_path(path: &str) -> Result<&str> {
+    let relative_path = path.trim_start_matches('/');
+
+    let is_escaping = Path::new(relative_path)
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir));
+
+    if is_escaping {
+        return Err(anyhow!("invalid storage path: {}", path));
+    }
+
+    Ok(relative_path)
+}
+
+// a.txt -> a (1).txt, the same as the rename conflict behavior of onedrive
+fn conflict_free_name(filename: &str, existing_names: &[String]) -> String {
+    if !existing_names.iter().any(|name| name == filename) {
+        return filename.to_string();
+    }
+
+    let (stem, extension) = match filename.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+        _ => (filename, String::new()),
+    };
+
+    (1..)
+        .map(|index| format!("{} ({}){}", stem, index, extension))
+        .find(|name| !existing_names.contains(name))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{conflict_free_name, relative_path};
+
+    #[test]
+    fn test_relative_path() {
+        assert_eq!(relative_path("/a/b.txt").unwrap(), "a/b.txt");
+        assert!(relative_path("/a/../../etc/passwd").is_err());
+    }
+
+    #[test]
+    fn test_conflict_free_name() {
+        let existing_names = vec!["a.txt".to_string(), "a (1).txt".to_string()];
+
+        assert_eq!(conflict_free_name("b.txt", &existing_names), "b.txt");
+        assert_eq!(conflict_free_name("a.txt", &existing_names), "a (2).txt");
+        assert_eq!(
+            conflict_free_name(".env", &[".env".to_string()]),
+            ".env (1)"
+        );
+    }
+}
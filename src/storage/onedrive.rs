@@ -0,0 +1,142 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{StorageBackend, StoredItem, UploadTarget};
+use crate::{
+    client::OneDriveClient,
+    utils::{get_http_client, HttpRoute},
+};
+use anyhow::{anyhow, Context, Error, Result};
+use futures::future::BoxFuture;
+use onedrive_api::{resource::DriveItem, UploadSession};
+use std::{ops::Range, sync::Arc};
+
+pub struct OneDriveStorage {
+    onedrive: Arc<OneDriveClient>,
+    // upload urls are pre-authenticated, parts are uploaded without the access token
+    http_client: reqwest::Client,
+}
+
+impl OneDriveStorage {
+    pub fn new(onedrive: Arc<OneDriveClient>) -> Result<Self> {
+        let http_client = get_http_client(HttpRoute::Graph)?;
+
+        Ok(Self {
+            onedrive,
+            http_client,
+        })
+    }
+}
+
+impl StorageBackend for OneDriveStorage {
+    fn name(&self) -> &'static str {
+        "onedrive"
+    }
+
+    fn create_upload_session<'a>(
+        &'a self,
+        chat_id: i64,
+        root_path: &'a str,
+        filename: &'a str,
+        _total_length: u64,
+    ) -> BoxFuture<'a, Result<UploadTarget>> {
+        Box::pin(async move {
+            let target = self.onedrive.get_drive_target(chat_id).await?;
+
+            let (upload_url, current_length) = self
+                .onedrive
+                .multipart_upload_session_builder(root_path, filename, &target)
+                .await?;
+
+            Ok(UploadTarget {
+                upload_url,
+                current_length,
+            })
+        })
+    }
+
+    fn upload_range<'a>(
+        &'a self,
+        upload_url: &'a str,
+        buffer: &'a [u8],
+        range: Range<u64>,
+        total_length: u64,
+    ) -> BoxFuture<'a, Result<Option<StoredItem>>> {
+        Box::pin(async move {
+            let upload_session = UploadSession::from_upload_url(upload_url);
+
+            match upload_session
+                .upload_part(buffer.to_owned(), range, total_length, &self.http_client)
+                .await
+            {
+                Ok(drive_item) => Ok(drive_item.map(StoredItem::from)),
+                // normal, retried by the caller
+                // 408: Request Timeout
+                // 500: Internal Server Error
+                // 502: Bad Gateway
+                // 503: Service Unavailable
+                // 504: Gateway Timeout
+                // 416: Requested Range Not Satisfiable, probably because the fragment has already been received
+                //
+                // probably has some problem
+                // 409: Conflict, probably caused by rename, too many files with the same name uploaded at once
+                // 404: Not Found, probably because the item has already been uploaded
+                Err(e)
+                    if e.status_code()
+                        .is_some_and(|status_code| status_code.as_u16() == 416) =>
+                {
+                    Ok(None)
+                }
+                Err(e) => Err(Error::from(e)).context("failed to upload part to onedrive"),
+            }
+        })
+    }
+
+    // onedrive completes the session on the last part, so the drive item is already returned
+    fn finalize<'a>(
+        &'a self,
+        _upload_url: &'a str,
+        _total_length: u64,
+    ) -> BoxFuture<'a, Result<StoredItem>> {
+        Box::pin(async { Err(anyhow!("failed to get drive item after upload")) })
+    }
+
+    fn stat<'a>(
+        &'a self,
+        chat_id: i64,
+        path: &'a str,
+    ) -> BoxFuture<'a, Result<Option<StoredItem>>> {
+        Box::pin(async move {
+            let target = self.onedrive.get_drive_target(chat_id).await?;
+
+            let drive_item = self.onedrive.get_item(path, &target).await?;
+
+            Ok(drive_item.map(StoredItem::from))
+        })
+    }
+
+    fn list<'a>(&'a self, chat_id: i64, path: &'a str) -> BoxFuture<'a, Result<Vec<StoredItem>>> {
+        Box::pin(async move {
+            let target = self.onedrive.get_drive_target(chat_id).await?;
+
+            let drive_items = self.onedrive.list_children(path, &target).await?;
+
+            Ok(drive_items.into_iter().map(StoredItem::from).collect())
+        })
+    }
+}
+
+impl From<DriveItem> for StoredItem {
+    fn from(drive_item: DriveItem) -> Self {
+        Self {
+            name: drive_item.name.unwrap_or_default(),
+            size: drive_item.size.unwrap_or_default() as u64,
+            is_dir: drive_item.folder.is_some(),
+            id: drive_item.id.map(|id| id.as_str().to_string()),
+        }
+    }
+}
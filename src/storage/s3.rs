@@ -0,0 +1,386 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    conflict_free_name, join_path, relative_path, StorageBackend, StoredItem, UploadTarget,
+};
+use crate::env::S3Env;
+use anyhow::{anyhow, Context, Result};
+use futures::future::BoxFuture;
+use s3::{creds::Credentials, error::S3Error, serde_types::Part, Bucket, Region};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, ops::Range};
+use tokio::sync::Mutex;
+
+// every part except the last one must be at least 5 MiB
+const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+const MAX_PARTS: u64 = 10000;
+
+// any s3 compatible service, such as minio or the s3 gateway of a nas
+pub struct S3Storage {
+    bucket: Box<Bucket>,
+    // transfers send smaller parts than s3 accepts, they are buffered until a part is full,
+    // so an interrupted upload can't be resumed after restart
+    uploads: Mutex<HashMap<String, MultipartUpload>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct UploadSession {
+    key: String,
+    upload_id: String,
+}
+
+#[derive(Default)]
+struct MultipartUpload {
+    buffer: Vec<u8>,
+    received_length: u64,
+    parts: Vec<Part>,
+}
+
+impl S3Storage {
+    pub fn new(
+        S3Env {
+            endpoint,
+            region,
+            bucket,
+            access_key,
+            secret_key,
+            path_style,
+        }: &S3Env,
+    ) -> Result<Self> {
+        let endpoint = endpoint
+            .as_ref()
+            .ok_or_else(|| anyhow!("storage_s3_endpoint is required by s3 storage"))?;
+        let bucket_name = bucket
+            .as_ref()
+            .ok_or_else(|| anyhow!("storage_s3_bucket is required by s3 storage"))?;
+
+        let region = Region::Custom {
+            region: region.clone(),
+            endpoint: endpoint.clone(),
+        };
+        let credentials = Credentials::new(
+            access_key.as_deref(),
+            secret_key.as_deref(),
+            None,
+            None,
+            None,
+        )
+        .context("invalid s3 credentials")?;
+
+        let mut bucket =
+            Bucket::new(bucket_name, region, credentials).context("failed to create s3 bucket")?;
+
+        if *path_style {
+            bucket = bucket.with_path_style();
+        }
+
+        Ok(Self {
+            bucket,
+            uploads: Mutex::new(HashMap::new()),
+        })
+    }
+
+    async fn put_part(
+        &self,
+        session: &UploadSession,
+        upload: &mut MultipartUpload,
+        chunk: Vec<u8>,
+    ) -> Result<()> {
+        let part_number = upload.parts.len() as u32 + 1;
+
+        let part = self
+            .bucket
+            .put_multipart_chunk(
+                chunk,
+                &session.key,
+                part_number,
+                &session.upload_id,
+                &content_type(&session.key),
+            )
+            .await
+            .context("failed to upload part to s3")?;
+
+        upload.parts.push(part);
+
+        Ok(())
+    }
+}
+
+fn part_size(total_length: u64) -> usize {
+    MIN_PART_SIZE.max(total_length.div_ceil(MAX_PARTS) as usize)
+}
+
+fn content_type(key: &str) -> String {
+    mime_guess::from_path(key)
+        .first_or_octet_stream()
+        .to_string()
+}
+
+fn parse_upload_url(upload_url: &str) -> Result<UploadSession> {
+    serde_json::from_str(upload_url).context("invalid s3 upload session")
+}
+
+const fn is_not_found(e: &S3Error) -> bool {
+    matches!(e, S3Error::HttpFailWithBody(404, _))
+}
+
+impl StorageBackend for S3Storage {
+    fn name(&self) -> &'static str {
+        "s3"
+    }
+
+    fn create_upload_session<'a>(
+        &'a self,
+        chat_id: i64,
+        root_path: &'a str,
+        filename: &'a str,
+        _total_length: u64,
+    ) -> BoxFuture<'a, Result<UploadTarget>> {
+        Box::pin(async move {
+            // objects are overwritten silently, rename like onedrive does
+            let existing_names = self
+                .list(chat_id, root_path)
+                .await?
+                .into_iter()
+                .map(|item| item.name)
+                .collect::<Vec<_>>();
+            let name = conflict_free_name(filename, &existing_names);
+
+            let key = relative_path(&join_path(root_path, &name))?.to_string();
+
+            let response = self
+                .bucket
+                .initiate_multipart_upload(&key, &content_type(&key))
+                .await
+                .context("failed to create s3 multipart upload")?;
+
+            let session = UploadSession {
+                key,
+                upload_id: response.upload_id,
+            };
+
+            self.uploads
+                .lock()
+                .await
+                .insert(session.upload_id.clone(), MultipartUpload::default());
+
+            tracing::debug!("built s3 upload session for {}", name);
+
+            Ok(UploadTarget {
+                upload_url: serde_json::to_string(&session)
+                    .context("failed to serialize s3 upload session")?,
+                current_length: 0,
+            })
+        })
+    }
+
+    fn upload_range<'a>(
+        &'a self,
+        upload_url: &'a str,
+        buffer: &'a [u8],
+        range: Range<u64>,
+        total_length: u64,
+    ) -> BoxFuture<'a, Result<Option<StoredItem>>> {
+        Box::pin(async move {
+            let session = parse_upload_url(upload_url)?;
+
+            // take the upload out so that other uploads are not blocked by this part
+            let mut upload = self
+                .uploads
+                .lock()
+                .await
+                .remove(&session.upload_id)
+                .ok_or_else(|| {
+                    anyhow!("s3 upload session not found, it is lost after restart, please retry")
+                })?;
+
+            let result = async {
+                // the range has been received, probably a retry
+                if range.end <= upload.received_length {
+                    return Ok(());
+                }
+
+                if range.start != upload.received_length {
+                    return Err(anyhow!(
+                        "s3 upload expects range from {}, got {}",
+                        upload.received_length,
+                        range.start
+                    ));
+                }
+
+                let part_size = part_size(total_length);
+
+                if upload.buffer.len() + buffer.len() >= part_size {
+                    let mut chunk = upload.buffer.clone();
+                    chunk.extend_from_slice(buffer);
+
+                    self.put_part(&session, &mut upload, chunk).await?;
+
+                    upload.buffer.clear();
+                } else {
+                    upload.buffer.extend_from_slice(buffer);
+                }
+
+                upload.received_length = range.end;
+
+                Ok(())
+            }
+            .await;
+
+            self.uploads
+                .lock()
+                .await
+                .insert(session.upload_id.clone(), upload);
+
+            result.map(|()| None)
+        })
+    }
+
+    fn finalize<'a>(
+        &'a self,
+        upload_url: &'a str,
+        total_length: u64,
+    ) -> BoxFuture<'a, Result<StoredItem>> {
+        Box::pin(async move {
+            let session = parse_upload_url(upload_url)?;
+
+            let mut upload = self
+                .uploads
+                .lock()
+                .await
+                .remove(&session.upload_id)
+                .ok_or_else(|| anyhow!("s3 upload session not found"))?;
+
+            if upload.received_length != total_length {
+                return Err(anyhow!(
+                    "s3 upload received {} bytes, expected {}",
+                    upload.received_length,
+                    total_length
+                ));
+            }
+
+            if total_length == 0 {
+                // multipart uploads need at least one part
+                self.bucket
+                    .abort_upload(&session.key, &session.upload_id)
+                    .await
+                    .context("failed to abort s3 multipart upload")?;
+                self.bucket
+                    .put_object(&session.key, &[])
+                    .await
+                    .context("failed to put empty s3 object")?;
+            } else {
+                // the last part may be smaller than the minimum
+                if !upload.buffer.is_empty() {
+                    let chunk = std::mem::take(&mut upload.buffer);
+
+                    self.put_part(&session, &mut upload, chunk).await?;
+                }
+
+                self.bucket
+                    .complete_multipart_upload(&session.key, &session.upload_id, upload.parts)
+                    .await
+                    .context("failed to complete s3 multipart upload")?;
+            }
+
+            self.stat(0, &session.key)
+                .await?
+                .ok_or_else(|| anyhow!("s3 object not found after upload: {}", session.key))
+        })
+    }
+
+    fn stat<'a>(
+        &'a self,
+        _chat_id: i64,
+        path: &'a str,
+    ) -> BoxFuture<'a, Result<Option<StoredItem>>> {
+        Box::pin(async move {
+            let key = relative_path(path)?;
+            let name = key.rsplit('/').next().unwrap_or_default().to_string();
+
+            match self.bucket.head_object(key).await {
+                Ok((head, _)) => {
+                    return Ok(Some(StoredItem {
+                        name,
+                        size: head.content_length.unwrap_or_default() as u64,
+                        is_dir: false,
+                        id: None,
+                    }))
+                }
+                Err(e) if is_not_found(&e) => {}
+                Err(e) => {
+                    return Err(e)
+                        .context("failed to get s3 object")
+                        .context(path.to_string())
+                }
+            }
+
+            // directories are only prefixes of keys in s3
+            let is_dir = key.is_empty() || !self.list(0, path).await?.is_empty();
+
+            Ok(is_dir.then_some(StoredItem {
+                name,
+                size: 0,
+                is_dir: true,
+                id: None,
+            }))
+        })
+    }
+
+    fn list<'a>(&'a self, _chat_id: i64, path: &'a str) -> BoxFuture<'a, Result<Vec<StoredItem>>> {
+        Box::pin(async move {
+            let prefix = match relative_path(path)?.trim_end_matches('/') {
+                "" => String::new(),
+                dir => format!("{}/", dir),
+            };
+
+            let results = self
+                .bucket
+                .list(prefix.clone(), Some("/".to_string()))
+                .await
+                .context("failed to list s3 objects")
+                .context(path.to_string())?;
+
+            let mut items = Vec::new();
+
+            for result in results {
+                for object in result.contents {
+                    let name = object.key.strip_prefix(&prefix).unwrap_or(&object.key);
+
+                    // the placeholder object of the directory itself
+                    if name.is_empty() {
+                        continue;
+                    }
+
+                    items.push(StoredItem {
+                        name: name.to_string(),
+                        size: object.size,
+                        is_dir: false,
+                        id: None,
+                    });
+                }
+
+                for common_prefix in result.common_prefixes.unwrap_or_default() {
+                    items.push(StoredItem {
+                        name: common_prefix
+                            .prefix
+                            .strip_prefix(&prefix)
+                            .unwrap_or(&common_prefix.prefix)
+                            .trim_end_matches('/')
+                            .to_string(),
+                        size: 0,
+                        is_dir: true,
+                        id: None,
+                    });
+                }
+            }
+
+            Ok(items)
+        })
+    }
+}
@@ -0,0 +1,118 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+// controls of queued tasks from outside telegram,
+// false is returned if the task doesn't exist or can't be controlled in its status
+
+use super::tasks::{self, TaskStatus};
+use crate::{client::utils::chat_from_hex, error::ResultExt, state::AppState};
+use anyhow::{Context, Result};
+use grammers_client::InputMessage;
+
+pub async fn cancel_task(state: &AppState, id: i64) -> Result<bool> {
+    let session = &state.task_session;
+
+    let Some(task) = session.get_task(id).await? else {
+        return Ok(false);
+    };
+
+    // must before aborting, so that the handler records it as cancelled instead of paused
+    session.delete_task(id).await?;
+
+    if !session.abort_task(id).await {
+        session.record_cancelled_task(&task).await;
+    }
+
+    mark_indicator_cancelled(&task, state).await.trace();
+
+    Ok(true)
+}
+
+pub async fn pause_task(state: &AppState, id: i64) -> Result<bool> {
+    let session = &state.task_session;
+
+    let Some(task) = session.get_task(id).await? else {
+        return Ok(false);
+    };
+
+    if !matches!(
+        task.status,
+        TaskStatus::Waiting | TaskStatus::Fetched | TaskStatus::Started
+    ) {
+        return Ok(false);
+    }
+
+    session.set_task_status(id, TaskStatus::Paused).await?;
+
+    // in case it's fetched meanwhile, the handler keeps the task as paused once aborted
+    session.abort_task(id).await;
+
+    tracing::info!("task {} paused", task.filename);
+
+    Ok(true)
+}
+
+pub async fn resume_task(state: &AppState, id: i64) -> Result<bool> {
+    requeue_task(state, id, TaskStatus::Paused).await
+}
+
+pub async fn retry_task(state: &AppState, id: i64) -> Result<bool> {
+    requeue_task(state, id, TaskStatus::Failed).await
+}
+
+// the uploaded part may not match what's downloaded again, so the upload restarts
+async fn requeue_task(state: &AppState, id: i64, expected_status: TaskStatus) -> Result<bool> {
+    let session = &state.task_session;
+
+    let Some(task) = session.get_task(id).await? else {
+        return Ok(false);
+    };
+
+    if task.status != expected_status {
+        return Ok(false);
+    }
+
+    let upload_target = state
+        .storage
+        .create_upload_session(
+            task.chat_id,
+            &task.root_path,
+            &task.filename,
+            task.total_length as u64,
+        )
+        .await
+        .context("failed to create upload session for requeued task")?;
+
+    session
+        .requeue_task(id, &upload_target.upload_url, upload_target.current_length)
+        .await?;
+
+    tracing::info!("task {} requeued", task.filename);
+
+    Ok(true)
+}
+
+async fn mark_indicator_cancelled(task: &tasks::Model, state: &AppState) -> Result<()> {
+    if !task.has_chat() {
+        return Ok(());
+    }
+
+    let chat_bot = chat_from_hex(&task.chat_bot_hex)?;
+
+    let message_indicator = state
+        .telegram_bot
+        .get_message(chat_bot, task.message_indicator_id)
+        .await?;
+
+    let response = format!("{}\n\nCancelled.", message_indicator.text());
+    message_indicator
+        .edit(task.message_indicator_id, InputMessage::html(&response))
+        .await
+        .context(response)?;
+
+    Ok(())
+}
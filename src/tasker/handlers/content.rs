@@ -0,0 +1,31 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    tasks::{self, CmdType},
+    transfer::{multi_parts_uploader_from_content, multi_parts_uploader_from_spool},
+    Progress,
+};
+use crate::storage::{StorageBackend, StoredItem};
+use anyhow::Result;
+use std::sync::Arc;
+
+pub async fn handler(
+    task: tasks::Model,
+    progress: Arc<Progress>,
+    storage: &dyn StorageBackend,
+) -> Result<Option<StoredItem>> {
+    let stored_item = if task.cmd_type == CmdType::Upload {
+        multi_parts_uploader_from_spool(&task, progress.clone(), storage).await?
+    } else {
+        multi_parts_uploader_from_content(&task, progress.clone(), storage).await?
+    };
+
+    progress.update_filename(task.id, &stored_item.name).await?;
+
+    Ok(Some(stored_item))
+}
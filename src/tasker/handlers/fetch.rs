@@ -0,0 +1,23 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{tasks, transfer::multi_parts_uploader_from_remote, Progress};
+use crate::storage::{StorageBackend, StoredItem};
+use anyhow::Result;
+use std::sync::Arc;
+
+pub async fn handler(
+    task: tasks::Model,
+    progress: Arc<Progress>,
+    storage: &dyn StorageBackend,
+) -> Result<Option<StoredItem>> {
+    let stored_item = multi_parts_uploader_from_remote(&task, progress.clone(), storage).await?;
+
+    progress.update_filename(task.id, &stored_item.name).await?;
+
+    Ok(Some(stored_item))
+}
@@ -6,31 +6,32 @@
 */
 
 use super::{tasks, transfer::multi_parts_uploader_from_tg_file, Progress};
-use crate::{error::TaskAbortError, state::AppState};
+use crate::{error::TaskAbortError, state::AppState, storage::StoredItem};
 use anyhow::Result;
 use std::sync::Arc;
 use tokio_util::sync::CancellationToken;
 
+// nothing is stored if the task is aborted
 pub async fn handler(
     task: tasks::Model,
     progress: Arc<Progress>,
     cancellation_token: CancellationToken,
     state: AppState,
-) -> Result<()> {
-    let filename =
+) -> Result<Option<StoredItem>> {
+    let stored_item =
         match multi_parts_uploader_from_tg_file(&task, progress.clone(), cancellation_token, state)
             .await
         {
-            Ok(filename) => filename,
+            Ok(stored_item) => stored_item,
             Err(e) => {
                 if e.downcast_ref::<TaskAbortError>().is_some() {
-                    return Ok(());
+                    return Ok(None);
                 }
                 return Err(e);
             }
         };
 
-    progress.update_filename(task.id, &filename).await?;
+    progress.update_filename(task.id, &stored_item.name).await?;
 
-    Ok(())
+    Ok(Some(stored_item))
 }
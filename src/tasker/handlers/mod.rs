@@ -5,6 +5,8 @@
 :license: MIT, see LICENSE for more details.
 */
 
+pub mod content;
+pub mod fetch;
 pub mod file;
 pub mod url;
 
@@ -6,13 +6,18 @@
 */
 
 use super::{tasks, transfer::multi_parts_uploader_from_url, Progress};
+use crate::storage::{StorageBackend, StoredItem};
 use anyhow::Result;
 use std::sync::Arc;
 
-pub async fn handler(task: tasks::Model, progress: Arc<Progress>) -> Result<()> {
-    let filename = multi_parts_uploader_from_url(&task, progress.clone()).await?;
+pub async fn handler(
+    task: tasks::Model,
+    progress: Arc<Progress>,
+    storage: &dyn StorageBackend,
+) -> Result<Option<StoredItem>> {
+    let stored_item = multi_parts_uploader_from_url(&task, progress.clone(), storage).await?;
 
-    progress.update_filename(task.id, &filename).await?;
+    progress.update_filename(task.id, &stored_item.name).await?;
 
-    Ok(())
+    Ok(Some(stored_item))
 }
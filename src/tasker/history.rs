@@ -0,0 +1,278 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::tasks::{self, CmdType, TaskStatus};
+use crate::utils::{create_table_if_not_exists, get_current_timestamp, month_start};
+use anyhow::{Context, Result};
+use sea_orm::{
+    entity::prelude::DeriveEntityModel, ActiveModelBehavior, ActiveValue, ColumnTrait,
+    ConnectionTrait, DatabaseConnection, DerivePrimaryKey, DeriveRelation, EntityName, EntityTrait,
+    EnumIter, PrimaryKeyTrait, QueryFilter, QueryOrder, QuerySelect, Set, Statement,
+};
+
+// older records are dropped
+const MAX_HISTORY_NUM: i64 = 1000;
+
+// finished transfers, unlike tasks they are kept across restarts
+#[derive(Clone, Debug, DeriveEntityModel)]
+#[sea_orm(table_name = "history")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub task_id: i64,
+    pub cmd_type: CmdType,
+    pub filename: String,
+    pub root_path: String,
+    pub total_length: i64,
+    pub chat_id: i64,
+    // completed, failed or cancelled
+    pub status: TaskStatus,
+    pub error: Option<String>,
+    pub finished_timestamp: i64,
+    // telegram user who added the task, for quotas
+    pub sender_id: Option<i64>,
+}
+
+#[derive(Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+pub struct TaskHistory {
+    connection: DatabaseConnection,
+}
+
+impl TaskHistory {
+    pub async fn new(session_path: &str) -> Result<Self> {
+        let connection = sea_orm::Database::connect(format!("sqlite://{}?mode=rwc", session_path))
+            .await
+            .context("failed to connect to task history")?;
+
+        let columns = Self::get_columns(&connection).await?;
+
+        // history before quotas has no sender column
+        if columns.is_empty() {
+            create_table_if_not_exists(&connection, Entity).await?;
+        } else if !columns.iter().any(|column| column == "sender_id") {
+            connection
+                .execute_unprepared("ALTER TABLE history ADD COLUMN sender_id INTEGER")
+                .await
+                .context("failed to add sender_id to task history")?;
+        }
+
+        Ok(Self { connection })
+    }
+
+    // empty if the table doesn't exist
+    async fn get_columns(connection: &DatabaseConnection) -> Result<Vec<String>> {
+        let backend = connection.get_database_backend();
+
+        let rows = connection
+            .query_all(Statement::from_string(
+                backend,
+                format!("PRAGMA table_info({})", Entity.table_name()),
+            ))
+            .await
+            .context("failed to get columns of task history")?;
+
+        rows.iter()
+            .map(|row| {
+                row.try_get::<String>("", "name")
+                    .context("failed to get column name of task history")
+            })
+            .collect()
+    }
+
+    pub async fn insert(
+        &self,
+        task: &tasks::Model,
+        status: TaskStatus,
+        error: Option<String>,
+    ) -> Result<()> {
+        let insert_item = ActiveModel {
+            id: ActiveValue::default(),
+            task_id: Set(task.id),
+            cmd_type: Set(task.cmd_type.clone()),
+            filename: Set(task.filename.clone()),
+            root_path: Set(task.root_path.clone()),
+            total_length: Set(task.total_length),
+            chat_id: Set(task.chat_id),
+            status: Set(status),
+            error: Set(error),
+            finished_timestamp: Set(get_current_timestamp()),
+            sender_id: Set(task.sender_id),
+        };
+
+        let id = Entity::insert(insert_item)
+            .exec(&self.connection)
+            .await
+            .context("failed to insert task history")?
+            .last_insert_id;
+
+        // records of this month are kept for quotas
+        Entity::delete_many()
+            .filter(Column::Id.lte(id - MAX_HISTORY_NUM))
+            .filter(Column::FinishedTimestamp.lt(month_start()))
+            .exec(&self.connection)
+            .await
+            .context("failed to drop old task history")?;
+
+        Ok(())
+    }
+
+    // the latest first
+    pub async fn list(&self, limit: u64) -> Result<Vec<Model>> {
+        Entity::find()
+            .order_by_desc(Column::Id)
+            .limit(limit)
+            .all(&self.connection)
+            .await
+            .context("failed to list task history")
+    }
+
+    // task ids restart with the task session, so the latest record is taken
+    pub async fn get_by_task_id(&self, task_id: i64) -> Result<Option<Model>> {
+        Entity::find()
+            .filter(Column::TaskId.eq(task_id))
+            .order_by_desc(Column::Id)
+            .one(&self.connection)
+            .await
+            .context("failed to get task history")
+    }
+
+    // completed transfers since the timestamp, of a sender or everyone
+    pub async fn list_completed_since(
+        &self,
+        timestamp: i64,
+        sender_id: Option<i64>,
+    ) -> Result<Vec<Model>> {
+        let mut query = Entity::find()
+            .filter(Column::Status.eq(TaskStatus::Completed))
+            .filter(Column::FinishedTimestamp.gte(timestamp))
+            .filter(Column::SenderId.is_not_null());
+
+        if let Some(sender_id) = sender_id {
+            query = query.filter(Column::SenderId.eq(sender_id));
+        }
+
+        query
+            .all(&self.connection)
+            .await
+            .context("failed to list completed task history")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TempPath;
+
+    fn new_task(id: i64) -> tasks::Model {
+        tasks::Model {
+            id,
+            cmd_type: CmdType::Url,
+            filename: format!("{}.txt", id),
+            root_path: "/Telegram".to_string(),
+            url: None,
+            headers: None,
+            profile: None,
+            content: None,
+            spool_path: None,
+            upload_url: String::new(),
+            current_length: 0,
+            total_length: 1,
+            chat_id: 1,
+            chat_bot_hex: String::new(),
+            chat_user_hex: String::new(),
+            chat_origin_hex: None,
+            message_id: 1,
+            message_indicator_id: 2,
+            message_origin_id: None,
+            status: TaskStatus::Started,
+            auto_delete: false,
+            sender_id: Some(id % 2),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_task_history() {
+        let session_path = TempPath::new("history");
+        let history = TaskHistory::new(session_path.as_str()).await.unwrap();
+
+        history
+            .insert(&new_task(1), TaskStatus::Completed, None)
+            .await
+            .unwrap();
+        history
+            .insert(
+                &new_task(2),
+                TaskStatus::Failed,
+                Some("timeout".to_string()),
+            )
+            .await
+            .unwrap();
+
+        let records = history.list(10).await.unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].filename, "2.txt");
+        assert_eq!(records[0].status, TaskStatus::Failed);
+        assert_eq!(records[0].error.as_deref(), Some("timeout"));
+        assert_eq!(records[1].status, TaskStatus::Completed);
+
+        assert_eq!(history.list(1).await.unwrap().len(), 1);
+
+        history
+            .insert(&new_task(2), TaskStatus::Completed, None)
+            .await
+            .unwrap();
+
+        let record = history.get_by_task_id(2).await.unwrap().unwrap();
+        assert_eq!(record.status, TaskStatus::Completed);
+        assert!(history.get_by_task_id(3).await.unwrap().is_none());
+
+        let records = history.list_completed_since(0, None).await.unwrap();
+        assert_eq!(records.len(), 2);
+        let records = history.list_completed_since(0, Some(1)).await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].task_id, 1);
+        assert!(history
+            .list_completed_since(get_current_timestamp() + 1, None)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_add_sender_column() {
+        let session_path = TempPath::new("history");
+        let session_path = session_path.as_str();
+
+        // history before quotas
+        let connection = sea_orm::Database::connect(format!("sqlite://{}?mode=rwc", session_path))
+            .await
+            .unwrap();
+        connection
+            .execute_unprepared(
+                "CREATE TABLE history (id INTEGER PRIMARY KEY AUTOINCREMENT, task_id INTEGER NOT NULL, cmd_type TEXT NOT NULL, filename TEXT NOT NULL, root_path TEXT NOT NULL, total_length INTEGER NOT NULL, chat_id INTEGER NOT NULL, status TEXT NOT NULL, error TEXT, finished_timestamp INTEGER NOT NULL)",
+            )
+            .await
+            .unwrap();
+        drop(connection);
+
+        let history = TaskHistory::new(session_path).await.unwrap();
+        history
+            .insert(&new_task(1), TaskStatus::Completed, None)
+            .await
+            .unwrap();
+        drop(history);
+
+        // the column is only added once
+        let history = TaskHistory::new(session_path).await.unwrap();
+        let records = history.list_completed_since(0, Some(1)).await.unwrap();
+        assert_eq!(records.len(), 1);
+    }
+}
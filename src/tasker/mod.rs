@@ -5,28 +5,45 @@
 :license: MIT, see LICENSE for more details.
 */
 
+mod control;
 mod handlers;
+mod history;
 mod progress;
+mod quota;
+mod scheduler;
 mod session;
 mod tasks;
 mod transfer;
+mod webhook;
 
 use crate::{
     client::utils::chat_from_hex,
-    env::ENV,
+    env::{StorageKind, ENV},
     error::{ErrorExt, ResultExt, ResultUnwrapExt},
     message::TelegramMessage,
+    metrics::METRICS,
     state::AppState,
 };
 use anyhow::{Context, Result};
+pub use control::{cancel_task, pause_task, resume_task, retry_task};
 use grammers_client::InputMessage;
 use path_slash::PathBufExt;
+pub use progress::LatestMessages;
 use progress::Progress;
-pub use session::{BatchAborter, TaskAborter, TaskSession};
-use std::{path::Path, sync::Arc, time::Duration};
+pub use session::{BatchAborter, BatchAborters, TaskAborter, TaskSession};
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 pub use tasks::{CmdType, InsertTask};
 use tokio::sync::Semaphore;
 use tokio_util::sync::CancellationToken;
+use webhook::TaskEvent;
+pub use webhook::Webhook;
 
 pub struct Tasker {
     state: AppState,
@@ -52,9 +69,7 @@ impl Tasker {
             progress_clone.run().await;
         });
 
-        let handler_num = ENV.get().unwrap().task_handler_num;
-
-        let semaphore = Arc::new(Semaphore::new(handler_num as usize));
+        let semaphore = self.state.handler_limit.semaphore.clone();
 
         loop {
             self.handle_tasks(semaphore.clone()).await.trace();
@@ -68,23 +83,29 @@ impl Tasker {
         let task = self.session().fetch_task().await?;
 
         if let Some(task) = task {
-            let chat = chat_from_hex(&task.chat_bot_hex)?;
+            let message = if task.has_chat() {
+                let chat = chat_from_hex(&task.chat_bot_hex)?;
+
+                // in case that message is sent and deleted immediately
+                let Ok(message) = self
+                    .state
+                    .telegram_bot
+                    .get_message(chat, task.message_id)
+                    .await
+                else {
+                    self.state
+                        .task_session
+                        .delete_task_from_message_id_if_exists(chat.id, task.message_id)
+                        .await?;
 
-            // in case that message is sent and deleted immediately
-            let Ok(message) = self
-                .state
-                .telegram_bot
-                .get_message(chat, task.message_id)
-                .await
-            else {
-                self.state
-                    .task_session
-                    .delete_task_from_message_id_if_exists(chat.id, task.message_id)
-                    .await?;
+                    tracing::info!("task {} aborted", task.filename);
 
-                tracing::info!("task {} aborted", task.filename);
+                    return Ok(());
+                };
 
-                return Ok(());
+                Some(message)
+            } else {
+                None
             };
 
             let semaphore_clone = semaphore.clone();
@@ -99,7 +120,7 @@ impl Tasker {
                 &task.filename,
             );
             let cancellation_token = aborter.token.clone();
-            aborters.insert((chat.id, task.message_indicator_id), aborter);
+            aborters.insert(task.aborter_key(), aborter);
             drop(aborters);
 
             tokio::spawn(async move {
@@ -108,6 +129,7 @@ impl Tasker {
                     .await
                     .context("failed to acquire semaphore for task handler")
                     .unwrap_or_trace();
+                let _worker = METRICS.start_worker();
 
                 if let Err(e) = handler_dispatch(
                     task,
@@ -118,7 +140,10 @@ impl Tasker {
                 )
                 .await
                 {
-                    e.send(message).await.unwrap_both().trace();
+                    match message {
+                        Some(message) => e.send(message).await.unwrap_both().trace(),
+                        None => e.trace(),
+                    }
                 }
             });
         }
@@ -127,27 +152,71 @@ impl Tasker {
     }
 }
 
+// number of tasks handled at the same time, resized when the config file is reloaded
+pub struct HandlerLimit {
+    semaphore: Arc<Semaphore>,
+    num: AtomicU8,
+}
+
+impl HandlerLimit {
+    pub fn new(num: u8) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(num as usize)),
+            num: AtomicU8::new(num),
+        }
+    }
+
+    pub fn resize(&self, num: u8) {
+        let old_num = self.num.swap(num, Ordering::AcqRel);
+
+        if num > old_num {
+            self.semaphore.add_permits((num - old_num) as usize);
+        } else if num < old_num {
+            // running tasks keep their permits, the extra ones are dropped once released
+            let semaphore = self.semaphore.clone();
+            tokio::spawn(async move {
+                if let Ok(permits) = semaphore.acquire_many(u32::from(old_num - num)).await {
+                    permits.forget();
+                }
+            });
+        }
+
+        tracing::info!("task handler number changed to {}", num);
+    }
+}
+
 async fn handler_dispatch(
     task: tasks::Model,
-    message: TelegramMessage,
+    message: Option<TelegramMessage>,
     progress: Arc<Progress>,
     cancellation_token: CancellationToken,
     state: AppState,
 ) -> Result<()> {
     let session = &state.task_session;
     let telegram_bot = &state.telegram_bot;
-    let telegram_user = &state.telegram_user;
 
     session
         .set_task_status(task.id, tasks::TaskStatus::Started)
         .await?;
 
+    session.webhook.fire(TaskEvent::Started, &task, None, None);
+
     let fut = async {
         match task.cmd_type {
             CmdType::Url => {
                 tracing::info!("handle url task");
 
-                handlers::url::handler(task.clone(), progress).await
+                handlers::url::handler(task.clone(), progress, state.storage.as_ref()).await
+            }
+            CmdType::Ftp | CmdType::Sftp | CmdType::WebDav => {
+                tracing::info!("handle fetch task");
+
+                handlers::fetch::handler(task.clone(), progress, state.storage.as_ref()).await
+            }
+            CmdType::Note | CmdType::Export | CmdType::Upload => {
+                tracing::info!("handle note or export task");
+
+                handlers::content::handler(task.clone(), progress, state.storage.as_ref()).await
             }
             CmdType::File | CmdType::Link => {
                 tracing::info!("handle file or link task");
@@ -170,16 +239,14 @@ async fn handler_dispatch(
         () = cancellation_token.cancelled() => {
             aborted = true;
 
-            Ok(())
+            Ok(None)
         }
     };
 
-    let chat_id = message.chat().id();
+    let chat_id = task.chat_id;
 
     let mut task_aborters = state.task_session.task_aborters.lock().await;
-    let task_aborter_exists = task_aborters
-        .remove(&(chat_id, task.message_indicator_id))
-        .is_some();
+    let task_aborter_exists = task_aborters.remove(&task.aborter_key()).is_some();
     drop(task_aborters);
 
     let batch_aborters = state.task_session.batch_aborters.lock().await;
@@ -188,16 +255,44 @@ async fn handler_dispatch(
     drop(batch_aborters);
 
     if aborted {
+        // a cancelled task is deleted, a paused one is kept to be resumed
+        if session.get_task(task.id).await?.is_some() {
+            session
+                .set_task_status(task.id, tasks::TaskStatus::Paused)
+                .await?;
+        } else {
+            session.record_cancelled_task(&task).await;
+        }
+
         return Ok(());
     }
 
     match result {
-        Ok(()) => {
+        Ok(stored_item) => {
             session
                 .set_task_status(task.id, tasks::TaskStatus::Completed)
                 .await?;
 
-            if task_aborter_exists {
+            session.remove_spool_file(&task).await;
+
+            session
+                .history
+                .insert(&task, tasks::TaskStatus::Completed, None)
+                .await
+                .trace();
+
+            session
+                .webhook
+                .fire(TaskEvent::Completed, &task, stored_item.as_ref(), None);
+
+            // the part uploaded before a restart isn't counted
+            METRICS.add_transferred_bytes(
+                &task.cmd_type.to_string(),
+                &get_account_label(&state).await,
+                (task.total_length - task.current_length) as u64,
+            );
+
+            if task_aborter_exists && task.has_chat() {
                 if task.auto_delete {
                     let chat_bot = chat_from_hex(&task.chat_bot_hex)?;
                     let chat_user = chat_from_hex(&task.chat_user_hex)?;
@@ -216,7 +311,8 @@ async fn handler_dispatch(
                         batch_aborters.remove(&(chat_id, task.message_id));
                         drop(batch_aborters);
 
-                        telegram_user
+                        state
+                            .reader(chat_user)
                             .delete_messages(chat_user, &[task.message_id])
                             .await?;
                     }
@@ -226,13 +322,33 @@ async fn handler_dispatch(
             }
         }
         Err(e) => {
-            e.send(message.clone()).await.unwrap_both().trace();
+            let error = format!("{:#}", e);
+
+            session
+                .webhook
+                .fire(TaskEvent::Failed, &task, None, Some(&error));
 
+            session
+                .history
+                .insert(&task, tasks::TaskStatus::Failed, Some(error))
+                .await
+                .trace();
+
+            // kept to be retried on the dashboard
             session
                 .set_task_status(task.id, tasks::TaskStatus::Failed)
                 .await?;
 
-            handle_failed_task(task.clone(), state.clone()).await?;
+            match message {
+                Some(message) => {
+                    e.send(message).await.unwrap_both().trace();
+
+                    handle_failed_task(task.clone(), state.clone()).await?;
+                }
+                None => e.trace(),
+            }
+
+            return Ok(());
         }
     }
 
@@ -241,6 +357,21 @@ async fn handler_dispatch(
     Ok(())
 }
 
+// other storage backends have no accounts
+async fn get_account_label(state: &AppState) -> String {
+    if ENV.get().unwrap().storage.kind != StorageKind::OneDrive {
+        return state.storage.name().to_string();
+    }
+
+    state
+        .onedrive
+        .get_current_username()
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+}
+
 async fn handle_completed_task(task: tasks::Model, state: AppState) -> Result<()> {
     let chat_bot = chat_from_hex(&task.chat_bot_hex)?;
 
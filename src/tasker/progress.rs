@@ -7,13 +7,33 @@
 
 use super::{TaskSession, session::ChatHex, tasks};
 use crate::{
-    client::utils::chat_from_hex,
+    client::utils::{chat_from_hex, format_message_link},
     error::{ErrorExt, ResultExt, ResultUnwrapExt},
     state::AppState,
 };
 use anyhow::{Context, Result, anyhow};
 use grammers_client::InputMessage;
-use std::{collections::HashMap, time::Duration};
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+// latest message id of each chat received by the bot,
+// for private chats whose history can't be read by the bot
+#[derive(Default)]
+pub struct LatestMessages {
+    message_ids: Mutex<HashMap<i64, i32>>,
+}
+
+impl LatestMessages {
+    pub fn record(&self, chat_id: i64, message_id: i32) {
+        let mut message_ids = self.message_ids.lock().unwrap();
+
+        let latest_id = message_ids.entry(chat_id).or_default();
+        *latest_id = (*latest_id).max(message_id);
+    }
+
+    pub fn get(&self, chat_id: i64) -> Option<i32> {
+        self.message_ids.lock().unwrap().get(&chat_id).copied()
+    }
+}
 
 pub struct Progress {
     state: AppState,
@@ -151,11 +171,11 @@ impl Progress {
         let mut response = "Progress:\n".to_string();
 
         for task_progress in current_tasks {
+            let name = format_message_link(chat, task_progress.message_id, &task_progress.filename);
+
             response += &format!(
-                "\n<a href=\"https://t.me/c/{}/{}\">{}</a>: {:.2}/{:.2}MB",
-                chat.id,
-                task_progress.message_id,
-                task_progress.filename,
+                "\n{}: {:.2}/{:.2}MB",
+                name,
                 task_progress.current_length as f64 / 1024. / 1024.,
                 task_progress.total_length as f64 / 1024. / 1024.
             );
@@ -175,17 +195,29 @@ impl Progress {
             .ok_or_else(|| anyhow!("chat_bot_hex not in chat_progress_message_id"))?;
 
         if let Some(progress_message_id) = progress_message_id {
-            let chat_user = chat_from_hex(chat_user_hex)?;
-
-            let latest_message = telegram_user
-                .iter_messages(chat_user)
-                .limit(1)
-                .next()
-                .await
-                .context("failed to iter messages for latest message")?;
-
-            if let Some(latest_message) = latest_message {
-                if latest_message.id() == *progress_message_id {
+            // the history of private chats can't be read, messages received there are recorded instead
+            let latest_message_id = if chat.is_user() {
+                Some(
+                    self.state
+                        .latest_messages
+                        .get(chat.id)
+                        .unwrap_or_default()
+                        .max(*progress_message_id),
+                )
+            } else {
+                let chat_user = chat_from_hex(chat_user_hex)?;
+
+                telegram_user
+                    .iter_messages(chat_user)
+                    .limit(1)
+                    .next()
+                    .await
+                    .context("failed to iter messages for latest message")?
+                    .map(|message| message.id())
+            };
+
+            if let Some(latest_message_id) = latest_message_id {
+                if latest_message_id == *progress_message_id {
                     if *last_progress_response != response {
                         telegram_bot
                             .edit_message(
@@ -0,0 +1,177 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+// usage of a telegram user is counted from completed transfers in the history,
+// and tasks still in the session, which will be transferred
+
+use super::{session::TaskSession, tasks::TaskStatus};
+use crate::{
+    env::Quota,
+    utils::{day_start, month_start},
+};
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+use tokio::sync::OwnedMutexGuard;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub daily_bytes: u64,
+    pub daily_tasks: u64,
+    pub monthly_bytes: u64,
+    pub monthly_tasks: u64,
+    // waiting, running and paused tasks
+    pub pending_bytes: u64,
+    pub pending_tasks: u64,
+    // fetched by the tasker or started
+    pub running_tasks: u64,
+}
+
+impl TaskSession {
+    // keyed by sender id, only senders with transfers this month or pending tasks
+    pub async fn get_usages(&self) -> Result<BTreeMap<i64, Usage>> {
+        self.collect_usages(None).await
+    }
+
+    pub async fn get_usage(&self, sender_id: i64) -> Result<Usage> {
+        Ok(self
+            .collect_usages(Some(sender_id))
+            .await?
+            .remove(&sender_id)
+            .unwrap_or_default())
+    }
+
+    async fn collect_usages(&self, sender_id: Option<i64>) -> Result<BTreeMap<i64, Usage>> {
+        let mut usages = BTreeMap::<i64, Usage>::new();
+
+        let day_start = day_start();
+
+        for record in self
+            .history
+            .list_completed_since(month_start(), sender_id)
+            .await?
+        {
+            let Some(record_sender_id) = record.sender_id else {
+                continue;
+            };
+
+            let usage = usages.entry(record_sender_id).or_default();
+            let bytes = record.total_length.max(0) as u64;
+
+            usage.monthly_bytes += bytes;
+            usage.monthly_tasks += 1;
+
+            if record.finished_timestamp >= day_start {
+                usage.daily_bytes += bytes;
+                usage.daily_tasks += 1;
+            }
+        }
+
+        for task in self.list_sender_tasks(sender_id).await? {
+            let Some(task_sender_id) = task.sender_id else {
+                continue;
+            };
+
+            let usage = usages.entry(task_sender_id).or_default();
+
+            usage.pending_bytes += task.total_length.max(0) as u64;
+            usage.pending_tasks += 1;
+
+            if matches!(task.status, TaskStatus::Fetched | TaskStatus::Started) {
+                usage.running_tasks += 1;
+            }
+        }
+
+        Ok(usages)
+    }
+
+    // pending tasks are counted in, so that a batch can't exceed the quota,
+    // check before creating the upload session, and keep the guard until the task is inserted,
+    // so that concurrent tasks of the sender are counted one after another
+    pub async fn check_quota(
+        &self,
+        sender_id: Option<i64>,
+        total_length: u64,
+    ) -> Result<Option<OwnedMutexGuard<()>>> {
+        // tasks from the http api are not limited
+        let Some(sender_id) = sender_id else {
+            return Ok(None);
+        };
+
+        let guard = self.lock_sender(sender_id).await;
+
+        let quota = Quota::read(sender_id);
+
+        if quota == Quota::default() {
+            return Ok(Some(guard));
+        }
+
+        let usage = self.get_usage(sender_id).await?;
+
+        let checks = [
+            (
+                "daily transfer size",
+                quota.daily_bytes,
+                usage.daily_bytes + usage.pending_bytes + total_length,
+            ),
+            (
+                "monthly transfer size",
+                quota.monthly_bytes,
+                usage.monthly_bytes + usage.pending_bytes + total_length,
+            ),
+            (
+                "daily tasks",
+                quota.daily_tasks,
+                usage.daily_tasks + usage.pending_tasks + 1,
+            ),
+            (
+                "monthly tasks",
+                quota.monthly_tasks,
+                usage.monthly_tasks + usage.pending_tasks + 1,
+            ),
+            ("pending tasks", quota.max_pending, usage.pending_tasks + 1),
+        ];
+
+        for (name, limit, value) in checks {
+            if limit.is_some_and(|limit| value > limit) {
+                tracing::info!("{} quota of user {} exceeded", name, sender_id);
+
+                return Err(anyhow!(
+                    "{} quota of user {} exceeded, see /usage",
+                    name,
+                    sender_id
+                ));
+            }
+        }
+
+        Ok(Some(guard))
+    }
+
+    // senders running as many tasks as max_running, their waiting tasks are skipped by the tasker
+    pub async fn get_busy_senders(&self) -> Result<Vec<i64>> {
+        let mut running_tasks = BTreeMap::<i64, u64>::new();
+
+        for task in self.list_sender_tasks(None).await? {
+            if !matches!(task.status, TaskStatus::Fetched | TaskStatus::Started) {
+                continue;
+            }
+
+            if let Some(sender_id) = task.sender_id {
+                *running_tasks.entry(sender_id).or_default() += 1;
+            }
+        }
+
+        Ok(running_tasks
+            .into_iter()
+            .filter(|(sender_id, running_tasks)| {
+                Quota::read(*sender_id)
+                    .max_running
+                    .is_some_and(|max_running| *running_tasks >= max_running)
+            })
+            .map(|(sender_id, _)| sender_id)
+            .collect())
+    }
+}
@@ -0,0 +1,157 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+// start-time fair queuing over chats and senders,
+// a task starts at the later virtual time of its chat and its sender, and moves both forward,
+// the chat by the inverse of its weight, the sender by the inverse of both weights,
+// so a chat weight takes effect even if only one sender is in the chat
+
+use std::collections::HashMap;
+
+// virtual time taken by a task of weight 1
+const STRIDE: u64 = 1 << 16;
+
+pub struct Candidate {
+    pub chat_id: i64,
+    pub sender_id: Option<i64>,
+    pub chat_weight: u64,
+    pub sender_weight: u64,
+}
+
+#[derive(Default)]
+pub struct Scheduler {
+    virtual_time: u64,
+    chat_times: HashMap<i64, u64>,
+    sender_times: HashMap<i64, u64>,
+}
+
+impl Scheduler {
+    // candidates are the first waiting task of each chat ordered by id,
+    // a tie goes to the chat served least, then to the earlier task
+    pub fn pick(&mut self, candidates: &[Candidate]) -> Option<usize> {
+        let (index, (start_time, _)) = candidates
+            .iter()
+            .enumerate()
+            .map(|(index, candidate)| (index, self.start_time(candidate)))
+            .min_by_key(|(_, key)| *key)?;
+
+        let candidate = &candidates[index];
+
+        self.virtual_time = start_time;
+
+        self.chat_times.insert(
+            candidate.chat_id,
+            start_time + stride(candidate.chat_weight),
+        );
+
+        if let Some(sender_id) = candidate.sender_id {
+            self.sender_times.insert(
+                sender_id,
+                start_time
+                    + stride(
+                        candidate
+                            .chat_weight
+                            .max(1)
+                            .saturating_mul(candidate.sender_weight),
+                    ),
+            );
+        }
+
+        // times behind the virtual time make no difference, so idle chats and senders are dropped
+        let virtual_time = self.virtual_time;
+        self.chat_times.retain(|_, time| *time > virtual_time);
+        self.sender_times.retain(|_, time| *time > virtual_time);
+
+        Some(index)
+    }
+
+    fn start_time(&self, candidate: &Candidate) -> (u64, u64) {
+        let chat_time = self
+            .chat_times
+            .get(&candidate.chat_id)
+            .copied()
+            .unwrap_or_default();
+
+        let sender_time = candidate
+            .sender_id
+            .and_then(|sender_id| self.sender_times.get(&sender_id).copied())
+            .unwrap_or_default();
+
+        (self.virtual_time.max(chat_time).max(sender_time), chat_time)
+    }
+}
+
+// at least 1, so that a chat or a sender can't take every turn however large its weight is
+fn stride(weight: u64) -> u64 {
+    (STRIDE / weight.max(1)).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn candidate(chat_id: i64, sender_id: i64, chat_weight: u64) -> Candidate {
+        Candidate {
+            chat_id,
+            sender_id: Some(sender_id),
+            chat_weight,
+            sender_weight: 1,
+        }
+    }
+
+    // chat ids picked in turn, every chat always has waiting tasks
+    fn picks(scheduler: &mut Scheduler, candidates: &[Candidate], times: usize) -> Vec<i64> {
+        (0..times)
+            .map(|_| candidates[scheduler.pick(candidates).unwrap()].chat_id)
+            .collect()
+    }
+
+    #[test]
+    fn test_scheduler() {
+        let mut scheduler = Scheduler::default();
+        assert_eq!(scheduler.pick(&[]), None);
+
+        // round-robin with the same weights
+        let candidates = [candidate(1, 10, 1), candidate(2, 20, 1)];
+        assert_eq!(picks(&mut scheduler, &candidates, 4), [1, 2, 1, 2]);
+
+        // a chat of weight 2 gets twice the turns
+        let mut scheduler = Scheduler::default();
+        let candidates = [candidate(1, 10, 2), candidate(2, 20, 1)];
+        assert_eq!(picks(&mut scheduler, &candidates, 6), [1, 2, 1, 1, 2, 1]);
+
+        // a sender in two chats shares its turns between them
+        let mut scheduler = Scheduler::default();
+        let candidates = [
+            candidate(1, 10, 1),
+            candidate(2, 10, 1),
+            candidate(3, 30, 1),
+        ];
+        assert_eq!(
+            picks(&mut scheduler, &candidates, 8),
+            [1, 3, 2, 3, 1, 3, 2, 3]
+        );
+
+        // a chat joining later doesn't catch up on the turns it missed
+        let mut scheduler = Scheduler::default();
+        picks(&mut scheduler, &[candidate(1, 10, 1)], 5);
+        let candidates = [candidate(1, 10, 1), candidate(2, 20, 1)];
+        assert_eq!(picks(&mut scheduler, &candidates, 4), [2, 1, 2, 1]);
+
+        // weights beyond the stride still move the times forward
+        let mut scheduler = Scheduler::default();
+        let candidates = [Candidate {
+            chat_id: 1,
+            sender_id: Some(10),
+            chat_weight: u64::MAX,
+            sender_weight: u64::MAX,
+        }];
+        picks(&mut scheduler, &candidates, 2);
+        assert_eq!(scheduler.chat_times[&1], 2);
+        assert_eq!(scheduler.sender_times[&10], 2);
+    }
+}
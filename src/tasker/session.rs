@@ -5,14 +5,27 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use super::tasks::{self, InsertTask, TaskStatus};
+use super::{
+    history::TaskHistory,
+    scheduler::{Candidate, Scheduler},
+    tasks::{self, InsertTask, TaskStatus},
+    webhook::{TaskEvent, Webhook},
+};
+use crate::{
+    env::{get_chat_config, get_quota_config},
+    error::ResultExt,
+    utils::{create_table_if_not_exists, random_token},
+};
 use anyhow::{Context, Ok, Result};
 use sea_orm::{
-    sea_query::Expr, ActiveValue, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection,
-    EntityName, EntityTrait, PaginatorTrait, QueryFilter, Schema, Set,
+    sea_query::Expr, ActiveValue, ColumnTrait, Condition, DatabaseConnection, EntityTrait,
+    Iterable, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Select, Set,
 };
 use std::{collections::HashMap, path::Path, sync::Arc};
-use tokio::{fs, sync::Mutex};
+use tokio::{
+    fs,
+    sync::{Mutex, OwnedMutexGuard},
+};
 use tokio_util::sync::CancellationToken;
 
 // (chat id, message indicator id) -> aborter
@@ -23,24 +36,52 @@ pub struct TaskSession {
     connection: DatabaseConnection,
     pub task_aborters: TaskAborters,
     pub batch_aborters: BatchAborters,
+    pub history: TaskHistory,
+    pub webhook: Webhook,
+    // raw uploads of the http api are kept on disk instead of the task session
+    spool_dir: String,
+    quota_locks: Mutex<HashMap<i64, Arc<Mutex<()>>>>,
+    // tasks are fetched by one loop, the lock is never held across an await
+    scheduler: std::sync::Mutex<Scheduler>,
 }
 
 impl TaskSession {
-    pub async fn new(session_path: &str) -> Result<Self> {
+    pub async fn new(
+        session_path: &str,
+        spool_dir: &str,
+        history_session_path: &str,
+        webhook: Webhook,
+    ) -> Result<Self> {
         if Path::new(session_path).exists() {
             fs::remove_file(session_path)
                 .await
                 .context("failed to remove old task session")?;
         }
 
+        // files of the last run have no task anymore
+        if Path::new(spool_dir).exists() {
+            fs::remove_dir_all(spool_dir)
+                .await
+                .context("failed to remove old upload spool")?;
+        }
+        fs::create_dir_all(spool_dir)
+            .await
+            .context("failed to create upload spool")?;
+
         let connection = Self::connect_db(session_path).await?;
         let task_aborters = Arc::new(Mutex::new(HashMap::new()));
         let batch_aborters = Arc::new(Mutex::new(HashMap::new()));
+        let history = TaskHistory::new(history_session_path).await?;
 
         Ok(Self {
             connection,
             task_aborters,
             batch_aborters,
+            history,
+            webhook,
+            spool_dir: spool_dir.to_string(),
+            quota_locks: Mutex::new(HashMap::new()),
+            scheduler: std::sync::Mutex::new(Scheduler::default()),
         })
     }
 
@@ -49,42 +90,56 @@ impl TaskSession {
             .await
             .context("failed to connect to task session")?;
 
-        Self::create_table_if_not_exists(&connection).await?;
+        create_table_if_not_exists(&connection, tasks::Entity).await?;
 
         Ok(connection)
     }
 
-    async fn create_table_if_not_exists(connection: &DatabaseConnection) -> Result<()> {
-        if !Self::is_table_exists(connection).await {
-            let backend = connection.get_database_backend();
+    // tasks are first in first out within a chat, chats and senders take turns by their weights
+    pub async fn fetch_task(&self) -> Result<Option<tasks::Model>> {
+        let busy_senders = self.get_busy_senders().await?;
 
-            let table_create_statement =
-                Schema::new(backend).create_table_from_entity(tasks::Entity);
+        let head_ids = tasks::Entity::find()
+            .select_only()
+            .column_as(Expr::col(tasks::Column::Id).min(), "id")
+            .filter(tasks::Column::Status.eq(TaskStatus::Waiting))
+            .filter(
+                Condition::any()
+                    .add(tasks::Column::SenderId.is_null())
+                    .add(tasks::Column::SenderId.is_not_in(busy_senders)),
+            )
+            .group_by(tasks::Column::ChatId)
+            .into_tuple::<i64>()
+            .all(&self.connection)
+            .await
+            .context("failed to get the first waiting task of chats")?;
 
-            connection
-                .execute(backend.build(&table_create_statement))
-                .await
-                .context(format!(
-                    "failed to create table {}",
-                    tasks::Entity.table_name()
-                ))?;
+        if head_ids.is_empty() {
+            return Ok(None);
         }
 
-        Ok(())
-    }
-
-    async fn is_table_exists(connection: &DatabaseConnection) -> bool {
-        let result = tasks::Entity::find().all(connection).await;
-
-        result.is_ok()
-    }
-
-    pub async fn fetch_task(&self) -> Result<Option<tasks::Model>> {
-        let task = tasks::Entity::find()
-            .filter(tasks::Column::Status.eq(TaskStatus::Waiting))
-            .one(&self.connection)
+        let mut heads = tasks::Entity::find()
+            .filter(tasks::Column::Id.is_in(head_ids))
+            .order_by_asc(tasks::Column::Id)
+            .all(&self.connection)
             .await
-            .context("failed to get a task")?;
+            .context("failed to get waiting tasks")?;
+
+        let candidates = heads
+            .iter()
+            .map(|task| Candidate {
+                chat_id: task.chat_id,
+                sender_id: task.sender_id,
+                chat_weight: get_chat_config(task.chat_id).weight.unwrap_or(1),
+                sender_weight: task
+                    .sender_id
+                    .and_then(|sender_id| get_quota_config(sender_id).weight)
+                    .unwrap_or(1),
+            })
+            .collect::<Vec<_>>();
+
+        let index = self.scheduler.lock().unwrap().pick(&candidates);
+        let task = index.map(|index| heads.swap_remove(index));
 
         if let Some(task) = &task {
             self.set_task_status(task.id, tasks::TaskStatus::Fetched)
@@ -101,6 +156,10 @@ impl TaskSession {
             filename,
             root_path,
             url,
+            headers,
+            profile,
+            content,
+            spool_path,
             upload_url,
             current_length,
             total_length,
@@ -112,6 +171,7 @@ impl TaskSession {
             message_indicator_id,
             message_origin_id,
             auto_delete,
+            sender_id,
         }: InsertTask,
     ) -> Result<i64> {
         let insert_item = tasks::ActiveModel {
@@ -120,6 +180,10 @@ impl TaskSession {
             filename: Set(filename.to_string()),
             root_path: Set(root_path.to_string()),
             url: Set(url),
+            headers: Set(headers),
+            profile: Set(profile),
+            content: Set(content),
+            spool_path: Set(spool_path),
             upload_url: Set(upload_url.to_string()),
             current_length: Set(current_length as i64),
             total_length: Set(total_length as i64),
@@ -132,6 +196,7 @@ impl TaskSession {
             message_origin_id: Set(message_origin_id),
             status: Set(TaskStatus::Waiting),
             auto_delete: Set(auto_delete),
+            sender_id: Set(sender_id),
         };
 
         let id = tasks::Entity::insert(insert_item)
@@ -140,9 +205,62 @@ impl TaskSession {
             .context("failed to insert url task")?
             .last_insert_id;
 
+        if self.webhook.is_enabled() {
+            let task = self.get_task(id).await?;
+
+            if let Some(task) = task {
+                self.webhook.fire(TaskEvent::Queued, &task, None, None);
+            }
+        }
+
         Ok(id)
     }
 
+    pub async fn get_task(&self, id: i64) -> Result<Option<tasks::Model>> {
+        tasks::Entity::find_by_id(id)
+            .one(&self.connection)
+            .await
+            .context("failed to get task")
+    }
+
+    // content may be large and is only needed by the uploader, so it's left out of lists
+    fn find_tasks_without_content() -> Select<tasks::Entity> {
+        tasks::Entity::find()
+            .select_only()
+            .columns(
+                tasks::Column::iter().filter(|column| !matches!(column, tasks::Column::Content)),
+            )
+            .column_as(Expr::cust("NULL"), "content")
+    }
+
+    pub async fn list_tasks(&self) -> Result<Vec<tasks::Model>> {
+        Self::find_tasks_without_content()
+            .all(&self.connection)
+            .await
+            .context("failed to list tasks")
+    }
+
+    // unfinished tasks of a sender or everyone, not including those from the http api
+    pub async fn list_sender_tasks(&self, sender_id: Option<i64>) -> Result<Vec<tasks::Model>> {
+        let mut query = Self::find_tasks_without_content()
+            .filter(tasks::Column::SenderId.is_not_null())
+            .filter(tasks::Column::Status.is_in([
+                TaskStatus::Waiting,
+                TaskStatus::Fetched,
+                TaskStatus::Started,
+                TaskStatus::Paused,
+            ]));
+
+        if let Some(sender_id) = sender_id {
+            query = query.filter(tasks::Column::SenderId.eq(sender_id));
+        }
+
+        query
+            .all(&self.connection)
+            .await
+            .context("failed to list sender tasks")
+    }
+
     pub async fn set_task_status(&self, id: i64, status: TaskStatus) -> Result<()> {
         tasks::Entity::update_many()
             .filter(tasks::Column::Id.eq(id))
@@ -168,11 +286,30 @@ impl TaskSession {
         Ok(())
     }
 
+    // the task restarts from a new upload session
+    pub async fn requeue_task(&self, id: i64, upload_url: &str, current_length: u64) -> Result<()> {
+        tasks::Entity::update_many()
+            .filter(tasks::Column::Id.eq(id))
+            .col_expr(tasks::Column::UploadUrl, Expr::value(upload_url))
+            .col_expr(
+                tasks::Column::CurrentLength,
+                Expr::value(current_length as i64),
+            )
+            .col_expr(tasks::Column::Status, Expr::value(TaskStatus::Waiting))
+            .exec(&self.connection)
+            .await
+            .context("failed to requeue task")?;
+
+        Ok(())
+    }
+
     pub async fn get_chats_current_tasks(&self) -> Result<HashMap<ChatHex, Vec<tasks::Model>>> {
         let mut chats = HashMap::new();
 
-        let tasks = tasks::Entity::find()
+        // chatless tasks from the http api have no progress message
+        let tasks = Self::find_tasks_without_content()
             .filter(tasks::Column::Status.eq(TaskStatus::Started))
+            .filter(tasks::Column::ChatBotHex.ne(""))
             .all(&self.connection)
             .await
             .context("failed to get chat current tasks")?;
@@ -241,6 +378,25 @@ impl TaskSession {
         Ok(())
     }
 
+    // returns false if the task is not running
+    pub async fn abort_task(&self, id: i64) -> bool {
+        let mut task_aborters = self.task_aborters.lock().await;
+
+        let Some(key) = task_aborters
+            .iter()
+            .find(|(_, aborter)| aborter.id == id)
+            .map(|(key, _)| *key)
+        else {
+            return false;
+        };
+
+        if let Some(aborter) = task_aborters.remove(&key) {
+            aborter.abort();
+        }
+
+        true
+    }
+
     pub async fn clear(&self) -> Result<()> {
         let mut aborters_guard = self.task_aborters.lock().await;
         let aborters = aborters_guard.values();
@@ -256,39 +412,74 @@ impl TaskSession {
             .await
             .context("failed to clear tasks")?;
 
+        fs::remove_dir_all(&self.spool_dir)
+            .await
+            .context("failed to clear upload spool")?;
+        fs::create_dir_all(&self.spool_dir)
+            .await
+            .context("failed to create upload spool")?;
+
         Ok(())
     }
 
+    // a running task is recorded by its handler once aborted, so only call this for the others
+    pub async fn record_cancelled_task(&self, task: &tasks::Model) {
+        self.remove_spool_file(task).await;
+
+        self.history
+            .insert(task, TaskStatus::Cancelled, None)
+            .await
+            .trace();
+
+        self.webhook.fire(TaskEvent::Cancelled, task, None, None);
+    }
+
+    // for tasks not running yet
     pub async fn delete_task_from_message_indicator_id_if_exists(
         &self,
         chat_id: i64,
         message_id: i32,
     ) -> Result<()> {
-        tasks::Entity::delete_many()
-            .filter(tasks::Column::ChatId.eq(chat_id))
-            .filter(tasks::Column::MessageIndicatorId.eq(message_id))
-            .exec(&self.connection)
-            .await
-            .context("failed to delete task from message indicator id")?;
+        let condition = Condition::all()
+            .add(tasks::Column::ChatId.eq(chat_id))
+            .add(tasks::Column::MessageIndicatorId.eq(message_id));
 
-        Ok(())
+        self.delete_cancelled_tasks(condition)
+            .await
+            .context("failed to delete task from message indicator id")
     }
 
+    // for tasks not running yet
     pub async fn delete_task_from_message_id_if_exists(
         &self,
         chat_id: i64,
         message_id: i32,
     ) -> Result<()> {
+        let condition = Condition::all().add(tasks::Column::ChatId.eq(chat_id)).add(
+            Condition::any()
+                .add(tasks::Column::MessageIndicatorId.eq(message_id))
+                .add(tasks::Column::MessageId.eq(message_id)),
+        );
+
+        self.delete_cancelled_tasks(condition)
+            .await
+            .context("failed to delete task from message id or message indicator id")
+    }
+
+    async fn delete_cancelled_tasks(&self, condition: Condition) -> Result<()> {
+        let tasks = tasks::Entity::find()
+            .filter(condition.clone())
+            .all(&self.connection)
+            .await?;
+
         tasks::Entity::delete_many()
-            .filter(tasks::Column::ChatId.eq(chat_id))
-            .filter(
-                Condition::any()
-                    .add(tasks::Column::MessageIndicatorId.eq(message_id))
-                    .add(tasks::Column::MessageId.eq(message_id)),
-            )
+            .filter(condition)
             .exec(&self.connection)
-            .await
-            .context("failed to delete task from message id or message indicator id")?;
+            .await?;
+
+        for task in &tasks {
+            self.record_cancelled_task(task).await;
+        }
 
         Ok(())
     }
@@ -317,6 +508,68 @@ impl TaskSession {
         Ok(false)
     }
 
+    // unfinished tasks of a chat, or only those of a message or an indicator in it
+    pub async fn list_chat_tasks(
+        &self,
+        chat_id: i64,
+        message_id: Option<i32>,
+    ) -> Result<Vec<tasks::Model>> {
+        let mut query = Self::find_tasks_without_content()
+            .filter(tasks::Column::ChatId.eq(chat_id))
+            .filter(tasks::Column::Status.is_in([
+                TaskStatus::Waiting,
+                TaskStatus::Fetched,
+                TaskStatus::Started,
+                TaskStatus::Paused,
+            ]));
+
+        if let Some(message_id) = message_id {
+            query = query.filter(
+                Condition::any()
+                    .add(tasks::Column::MessageId.eq(message_id))
+                    .add(tasks::Column::MessageIndicatorId.eq(message_id)),
+            );
+        }
+
+        query
+            .all(&self.connection)
+            .await
+            .context("failed to list chat tasks")
+    }
+
+    // one at a time for each sender
+    pub async fn lock_sender(&self, sender_id: i64) -> OwnedMutexGuard<()> {
+        let lock = self
+            .quota_locks
+            .lock()
+            .await
+            .entry(sender_id)
+            .or_default()
+            .clone();
+
+        lock.lock_owned().await
+    }
+
+    pub fn new_spool_path(&self) -> String {
+        Path::new(&self.spool_dir)
+            .join(random_token())
+            .to_string_lossy()
+            .to_string()
+    }
+
+    // failed tasks keep the file to be retried
+    pub async fn remove_spool_file(&self, task: &tasks::Model) {
+        let Some(spool_path) = &task.spool_path else {
+            return;
+        };
+
+        fs::remove_file(spool_path)
+            .await
+            .context("failed to remove spooled upload")
+            .context(spool_path.clone())
+            .trace();
+    }
+
     pub async fn get_message_indicator_ids(
         &self,
         chat_id: i64,
@@ -23,7 +23,19 @@ pub struct Model {
     pub root_path: String,
     // for /url
     pub url: Option<String>,
-    // onedrive upload url
+    // request headers given with /url in json, only sent to the host of url
+    // for /url
+    pub headers: Option<String>,
+    // name of the credential profile, resolved when the task runs
+    // for /url and /fetch
+    pub profile: Option<String>,
+    // file content generated in memory
+    // for /note and exported contacts, locations and polls
+    pub content: Option<Vec<u8>>,
+    // file received and kept on disk until the task is completed or cancelled
+    // for raw uploads of the http api
+    pub spool_path: Option<String>,
+    // onedrive upload url, or the upload session id of other storage backends
     pub upload_url: String,
     pub current_length: i64,
     pub total_length: i64,
@@ -43,6 +55,24 @@ pub struct Model {
     pub message_origin_id: Option<i32>,
     pub status: TaskStatus,
     pub auto_delete: bool,
+    // telegram user who added the task, none for the http api
+    pub sender_id: Option<i64>,
+}
+
+impl Model {
+    // tasks from the http api have no chat unless a notification chat is set
+    pub fn has_chat(&self) -> bool {
+        !self.chat_bot_hex.is_empty()
+    }
+
+    // chatless tasks have no indicator, the negative task id keeps the key unique
+    pub fn aborter_key(&self) -> (i64, i32) {
+        if self.has_chat() {
+            (self.chat_id, self.message_indicator_id)
+        } else {
+            (self.chat_id, -(self.id as i32))
+        }
+    }
 }
 
 #[derive(Clone, Debug, EnumIter, DeriveRelation)]
@@ -55,6 +85,13 @@ pub enum CmdType {
     File,
     Link,
     Url,
+    Note,
+    Export,
+    Ftp,
+    Sftp,
+    WebDav,
+    // raw body uploaded through the http api
+    Upload,
 }
 
 impl ValueType for CmdType {
@@ -64,6 +101,12 @@ impl ValueType for CmdType {
                 "file" => Ok(Self::File),
                 "link" => Ok(Self::Link),
                 "url" => Ok(Self::Url),
+                "note" => Ok(Self::Note),
+                "export" => Ok(Self::Export),
+                "ftp" => Ok(Self::Ftp),
+                "sftp" => Ok(Self::Sftp),
+                "webdav" => Ok(Self::WebDav),
+                "upload" => Ok(Self::Upload),
                 _ => Err(ValueTypeErr),
             },
             _ => Err(ValueTypeErr),
@@ -86,9 +129,15 @@ impl ValueType for CmdType {
 impl From<CmdType> for Value {
     fn from(value: CmdType) -> Self {
         match value {
-            CmdType::File | CmdType::Link | CmdType::Url => {
-                Self::String(Some(Box::new(value.to_string())))
-            }
+            CmdType::File
+            | CmdType::Link
+            | CmdType::Url
+            | CmdType::Note
+            | CmdType::Export
+            | CmdType::Ftp
+            | CmdType::Sftp
+            | CmdType::WebDav
+            | CmdType::Upload => Self::String(Some(Box::new(value.to_string()))),
         }
     }
 }
@@ -101,8 +150,14 @@ impl TryGetable for CmdType {
             "file" => Ok(Self::File),
             "link" => Ok(Self::Link),
             "url" => Ok(Self::Url),
+            "note" => Ok(Self::Note),
+            "export" => Ok(Self::Export),
+            "ftp" => Ok(Self::Ftp),
+            "sftp" => Ok(Self::Sftp),
+            "webdav" => Ok(Self::WebDav),
+            "upload" => Ok(Self::Upload),
             _ => Err(TryGetError::DbErr(DbErr::Type(format!(
-                "cmd type value should be one of file, photo, link and url: {}",
+                "cmd type value should be one of file, photo, link, url, note, export, ftp, sftp, webdav and upload: {}",
                 value
             )))),
         }
@@ -115,6 +170,12 @@ impl Display for CmdType {
             Self::File => write!(f, "file"),
             Self::Link => write!(f, "link"),
             Self::Url => write!(f, "url"),
+            Self::Note => write!(f, "note"),
+            Self::Export => write!(f, "export"),
+            Self::Ftp => write!(f, "ftp"),
+            Self::Sftp => write!(f, "sftp"),
+            Self::WebDav => write!(f, "webdav"),
+            Self::Upload => write!(f, "upload"),
         }
     }
 }
@@ -129,8 +190,12 @@ pub enum TaskStatus {
     Fetched,
     // task started by handler
     Started,
+    // held back from the tasker until resumed on the dashboard
+    Paused,
     Completed,
     Failed,
+    // only recorded in history
+    Cancelled,
 }
 
 impl ValueType for TaskStatus {
@@ -140,8 +205,10 @@ impl ValueType for TaskStatus {
                 "waiting" => Ok(Self::Waiting),
                 "fetched" => Ok(Self::Fetched),
                 "started" => Ok(Self::Started),
+                "paused" => Ok(Self::Paused),
                 "completed" => Ok(Self::Completed),
                 "failed" => Ok(Self::Failed),
+                "cancelled" => Ok(Self::Cancelled),
                 _ => Err(ValueTypeErr),
             },
             _ => Err(ValueTypeErr),
@@ -167,8 +234,10 @@ impl From<TaskStatus> for Value {
             TaskStatus::Waiting
             | TaskStatus::Fetched
             | TaskStatus::Started
+            | TaskStatus::Paused
             | TaskStatus::Completed
-            | TaskStatus::Failed => Self::String(Some(Box::new(value.to_string()))),
+            | TaskStatus::Failed
+            | TaskStatus::Cancelled => Self::String(Some(Box::new(value.to_string()))),
         }
     }
 }
@@ -181,10 +250,12 @@ impl TryGetable for TaskStatus {
             "waiting" => Ok(Self::Waiting),
             "fetched" => Ok(Self::Fetched),
             "started" => Ok(Self::Started),
+            "paused" => Ok(Self::Paused),
             "completed" => Ok(Self::Completed),
             "failed" => Ok(Self::Failed),
+            "cancelled" => Ok(Self::Cancelled),
             _ => Err(TryGetError::DbErr(DbErr::Type(format!(
-                "task status value should be one of waiting, started, completed and failed: {}",
+                "task status value should be one of waiting, fetched, started, paused, completed, failed and cancelled: {}",
                 value
             )))),
         }
@@ -197,8 +268,10 @@ impl Display for TaskStatus {
             Self::Waiting => write!(f, "waiting"),
             Self::Fetched => write!(f, "fetched"),
             Self::Started => write!(f, "started"),
+            Self::Paused => write!(f, "paused"),
             Self::Completed => write!(f, "completed"),
             Self::Failed => write!(f, "failed"),
+            Self::Cancelled => write!(f, "cancelled"),
         }
     }
 }
@@ -208,6 +281,10 @@ pub struct InsertTask {
     pub filename: String,
     pub root_path: String,
     pub url: Option<String>,
+    pub headers: Option<String>,
+    pub profile: Option<String>,
+    pub content: Option<Vec<u8>>,
+    pub spool_path: Option<String>,
     pub upload_url: String,
     pub current_length: u64,
     pub total_length: u64,
@@ -219,4 +296,5 @@ pub struct InsertTask {
     pub message_indicator_id: i32,
     pub message_origin_id: Option<i32>,
     pub auto_delete: bool,
+    pub sender_id: Option<i64>,
 }
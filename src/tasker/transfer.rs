@@ -7,13 +7,29 @@
 
 use super::{tasks, Progress};
 use crate::{
-    client::utils::chat_from_hex, error::TaskAbortError, state::AppState, utils::get_http_client,
+    client::{remote::RemoteClient, utils::chat_from_hex},
+    env::ENV,
+    error::TaskAbortError,
+    metrics::METRICS,
+    state::AppState,
+    storage::{StorageBackend, StoredItem},
+    utils::{send_with_host_headers, HttpRoute, RequestHeaders},
 };
-use anyhow::{anyhow, Context, Error, Result};
+use anyhow::{anyhow, Context, Result};
 use grammers_client::client::files::MAX_CHUNK_SIZE;
-use onedrive_api::{resource::DriveItem, UploadSession};
-use std::{collections::VecDeque, ops::Range, sync::Arc, time::Duration};
+use reqwest::Method;
+use std::{
+    collections::VecDeque,
+    io::SeekFrom,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
 use tokio_util::sync::CancellationToken;
+use url::Url;
 
 const MAX_RETRIES: i32 = 5;
 
@@ -21,20 +37,27 @@ pub async fn multi_parts_uploader_from_url(
     tasks::Model {
         id,
         url,
+        headers,
+        profile,
         upload_url,
         current_length,
         total_length,
         ..
     }: &tasks::Model,
     progress: Arc<Progress>,
-) -> Result<String> {
+    storage: &dyn StorageBackend,
+) -> Result<StoredItem> {
     const PART_SIZE: usize = 3276800;
 
-    let http_client = get_http_client()?;
-
-    let url = url.clone().ok_or_else(|| anyhow!("url is none"))?;
+    let url = url.as_deref().ok_or_else(|| anyhow!("url is none"))?;
 
-    let upload_session = UploadSession::from_upload_url(upload_url);
+    // request headers only go to the file host, not to onedrive
+    let headers = match headers {
+        Some(headers) => serde_json::from_str::<RequestHeaders>(headers)
+            .context("failed to deserialize request headers")?,
+        None => RequestHeaders::new(),
+    };
+    let origin = Url::parse(url).context("failed to parse url")?;
 
     let mut current_length = current_length.to_owned() as u64;
     let total_length = total_length.to_owned() as u64;
@@ -43,9 +66,12 @@ pub async fn multi_parts_uploader_from_url(
         .set_current_length(id.to_owned(), current_length)
         .await?;
 
-    let mut response = http_client
-        .get(url)
-        .send()
+    // credentials of the profile are looked up now, so that they are never stored in tasks
+    let url_env = &ENV.get().unwrap().url;
+    let mut response =
+        send_with_host_headers(HttpRoute::Origin, &Method::GET, url, |request_url| {
+            url_env.request_headers(profile.as_deref(), &headers, &origin, request_url)
+        })
         .await
         .context("failed to send request for /url")?;
 
@@ -62,14 +88,8 @@ pub async fn multi_parts_uploader_from_url(
 
         tracing::debug!("downloaded chunk from url");
 
-        let upload_response = upload_file(
-            &upload_session,
-            &buffer,
-            current_length,
-            total_length,
-            &http_client,
-        )
-        .await?;
+        let upload_response =
+            upload_part(storage, upload_url, &buffer, current_length, total_length).await?;
 
         tracing::debug!("uploaded chunk from url");
 
@@ -83,18 +103,103 @@ pub async fn multi_parts_uploader_from_url(
         }
     };
 
-    let filename = upload_response
-        .ok_or_else(|| anyhow!("failed to get drive item after upload"))?
-        .name
-        .ok_or_else(|| anyhow!("drive item name not found"))?;
+    let stored_item = finish_upload(storage, upload_url, upload_response, total_length).await?;
 
     tracing::info!(
         "uploaded file from url: {} size: {}",
-        filename,
+        stored_item.name,
         total_length
     );
 
-    Ok(filename)
+    Ok(stored_item)
+}
+
+pub async fn multi_parts_uploader_from_remote(
+    tasks::Model {
+        id,
+        url,
+        profile,
+        upload_url,
+        current_length,
+        total_length,
+        ..
+    }: &tasks::Model,
+    progress: Arc<Progress>,
+    storage: &dyn StorageBackend,
+) -> Result<StoredItem> {
+    const PART_SIZE: usize = 3276800;
+
+    let url = url.as_ref().ok_or_else(|| anyhow!("url is none"))?;
+    let url = Url::parse(url).context("failed to parse remote url")?;
+
+    let profile = match profile {
+        Some(profile) => Some(
+            ENV.get()
+                .unwrap()
+                .fetch
+                .get_profile(profile)
+                .ok_or_else(|| anyhow!("fetch profile not found: {}", profile))?,
+        ),
+        None => None,
+    };
+
+    let mut current_length = current_length.to_owned() as u64;
+    let total_length = total_length.to_owned() as u64;
+
+    progress
+        .set_current_length(id.to_owned(), current_length)
+        .await?;
+
+    let mut remote_client = RemoteClient::connect(&url, profile).await?;
+    let path = remote_client.path_from_url(&url);
+
+    // resume from the range expected by the upload session
+    let mut reader = remote_client.open(&path, current_length).await?;
+
+    let mut upload_response = None;
+
+    while current_length < total_length {
+        let mut buffer = Vec::with_capacity(PART_SIZE);
+
+        (&mut reader)
+            .take(PART_SIZE as u64)
+            .read_to_end(&mut buffer)
+            .await
+            .context("failed to read remote file")?;
+
+        if buffer.is_empty() {
+            return Err(anyhow!(
+                "remote file ended early at {} of {} bytes",
+                current_length,
+                total_length
+            ));
+        }
+
+        tracing::debug!("downloaded chunk from remote");
+
+        upload_response =
+            upload_part(storage, upload_url, &buffer, current_length, total_length).await?;
+
+        tracing::debug!("uploaded chunk from remote");
+
+        current_length += buffer.len() as u64;
+        progress
+            .set_current_length(id.to_owned(), current_length)
+            .await?;
+    }
+
+    drop(reader);
+    remote_client.close().await;
+
+    let stored_item = finish_upload(storage, upload_url, upload_response, total_length).await?;
+
+    tracing::info!(
+        "uploaded file from remote: {} size: {}",
+        stored_item.name,
+        total_length
+    );
+
+    Ok(stored_item)
 }
 
 pub async fn multi_parts_uploader_from_tg_file(
@@ -113,12 +218,10 @@ pub async fn multi_parts_uploader_from_tg_file(
     progress: Arc<Progress>,
     cancellation_token: CancellationToken,
     state: AppState,
-) -> Result<String> {
+) -> Result<StoredItem> {
     const WORKER_COUNT: i32 = 4;
 
-    let http_client = get_http_client()?;
-
-    let upload_session = UploadSession::from_upload_url(upload_url);
+    let storage = state.storage.as_ref();
 
     let mut current_length = current_length.to_owned() as u64;
     let total_length = total_length.to_owned() as u64;
@@ -130,10 +233,15 @@ pub async fn multi_parts_uploader_from_tg_file(
     let mut upload_response = None;
 
     let telegram_user = &state.telegram_user;
-    let chat = chat_from_hex(chat_user_hex)?;
 
-    let message = match cmd_type {
-        tasks::CmdType::File => telegram_user.get_message(chat, *message_id).await?,
+    // media is downloaded by the client which read the message
+    let (reader, message) = match cmd_type {
+        tasks::CmdType::File => {
+            let chat = chat_from_hex(chat_user_hex)?;
+            let reader = state.reader(chat);
+
+            (reader, reader.get_message(chat, *message_id).await?)
+        }
         tasks::CmdType::Link => {
             let chat = chat_from_hex(
                 chat_origin_hex
@@ -145,9 +253,18 @@ pub async fn multi_parts_uploader_from_tg_file(
                 .as_ref()
                 .ok_or_else(|| anyhow!("message_id_origin is None"))?;
 
-            telegram_user.get_message(chat, *message_origin_id).await?
+            (
+                telegram_user,
+                telegram_user.get_message(chat, *message_origin_id).await?,
+            )
         }
-        tasks::CmdType::Url => return Err(anyhow!("invalid cmd type")),
+        tasks::CmdType::Url
+        | tasks::CmdType::Ftp
+        | tasks::CmdType::Sftp
+        | tasks::CmdType::WebDav
+        | tasks::CmdType::Note
+        | tasks::CmdType::Export
+        | tasks::CmdType::Upload => return Err(anyhow!("invalid cmd type")),
     };
 
     let media = Arc::new(
@@ -166,14 +283,14 @@ pub async fn multi_parts_uploader_from_tg_file(
     let mut current_chunk_num = 0;
 
     while current_chunk_num < total_chunks_num {
-        let telegram_user_clone = telegram_user.clone();
+        let reader_clone = reader.clone();
         let media_clone = media.clone();
 
         let cancellation_token_clone = cancellation_token.clone();
 
         // create a worker
         work_handles.push_back(tokio::spawn(async move {
-            let mut download = telegram_user_clone
+            let mut download = reader_clone
                 .iter_download(media_clone.as_ref())
                 .skip_chunks(current_chunk_num);
 
@@ -188,6 +305,7 @@ pub async fn multi_parts_uploader_from_tg_file(
                                 tokio::time::sleep(Duration::from_secs(2)).await;
 
                                 retries += 1;
+                                METRICS.inc_retries("download");
 
                                 continue;
                             }
@@ -222,14 +340,8 @@ pub async fn multi_parts_uploader_from_tg_file(
 
             tracing::debug!("downloaded chunk from telegram");
 
-            upload_response = upload_file(
-                &upload_session,
-                &chunk,
-                current_length,
-                total_length,
-                &http_client,
-            )
-            .await?;
+            upload_response =
+                upload_part(storage, upload_url, &chunk, current_length, total_length).await?;
 
             tracing::debug!("uploaded chunk from telegram");
 
@@ -240,81 +352,243 @@ pub async fn multi_parts_uploader_from_tg_file(
         }
     }
 
-    let filename = upload_response
-        .ok_or_else(|| anyhow!("failed to get drive item after upload"))?
-        .name
-        .ok_or_else(|| anyhow!("drive item name not found"))?;
+    let stored_item = finish_upload(storage, upload_url, upload_response, total_length).await?;
 
     tracing::info!(
         "uploaded file from telegram: {} size: {}",
-        filename,
+        stored_item.name,
         total_length
     );
 
-    Ok(filename)
+    Ok(stored_item)
 }
 
-async fn upload_file(
-    upload_session: &UploadSession,
+pub async fn multi_parts_uploader_from_content(
+    tasks::Model {
+        id,
+        content,
+        upload_url,
+        current_length,
+        total_length,
+        ..
+    }: &tasks::Model,
+    progress: Arc<Progress>,
+    storage: &dyn StorageBackend,
+) -> Result<StoredItem> {
+    const PART_SIZE: usize = 3276800;
+
+    let content = content.as_ref().ok_or_else(|| anyhow!("content is none"))?;
+
+    let mut current_length = current_length.to_owned() as u64;
+    let total_length = total_length.to_owned() as u64;
+
+    progress
+        .set_current_length(id.to_owned(), current_length)
+        .await?;
+
+    let mut upload_response = None;
+
+    for buffer in content[current_length as usize..].chunks(PART_SIZE) {
+        upload_response =
+            upload_part(storage, upload_url, buffer, current_length, total_length).await?;
+
+        current_length += buffer.len() as u64;
+        progress
+            .set_current_length(id.to_owned(), current_length)
+            .await?;
+    }
+
+    let stored_item = finish_upload(storage, upload_url, upload_response, total_length).await?;
+
+    tracing::info!(
+        "uploaded file from content: {} size: {}",
+        stored_item.name,
+        total_length
+    );
+
+    Ok(stored_item)
+}
+
+// the file is read part by part, so large uploads are never held in memory
+pub async fn multi_parts_uploader_from_spool(
+    tasks::Model {
+        id,
+        spool_path,
+        upload_url,
+        current_length,
+        total_length,
+        ..
+    }: &tasks::Model,
+    progress: Arc<Progress>,
+    storage: &dyn StorageBackend,
+) -> Result<StoredItem> {
+    const PART_SIZE: usize = 3276800;
+
+    let spool_path = spool_path
+        .as_ref()
+        .ok_or_else(|| anyhow!("spool path is none"))?;
+
+    let mut current_length = current_length.to_owned() as u64;
+    let total_length = total_length.to_owned() as u64;
+
+    let mut file = fs::File::open(spool_path)
+        .await
+        .context("failed to open spooled upload")
+        .context(spool_path.clone())?;
+
+    file.seek(SeekFrom::Start(current_length))
+        .await
+        .context("failed to seek spooled upload")?;
+
+    progress
+        .set_current_length(id.to_owned(), current_length)
+        .await?;
+
+    let mut upload_response = None;
+    let mut buffer = vec![0; PART_SIZE];
+
+    while current_length < total_length {
+        let length = ((total_length - current_length) as usize).min(PART_SIZE);
+
+        file.read_exact(&mut buffer[..length])
+            .await
+            .context("failed to read spooled upload")?;
+
+        upload_response = upload_part(
+            storage,
+            upload_url,
+            &buffer[..length],
+            current_length,
+            total_length,
+        )
+        .await?;
+
+        current_length += length as u64;
+        progress
+            .set_current_length(id.to_owned(), current_length)
+            .await?;
+    }
+
+    let stored_item = finish_upload(storage, upload_url, upload_response, total_length).await?;
+
+    tracing::info!(
+        "uploaded file from spool: {} size: {}",
+        stored_item.name,
+        total_length
+    );
+
+    Ok(stored_item)
+}
+
+async fn upload_part(
+    storage: &dyn StorageBackend,
+    upload_url: &str,
     buffer: &[u8],
     current_length: u64,
     total_length: u64,
-    http_client: &reqwest::Client,
-) -> Result<Option<DriveItem>> {
-    let mut upload_response = None;
-
+) -> Result<Option<StoredItem>> {
     let mut tries = 0;
+    let start = Instant::now();
 
     loop {
         tries += 1;
 
-        let result = upload_session
-            .upload_part(
-                buffer.to_owned(),
-                Range {
-                    start: current_length,
-                    end: current_length + buffer.len() as u64,
-                },
+        let result = storage
+            .upload_range(
+                upload_url,
+                buffer,
+                current_length..current_length + buffer.len() as u64,
                 total_length,
-                http_client,
             )
             .await;
 
         match result {
-            Ok(response) => {
-                upload_response = response;
+            Ok(upload_response) => {
+                METRICS.observe_part_upload(start.elapsed());
 
-                break;
+                break Ok(upload_response);
             }
             Err(e) => {
-                if let Some(status_code) = e.status_code() {
-                    // normal
-                    // 408: Request Timeout
-                    // 500: Internal Server Error
-                    // 502: Bad Gateway
-                    // 503: Service Unavailable
-                    // 504: Gateway Timeout
-                    // 416: Requested Range Not Satisfiable, probably because the fragment has already been received
-                    //
-                    // probably has some problem
-                    // 409: Conflict, probably caused by rename, too many files with the same name uploaded at once
-                    // 404: Not Found, probably because the item has already been uploaded
-
-                    if status_code.as_u16() == 416 {
-                        break;
-                    }
-                }
-
                 if tries < MAX_RETRIES {
                     tokio::time::sleep(Duration::from_secs(2)).await;
 
+                    METRICS.inc_retries("upload");
+
                     continue;
                 }
 
-                return Err(Error::from(e)).context("failed to upload part");
+                break Err(e).context("failed to upload part");
             }
         }
     }
+}
+
+// onedrive returns the item with the last part, other backends have to be finalized
+async fn finish_upload(
+    storage: &dyn StorageBackend,
+    upload_url: &str,
+    upload_response: Option<StoredItem>,
+    total_length: u64,
+) -> Result<StoredItem> {
+    let stored_item = match upload_response {
+        Some(stored_item) => stored_item,
+        None => storage.finalize(upload_url, total_length).await?,
+    };
+
+    if stored_item.name.is_empty() {
+        return Err(anyhow!("stored item name not found"));
+    }
+
+    Ok(stored_item)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{finish_upload, upload_part};
+    use crate::{
+        storage::{LocalStorage, StorageBackend},
+        utils::TempPath,
+    };
+
+    #[tokio::test]
+    async fn test_upload_to_local_storage() {
+        let root = TempPath::new("transfer");
+        let storage = LocalStorage::new(root.as_str());
+
+        let content = vec![7u8; 1000];
+        let total_length = content.len() as u64;
 
-    Ok(upload_response)
+        let target = storage
+            .create_upload_session(0, "/files", "a.bin", total_length)
+            .await
+            .unwrap();
+
+        let mut current_length = target.current_length;
+        let mut upload_response = None;
+
+        for buffer in content.chunks(300) {
+            upload_response = upload_part(
+                &storage,
+                &target.upload_url,
+                buffer,
+                current_length,
+                total_length,
+            )
+            .await
+            .unwrap();
+
+            current_length += buffer.len() as u64;
+        }
+
+        let stored_item =
+            finish_upload(&storage, &target.upload_url, upload_response, total_length)
+                .await
+                .unwrap();
+
+        assert_eq!(stored_item.name, "a.bin");
+        assert_eq!(
+            std::fs::read(root.join("files").join("a.bin")).unwrap(),
+            content
+        );
+    }
 }
@@ -0,0 +1,476 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::tasks;
+use crate::{
+    env::WebhookEnv,
+    storage::StoredItem,
+    trace::redact,
+    utils::{create_table_if_not_exists, get_current_timestamp},
+};
+use anyhow::{Context, Result};
+use hmac::{Hmac, Mac};
+use path_slash::PathBufExt;
+use reqwest::header::CONTENT_TYPE;
+use sea_orm::{
+    entity::prelude::DeriveEntityModel, ActiveModelBehavior, ActiveValue, ColumnTrait,
+    DatabaseConnection, DerivePrimaryKey, DeriveRelation, EntityTrait, EnumIter, PrimaryKeyTrait,
+    QueryFilter, Set,
+};
+use serde::Serialize;
+use sha2::Sha256;
+use std::{fmt::Write, path::Path, time::Duration};
+
+const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+// signed together with the body, so that a captured request can't be replayed later
+const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+const EVENT_HEADER: &str = "X-Webhook-Event";
+const MAX_ATTEMPTS: u32 = 5;
+// doubled after each failed attempt
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// older deliveries are dropped
+const MAX_LOG_NUM: i64 = 1000;
+
+// one row per delivery, attempts included
+#[derive(Clone, Debug, DeriveEntityModel)]
+#[sea_orm(table_name = "webhook_deliveries")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub event: String,
+    pub url: String,
+    pub task_id: i64,
+    pub attempts: i32,
+    // status code of the last attempt, none if no response
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub delivered: bool,
+    pub timestamp: i64,
+}
+
+#[derive(Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskEvent {
+    Queued,
+    Started,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl TaskEvent {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Started => "started",
+            Self::Completed => "completed",
+            Self::Failed => "failed",
+            Self::Cancelled => "cancelled",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    event: TaskEvent,
+    timestamp: i64,
+    task_id: i64,
+    // the stored name once completed, it may differ from the requested one
+    filename: &'a str,
+    path: String,
+    // only known for onedrive once completed
+    item_id: Option<&'a str>,
+    size: i64,
+    // the command type of the task
+    source: String,
+    // redacted, urls may carry credentials
+    url: Option<String>,
+    // none for tasks from the http api
+    chat_id: Option<i64>,
+    sender_id: Option<i64>,
+    error: Option<&'a str>,
+}
+
+#[derive(Clone)]
+pub struct Webhook {
+    connection: DatabaseConnection,
+    urls: Vec<String>,
+    secret: Option<String>,
+    http_client: reqwest::Client,
+}
+
+impl Webhook {
+    pub async fn new(
+        WebhookEnv {
+            urls,
+            secret,
+            log_path,
+        }: &WebhookEnv,
+        http_client: reqwest::Client,
+    ) -> Result<Self> {
+        let connection = sea_orm::Database::connect(format!("sqlite://{}?mode=rwc", log_path))
+            .await
+            .context("failed to connect to webhook log")?;
+
+        create_table_if_not_exists(&connection, Entity).await?;
+
+        Ok(Self {
+            connection,
+            urls: urls.clone(),
+            secret: secret.clone(),
+            http_client,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.urls.is_empty()
+    }
+
+    // deliveries run in the background, so the task is never held up by a slow receiver
+    pub fn fire(
+        &self,
+        event: TaskEvent,
+        task: &tasks::Model,
+        stored_item: Option<&StoredItem>,
+        error: Option<&str>,
+    ) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let body = match serde_json::to_string(&new_payload(event, task, stored_item, error)) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::warn!("failed to serialize webhook payload: {}", e);
+
+                return;
+            }
+        };
+
+        for url in &self.urls {
+            let webhook = self.clone();
+            let url = url.clone();
+            let body = body.clone();
+            let task_id = task.id;
+
+            tokio::spawn(async move {
+                webhook.deliver(&url, event, task_id, &body).await;
+            });
+        }
+    }
+
+    async fn deliver(&self, url: &str, event: TaskEvent, task_id: i64, body: &str) {
+        let mut attempts = 0;
+        let mut status_code = None;
+        let mut error = None;
+        let mut delivered = false;
+
+        while attempts < MAX_ATTEMPTS {
+            if attempts > 0 {
+                tokio::time::sleep(RETRY_DELAY * 2u32.pow(attempts - 1)).await;
+            }
+
+            attempts += 1;
+
+            match self.post(url, event, body).await {
+                Ok(status) => {
+                    status_code = Some(i32::from(status.as_u16()));
+
+                    if status.is_success() {
+                        error = None;
+                        delivered = true;
+
+                        break;
+                    }
+
+                    error = Some(format!("unexpected status {}", status));
+                }
+                Err(e) => {
+                    status_code = None;
+                    error = Some(format!("{:#}", e));
+                }
+            }
+        }
+
+        if !delivered {
+            tracing::warn!(
+                "failed to deliver webhook {} of task {} to {}: {}",
+                event.as_str(),
+                task_id,
+                url,
+                error.as_deref().unwrap_or_default()
+            );
+        }
+
+        let insert_item = ActiveModel {
+            id: ActiveValue::default(),
+            event: Set(event.as_str().to_string()),
+            url: Set(url.to_string()),
+            task_id: Set(task_id),
+            attempts: Set(attempts as i32),
+            status_code: Set(status_code),
+            error: Set(error),
+            delivered: Set(delivered),
+            timestamp: Set(get_current_timestamp()),
+        };
+
+        if let Err(e) = self.insert_log(insert_item).await {
+            tracing::warn!("{:#}", e);
+        }
+    }
+
+    async fn post(&self, url: &str, event: TaskEvent, body: &str) -> Result<reqwest::StatusCode> {
+        let mut request = self
+            .http_client
+            .post(url)
+            .timeout(REQUEST_TIMEOUT)
+            .header(CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, event.as_str())
+            .body(body.to_string());
+
+        if let Some(secret) = &self.secret {
+            // renewed for each attempt
+            let timestamp = get_current_timestamp();
+
+            request = request
+                .header(TIMESTAMP_HEADER, timestamp)
+                .header(SIGNATURE_HEADER, sign(secret, timestamp, body));
+        }
+
+        let response = request
+            .send()
+            .await
+            .context("failed to send webhook request")?;
+
+        Ok(response.status())
+    }
+
+    async fn insert_log(&self, insert_item: ActiveModel) -> Result<()> {
+        let id = Entity::insert(insert_item)
+            .exec(&self.connection)
+            .await
+            .context("failed to insert webhook delivery")?
+            .last_insert_id;
+
+        Entity::delete_many()
+            .filter(Column::Id.lte(id - MAX_LOG_NUM))
+            .exec(&self.connection)
+            .await
+            .context("failed to drop old webhook deliveries")?;
+
+        Ok(())
+    }
+}
+
+fn new_payload<'a>(
+    event: TaskEvent,
+    task: &'a tasks::Model,
+    stored_item: Option<&'a StoredItem>,
+    error: Option<&'a str>,
+) -> Payload<'a> {
+    let filename = stored_item.map_or(task.filename.as_str(), |item| item.name.as_str());
+
+    Payload {
+        event,
+        timestamp: get_current_timestamp(),
+        task_id: task.id,
+        filename,
+        path: Path::new(&task.root_path)
+            .join(filename)
+            .to_slash_lossy()
+            .to_string(),
+        item_id: stored_item.and_then(|item| item.id.as_deref()),
+        size: task.total_length,
+        source: task.cmd_type.to_string(),
+        url: task.url.as_deref().map(redact),
+        chat_id: task.has_chat().then_some(task.chat_id),
+        sender_id: task.sender_id,
+        error,
+    }
+}
+
+// the receiver recomputes it over the timestamp header, a dot and the raw body
+// with the shared secret, and rejects stale timestamps
+fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let message = format!("{}.{}", timestamp, body);
+
+    format!(
+        "sha256={}",
+        to_hex(&hmac_sha256(secret.as_bytes(), message.as_bytes()))
+    )
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    // hmac takes keys of any length
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(data);
+
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        write!(hex, "{:02x}", byte).unwrap();
+        hex
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tasker::tasks::{CmdType, TaskStatus},
+        utils::TempPath,
+    };
+    use axum::{http::HeaderMap, http::StatusCode, routing::post, Extension, Router};
+    use sea_orm::QueryOrder;
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+
+    // (timestamp, signature, body) of each request
+    type Received = Arc<Mutex<Vec<(Option<i64>, Option<String>, String)>>>;
+
+    #[test]
+    fn test_hmac_sha256() {
+        // rfc 4231 test case 2
+        assert_eq!(
+            to_hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    // fails the first request to check the retry
+    async fn receiver_handler(
+        Extension(received): Extension<Received>,
+        headers: HeaderMap,
+        body: String,
+    ) -> StatusCode {
+        let timestamp = headers
+            .get(TIMESTAMP_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok());
+        let signature = headers
+            .get(SIGNATURE_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(ToString::to_string);
+
+        let mut received = received.lock().unwrap();
+        received.push((timestamp, signature, body));
+
+        if received.len() == 1 {
+            StatusCode::INTERNAL_SERVER_ERROR
+        } else {
+            StatusCode::OK
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deliver() {
+        let received = Received::default();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let router = Router::new()
+            .route("/hook", post(receiver_handler))
+            .layer(Extension(received.clone()));
+        tokio::spawn(async move {
+            axum::serve(listener, router).await.unwrap();
+        });
+
+        let log_path = TempPath::new("webhook");
+        let env = WebhookEnv {
+            urls: vec![url.clone()],
+            secret: Some("secret".to_string()),
+            log_path: log_path.as_str().to_string(),
+        };
+        let webhook = Webhook::new(&env, reqwest::Client::new()).await.unwrap();
+
+        let task = tasks::Model {
+            id: 1,
+            cmd_type: CmdType::Url,
+            filename: "a.txt".to_string(),
+            root_path: "/Telegram".to_string(),
+            url: Some("https://example.com/a.txt?token=abcdef".to_string()),
+            headers: None,
+            profile: None,
+            content: None,
+            spool_path: None,
+            upload_url: String::new(),
+            current_length: 0,
+            total_length: 10,
+            chat_id: 1,
+            chat_bot_hex: "00".to_string(),
+            chat_user_hex: "00".to_string(),
+            chat_origin_hex: None,
+            message_id: 1,
+            message_indicator_id: 2,
+            message_origin_id: None,
+            status: TaskStatus::Started,
+            auto_delete: false,
+            sender_id: Some(42),
+        };
+        let stored_item = StoredItem {
+            name: "a (1).txt".to_string(),
+            size: 10,
+            is_dir: false,
+            id: Some("item".to_string()),
+        };
+
+        let body = serde_json::to_string(&new_payload(
+            TaskEvent::Completed,
+            &task,
+            Some(&stored_item),
+            None,
+        ))
+        .unwrap();
+        webhook
+            .deliver(&url, TaskEvent::Completed, task.id, &body)
+            .await;
+
+        let received = received.lock().unwrap().clone();
+        assert_eq!(received.len(), 2);
+        for (timestamp, signature, received_body) in &received {
+            let timestamp = timestamp.unwrap();
+            assert!((get_current_timestamp() - timestamp).abs() < 60);
+            assert_eq!(
+                signature.as_deref(),
+                Some(sign("secret", timestamp, &body).as_str())
+            );
+            assert_eq!(received_body, &body);
+        }
+
+        // the same body signed at another time doesn't match
+        assert_ne!(
+            sign("secret", received[0].0.unwrap() - 600, &body),
+            received[0].1.clone().unwrap()
+        );
+
+        let payload: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(payload["event"], "completed");
+        assert_eq!(payload["path"], "/Telegram/a (1).txt");
+        assert_eq!(payload["item_id"], "item");
+        assert_eq!(payload["sender_id"], 42);
+        assert_eq!(payload["url"], "https://example.com/a.txt?token=***");
+
+        let deliveries = Entity::find()
+            .order_by_desc(Column::Id)
+            .all(&webhook.connection)
+            .await
+            .unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].attempts, 2);
+        assert_eq!(deliveries[0].status_code, Some(200));
+        assert!(deliveries[0].delivered);
+        assert!(deliveries[0].error.is_none());
+    }
+}
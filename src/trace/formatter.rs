@@ -5,7 +5,10 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use super::visitor::{MessageVisitor, MetaVisitor};
+use super::{
+    redactor::redact,
+    visitor::{MessageVisitor, MetaVisitor},
+};
 use ansi_term::Color;
 use tracing::{Level, Subscriber};
 use tracing_subscriber::{
@@ -32,7 +35,8 @@ where
     ) -> std::fmt::Result {
         let mut message_visitor = MessageVisitor::default();
         event.record(&mut message_visitor);
-        let message = message_visitor.message;
+        // both stdout and log files, which are sent by /logs, are redacted
+        let message = redact(&message_visitor.message);
 
         write_message(writer, event, message)
     }
@@ -7,18 +7,33 @@
 
 mod cleaner;
 mod formatter;
+mod redactor;
 mod visitor;
 
 use crate::env::{ENV, LOGS_PATH};
+use anyhow::{Context, Result};
 use formatter::EventFormatter;
+pub use redactor::{redact, reload_secrets};
+use std::sync::OnceLock;
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
 use tracing_log::LogTracer;
-use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use tracing_subscriber::{
+    fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry,
+};
+
+// to change the trace level when the config file is reloaded
+static FILTER_HANDLE: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
 
 pub fn trace_registor() {
     LogTracer::init().unwrap();
 
-    let trace_level = &ENV.get().unwrap().trace_level;
+    let env = ENV.get().unwrap();
+    let trace_level = &env.trace_level;
+
+    redactor::set_secrets(env);
+
+    let (filter_layer, filter_handle) = reload::Layer::new(new_filter(trace_level));
+    FILTER_HANDLE.set(filter_handle).unwrap();
 
     let stdout_layer = fmt::layer()
         .with_writer(std::io::stdout)
@@ -29,14 +44,26 @@ pub fn trace_registor() {
         .event_format(EventFormatter);
 
     tracing_subscriber::registry()
+        .with(filter_layer)
         .with(stdout_layer)
         .with(file_layer)
-        .with(EnvFilter::new(trace_level).add_directive("sqlx=error".parse().unwrap()))
         .init();
 
     cleaner::run();
 }
 
+pub fn set_trace_level(trace_level: &str) -> Result<()> {
+    FILTER_HANDLE
+        .get()
+        .context("tracing is not registered")?
+        .reload(new_filter(trace_level))
+        .context("failed to reload trace level")
+}
+
+fn new_filter(trace_level: &str) -> EnvFilter {
+    EnvFilter::new(trace_level).add_directive("sqlx=error".parse().unwrap())
+}
+
 fn log_writer_builder() -> RollingFileAppender {
     RollingFileAppender::builder()
         .rotation(Rotation::DAILY)
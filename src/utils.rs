@@ -5,16 +5,202 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use anyhow::{Context, Result};
-use chrono::Utc;
-use reqwest::header;
+use crate::env::{redact_proxy_url, ENV};
+use anyhow::{anyhow, Context, Error, Result};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use chrono::{Datelike, Utc};
+use rand::Rng;
+use reqwest::{header, redirect, Method, NoProxy, Proxy, Response};
+use sea_orm::{ConnectionTrait, EntityTrait, Schema};
+use std::error::Error as StdError;
+use url::Url;
+
+const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.0 Safari/605.1.15";
+
+// same as the default policy of reqwest
+const MAX_REDIRECTS: usize = 10;
+
+// extra (name, value) headers of a /url request, e.g. cookies and credentials
+pub type RequestHeaders = Vec<(String, String)>;
 
 pub fn get_current_timestamp() -> i64 {
     Utc::now().timestamp()
 }
 
-pub fn get_http_client() -> Result<reqwest::Client> {
-    const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.0 Safari/605.1.15";
+// days and months of quotas are in utc
+pub fn day_start() -> i64 {
+    let timestamp = get_current_timestamp();
+
+    timestamp - timestamp.rem_euclid(24 * 60 * 60)
+}
+
+pub fn month_start() -> i64 {
+    Utc::now()
+        .date_naive()
+        .with_day(1)
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map_or(0, |date| date.and_utc().timestamp())
+}
+
+// 256 bits, safe to put in urls
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill(&mut bytes);
+
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+// which proxy setting applies to the outgoing http traffic
+#[derive(Clone, Copy)]
+pub enum HttpRoute {
+    // file hosts of /url
+    Origin,
+    // graph api and onedrive upload urls
+    Graph,
+}
+
+impl HttpRoute {
+    fn proxy(self) -> Option<&'static str> {
+        let proxy = &ENV.get().unwrap().proxy;
+
+        match self {
+            Self::Origin => proxy.url.as_deref(),
+            Self::Graph => proxy.graph.as_deref(),
+        }
+    }
+
+    const fn env_name(self) -> &'static str {
+        match self {
+            Self::Origin => "url_proxy",
+            Self::Graph => "od_proxy",
+        }
+    }
+
+    // tell proxy and tls failures apart from unreachable origins
+    pub fn map_err(self, e: reqwest::Error) -> Error {
+        if is_certificate_error(&e) {
+            let host = e
+                .url()
+                .and_then(|url| url.host_str())
+                .unwrap_or_default()
+                .to_string();
+
+            return Error::new(e).context(format!(
+                "tls verification failed for {}, set tls_ca_bundle if it uses a private ca, or add it to tls_insecure_hosts if it is a trusted self-signed origin",
+                host
+            ));
+        }
+
+        match self.proxy() {
+            Some(proxy) if e.is_connect() => Error::new(e).context(format!(
+                "failed to connect through proxy {}, check {} or add the host to proxy_bypass",
+                redact_proxy_url(proxy),
+                self.env_name()
+            )),
+            _ => Error::new(e),
+        }
+    }
+}
+
+// native-tls doesn't expose the kind of its errors, only the messages of the sources tell
+fn is_certificate_error(e: &reqwest::Error) -> bool {
+    let mut source = e.source();
+
+    while let Some(err) = source {
+        if err.to_string().to_lowercase().contains("certificate") {
+            return true;
+        }
+
+        source = err.source();
+    }
+
+    false
+}
+
+pub fn get_http_client(route: HttpRoute) -> Result<reqwest::Client> {
+    build_http_client(route, false, &[], redirect::Policy::default())
+}
+
+pub fn get_http_client_for_url(
+    route: HttpRoute,
+    url: &str,
+    request_headers: &[(String, String)],
+) -> Result<reqwest::Client> {
+    let is_insecure = is_insecure_url(&Url::parse(url).context("failed to parse url")?);
+
+    build_http_client(
+        route,
+        is_insecure,
+        request_headers,
+        redirect::Policy::default(),
+    )
+}
+
+// redirects are followed here instead of by reqwest, so that headers are picked for each host
+// and credentials don't follow a redirect to another host
+pub async fn send_with_host_headers<F>(
+    route: HttpRoute,
+    method: &Method,
+    url: &str,
+    headers_for: F,
+) -> Result<Response>
+where
+    F: Fn(&Url) -> Result<RequestHeaders>,
+{
+    let mut url = Url::parse(url).context("failed to parse url")?;
+
+    for _ in 0..=MAX_REDIRECTS {
+        let http_client = build_http_client(
+            route,
+            is_insecure_url(&url),
+            &headers_for(&url)?,
+            redirect::Policy::none(),
+        )?;
+
+        let response = http_client
+            .request(method.clone(), url.clone())
+            .send()
+            .await
+            .map_err(|e| route.map_err(e))?;
+
+        let location = match response.headers().get(header::LOCATION) {
+            Some(location) if response.status().is_redirection() => location,
+            _ => return Ok(response),
+        };
+
+        url = location
+            .to_str()
+            .ok()
+            .and_then(|location| url.join(location).ok())
+            .ok_or_else(|| anyhow!("invalid redirect location"))?;
+    }
+
+    Err(anyhow!("too many redirects"))
+}
+
+// certificates are only left unverified when the host of the url is in tls_insecure_hosts
+fn is_insecure_url(url: &Url) -> bool {
+    let is_insecure = url
+        .host_str()
+        .is_some_and(|host| ENV.get().unwrap().http.is_insecure_host(host));
+
+    if is_insecure {
+        tracing::warn!("tls verification disabled for {}", url);
+    }
+
+    is_insecure
+}
+
+fn build_http_client(
+    route: HttpRoute,
+    is_insecure: bool,
+    request_headers: &[(String, String)],
+    redirect_policy: redirect::Policy,
+) -> Result<reqwest::Client> {
+    let http_env = &ENV.get().unwrap().http;
 
     let headers = {
         let mut headers = header::HeaderMap::new();
@@ -23,17 +209,159 @@ pub fn get_http_client() -> Result<reqwest::Client> {
             USER_AGENT.parse().context("failed to parse user agent")?,
         );
 
+        // later headers with the same name override the previous ones, including user agent
+        for (name, value) in request_headers {
+            let name = header::HeaderName::from_bytes(name.as_bytes())
+                .context("invalid header name")
+                .context(name.to_string())?;
+            let mut value = header::HeaderValue::from_str(value)
+                .context("invalid header value")
+                .context(name.to_string())?;
+
+            // keep credentials out of debug output
+            if name == header::AUTHORIZATION || name == header::COOKIE {
+                value.set_sensitive(true);
+            }
+
+            headers.insert(name, value);
+        }
+
         headers
     };
 
-    reqwest::Client::builder()
+    let mut builder = reqwest::Client::builder()
         .default_headers(headers)
-        .danger_accept_invalid_certs(true)
-        .danger_accept_invalid_hostnames(true)
-        .build()
-        .context("failed to build http client")
+        .danger_accept_invalid_certs(is_insecure)
+        .danger_accept_invalid_hostnames(is_insecure)
+        .redirect(redirect_policy)
+        .connect_timeout(http_env.connect_timeout)
+        .read_timeout(http_env.read_timeout)
+        .tcp_keepalive(http_env.tcp_keepalive)
+        .pool_max_idle_per_host(http_env.pool_max_idle_per_host);
+
+    for ca_cert in &http_env.ca_certs {
+        builder = builder.add_root_certificate(ca_cert.clone());
+    }
+
+    if let Some(proxy) = route.proxy() {
+        let no_proxy = ENV.get().unwrap().proxy.no_proxy();
+
+        let proxy = Proxy::all(proxy)
+            .context("invalid proxy url")
+            .context(route.env_name())?
+            .no_proxy(NoProxy::from_string(&no_proxy));
+
+        builder = builder.proxy(proxy);
+    }
+
+    builder.build().context("failed to build http client")
+}
+
+// tables are kept if they exist, their owners migrate columns added later
+pub async fn create_table_if_not_exists<C, E>(connection: &C, entity: E) -> Result<()>
+where
+    C: ConnectionTrait,
+    E: EntityTrait,
+{
+    let backend = connection.get_database_backend();
+
+    let mut table_create_statement = Schema::new(backend).create_table_from_entity(entity);
+
+    connection
+        .execute(backend.build(table_create_statement.if_not_exists()))
+        .await
+        .context(format!("failed to create table {}", entity.table_name()))?;
+
+    Ok(())
+}
+
+pub fn basic_auth_header(credentials: &str) -> String {
+    format!("Basic {}", STANDARD.encode(credentials))
 }
 
 pub fn get_ext(filename: &str) -> String {
     filename.split('.').last().unwrap().to_lowercase()
 }
+
+// a unique path in the temp dir, removed with everything under it once dropped,
+// so that failed tests don't leave files behind either
+#[cfg(test)]
+pub struct TempPath(std::path::PathBuf);
+
+#[cfg(test)]
+impl TempPath {
+    pub fn new(name: &str) -> Self {
+        Self(std::env::temp_dir().join(format!(
+            "telegram-onedrive-{}-{}",
+            name,
+            rand::random::<u32>()
+        )))
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TempPath {
+    type Target = std::path::Path;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        // nothing to remove if the test didn't create it
+        let _ = if self.0.is_dir() {
+            std::fs::remove_dir_all(&self.0)
+        } else {
+            std::fs::remove_file(&self.0)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum_server::tls_rustls::RustlsConfig;
+    use rcgen::{generate_simple_self_signed, CertifiedKey};
+    use std::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_is_certificate_error() {
+        let CertifiedKey { cert, key_pair } =
+            generate_simple_self_signed(vec!["127.0.0.1".to_string()]).unwrap();
+        let config = RustlsConfig::from_pem(
+            cert.pem().into_bytes(),
+            key_pair.serialize_pem().into_bytes(),
+        )
+        .await
+        .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum_server::from_tcp_rustls(listener, config)
+                .serve(Router::new().into_make_service())
+                .await
+                .unwrap();
+        });
+
+        // the self signed cert isn't trusted
+        let e = reqwest::get(format!("https://{}", addr)).await.unwrap_err();
+        assert!(is_certificate_error(&e));
+
+        // nothing listens on the port any more
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let e = reqwest::get(format!("http://{}", addr)).await.unwrap_err();
+        assert!(!is_certificate_error(&e));
+    }
+}
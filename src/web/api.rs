@@ -0,0 +1,550 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    dashboard::get_account_views,
+    models::{
+        AccountView, ApiTaskView, CreateTaskParams, CreatedTask, DirItemsParams, DirParams,
+        DirView, ItemView, UploadParams,
+    },
+    notify::{get_notify_chat, NotifyChat},
+};
+use crate::{
+    client::utils::chat_from_hex,
+    env::ENV,
+    error::HttpError,
+    handlers::{
+        link::{probe_link, LinkFile},
+        url::{probe_url, UrlFile},
+    },
+    state::AppState,
+    tasker::{cancel_task, CmdType, InsertTask},
+};
+use anyhow::{anyhow, Context};
+use axum::{
+    body::{self, Body},
+    debug_handler,
+    extract::{Path, Query, Request},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response, Result},
+    Extension, Json,
+};
+use futures::TryStreamExt;
+use grammers_client::InputMessage;
+use sha2::{Digest, Sha256};
+use tokio::{fs, io::AsyncWriteExt};
+
+// raw bodies are spooled on disk until uploaded, larger files should be passed by url
+const MAX_UPLOAD_LENGTH: u64 = 100 * 1024 * 1024;
+// the default limit of axum for json
+const MAX_JSON_LENGTH: usize = 2 * 1024 * 1024;
+
+fn get_bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+// digests have the same length, and all tokens are compared so that the time doesn't tell
+fn check_token(token: &str, tokens: &[String]) -> bool {
+    let digest = Sha256::digest(token.as_bytes());
+
+    tokens.iter().fold(false, |is_valid, expected_token| {
+        let is_equal = Sha256::digest(expected_token.as_bytes())
+            .iter()
+            .zip(digest.iter())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0;
+
+        is_valid | is_equal
+    })
+}
+
+pub async fn require_token(request: Request, next: Next) -> Response {
+    let tokens = &ENV.get().unwrap().api.tokens;
+
+    let is_authorized =
+        get_bearer_token(request.headers()).is_some_and(|token| check_token(token, tokens));
+
+    if !is_authorized {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    next.run(request).await
+}
+
+pub const OPENAPI_PATH: &str = "/api/openapi.json";
+
+#[debug_handler]
+pub async fn openapi_handler() -> Response {
+    (
+        [(header::CONTENT_TYPE, "application/json")],
+        include_str!("openapi.json"),
+    )
+        .into_response()
+}
+
+pub const TASKS_PATH: &str = "/api/tasks";
+
+#[debug_handler]
+pub async fn create_task_handler(
+    Extension(state): Extension<AppState>,
+    Query(UploadParams {
+        filename,
+        root_path,
+    }): Query<UploadParams>,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    let is_json = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+
+    let result = if is_json {
+        let body = match body::to_bytes(body, MAX_JSON_LENGTH).await {
+            Ok(body) => body,
+            Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        };
+
+        match serde_json::from_slice(&body) {
+            Ok(params) => create_remote_task(&state, params).await,
+            Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        }
+    } else {
+        create_upload_task(&state, filename, root_path, body).await
+    };
+
+    // mostly an invalid url, link or path
+    match result {
+        Ok(id) => (StatusCode::CREATED, Json(CreatedTask { id })).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, format!("{:#}", e)).into_response(),
+    }
+}
+
+struct NewTask {
+    cmd_type: CmdType,
+    filename: String,
+    root_path: Option<String>,
+    url: Option<String>,
+    headers: Option<String>,
+    profile: Option<String>,
+    spool_path: Option<String>,
+    total_length: u64,
+    chat_origin_hex: Option<String>,
+    message_origin_id: Option<i32>,
+    // shown in the notification
+    source: String,
+}
+
+async fn create_remote_task(state: &AppState, params: CreateTaskParams) -> anyhow::Result<i64> {
+    let new_task = match params {
+        CreateTaskParams::Url {
+            url,
+            options,
+            root_path,
+        } => {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(anyhow!("not an http url"));
+            }
+
+            let UrlFile {
+                url,
+                filename,
+                total_length,
+                headers,
+                profile,
+            } = probe_url(&url, options.iter().map(String::as_str), state).await?;
+
+            NewTask {
+                cmd_type: CmdType::Url,
+                filename,
+                root_path,
+                url: Some(url.clone()),
+                headers,
+                profile,
+                spool_path: None,
+                total_length,
+                chat_origin_hex: None,
+                message_origin_id: None,
+                source: url,
+            }
+        }
+        CreateTaskParams::Link { link, root_path } => {
+            let LinkFile {
+                filename,
+                total_length,
+                chat_origin_hex,
+                message_origin_id,
+            } = probe_link(&link, state).await?;
+
+            NewTask {
+                cmd_type: CmdType::Link,
+                filename,
+                root_path,
+                url: None,
+                headers: None,
+                profile: None,
+                spool_path: None,
+                total_length,
+                chat_origin_hex: Some(chat_origin_hex),
+                message_origin_id: Some(message_origin_id),
+                source: link,
+            }
+        }
+    };
+
+    insert_api_task(state, new_task).await
+}
+
+async fn create_upload_task(
+    state: &AppState,
+    filename: Option<String>,
+    root_path: Option<String>,
+    body: Body,
+) -> anyhow::Result<i64> {
+    let filename = filename.ok_or_else(|| anyhow!("filename is required for uploading"))?;
+
+    if filename.is_empty() || filename.contains('/') {
+        return Err(anyhow!("invalid filename"));
+    }
+
+    let spool_path = state.task_session.new_spool_path();
+
+    let result = async {
+        let total_length = spool_body(&spool_path, body).await?;
+
+        if total_length == 0 {
+            return Err(anyhow!("empty body"));
+        }
+
+        insert_api_task(
+            state,
+            NewTask {
+                cmd_type: CmdType::Upload,
+                filename: filename.clone(),
+                root_path,
+                url: None,
+                headers: None,
+                profile: None,
+                spool_path: Some(spool_path.clone()),
+                total_length,
+                chat_origin_hex: None,
+                message_origin_id: None,
+                source: filename,
+            },
+        )
+        .await
+    }
+    .await;
+
+    // the file may not be created yet
+    if result.is_err() {
+        let _ = fs::remove_file(&spool_path).await;
+    }
+
+    result
+}
+
+// returns the length of the body
+async fn spool_body(spool_path: &str, body: Body) -> anyhow::Result<u64> {
+    let mut file = fs::File::create(spool_path)
+        .await
+        .context("failed to create spool file")?;
+
+    let mut stream = body.into_data_stream();
+    let mut total_length = 0;
+
+    while let Some(chunk) = stream.try_next().await.context("failed to receive body")? {
+        total_length += chunk.len() as u64;
+
+        if total_length > MAX_UPLOAD_LENGTH {
+            return Err(anyhow!(
+                "body is larger than {} bytes, pass the file by url instead",
+                MAX_UPLOAD_LENGTH
+            ));
+        }
+
+        file.write_all(&chunk)
+            .await
+            .context("failed to write spool file")?;
+    }
+
+    file.flush().await.context("failed to write spool file")?;
+
+    Ok(total_length)
+}
+
+// the notification message plays the role of both the sent and the indicator message,
+// so deleting it cancels the task like in other chats
+async fn insert_api_task(
+    state: &AppState,
+    NewTask {
+        cmd_type,
+        filename,
+        root_path,
+        url,
+        headers,
+        profile,
+        spool_path,
+        total_length,
+        chat_origin_hex,
+        message_origin_id,
+        source,
+    }: NewTask,
+) -> anyhow::Result<i64> {
+    let root_path = match root_path {
+        Some(root_path) if root_path.starts_with('/') => root_path,
+        Some(_) => return Err(anyhow!("directory path should start with /")),
+        None => state.onedrive.get_root_path(true).await?,
+    };
+
+    let (chat_id, chat_bot_hex, chat_user_hex, message_id) = match get_notify_chat().await? {
+        Some(NotifyChat {
+            chat_id,
+            chat_bot_hex,
+            chat_user_hex,
+        }) => {
+            let chat = chat_from_hex(&chat_bot_hex)?;
+
+            let response = format!("{}\n\nAdded through api.", source);
+            let message_id = state
+                .telegram_bot
+                .send_message(chat, InputMessage::html(&response))
+                .await
+                .context(response)?
+                .id();
+
+            (chat_id, chat_bot_hex, chat_user_hex, message_id)
+        }
+        None => (0, String::new(), String::new(), 0),
+    };
+
+    let upload_target = state
+        .storage
+        .create_upload_session(chat_id, &root_path, &filename, total_length)
+        .await?;
+
+    // in case if cancellation happens before inserting the task
+    let _aborters = state.task_session.task_aborters.lock().await;
+
+    let id = state
+        .task_session
+        .insert_task(InsertTask {
+            cmd_type,
+            filename: filename.clone(),
+            root_path,
+            url,
+            headers,
+            profile,
+            content: None,
+            spool_path,
+            upload_url: upload_target.upload_url,
+            current_length: upload_target.current_length,
+            total_length,
+            chat_id,
+            chat_bot_hex,
+            chat_user_hex,
+            chat_origin_hex,
+            message_id,
+            message_indicator_id: message_id,
+            message_origin_id,
+            auto_delete: false,
+            sender_id: None,
+        })
+        .await?;
+
+    tracing::info!("inserted api task: {} size: {}", filename, total_length);
+
+    Ok(id)
+}
+
+pub const TASK_PATH: &str = "/api/tasks/:id";
+
+#[debug_handler]
+pub async fn task_handler(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Response> {
+    let task = state
+        .task_session
+        .get_task(id)
+        .await
+        .map_err(|e| HttpError::new(format!("{:#}", e)))?;
+
+    if let Some(task) = task {
+        return Ok(Json(ApiTaskView {
+            id: task.id,
+            cmd_type: task.cmd_type.to_string(),
+            filename: task.filename,
+            root_path: task.root_path,
+            status: task.status.to_string(),
+            error: None,
+            current_length: task.current_length,
+            total_length: task.total_length,
+        })
+        .into_response());
+    }
+
+    let record = state
+        .task_session
+        .history
+        .get_by_task_id(id)
+        .await
+        .map_err(|e| HttpError::new(format!("{:#}", e)))?;
+
+    let Some(record) = record else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    Ok(Json(ApiTaskView {
+        id: record.task_id,
+        cmd_type: record.cmd_type.to_string(),
+        filename: record.filename,
+        root_path: record.root_path,
+        status: record.status.to_string(),
+        error: record.error,
+        current_length: record.total_length,
+        total_length: record.total_length,
+    })
+    .into_response())
+}
+
+#[debug_handler]
+pub async fn delete_task_handler(
+    Extension(state): Extension<AppState>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode> {
+    let is_cancelled = cancel_task(&state, id)
+        .await
+        .map_err(|e| HttpError::new(format!("{:#}", e)))?;
+
+    if !is_cancelled {
+        return Ok(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::OK)
+}
+
+pub const API_ACCOUNTS_PATH: &str = "/api/accounts";
+
+#[debug_handler]
+pub async fn accounts_handler(
+    Extension(state): Extension<AppState>,
+) -> Result<Json<Vec<AccountView>>> {
+    let account_views = get_account_views(&state)
+        .await
+        .map_err(|e| HttpError::new(format!("{:#}", e)))?;
+
+    Ok(Json(account_views))
+}
+
+pub const DIR_PATH: &str = "/api/dir";
+
+#[debug_handler]
+pub async fn dir_handler(Extension(state): Extension<AppState>) -> Result<Json<DirView>> {
+    let onedrive = &state.onedrive;
+
+    let root_path = onedrive
+        .get_root_path(false)
+        .await
+        .map_err(|e| HttpError::new(format!("{:#}", e)))?;
+    let is_temp = onedrive.does_temp_root_path_exist().await;
+
+    Ok(Json(DirView { root_path, is_temp }))
+}
+
+#[debug_handler]
+pub async fn set_dir_handler(
+    Extension(state): Extension<AppState>,
+    Json(DirParams { root_path }): Json<DirParams>,
+) -> Response {
+    // mostly an invalid path
+    if let Err(e) = state.onedrive.set_root_path(&root_path).await {
+        return (StatusCode::BAD_REQUEST, format!("{:#}", e)).into_response();
+    }
+
+    tracing::info!("set directory to {} through api", root_path);
+
+    StatusCode::OK.into_response()
+}
+
+pub const DIR_ITEMS_PATH: &str = "/api/dir/items";
+
+#[debug_handler]
+pub async fn dir_items_handler(
+    Extension(state): Extension<AppState>,
+    Query(DirItemsParams { path }): Query<DirItemsParams>,
+) -> Result<Json<Vec<ItemView>>> {
+    let path = match path {
+        Some(path) => path,
+        None => state
+            .onedrive
+            .get_root_path(false)
+            .await
+            .map_err(|e| HttpError::new(format!("{:#}", e)))?,
+    };
+
+    // the same chat as uploads through api
+    let chat_id = get_notify_chat()
+        .await
+        .map_err(|e| HttpError::new(format!("{:#}", e)))?
+        .map(|chat| chat.chat_id)
+        .unwrap_or_default();
+
+    let items = state
+        .storage
+        .list(chat_id, &path)
+        .await
+        .map_err(|e| HttpError::new(format!("{:#}", e)))?;
+
+    let item_views = items
+        .into_iter()
+        .map(|item| ItemView {
+            name: item.name,
+            size: item.size,
+            is_dir: item.is_dir,
+        })
+        .collect();
+
+    Ok(Json(item_views))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_get_bearer_token() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(get_bearer_token(&headers), None);
+
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Basic abc"));
+        assert_eq!(get_bearer_token(&headers), None);
+
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer abc"),
+        );
+        assert_eq!(get_bearer_token(&headers), Some("abc"));
+    }
+
+    #[test]
+    fn test_check_token() {
+        let tokens = vec!["abc".to_string(), "def".to_string()];
+
+        assert!(check_token("abc", &tokens));
+        assert!(check_token("def", &tokens));
+        assert!(!check_token("ab", &tokens));
+        assert!(!check_token("", &tokens));
+        assert!(!check_token("abc", &[]));
+    }
+}
@@ -0,0 +1,210 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::models::LoginParams;
+use crate::{
+    env::ENV,
+    utils::{get_current_timestamp, random_token},
+};
+use axum::{
+    debug_handler,
+    extract::Request,
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+const SESSION_COOKIE: &str = "dashboard_session";
+const SESSION_EXPIRATION_SECS: i64 = 7 * 24 * 60 * 60;
+// slows down guessing the password
+const LOGIN_FAILURE_DELAY: Duration = Duration::from_secs(1);
+
+// logged in browsers, they have to login again after restarting
+#[derive(Clone)]
+pub struct Sessions {
+    password_digest: Arc<[u8]>,
+    // token -> expiration timestamp
+    tokens: Arc<Mutex<HashMap<String, i64>>>,
+}
+
+impl Sessions {
+    pub fn new(password: &str) -> Self {
+        Self {
+            password_digest: Sha256::digest(password.as_bytes()).to_vec().into(),
+            tokens: Arc::default(),
+        }
+    }
+
+    // digests have the same length, and all bytes are compared so that the time doesn't tell
+    fn check_password(&self, password: &str) -> bool {
+        Sha256::digest(password.as_bytes())
+            .iter()
+            .zip(self.password_digest.iter())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+    }
+
+    fn create(&self) -> String {
+        let mut tokens = self.tokens.lock().unwrap();
+
+        let current_timestamp = get_current_timestamp();
+        tokens.retain(|_, expiration_timestamp| *expiration_timestamp > current_timestamp);
+
+        let token = random_token();
+        tokens.insert(token.clone(), current_timestamp + SESSION_EXPIRATION_SECS);
+
+        token
+    }
+
+    fn is_valid(&self, token: &str) -> bool {
+        self.tokens
+            .lock()
+            .unwrap()
+            .get(token)
+            .is_some_and(|expiration_timestamp| *expiration_timestamp > get_current_timestamp())
+    }
+
+    fn remove(&self, token: &str) {
+        self.tokens.lock().unwrap().remove(token);
+    }
+}
+
+fn get_session_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, token)| token.to_string())
+}
+
+// the cookie is only sent over https unless tls ends at the reverse proxy
+fn session_cookie(token: &str, max_age_secs: i64) -> String {
+    let secure = if ENV.get().unwrap().use_reverse_proxy {
+        ""
+    } else {
+        "; Secure"
+    };
+
+    format!(
+        "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Strict{}",
+        SESSION_COOKIE, token, max_age_secs, secure
+    )
+}
+
+pub const LOGIN_PATH: &str = "/login";
+
+#[debug_handler]
+pub async fn login_handler(
+    Extension(sessions): Extension<Sessions>,
+    Json(LoginParams { password }): Json<LoginParams>,
+) -> Response {
+    if !sessions.check_password(&password) {
+        tracing::warn!("rejected dashboard login with wrong password");
+
+        tokio::time::sleep(LOGIN_FAILURE_DELAY).await;
+
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    tracing::info!("dashboard logged in");
+
+    let token = sessions.create();
+
+    (
+        [(
+            header::SET_COOKIE,
+            session_cookie(&token, SESSION_EXPIRATION_SECS),
+        )],
+        StatusCode::OK,
+    )
+        .into_response()
+}
+
+pub const LOGOUT_PATH: &str = "/logout";
+
+#[debug_handler]
+pub async fn logout_handler(
+    Extension(sessions): Extension<Sessions>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(token) = get_session_token(&headers) {
+        sessions.remove(&token);
+    }
+
+    (
+        [(header::SET_COOKIE, session_cookie("", 0))],
+        StatusCode::OK,
+    )
+        .into_response()
+}
+
+pub async fn require_login(
+    Extension(sessions): Extension<Sessions>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let is_logged_in =
+        get_session_token(request.headers()).is_some_and(|token| sessions.is_valid(&token));
+
+    if !is_logged_in {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_sessions() {
+        let sessions = Sessions::new("password");
+
+        assert!(sessions.check_password("password"));
+        assert!(!sessions.check_password("passwor"));
+        assert!(!sessions.check_password(""));
+
+        let token = sessions.create();
+        assert!(sessions.is_valid(&token));
+        assert!(!sessions.is_valid("token"));
+
+        sessions.remove(&token);
+        assert!(!sessions.is_valid(&token));
+
+        let token = sessions.create();
+        sessions
+            .tokens
+            .lock()
+            .unwrap()
+            .insert(token.clone(), get_current_timestamp() - 1);
+        assert!(!sessions.is_valid(&token));
+    }
+
+    #[test]
+    fn test_get_session_token() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(get_session_token(&headers), None);
+
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_static("theme=dark; dashboard_session=abc; lang=en"),
+        );
+        assert_eq!(get_session_token(&headers).as_deref(), Some("abc"));
+    }
+}
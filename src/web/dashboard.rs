@@ -0,0 +1,228 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::models::{AccountView, HistoryView, Settings, SettingsParams, TaskAction, TaskView};
+use crate::{
+    error::{ErrorExt, HttpError},
+    state::AppState,
+    tasker::{cancel_task, pause_task, resume_task, retry_task},
+};
+use anyhow::Context;
+use axum::{
+    debug_handler,
+    extract::Path,
+    http::StatusCode,
+    response::{
+        sse::{Event, KeepAlive},
+        Html, IntoResponse, Response, Result, Sse,
+    },
+    Extension, Json,
+};
+use futures::{stream, Stream};
+use std::{sync::atomic::Ordering, time::Duration};
+use tokio::fs;
+
+const EVENT_INTERVAL: Duration = Duration::from_secs(1);
+const HISTORY_LIMIT: u64 = 200;
+
+pub const INDEX_PATH: &str = "/";
+
+#[debug_handler]
+pub async fn index_handler() -> Result<Html<String>> {
+    let html = fs::read_to_string("./dashboard.html")
+        .await
+        .context("failed to read dashboard.html")
+        .map_err(|e| HttpError::new(format!("{:#}", e)))?;
+
+    Ok(Html(html))
+}
+
+pub const EVENTS_PATH: &str = "/events";
+
+// the whole queue is pushed every second, the browser reconnects if the stream ends
+#[debug_handler]
+pub async fn events_handler(
+    Extension(state): Extension<AppState>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let stream = stream::unfold((state, true), |(state, is_first)| async move {
+        if !is_first {
+            tokio::time::sleep(EVENT_INTERVAL).await;
+        }
+
+        let tasks = match state.task_session.list_tasks().await {
+            Ok(tasks) => tasks,
+            Err(e) => {
+                e.trace();
+
+                return None;
+            }
+        };
+
+        let task_views = tasks
+            .into_iter()
+            .map(|task| TaskView {
+                id: task.id,
+                cmd_type: task.cmd_type.to_string(),
+                filename: task.filename,
+                root_path: task.root_path,
+                status: task.status.to_string(),
+                current_length: task.current_length,
+                total_length: task.total_length,
+                chat_id: task.chat_id,
+            })
+            .collect::<Vec<_>>();
+
+        Some((Event::default().json_data(task_views), (state, false)))
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+pub const TASK_ACTION_PATH: &str = "/tasks/:id/:action";
+
+#[debug_handler]
+pub async fn task_action_handler(
+    Extension(state): Extension<AppState>,
+    Path((id, action)): Path<(i64, TaskAction)>,
+) -> Result<Response> {
+    let result = match action {
+        TaskAction::Cancel => cancel_task(&state, id).await,
+        TaskAction::Pause => pause_task(&state, id).await,
+        TaskAction::Resume => resume_task(&state, id).await,
+        TaskAction::Retry => retry_task(&state, id).await,
+    };
+
+    let is_done = result.map_err(|e| HttpError::new(format!("{:#}", e)))?;
+
+    if !is_done {
+        return Ok((
+            StatusCode::CONFLICT,
+            "The task doesn't exist or can't be changed in its status.",
+        )
+            .into_response());
+    }
+
+    Ok(StatusCode::OK.into_response())
+}
+
+pub const HISTORY_PATH: &str = "/history";
+
+#[debug_handler]
+pub async fn history_handler(
+    Extension(state): Extension<AppState>,
+) -> Result<Json<Vec<HistoryView>>> {
+    let records = state
+        .task_session
+        .history
+        .list(HISTORY_LIMIT)
+        .await
+        .map_err(|e| HttpError::new(format!("{:#}", e)))?;
+
+    let history_views = records
+        .into_iter()
+        .map(|record| HistoryView {
+            id: record.id,
+            cmd_type: record.cmd_type.to_string(),
+            filename: record.filename,
+            root_path: record.root_path,
+            status: record.status.to_string(),
+            error: record.error,
+            total_length: record.total_length,
+            chat_id: record.chat_id,
+            finished_timestamp: record.finished_timestamp,
+        })
+        .collect();
+
+    Ok(Json(history_views))
+}
+
+pub const ACCOUNTS_PATH: &str = "/accounts";
+
+#[debug_handler]
+pub async fn accounts_handler(
+    Extension(state): Extension<AppState>,
+) -> Result<Json<Vec<AccountView>>> {
+    let account_views = get_account_views(&state)
+        .await
+        .map_err(|e| HttpError::new(format!("{:#}", e)))?;
+
+    Ok(Json(account_views))
+}
+
+// shared with the http api
+pub async fn get_account_views(state: &AppState) -> anyhow::Result<Vec<AccountView>> {
+    let onedrive = &state.onedrive;
+
+    let usernames = onedrive.get_usernames().await?;
+    let current_username = onedrive.get_current_username().await?;
+
+    // tokens of other accounts are only refreshed after switching to them
+    let mut quota = if current_username.is_some() {
+        onedrive.get_quota().await.ok()
+    } else {
+        None
+    };
+
+    let account_views = usernames
+        .into_iter()
+        .map(|username| {
+            let is_current = current_username.as_ref() == Some(&username);
+
+            AccountView {
+                quota: if is_current { quota.take() } else { None },
+                username,
+                is_current,
+            }
+        })
+        .collect();
+
+    Ok(account_views)
+}
+
+pub const SETTINGS_PATH: &str = "/settings";
+
+#[debug_handler]
+pub async fn settings_handler(Extension(state): Extension<AppState>) -> Result<Json<Settings>> {
+    let root_path = state
+        .onedrive
+        .get_root_path(false)
+        .await
+        .map_err(|e| HttpError::new(format!("{:#}", e)))?;
+
+    Ok(Json(Settings {
+        root_path,
+        auto_delete: state.should_auto_delete.load(Ordering::Acquire),
+    }))
+}
+
+#[debug_handler]
+pub async fn update_settings_handler(
+    Extension(state): Extension<AppState>,
+    Json(SettingsParams {
+        root_path,
+        auto_delete,
+    }): Json<SettingsParams>,
+) -> Result<Response> {
+    if let Some(root_path) = root_path {
+        let result = state.onedrive.set_root_path(&root_path).await;
+
+        // mostly an invalid path
+        if let Err(e) = result {
+            return Ok((StatusCode::BAD_REQUEST, format!("{:#}", e)).into_response());
+        }
+    }
+
+    if let Some(auto_delete) = auto_delete {
+        state
+            .should_auto_delete
+            .store(auto_delete, Ordering::Release);
+
+        tracing::info!("set auto delete to {} on dashboard", auto_delete);
+    }
+
+    Ok(StatusCode::OK.into_response())
+}
@@ -0,0 +1,86 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::models::Readiness;
+use crate::{
+    env::{StorageKind, ENV},
+    error::HttpError,
+    metrics::METRICS,
+    state::AppState,
+};
+use axum::{
+    debug_handler,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response, Result},
+    Extension, Json,
+};
+use std::collections::BTreeMap;
+
+pub const METRICS_PATH: &str = "/metrics";
+
+#[debug_handler]
+pub async fn metrics_handler(Extension(state): Extension<AppState>) -> Result<Response> {
+    let tasks = state
+        .task_session
+        .list_tasks()
+        .await
+        .map_err(|e| HttpError::new(format!("{:#}", e)))?;
+
+    let mut queue = BTreeMap::new();
+    for task in tasks {
+        *queue.entry(task.status.to_string()).or_default() += 1;
+    }
+
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        METRICS.render(&queue),
+    )
+        .into_response())
+}
+
+pub const HEALTHZ_PATH: &str = "/healthz";
+
+// alive as long as the bot can reach telegram
+#[debug_handler]
+pub async fn healthz_handler(Extension(state): Extension<AppState>) -> StatusCode {
+    if state.telegram_bot.is_authorized().await.unwrap_or(false) {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+pub const READYZ_PATH: &str = "/readyz";
+
+// ready to transfer once both telegram clients and onedrive are authorized
+#[debug_handler]
+pub async fn readyz_handler(Extension(state): Extension<AppState>) -> Response {
+    let telegram_bot = state.telegram_bot.is_authorized().await.unwrap_or(false);
+    let telegram_user = state.telegram_user.is_authorized().await.unwrap_or(false);
+    // other storage backends don't need onedrive
+    let onedrive = if ENV.get().unwrap().storage.kind == StorageKind::OneDrive {
+        Some(state.onedrive.is_authorized().await)
+    } else {
+        None
+    };
+
+    let status_code = if telegram_bot && telegram_user && onedrive != Some(false) {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        status_code,
+        Json(Readiness {
+            telegram_bot,
+            telegram_user,
+            onedrive,
+        }),
+    )
+        .into_response()
+}
@@ -0,0 +1,101 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+mod api;
+mod auth;
+mod dashboard;
+mod health;
+mod models;
+mod notify;
+
+use crate::{
+    auth_server,
+    env::{ApiEnv, DashboardEnv, ENV},
+    state::AppState,
+};
+use anyhow::Result;
+use auth::Sessions;
+use axum::{
+    middleware,
+    routing::{get, post},
+    Extension, Router,
+};
+use axum_server::Handle;
+pub use notify::{get_notify_chat, set_notify_chat, NotifyChat};
+
+// unlike the auth server, the web server keeps running with the bot,
+// health checks are always served
+pub async fn spawn(state: AppState) -> Result<()> {
+    let env = ENV.get().unwrap();
+    let DashboardEnv { password, port } = &env.dashboard;
+    let ApiEnv { tokens, .. } = &env.api;
+
+    let mut router = Router::new()
+        .route(health::HEALTHZ_PATH, get(health::healthz_handler))
+        .route(health::READYZ_PATH, get(health::readyz_handler));
+
+    if let Some(password) = password {
+        router = router.merge(dashboard_router(password));
+    }
+
+    // metrics are labeled with onedrive accounts, so they need a token like the api
+    if !tokens.is_empty() {
+        router = router.merge(api_router()).merge(metrics_router());
+    }
+
+    let router = router.layer(Extension(state));
+
+    // never shut down, so the handles are dropped
+    auth_server::serve("web server", *port, router, Handle::new()).await?;
+
+    Ok(())
+}
+
+fn dashboard_router(password: &str) -> Router {
+    Router::new()
+        .route(dashboard::EVENTS_PATH, get(dashboard::events_handler))
+        .route(
+            dashboard::TASK_ACTION_PATH,
+            post(dashboard::task_action_handler),
+        )
+        .route(dashboard::HISTORY_PATH, get(dashboard::history_handler))
+        .route(dashboard::ACCOUNTS_PATH, get(dashboard::accounts_handler))
+        .route(
+            dashboard::SETTINGS_PATH,
+            get(dashboard::settings_handler).post(dashboard::update_settings_handler),
+        )
+        .route(auth::LOGOUT_PATH, post(auth::logout_handler))
+        // routes above need login
+        .route_layer(middleware::from_fn(auth::require_login))
+        .route(dashboard::INDEX_PATH, get(dashboard::index_handler))
+        .route(auth::LOGIN_PATH, post(auth::login_handler))
+        .layer(Extension(Sessions::new(password)))
+}
+
+fn metrics_router() -> Router {
+    Router::new()
+        .route(health::METRICS_PATH, get(health::metrics_handler))
+        .route_layer(middleware::from_fn(api::require_token))
+}
+
+fn api_router() -> Router {
+    Router::new()
+        .route(api::TASKS_PATH, post(api::create_task_handler))
+        .route(
+            api::TASK_PATH,
+            get(api::task_handler).delete(api::delete_task_handler),
+        )
+        .route(api::API_ACCOUNTS_PATH, get(api::accounts_handler))
+        .route(
+            api::DIR_PATH,
+            get(api::dir_handler).put(api::set_dir_handler),
+        )
+        .route(api::DIR_ITEMS_PATH, get(api::dir_items_handler))
+        // routes above need a token
+        .route_layer(middleware::from_fn(api::require_token))
+        .route(api::OPENAPI_PATH, get(api::openapi_handler))
+}
@@ -0,0 +1,142 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::client::onedrive::DriveQuota;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct LoginParams {
+    pub password: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskAction {
+    Cancel,
+    Pause,
+    Resume,
+    Retry,
+}
+
+#[derive(Serialize)]
+pub struct TaskView {
+    pub id: i64,
+    pub cmd_type: String,
+    pub filename: String,
+    pub root_path: String,
+    pub status: String,
+    pub current_length: i64,
+    pub total_length: i64,
+    pub chat_id: i64,
+}
+
+#[derive(Serialize)]
+pub struct HistoryView {
+    pub id: i64,
+    pub cmd_type: String,
+    pub filename: String,
+    pub root_path: String,
+    pub status: String,
+    pub error: Option<String>,
+    pub total_length: i64,
+    pub chat_id: i64,
+    pub finished_timestamp: i64,
+}
+
+#[derive(Serialize)]
+pub struct AccountView {
+    pub username: String,
+    pub is_current: bool,
+    // only known for the current account
+    pub quota: Option<DriveQuota>,
+}
+
+#[derive(Serialize)]
+pub struct Settings {
+    pub root_path: String,
+    pub auto_delete: bool,
+}
+
+// fields not set are left unchanged
+#[derive(Deserialize)]
+pub struct SettingsParams {
+    pub root_path: Option<String>,
+    pub auto_delete: Option<bool>,
+}
+
+// a json body adds a url or link task, any other body is uploaded as a file
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CreateTaskParams {
+    Url {
+        url: String,
+        // request options, written like the lines following /url
+        #[serde(default)]
+        options: Vec<String>,
+        root_path: Option<String>,
+    },
+    Link {
+        link: String,
+        root_path: Option<String>,
+    },
+}
+
+#[derive(Deserialize)]
+pub struct UploadParams {
+    pub filename: Option<String>,
+    pub root_path: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct CreatedTask {
+    pub id: i64,
+}
+
+// a queued task, or the latest record in history once finished
+#[derive(Serialize)]
+pub struct ApiTaskView {
+    pub id: i64,
+    pub cmd_type: String,
+    pub filename: String,
+    pub root_path: String,
+    pub status: String,
+    pub error: Option<String>,
+    pub current_length: i64,
+    pub total_length: i64,
+}
+
+#[derive(Serialize)]
+pub struct DirView {
+    pub root_path: String,
+    pub is_temp: bool,
+}
+
+#[derive(Deserialize)]
+pub struct DirParams {
+    pub root_path: String,
+}
+
+#[derive(Deserialize)]
+pub struct DirItemsParams {
+    // the current directory if not set
+    pub path: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ItemView {
+    pub name: String,
+    pub size: u64,
+    pub is_dir: bool,
+}
+
+#[derive(Serialize)]
+pub struct Readiness {
+    pub telegram_bot: bool,
+    pub telegram_user: bool,
+    // none if files aren't stored in onedrive
+    pub onedrive: Option<bool>,
+}
@@ -0,0 +1,94 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+// the chat bound by /api notify, the bot can't resolve a chat from its id alone,
+// so the packed chats are saved when the command is sent in it
+
+use crate::env::ENV;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tokio::fs;
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotifyChat {
+    pub chat_id: i64,
+    pub chat_bot_hex: String,
+    pub chat_user_hex: String,
+}
+
+pub async fn get_notify_chat() -> Result<Option<NotifyChat>> {
+    load(&ENV.get().unwrap().api.notify_path).await
+}
+
+// none stops notifications
+pub async fn set_notify_chat(notify_chat: Option<&NotifyChat>) -> Result<()> {
+    save(&ENV.get().unwrap().api.notify_path, notify_chat).await
+}
+
+async fn load(path: &str) -> Result<Option<NotifyChat>> {
+    if !Path::new(path).exists() {
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(path)
+        .await
+        .context("failed to read api notify chat")?;
+
+    let notify_chat =
+        serde_json::from_str(&content).context("failed to deserialize api notify chat")?;
+
+    Ok(Some(notify_chat))
+}
+
+async fn save(path: &str, notify_chat: Option<&NotifyChat>) -> Result<()> {
+    match notify_chat {
+        Some(notify_chat) => {
+            let content = serde_json::to_string(notify_chat)
+                .context("failed to serialize api notify chat")?;
+
+            fs::write(path, content)
+                .await
+                .context("failed to write api notify chat")?;
+        }
+        None => {
+            if Path::new(path).exists() {
+                fs::remove_file(path)
+                    .await
+                    .context("failed to remove api notify chat")?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TempPath;
+
+    #[tokio::test]
+    async fn test_notify_chat() {
+        let path = TempPath::new("api-notify");
+        let path = path.as_str();
+
+        assert_eq!(load(path).await.unwrap(), None);
+
+        let notify_chat = NotifyChat {
+            chat_id: 1,
+            chat_bot_hex: "bot".to_string(),
+            chat_user_hex: "user".to_string(),
+        };
+        save(path, Some(&notify_chat)).await.unwrap();
+        assert_eq!(load(path).await.unwrap(), Some(notify_chat));
+
+        save(path, None).await.unwrap();
+        assert_eq!(load(path).await.unwrap(), None);
+        save(path, None).await.unwrap();
+    }
+}